@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use logicly_rs::io::{ParseMode, load_circuit_from_reader_with, parse_xml_with};
+
+// Loading goes through the same detection of compression and text encodings as loading a file,
+// and parsing text directly reaches the parser without deflate streams getting in the way.
+fuzz_target!(|data: &[u8]| {
+	for mode in [ParseMode::Strict, ParseMode::Lenient] {
+		let _ = load_circuit_from_reader_with(data, mode);
+		if let Ok(text) = std::str::from_utf8(data) {
+			let _ = parse_xml_with(text, mode);
+		}
+	}
+});
@@ -0,0 +1,356 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::io::{Circuit, Object, ObjectInner, SimpleGateType};
+
+/// A kind of building block recognized by [`Circuit::find_patterns`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PatternKind {
+	/// An xor and an and gate reading the same two signals.
+	HalfAdder,
+	/// Two half adders, the second adding the carry in to the sum of the first, with their carries combined by an or gate.
+	FullAdder,
+	/// Two and gates combined by an or gate, where one and gate reads the select signal and the other reads its negation.
+	Mux2,
+	/// Two nor gates, or two nand gates, each reading the output of the other.
+	SrLatch,
+}
+impl Display for PatternKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			PatternKind::HalfAdder => "half adder",
+			PatternKind::FullAdder => "full adder",
+			PatternKind::Mux2 => "2:1 multiplexer",
+			PatternKind::SrLatch => "SR latch",
+		})
+	}
+}
+
+/// One instance of a building block.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PatternMatch {
+	pub kind: PatternKind,
+	/// Name of the custom circuit definition the block is in, or None for the top level.
+	pub custom: Option<String>,
+	/// Uids of the gates that make up the block,
+	/// or the uid of a custom gate whose definition is exactly this block and nothing else.
+	pub uids: Vec<String>,
+	/// The signal playing each role, like `("cin", "fa0[1]")`, named by the object driving it.
+	/// Signals from objects with several outputs are followed by the output index, unless it is 0.
+	/// Roles whose input is not connected are left out.
+	pub roles: Vec<(&'static str, String)>,
+}
+impl PatternMatch {
+	pub fn role(&self, role: &str) -> Option<&str> {
+		self.roles.iter().find(|(r, _)| *r == role).map(|(_, signal)| &signal[..])
+	}
+}
+impl Display for PatternMatch {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if let Some(custom) = &self.custom {
+			write!(f, "in custom circuit {custom}: ")?;
+		}
+		write!(f, "{} {}: ", self.kind, self.uids.join(", "))?;
+		f.write_str(&self.roles.iter().map(|(role, signal)| format!("{role}={signal}")).collect::<Vec<_>>().join(", "))
+	}
+}
+
+/// The building blocks found in a circuit. See [`Circuit::find_patterns`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PatternReport {
+	pub matches: Vec<PatternMatch>,
+	/// Full adders where the carry out of each is the carry in of the next, as indices into `matches`, least significant first.
+	/// Only chains of at least two adders are listed.
+	pub carry_chains: Vec<Vec<usize>>,
+}
+impl Display for PatternReport {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if self.matches.is_empty() {
+			return writeln!(f, "No known building blocks found");
+		}
+		for m in &self.matches {
+			writeln!(f, "{m}")?;
+		}
+		for chain in &self.carry_chains {
+			let sums: Vec<&str> = chain.iter().map(|&i| self.matches[i].role("sum").unwrap_or("?")).collect();
+			writeln!(f, "{} full adders chained by carry: {}", chain.len(), sums.join(" -> "))?;
+		}
+		Ok(())
+	}
+}
+
+/// An output of an object, as (object index, output index).
+type Signal = (usize, u32);
+
+/// A match within one list of objects, before signals are given names.
+struct Found {
+	kind: PatternKind,
+	gates: Vec<usize>,
+	roles: Vec<(&'static str, Option<Signal>)>,
+}
+
+/// The signal connected to input `input` of object `i`, and whether that input is inverted.
+/// Buffers are skipped, as they do not change the function of a block.
+fn source(objects: &[Object], i: usize, input: usize) -> Option<(Signal, bool)> {
+	let (mut output, mut from) = objects[i].connections().get(input).copied().flatten()?;
+	let mut inverted = objects[i].inverted_inputs().get(input).copied().unwrap_or(false);
+	let mut seen = HashSet::new();
+	while let ObjectInner::SimpleGate { kind: SimpleGateType::Buffer, .. } = objects[from].inner && seen.insert(from) {
+		let Some(&Some((next_output, next))) = objects[from].connections().first() else { break };
+		inverted ^= objects[from].inverted_inputs().first().copied().unwrap_or(false);
+		(output, from) = (next_output, next);
+	}
+	Some(((from, output), inverted))
+}
+
+/// The inputs of object `i`, if it is a simple gate of this kind with `n` inputs, all connected and none inverted.
+fn plain_gate(objects: &[Object], i: usize, kind: SimpleGateType, n: usize) -> Option<Vec<Signal>> {
+	match &objects[i].inner {
+		ObjectInner::SimpleGate { kind: k, connections, .. } if *k == kind && connections.len() == n => {
+			(0..n).map(|input| source(objects, i, input).filter(|(_, inverted)| !inverted).map(|(signal, _)| signal)).collect()
+		},
+		_ => None,
+	}
+}
+
+fn same_pair(a: &[Signal], b: &[Signal]) -> bool {
+	a.len() == 2 && b.len() == 2 && a[0] != a[1] && ((a[0] == b[0] && a[1] == b[1]) || (a[0] == b[1] && a[1] == b[0]))
+}
+
+/// The signal `signal` reads as, seen through at most one not gate, as (signal, negated), and the not gate if there was one.
+fn literal(objects: &[Object], (signal, inverted): (Signal, bool)) -> ((Signal, bool), Option<usize>) {
+	let (i, _) = signal;
+	if let ObjectInner::SimpleGate { kind: SimpleGateType::Not, .. } = objects[i].inner
+		&& let Some((inner, inner_inverted)) = source(objects, i, 0) {
+		return ((inner, inverted ^ !inner_inverted), Some(i));
+	}
+	((signal, inverted), None)
+}
+
+fn half_adders(objects: &[Object]) -> Vec<(usize, usize, Vec<Signal>)> {
+	let mut found = vec![];
+	for x in 0..objects.len() {
+		let Some(xor_inputs) = plain_gate(objects, x, SimpleGateType::Xor, 2) else { continue };
+		for a in 0..objects.len() {
+			if plain_gate(objects, a, SimpleGateType::And, 2).is_some_and(|and_inputs| same_pair(&xor_inputs, &and_inputs)) {
+				found.push((x, a, xor_inputs.clone()));
+			}
+		}
+	}
+	found
+}
+
+fn find_in(objects: &[Object]) -> Vec<Found> {
+	let mut found = vec![];
+	let half = half_adders(objects);
+	let mut used_half = HashSet::new();
+	for (n1, (x1, a1, inputs)) in half.iter().enumerate() {
+		for (n2, (x2, a2, inputs2)) in half.iter().enumerate() {
+			let Some(k) = inputs2.iter().position(|&s| s == (*x1, 0)) else { continue };
+			let cin = inputs2[1 - k];
+			let Some(or) = (0..objects.len()).find(|&o| plain_gate(objects, o, SimpleGateType::Or, 2)
+				.is_some_and(|or_inputs| same_pair(&or_inputs, &[(*a1, 0), (*a2, 0)]))) else { continue };
+			used_half.extend([n1, n2]);
+			found.push(Found {
+				kind: PatternKind::FullAdder,
+				gates: vec![*x1, *a1, *x2, *a2, or],
+				roles: vec![("a", Some(inputs[0])), ("b", Some(inputs[1])), ("cin", Some(cin)), ("sum", Some((*x2, 0))), ("cout", Some((or, 0)))],
+			});
+		}
+	}
+	for (n, (x, a, inputs)) in half.iter().enumerate() {
+		if used_half.contains(&n) { continue }
+		found.push(Found {
+			kind: PatternKind::HalfAdder,
+			gates: vec![*x, *a],
+			roles: vec![("a", Some(inputs[0])), ("b", Some(inputs[1])), ("sum", Some((*x, 0))), ("carry", Some((*a, 0)))],
+		});
+	}
+
+	let and_inputs = |i: usize| match &objects[i].inner {
+		ObjectInner::SimpleGate { kind: SimpleGateType::And, connections, .. } if connections.len() == 2 =>
+			(0..2).map(|input| source(objects, i, input)).collect::<Option<Vec<_>>>(),
+		_ => None,
+	};
+	for or in 0..objects.len() {
+		let Some(or_inputs) = plain_gate(objects, or, SimpleGateType::Or, 2) else { continue };
+		let [(t0, 0), (t1, 0)] = or_inputs[..] else { continue };
+		if t0 == t1 { continue }
+		let (Some(first), Some(second)) = (and_inputs(t0), and_inputs(t1)) else { continue };
+		// Find an input of each and gate where one reads the select signal and the other its negation
+		let select = (0..2).flat_map(|i| (0..2).map(move |j| (i, j))).find_map(|(i, j)| {
+			let ((s0, negated0), not0) = literal(objects, first[i]);
+			let ((s1, negated1), not1) = literal(objects, second[j]);
+			(s0 == s1 && negated0 != negated1).then_some((i, j, s0, negated0, not0.or(not1)))
+		});
+		let Some((i, j, select, first_negated, not)) = select else { continue };
+		// The and gate reading the negated select passes its data input through when select is false
+		let (d0, d1) = if first_negated { (first[1 - i], second[1 - j]) } else { (second[1 - j], first[1 - i]) };
+		let (((d0, false), _), ((d1, false), _)) = (literal(objects, d0), literal(objects, d1)) else { continue };
+		found.push(Found {
+			kind: PatternKind::Mux2,
+			gates: [t0, t1, or].into_iter().chain(not).collect(),
+			roles: vec![("sel", Some(select)), ("d0", Some(d0)), ("d1", Some(d1)), ("out", Some((or, 0)))],
+		});
+	}
+
+	for kind in [SimpleGateType::Nor, SimpleGateType::Nand] {
+		for g1 in 0..objects.len() {
+			let Some(inputs1) = plain_gate(objects, g1, kind, 2) else { continue };
+			for g2 in g1 + 1..objects.len() {
+				let Some(inputs2) = plain_gate(objects, g2, kind, 2) else { continue };
+				let (Some(k1), Some(k2)) = (inputs1.iter().position(|&s| s == (g2, 0)), inputs2.iter().position(|&s| s == (g1, 0))) else { continue };
+				let (x1, x2) = (inputs1[1 - k1], inputs2[1 - k2]);
+				// With nor gates, the input next to Q resets the latch. With nand gates, it is an active low set.
+				let roles = match kind {
+					SimpleGateType::Nor => vec![("s", Some(x2)), ("r", Some(x1)), ("q", Some((g1, 0))), ("qn", Some((g2, 0)))],
+					_ => vec![("s_n", Some(x1)), ("r_n", Some(x2)), ("q", Some((g1, 0))), ("qn", Some((g2, 0)))],
+				};
+				found.push(Found { kind: PatternKind::SrLatch, gates: vec![g1, g2], roles });
+			}
+		}
+	}
+	found
+}
+
+/// If `objects` is exactly one building block, with its inputs and outputs wired straight to named inputs and outputs,
+/// returns the kind, and the input or output index of the custom gate for each role.
+fn whole_block(objects: &[Object], found: &[Found]) -> Option<Block> {
+	let [block] = found else { return None };
+	let gates = objects.iter().enumerate()
+		.filter(|(_, o)| matches!(o.inner, ObjectInner::SimpleGate { kind, .. } if kind != SimpleGateType::Buffer) || matches!(o.inner, ObjectInner::CustomGate { .. }))
+		.count();
+	if gates != block.gates.len() { return None }
+	let inputs: Vec<usize> = (0..objects.len()).filter(|&i| objects[i].is_named_input()).collect();
+	let outputs: Vec<usize> = (0..objects.len()).filter(|&i| objects[i].is_named_output()).collect();
+	let ports = block.roles.iter().map(|&(role, signal)| {
+		let signal = signal?;
+		let port = match inputs.iter().position(|&i| (i, 0) == signal) {
+			Some(input) => Port::Input(input),
+			None => Port::Output(outputs.iter().position(|&o| source(objects, o, 0).is_some_and(|(s, inverted)| s == signal && !inverted))?),
+		};
+		Some((role, port))
+	}).collect::<Option<Vec<_>>>()?;
+	Some((block.kind, ports))
+}
+
+/// The kind of block a custom circuit is, and the port of the custom gate for each role.
+type Block = (PatternKind, Vec<(&'static str, Port)>);
+#[derive(Clone, Copy, Debug)]
+enum Port {
+	Input(usize),
+	Output(usize),
+}
+
+fn signal_name(objects: &[Object], (i, output): Signal) -> String {
+	match output {
+		0 => objects[i].export_name_or_uid().to_string(),
+		_ => format!("{}[{output}]", objects[i].export_name_or_uid()),
+	}
+}
+
+impl Circuit {
+	/// Looks for common building blocks in the top level and each custom circuit definition:
+	/// half adders, full adders, 2:1 multiplexers, and SR latches.
+	///
+	/// Matching is structural, so blocks built differently, like a full adder using a three input xor gate, are not found.
+	/// Buffers between gates are skipped, and the inputs of and, or, xor, nand, and nor gates can be in any order.
+	/// Half adders that are part of a full adder are not listed separately.
+	/// Instances of a custom circuit that is exactly one block are listed as that block, with the custom gate's uid.
+	pub fn find_patterns(&self) -> PatternReport {
+		let mut report = PatternReport::default();
+		let mut blocks: HashMap<&str, Block> = HashMap::new();
+		// Definitions come before the circuits that use them
+		let levels = self.custom_library().iter().map(|c| (Some(c), &c.objects)).chain([(None, &self.objects)]);
+		for (custom, objects) in levels {
+			let mut found = find_in(objects);
+			if let Some(custom) = custom && let Some(block) = whole_block(objects, &found) {
+				blocks.insert(&custom.uid, block);
+			}
+			for (i, object) in objects.iter().enumerate() {
+				let ObjectInner::CustomGate { uuid, .. } = &object.inner else { continue };
+				let Some((kind, ports)) = blocks.get(&uuid[..]) else { continue };
+				let roles = ports.iter().map(|&(role, port)| (role, match port {
+					Port::Input(input) => source(objects, i, input).filter(|(_, inverted)| !inverted).map(|(signal, _)| signal),
+					Port::Output(output) => Some((i, output as u32)),
+				})).collect();
+				found.push(Found { kind: *kind, gates: vec![i], roles });
+			}
+			let start = report.matches.len();
+			report.matches.extend(found.iter().map(|f| PatternMatch {
+				kind: f.kind,
+				custom: custom.map(|c| c.display_name().to_string()),
+				uids: f.gates.iter().map(|&g| objects[g].uid().to_string()).collect(),
+				roles: f.roles.iter().filter_map(|&(role, signal)| Some((role, signal_name(objects, signal?)))).collect(),
+			}));
+			report.carry_chains.extend(carry_chains(&found).into_iter().map(|chain| chain.into_iter().map(|i| start + i).collect()));
+		}
+		report
+	}
+}
+
+fn carry_chains(found: &[Found]) -> Vec<Vec<usize>> {
+	let role = |f: &Found, name: &str| f.roles.iter().find(|(r, _)| *r == name).and_then(|(_, s)| *s);
+	let adders: Vec<usize> = (0..found.len()).filter(|&i| found[i].kind == PatternKind::FullAdder).collect();
+	let next = |i: usize| adders.iter().copied().find(|&j| j != i && role(&found[i], "cout").is_some() && role(&found[j], "cin") == role(&found[i], "cout"));
+	let mut chains = vec![];
+	for &first in &adders {
+		// Start from adders whose carry in does not come from another adder
+		if adders.iter().any(|&j| next(j) == Some(first)) { continue }
+		let mut chain = vec![first];
+		while let Some(n) = next(*chain.last().expect("chains are not empty")) {
+			if chain.contains(&n) { break }
+			chain.push(n);
+		}
+		if chain.len() >= 2 { chains.push(chain) }
+	}
+	chains
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::analyze::*;
+	use crate::io::parse_xml;
+	use crate::util::fixtures;
+
+	#[test]
+	fn ripple_adder_patterns(){
+		let report = parse_xml(fixtures::RIPPLE_ADDER).unwrap().find_patterns();
+		let adders: Vec<_> = report.matches.iter().filter(|m| m.kind == PatternKind::FullAdder).collect();
+		assert_eq!(adders.len(), 5);
+		assert_eq!(adders[0].to_string(), "in custom circuit FA: full adder x1, a1, x2, a2, o1: a=a, b=b, cin=cin, sum=x2, cout=o1");
+		assert_eq!(adders[1].to_string(), "full adder fa3: a=a3, b=b3, cin=fa2[1], sum=fa3, cout=fa3[1]");
+		// The least significant adder has nothing connected to its carry in
+		assert_eq!(adders[4].role("cin"), None);
+		assert_eq!(report.matches.len(), 5);
+		assert_eq!(report.carry_chains, [vec![4, 3, 2, 1]]);
+		assert!(report.to_string().ends_with("4 full adders chained by carry: fa0 -> fa1 -> fa2 -> fa3\n"), "{report}");
+		assert_eq!(parse_xml(fixtures::HALF_ADDER).unwrap().find_patterns().matches.iter().map(|m| m.kind).collect::<Vec<_>>(), [PatternKind::HalfAdder; 2]);
+	}
+	#[test]
+	fn mux_and_latch_patterns(){
+		// Buffers and swapped inputs do not hide the multiplexer
+		let mux = crate::circuit!{ in s, x, y; ns = not(s); t0 = and(ns, x); t1 = and(buffer(y), s); m = or(t1, t0); out q = m; };
+		let report = mux.find_patterns();
+		assert_eq!(report.matches.len(), 1);
+		assert_eq!(report.matches[0].kind, PatternKind::Mux2);
+		assert_eq!(report.matches[0].roles, [("sel", "s"), ("d0", "x"), ("d1", "y"), ("out", "m")].map(|(r, s)| (r, s.to_string())));
+		let latch = crate::circuit!{ in s, r; q = nor(r, qn); qn = nor(s, q); out q1 = q; };
+		let report = latch.find_patterns();
+		assert_eq!(report.matches.len(), 1);
+		assert_eq!(report.matches[0].to_string(), "SR latch q, qn: s=s, r=r, q=q, qn=qn");
+		let latch = crate::circuit!{ in sn, rn; q = nand(sn, qn); qn = nand(rn, q); out q1 = q; };
+		assert_eq!(latch.find_patterns().matches[0].roles[..2], [("s_n", "sn".to_string()), ("r_n", "rn".to_string())]);
+	}
+	#[test]
+	fn no_patterns_in_random_circuit(){
+		// Larger synthetic circuits do happen to contain an xor and an and gate reading the same two signals
+		let random = parse_xml(&fixtures::synthetic_circuit(6, 25, 4)).unwrap();
+		assert_eq!(random.find_patterns(), PatternReport::default());
+		assert_eq!(random.find_patterns().to_string(), "No known building blocks found\n");
+		assert_eq!(parse_xml(fixtures::MAJORITY).unwrap().find_patterns(), PatternReport::default());
+	}
+}
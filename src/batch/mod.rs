@@ -0,0 +1,207 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Context, Result, anyhow};
+use serde::Serialize;
+
+use crate::io::{ParseMode, load_circuit_with};
+use crate::simul::{ProgressHook, ProgressTask, Simulation, SimulationConfig, TruthTable};
+
+/// The outcome of checking one file against the expected truth table.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FileResult {
+	pub file: PathBuf,
+	pub passed: bool,
+	/// Rows whose outputs differ from the expected table. Every row fails if the file could not be simulated,
+	/// or its inputs or outputs have different names.
+	pub failed_cases: usize,
+	pub first_error: Option<String>,
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of characters and `?` matches one character.
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+	match (pattern.first(), name.first()) {
+		(None, _) => name.is_empty(),
+		(Some('*'), _) => wildcard_match(&pattern[1..], name) || (!name.is_empty() && wildcard_match(pattern, &name[1..])),
+		(Some('?'), Some(_)) => wildcard_match(&pattern[1..], &name[1..]),
+		(Some(p), Some(n)) => p == n && wildcard_match(&pattern[1..], &name[1..]),
+		(Some(_), None) => false,
+	}
+}
+
+/// Lists the files named by `patterns`, sorted by path and without duplicates.
+/// A pattern can be a file, a directory, which stands for the .logicly files in it,
+/// or a path whose last component contains `*` or `?`, like `submissions/*.logicly`.
+/// Fails if a pattern matches nothing, as that is usually a typo.
+pub fn expand_patterns(patterns: &[String]) -> Result<Vec<PathBuf>> {
+	let mut files = vec![];
+	for pattern in patterns {
+		let path = Path::new(pattern);
+		let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+		let (dir, name_pattern) = if path.is_dir() {
+			(path, String::from("*.logicly"))
+		} else if name.contains(['*', '?']) {
+			(path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new(".")), name.to_string())
+		} else {
+			files.push(path.to_path_buf());
+			continue;
+		};
+		let name_pattern: Vec<char> = name_pattern.chars().collect();
+		let len = files.len();
+		for entry in std::fs::read_dir(dir).with_context(|| format!("Error reading directory {}", dir.display()))? {
+			let entry = entry?;
+			let name: Vec<char> = entry.file_name().to_string_lossy().chars().collect();
+			if wildcard_match(&name_pattern, &name) && entry.file_type()?.is_file() {
+				files.push(entry.path());
+			}
+		}
+		if files.len() == len {
+			return Err(anyhow!("No files match {pattern}"));
+		}
+	}
+	files.sort();
+	files.dedup();
+	Ok(files)
+}
+
+/// Loads and simulates one file, and compares its truth table to `expected`.
+/// Problems with the file, like parse errors, are reported in the result instead of failing.
+pub fn check_file(file: &Path, expected: &TruthTable, mode: ParseMode, config: &SimulationConfig, limit: u128) -> FileResult {
+	let failure = |error: String| FileResult { file: file.to_path_buf(), passed: false, failed_cases: expected.num_rows(), first_error: Some(error) };
+	let table = load_circuit_with(file, mode)
+		.and_then(|circuit| Simulation::with_config(circuit, config.clone()))
+		.and_then(|mut simul| simul.try_get_truth_table(limit));
+	let table = match table {
+		Ok(table) => table,
+		Err(e) => return failure(format!("{e:#}")),
+	};
+	match table.assert_matches(expected) {
+		Ok(()) => FileResult { file: file.to_path_buf(), passed: true, failed_cases: 0, first_error: None },
+		Err(mismatch) if table.inputs() != expected.inputs() || table.outputs() != expected.outputs() => failure(mismatch.to_string()),
+		Err(mismatch) => FileResult {
+			file: file.to_path_buf(),
+			passed: false,
+			failed_cases: table.rows().zip(expected.rows()).filter(|(found, expected)| found != expected).count(),
+			first_error: Some(mismatch.to_string()),
+		},
+	}
+}
+
+/// Checks every file with [`check_file`], using up to `jobs` threads. The results are in the same order as `files`.
+/// The progress callback of `config` is called after each file, instead of while computing each table.
+/// Once the cancellation token of `config` is cancelled, the files being checked fail with a [`crate::simul::Cancelled`] error,
+/// and the files not started yet fail with the error `Cancelled`.
+pub fn check_files(files: &[PathBuf], expected: &TruthTable, mode: ParseMode, config: &SimulationConfig, limit: u128, jobs: usize) -> Vec<FileResult> {
+	let progress = config.progress.start(|| ProgressTask::Files, files.len());
+	let mut config = config.clone();
+	config.progress = ProgressHook::default();
+	let config = &config;
+	let next = AtomicUsize::new(0);
+	let finished = AtomicUsize::new(0);
+	let results = Mutex::new(vec![None; files.len()]);
+	std::thread::scope(|scope| {
+		for _ in 0..jobs.clamp(1, files.len().max(1)) {
+			scope.spawn(|| {
+				loop {
+					let i = next.fetch_add(1, Ordering::Relaxed);
+					let Some(file) = files.get(i) else { break };
+					if config.is_cancelled() { break }
+					let result = check_file(file, expected, mode, config, limit);
+					results.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(result);
+					progress.files_done(finished.fetch_add(1, Ordering::Relaxed) + 1);
+				}
+			});
+		}
+	});
+	let results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+	results.into_iter().zip(files).map(|(result, file)| result.unwrap_or_else(|| {
+		FileResult { file: file.clone(), passed: false, failed_cases: expected.num_rows(), first_error: Some(String::from("Cancelled")) }
+	})).collect()
+}
+
+/// Quotes a CSV field if it contains a separator, quote, or line break.
+fn csv_field(field: &str) -> String {
+	if field.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", field.replace('"', "\"\""))
+	} else {
+		field.to_string()
+	}
+}
+
+/// Writes one line per result, with the columns `file,result,failed_cases,first_error`.
+pub fn write_csv(results: &[FileResult], mut w: impl Write) -> Result<()> {
+	writeln!(w, "file,result,failed_cases,first_error")?;
+	for result in results {
+		writeln!(w, "{},{},{},{}",
+			csv_field(&result.file.display().to_string()),
+			if result.passed { "pass" } else { "fail" },
+			result.failed_cases,
+			csv_field(result.first_error.as_deref().unwrap_or_default()),
+		)?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use crate::batch::*;
+	use crate::io::{parse_raw_xml, parse_xml, save_raw_circuit};
+	use crate::util::fixtures;
+
+	#[test]
+	fn wildcards(){
+		let matches = |pattern: &str, name: &str| wildcard_match(&pattern.chars().collect::<Vec<_>>(), &name.chars().collect::<Vec<_>>());
+		assert!(matches("*.logicly", "adder.logicly"));
+		assert!(matches("a?c*", "abc"));
+		assert!(matches("*", ""));
+		assert!(!matches("*.logicly", "adder.logicly.bak"));
+		assert!(!matches("a?c", "ac"));
+	}
+	#[test]
+	fn batch_check(){
+		let dir = std::env::temp_dir().join(format!("logicly-rs-batch-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		save_raw_circuit(&parse_raw_xml(fixtures::XOR).unwrap(), dir.join("b_pass.logicly")).unwrap();
+		// An xnor gate, which gets every row wrong
+		save_raw_circuit(&parse_raw_xml(&fixtures::XOR.replace("xor@logic.ly", "xnor@logic.ly")).unwrap(), dir.join("a_fail.logicly")).unwrap();
+		fs::write(dir.join("c_broken.logicly"), "<logicly><object").unwrap();
+		fs::write(dir.join("notes.txt"), "not a circuit").unwrap();
+
+		let files = expand_patterns(&[dir.join("*.logicly").display().to_string()]).unwrap();
+		assert_eq!(files, ["a_fail.logicly", "b_pass.logicly", "c_broken.logicly"].map(|f| dir.join(f)));
+		assert_eq!(expand_patterns(&[dir.display().to_string()]).unwrap(), files);
+		assert_eq!(expand_patterns(&[dir.join("*.xml").display().to_string()]).unwrap_err().to_string(), format!("No files match {}", dir.join("*.xml").display()));
+
+		let expected = Simulation::from(parse_xml(fixtures::XOR).unwrap()).get_truth_table(100).unwrap();
+		let results = check_files(&files, &expected, ParseMode::Strict, &SimulationConfig::default(), 100, 4);
+		assert_eq!(results, check_files(&files, &expected, ParseMode::Strict, &SimulationConfig::default(), 100, 1));
+		assert_eq!(results.iter().map(|r| (r.passed, r.failed_cases)).collect::<Vec<_>>(), [(false, 4), (true, 0), (false, 4)]);
+		assert_eq!(results[0].first_error.as_deref(), Some("Row 0 (inputs 00): expected outputs 0, found 1"));
+		// Progress is reported per file, not per row of each file's table
+		let events = std::sync::Arc::new(Mutex::new(vec![]));
+		let recorded = events.clone();
+		let config = SimulationConfig::default().progress(Box::new(move |event| recorded.lock().unwrap().push((event.task, event.done, event.total))));
+		check_files(&files, &expected, ParseMode::Strict, &config, 100, 2);
+		let mut events = events.lock().unwrap().clone();
+		events.sort_by_key(|(_, done, _)| *done);
+		assert_eq!(events, [1, 2, 3].map(|done| (ProgressTask::Files, done, 3)));
+		let token = crate::simul::CancellationToken::new();
+		token.cancel();
+		let cancelled = check_files(&files, &expected, ParseMode::Strict, &SimulationConfig::default().cancellation(token), 100, 2);
+		assert!(cancelled.iter().all(|r| !r.passed && r.first_error.as_deref() == Some("Cancelled")), "{cancelled:?}");
+
+		let mut csv = vec![];
+		write_csv(&results, &mut csv).unwrap();
+		let csv = String::from_utf8(csv).unwrap();
+		let lines: Vec<&str> = csv.lines().collect();
+		assert_eq!(lines[0], "file,result,failed_cases,first_error");
+		assert_eq!(lines[1], format!("{},fail,4,\"Row 0 (inputs 00): expected outputs 0, found 1\"", dir.join("a_fail.logicly").display()));
+		assert_eq!(lines[2], format!("{},pass,0,", dir.join("b_pass.logicly").display()));
+		assert!(lines[3].starts_with(&format!("{},fail,4,", dir.join("c_broken.logicly").display())), "{csv}");
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,140 @@
+use std::{collections::{HashMap, HashSet}, fmt::Display};
+
+use crate::io::{Circuit, CustomCircuit, InputType, Object, ObjectInner, SimpleGateType, XorType};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComposeError {
+	/// The producer has no named output with this name.
+	UnknownOutput(String),
+	/// The consumer has no named input with this name.
+	UnknownInput(String),
+	/// The consumer input is a constant, so it cannot be driven by anything.
+	ConstantInput(String),
+	/// The consumer input is wired more than once.
+	InputWiredTwice(String),
+	/// Both circuits still expose a named input or output with this name after wiring.
+	DuplicateName(String),
+	/// Both circuits define a custom circuit with this uuid, but the definitions differ.
+	ConflictingCustom(String),
+}
+impl Display for ComposeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ComposeError::UnknownOutput(name) => write!(f, "Producer has no output named {name}"),
+			ComposeError::UnknownInput(name) => write!(f, "Consumer has no input named {name}"),
+			ComposeError::ConstantInput(name) => write!(f, "Consumer input {name} is a constant"),
+			ComposeError::InputWiredTwice(name) => write!(f, "Consumer input {name} is wired more than once"),
+			ComposeError::DuplicateName(name) => write!(f, "Both circuits expose a signal named {name}"),
+			ComposeError::ConflictingCustom(uid) => write!(f, "Both circuits define custom circuit {uid} differently"),
+		}
+	}
+}
+impl std::error::Error for ComposeError {}
+
+/// Connects named outputs of `producer` to named inputs of `consumer`, as `(output, input)` pairs.
+///
+/// The wired outputs lose their export name and the wired inputs become buffers,
+/// so the resulting circuit's interface is the remaining named inputs and outputs of both circuits.
+/// Consumer objects whose uid is also used in the producer are renamed to `consumer/{uid}`.
+pub fn compose(producer: &Circuit, consumer: &Circuit, wiring: &[(&str, &str)]) -> Result<Circuit, ComposeError> {
+	let mut objects = producer.objects.clone();
+	let offset = objects.len();
+	let producer_uids: HashSet<&str> = producer.objects.iter().map(|o| o.uid()).collect();
+	objects.extend(consumer.objects.iter().map(|o| {
+		let uid = if producer_uids.contains(o.uid()) { format!("consumer/{}", o.uid()) } else { o.uid().to_string() };
+		let (x, y) = o.position();
+		let mut obj = Object::new(uid, x, y, o.rotation(), o.inner.clone());
+		if let Some(connections) = obj.connections_mut() {
+			for (_, index) in connections.iter_mut().flatten() {
+				*index += offset;
+			}
+		}
+		obj
+	}));
+
+	let mut wired = HashSet::new();
+	for &(output, input) in wiring {
+		let source = producer.objects.iter().position(|o| o.is_named_output() && o.export_name_or_uid() == output)
+			.ok_or_else(|| ComposeError::UnknownOutput(output.to_string()))?;
+		let target = offset + consumer.objects.iter().position(|o| o.is_named_input() && o.export_name_or_uid() == input)
+			.ok_or_else(|| ComposeError::UnknownInput(input.to_string()))?;
+		if !wired.insert(target) {
+			return Err(ComposeError::InputWiredTwice(input.to_string()));
+		}
+		if let ObjectInner::Input { kind: InputType::True | InputType::False, .. } = objects[target].inner {
+			return Err(ComposeError::ConstantInput(input.to_string()));
+		}
+		if let ObjectInner::Output { export_name, .. } = &mut objects[source].inner {
+			*export_name = None;
+		}
+		objects[target].inner = ObjectInner::SimpleGate {
+			xor_type: XorType::Odd,
+			kind: SimpleGateType::Buffer,
+			connections: vec![Some((0, source))],
+			inverted: vec![],
+		};
+	}
+
+	let mut names = HashSet::new();
+	for obj in &objects {
+		if (obj.is_named_input() || obj.is_named_output()) && !names.insert((obj.is_output(), obj.export_name_or_uid())) {
+			return Err(ComposeError::DuplicateName(obj.export_name_or_uid().to_string()));
+		}
+	}
+
+	let mut customs: Vec<CustomCircuit> = producer.custom_library().to_vec();
+	for custom in consumer.custom_library() {
+		match customs.iter().find(|c| c.uid == custom.uid) {
+			Some(existing) if existing != custom => return Err(ComposeError::ConflictingCustom(custom.uid.clone())),
+			Some(_) => {},
+			None => customs.push(custom.clone()),
+		}
+	}
+
+	Ok(Circuit {
+		objects,
+		customs: if customs.is_empty() { None } else { Some(customs) },
+		merged_customs: [&producer.merged_customs[..], &consumer.merged_customs[..]].concat(),
+		warnings: [&producer.warnings[..], &consumer.warnings[..]].concat(),
+		schema_version: producer.schema_version.max(consumer.schema_version),
+		wire_points: HashMap::new(),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::compose::*;
+	use crate::io::parse_xml;
+	use crate::simul::Simulation;
+	use crate::util::*;
+
+	#[test]
+	fn compose_adder_incrementer(){
+		let adder = parse_xml(fixtures::RIPPLE_ADDER).unwrap();
+		let incrementer = parse_xml(fixtures::INCREMENTER).unwrap();
+		let circuit = compose(&adder, &incrementer, &[("s0", "x0"), ("s1", "x1"), ("s2", "x2"), ("s3", "x3")]).unwrap();
+		let mut simul: Simulation = circuit.into();
+		assert_eq!(simul.input_names(), vec!["a3", "a2", "a1", "a0", "b3", "b2", "b1", "b0"]);
+		assert_eq!(simul.output_names(), vec!["cout", "y3", "y2", "y1", "y0"]);
+		let mut f = simul.as_fn();
+		for a in 0..16 {
+			for b in 0..16 {
+				let inputs = [int_to_bits(a, 4), int_to_bits(b, 4)].concat();
+				let outputs = f(&inputs).unwrap();
+				assert_eq!(outputs[0], a + b >= 16, "{a} + {b}");
+				assert_eq!(bits_to_int(outputs[1..].iter()), (a + b + 1) % 16, "{a} + {b}");
+			}
+		}
+	}
+	#[test]
+	fn compose_errors(){
+		let adder = parse_xml(fixtures::RIPPLE_ADDER).unwrap();
+		let incrementer = parse_xml(fixtures::INCREMENTER).unwrap();
+		assert_eq!(compose(&adder, &incrementer, &[("s9", "x0")]), Err(ComposeError::UnknownOutput(String::from("s9"))));
+		assert_eq!(compose(&adder, &incrementer, &[("s0", "x9")]), Err(ComposeError::UnknownInput(String::from("x9"))));
+		assert_eq!(compose(&adder, &incrementer, &[("s0", "x0"), ("s1", "x0")]), Err(ComposeError::InputWiredTwice(String::from("x0"))));
+		assert_eq!(compose(&adder, &adder, &[("s0", "a0")]), Err(ComposeError::DuplicateName(String::from("a3"))));
+		let constant = parse_xml(&fixtures::gate_circuit("and", 1, None).replace(r#"type="switch@logic.ly""#, r#"type="constant_high@logic.ly""#)).unwrap();
+		assert_eq!(compose(&adder, &constant, &[("s0", "i0")]), Err(ComposeError::ConstantInput(String::from("i0"))));
+	}
+}
@@ -0,0 +1,49 @@
+use std::io::{BufRead, BufReader, Cursor};
+
+use anyhow::{anyhow, Context, Result};
+
+use super::{load_circuit_from_reader, xml, Circuit};
+
+/// Parses the circuit stored as `entry_name` in a .zip archive.
+/// The entry may be a compressed .logicly file or plain XML.
+pub fn read_circuit_from_archive(bytes: &[u8], entry_name: &str) -> Result<Circuit> {
+	let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).context("Error reading archive")?;
+	let entry = archive.by_name(entry_name).with_context(|| format!("Error reading {entry_name} from archive"))?;
+	let mut entry = BufReader::new(entry);
+	let start = entry.fill_buf().context("Error reading archive")?;
+	// Compressed .logicly files never start with a byte order mark or markup, so anything that does is XML
+	let is_xml = matches!(start.iter().find(|b| !b.is_ascii_whitespace()), Some(b'<' | 0 | 0xef | 0xfe | 0xff));
+	if is_xml {
+		let raw = xml::read_raw_circuit(entry).map_err(|e| anyhow!(e))?;
+		Circuit::try_from(raw).map_err(|e| anyhow!(e))
+	} else {
+		load_circuit_from_reader(entry)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Write;
+
+	use crate::io::archive::*;
+	use crate::io::parse_xml;
+	use crate::util::fixtures;
+
+	#[test]
+	fn read_from_archive(){
+		let mut logicly = flate2::write::DeflateEncoder::new(vec![], flate2::Compression::default());
+		logicly.write_all(fixtures::HALF_ADDER.as_bytes()).unwrap();
+		let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+		let options = zip::write::SimpleFileOptions::default();
+		writer.start_file("circuits/xor.xml", options).unwrap();
+		writer.write_all(fixtures::XOR.as_bytes()).unwrap();
+		writer.start_file("circuits/half_adder.logicly", options).unwrap();
+		writer.write_all(&logicly.finish().unwrap()).unwrap();
+		let bytes = writer.finish().unwrap().into_inner();
+
+		assert_eq!(read_circuit_from_archive(&bytes, "circuits/xor.xml").unwrap(), parse_xml(fixtures::XOR).unwrap());
+		assert_eq!(read_circuit_from_archive(&bytes, "circuits/half_adder.logicly").unwrap(), parse_xml(fixtures::HALF_ADDER).unwrap());
+		assert!(read_circuit_from_archive(&bytes, "missing.logicly").unwrap_err().to_string().contains("missing.logicly"));
+		assert!(read_circuit_from_archive(b"not a zip", "circuits/xor.xml").is_err());
+	}
+}
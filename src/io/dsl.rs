@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use super::{parse_xml, Circuit};
+
+/// Builds a [`Circuit`] from the terse text format read by [`parse_dsl`], panicking if it is invalid.
+/// Statements must be separated by `;`.
+///
+/// ```ignore
+/// let circuit = circuit!{ in a, b; g = and(a, b); out q = g; };
+/// ```
+#[macro_export]
+macro_rules! circuit {
+	// stringify! wraps long lines, so its newlines do not separate statements
+	($($tokens:tt)*) => {
+		$crate::io::dsl::parse_dsl(&stringify!($($tokens)*).replace('\n', " ")).unwrap()
+	};
+}
+
+const gate_kinds: &[&str] = &["and", "or", "nand", "nor", "xor", "xnor", "not", "buffer"];
+
+/// A signal: either a switch or gate by name, or a gate written inline.
+enum Expr {
+	Name(String),
+	Gate { kind: String, args: Vec<Expr> },
+}
+
+/// Converts a circuit written as statements separated by `;` or newlines into Logicly XML:
+/// - `in a, b` adds switches named a and b.
+/// - `g = and(a, b)` adds a gate named g. Arguments may be switches, gates defined anywhere in the circuit, or inline gates like `or(a, not(b))`.
+/// - `out q = g` adds a light bulb named q, driven by a switch, a gate, or an inline gate.
+///
+/// Gates can be `and`, `or`, `nand`, `nor`, `xor`, `xnor`, `not`, and `buffer`.
+pub fn dsl_to_xml(source: &str) -> Result<String, String> {
+	let mut builder = Builder::default();
+	let mut outputs = vec![];
+	for statement in source.split([';', '\n']).map(str::trim).filter(|s| !s.is_empty()) {
+		let fail = |message: String| format!("in `{statement}`: {message}");
+		if let Some(names) = statement.strip_prefix("in ") {
+			for name in names.split(',').map(str::trim) {
+				builder.define(name, "switch@logic.ly", &format!(r#"exportName="{name}" outputs="false""#)).map_err(fail)?;
+			}
+			continue;
+		}
+		let (name, expr) = statement.split_once('=').ok_or_else(|| fail(String::from("expected `in`, `out`, or `name = gate(...)`")))?;
+		let expr = Parser { rest: expr }.expr_to_end().map_err(fail)?;
+		match name.trim().strip_prefix("out ") {
+			Some(name) => outputs.push((name.trim().to_string(), expr, statement)),
+			None => match expr {
+				Expr::Gate { kind, args } => builder.gate(Some(name.trim()), &kind, args).map(|_| ()).map_err(fail)?,
+				Expr::Name(_) => return Err(fail(String::from("expected a gate like and(a, b)"))),
+			},
+		}
+	}
+	for (name, expr, statement) in outputs {
+		let fail = |message: String| format!("in `{statement}`: {message}");
+		builder.define(&name, "light_bulb@logic.ly", &format!(r#"exportName="{name}""#)).map_err(fail)?;
+		let source = builder.signal(expr).map_err(fail)?;
+		builder.connect(&name, 0, source);
+	}
+	builder.finish()
+}
+
+/// Parses a circuit written in the format described in [`dsl_to_xml`].
+pub fn parse_dsl(source: &str) -> Result<Circuit> {
+	parse_xml(&dsl_to_xml(source).map_err(|e| anyhow!(e))?)
+}
+
+#[derive(Default)]
+struct Builder {
+	objects: Vec<String>,
+	/// Connections as (gate, input index, source), checked once every name is defined.
+	connections: Vec<(String, usize, String)>,
+	/// Type of each defined object, by uid.
+	defined: HashMap<String, String>,
+	/// Number of gates written inline, used to name them.
+	anonymous: usize,
+}
+impl Builder {
+	fn define(&mut self, uid: &str, kind: &str, attributes: &str) -> Result<(), String> {
+		if uid.is_empty() || !uid.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+			return Err(format!("invalid name {uid:?}"));
+		}
+		if self.defined.insert(uid.to_string(), kind.to_string()).is_some() {
+			return Err(format!("{uid} is defined more than once"));
+		}
+		let y = self.objects.len() * 40;
+		self.objects.push(format!(r#"<object type="{kind}" uid="{uid}" x="0" y="{y}" rotation="0" {attributes}/>"#));
+		Ok(())
+	}
+	/// Adds a gate, returning its uid.
+	fn gate(&mut self, name: Option<&str>, kind: &str, args: Vec<Expr>) -> Result<String, String> {
+		let kind = gate_kinds.iter().find(|k| **k == kind).ok_or_else(|| format!("unknown gate type {kind}"))?;
+		let uid = match name {
+			Some(name) => name.to_string(),
+			None => {
+				self.anonymous += 1;
+				format!("_{}", self.anonymous)
+			},
+		};
+		self.define(&uid, &format!("{kind}@logic.ly"), &format!(r#"inputs="{}""#, args.len()))?;
+		for (index, arg) in args.into_iter().enumerate() {
+			let source = self.signal(arg)?;
+			self.connect(&uid, index, source);
+		}
+		Ok(uid)
+	}
+	/// Returns the uid of the object that drives a signal, adding any inline gates.
+	fn signal(&mut self, expr: Expr) -> Result<String, String> {
+		match expr {
+			Expr::Name(name) => Ok(name),
+			Expr::Gate { kind, args } => self.gate(None, &kind, args),
+		}
+	}
+	fn connect(&mut self, uid: &str, index: usize, source: String) {
+		self.connections.push((uid.to_string(), index, source));
+	}
+	fn finish(self) -> Result<String, String> {
+		let mut xml = String::from("<logicly>");
+		xml.extend(self.objects);
+		for (uid, index, source) in self.connections {
+			match self.defined.get(&source).map(String::as_str) {
+				Some("light_bulb@logic.ly") => return Err(format!("{uid} reads {source}, which is an output")),
+				Some(_) => {},
+				None => return Err(format!("{uid} reads {source}, which is not defined")),
+			}
+			xml += &format!(r#"<connection inputUID="{uid}" outputUID="{source}" inputIndex="{index}" outputIndex="0"/>"#);
+		}
+		Ok(xml + "</logicly>")
+	}
+}
+
+/// Reads expressions like `and(a, or(b, c))`.
+struct Parser<'a> {
+	rest: &'a str,
+}
+impl Parser<'_> {
+	fn expr_to_end(mut self) -> Result<Expr, String> {
+		let expr = self.expr()?;
+		match self.rest.trim() {
+			"" => Ok(expr),
+			rest => Err(format!("unexpected {rest:?}")),
+		}
+	}
+	fn expr(&mut self) -> Result<Expr, String> {
+		self.rest = self.rest.trim_start();
+		let len = self.rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(self.rest.len());
+		let (name, rest) = self.rest.split_at(len);
+		if name.is_empty() { return Err(format!("expected a name at {:?}", self.rest)) }
+		self.rest = rest.trim_start();
+		let Some(rest) = self.rest.strip_prefix('(') else { return Ok(Expr::Name(name.to_string())) };
+		self.rest = rest;
+		let mut args = vec![];
+		loop {
+			args.push(self.expr()?);
+			self.rest = self.rest.trim_start();
+			if let Some(rest) = self.rest.strip_prefix(',') {
+				self.rest = rest;
+			} else if let Some(rest) = self.rest.strip_prefix(')') {
+				self.rest = rest;
+				return Ok(Expr::Gate { kind: name.to_string(), args });
+			} else {
+				return Err(format!("expected , or ) at {:?}", self.rest));
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::io::dsl::*;
+	use crate::simul::Simulation;
+	use crate::util::fixtures;
+
+	fn table(circuit: Circuit) -> crate::simul::TruthTable {
+		Simulation::from(circuit).get_truth_table(100).unwrap()
+	}
+
+	#[test]
+	fn dsl_circuits(){
+		let majority = circuit!{
+			in a, b, c;
+			ab = and(a, b);
+			out m = or(ab, and(a, c), and(b, c));
+		};
+		assert_eq!(table(majority), table(parse_xml(fixtures::MAJORITY).unwrap()));
+		// Gates can be used before they are defined, and statements can be separated by newlines
+		let xor = parse_dsl("
+			in a, b
+			out q = g
+			g = xor(a, b)
+		").unwrap();
+		assert_eq!(table(xor), table(parse_xml(fixtures::XOR).unwrap()));
+		assert_eq!(table(circuit!{ in a; out q = not(buffer(a)); }).rows().map(|row| row[0]).collect::<Vec<_>>(), [true, false]);
+	}
+	#[test]
+	fn dsl_errors(){
+		let error = |source: &str| dsl_to_xml(source).unwrap_err();
+		assert_eq!(error("in a; out q = and(a, b)"), "_1 reads b, which is not defined");
+		assert_eq!(error("in a; g = maybe(a)"), "in `g = maybe(a)`: unknown gate type maybe");
+		assert_eq!(error("in a, a"), "in `in a, a`: a is defined more than once");
+		assert_eq!(error("in a; g = a"), "in `g = a`: expected a gate like and(a, b)");
+		assert_eq!(error("in a; g = and(a, a"), r#"in `g = and(a, a`: expected , or ) at """#);
+		assert_eq!(error("in a; out q = a; g = not(q)"), "g reads q, which is an output");
+		assert!(parse_dsl("in a; out q = not(a, a)").unwrap_err().to_string().contains("must have exactly one input"));
+	}
+}
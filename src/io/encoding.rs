@@ -0,0 +1,81 @@
+use std::io::{BufRead, BufReader, Read};
+
+const utf8_bom: &[u8] = &[0xef, 0xbb, 0xbf];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Utf16 {
+	LittleEndian, BigEndian
+}
+
+/// Detects the encoding of an XML document from its first bytes, and returns a reader of the document as UTF-8.
+///
+/// UTF-8 byte order marks are removed. UTF-16 documents are recognized by their byte order mark,
+/// or by starting with `<` encoded as UTF-16, and are transcoded as they are read.
+/// Other encodings are passed through unchanged, and are rejected by [`check_declared_encoding`].
+pub fn utf8_reader<'a>(mut input: impl BufRead + 'a) -> Result<Box<dyn BufRead + 'a>, String> {
+	let start = input.fill_buf().map_err(|e| format!("Error reading input: {e}"))?;
+	let (utf16, bom_len) = match start {
+		[0xef, 0xbb, 0xbf, ..] => (None, utf8_bom.len()),
+		[0xff, 0xfe, ..] => (Some(Utf16::LittleEndian), 2),
+		[0xfe, 0xff, ..] => (Some(Utf16::BigEndian), 2),
+		[b'<', 0, ..] => (Some(Utf16::LittleEndian), 0),
+		[0, b'<', ..] => (Some(Utf16::BigEndian), 0),
+		_ => (None, 0),
+	};
+	input.consume(bom_len);
+	Ok(match utf16 {
+		Some(order) => Box::new(BufReader::new(Utf16Reader { input, order, pending: vec![] })),
+		None => Box::new(input),
+	})
+}
+
+/// Checks the encoding named in an XML declaration. The document has already been transcoded by [`utf8_reader`],
+/// so this only fails for encodings that it does not support.
+pub fn check_declared_encoding(encoding: &str) -> Result<(), String> {
+	match &encoding.to_ascii_lowercase()[..] {
+		"utf-8" | "utf8" | "us-ascii" | "ascii" | "utf-16" | "utf-16le" | "utf-16be" => Ok(()),
+		_ => Err(format!("Unsupported encoding {encoding}: only UTF-8 and UTF-16 are supported")),
+	}
+}
+
+/// Transcodes UTF-16 to UTF-8.
+struct Utf16Reader<R> {
+	input: R,
+	order: Utf16,
+	/// UTF-8 bytes that have been decoded but not yet read.
+	pending: Vec<u8>,
+}
+impl<R: BufRead> Utf16Reader<R> {
+	fn next_unit(&mut self) -> std::io::Result<Option<u16>> {
+		let mut bytes = [0; 2];
+		match self.input.read(&mut bytes[..1])? {
+			0 => return Ok(None),
+			_ => self.input.read_exact(&mut bytes[1..]).map_err(|_| invalid_data("UTF-16 input ends in the middle of a character"))?,
+		}
+		Ok(Some(match self.order {
+			Utf16::LittleEndian => u16::from_le_bytes(bytes),
+			Utf16::BigEndian => u16::from_be_bytes(bytes),
+		}))
+	}
+}
+impl<R: BufRead> Read for Utf16Reader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		while self.pending.len() < buf.len() {
+			let Some(unit) = self.next_unit()? else { break };
+			let units = if (0xd800..0xdc00).contains(&unit) {
+				vec![unit, self.next_unit()?.unwrap_or(0)]
+			} else { vec![unit] };
+			let c = char::decode_utf16(units).next().and_then(Result::ok)
+				.ok_or_else(|| invalid_data("invalid UTF-16 surrogate pair"))?;
+			self.pending.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+		}
+		let len = self.pending.len().min(buf.len());
+		buf[..len].copy_from_slice(&self.pending[..len]);
+		self.pending.drain(..len);
+		Ok(len)
+	}
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,247 @@
+//! Export to the .circ format of Logisim Evolution.
+
+use std::collections::{HashMap, HashSet};
+
+use quick_xml::escape::escape;
+
+use super::{wires, Circuit, InputType, Object, ObjectInner, SimpleGateType, Wire, XorType};
+
+/// Logicly coordinates are multiplied by this, as Logisim components are larger.
+const scale: f64 = 2.;
+/// Logisim only connects wires and pins on its 10 pixel grid.
+const grid: i64 = 10;
+
+type Point = (i64, i64);
+
+/// One Logisim component, and where its pins are, in the order of the Logicly object's inputs and outputs.
+struct Component {
+	xml: String,
+	inputs: Vec<Point>,
+	outputs: Vec<Point>,
+}
+
+fn point((x, y): Point) -> String {
+	format!("({x},{y})")
+}
+
+fn comp(lib: Option<u8>, at: Point, name: &str, attributes: &[(&str, String)]) -> String {
+	let lib = lib.map(|lib| format!(" lib=\"{lib}\"")).unwrap_or_default();
+	let attributes: String = attributes.iter().map(|(name, value)| format!("      <a name=\"{name}\" val=\"{}\"/>\n", escape(value))).collect();
+	match attributes.is_empty() {
+		true => format!("    <comp{lib} loc=\"{}\" name=\"{}\"/>\n", point(at), escape(name)),
+		false => format!("    <comp{lib} loc=\"{}\" name=\"{}\">\n{attributes}    </comp>\n", point(at), escape(name)),
+	}
+}
+
+/// Vertical offsets of the inputs of a Logisim gate of size 50 with `count` inputs, relative to its output.
+fn gate_input_offsets(count: usize) -> Vec<i64> {
+	let count = count as i64;
+	let (start, step, lower_even) = if count <= 3 { (-10, 20, 20) } else { (-5, 10, 10) };
+	(0..count).map(|i| match count % 2 {
+		1 => start * (count - 1) + step * i,
+		_ => start * count + step * i + if i >= count / 2 { lower_even } else { 0 },
+	}).collect()
+}
+
+/// A custom gate is a box with its inputs on the left and its outputs on the right, this far apart.
+const custom_width: i64 = 60;
+const custom_pin_spacing: i64 = 20;
+
+/// Converts `object`, placed at `at`. Objects in custom circuits only become pins if they are named,
+/// as every pin of a Logisim circuit is a pin of its instances.
+fn component(object: &Object, at: Point, names: &HashMap<&str, String>, in_custom: bool) -> Component {
+	let (x, y) = at;
+	let label = |object: &Object| match &object.inner {
+		ObjectInner::Input { export_name: Some(name), .. } | ObjectInner::Output { export_name: Some(name), .. } => vec![("label", name.clone())],
+		_ => vec![],
+	};
+	match &object.inner {
+		ObjectInner::SimpleGate { kind, xor_type, connections, inverted } => {
+			use SimpleGateType as S;
+			let (name, length) = match kind {
+				S::And => ("AND Gate", 50),
+				S::Or => ("OR Gate", 50),
+				S::Nand => ("NAND Gate", 60),
+				S::Nor => ("NOR Gate", 60),
+				S::Xor => ("XOR Gate", 60),
+				S::Xnor => ("XNOR Gate", 70),
+				S::Not => ("NOT Gate", 30),
+				S::Buffer => ("Buffer", 20),
+			};
+			let mut attributes = vec![];
+			let inputs = match kind {
+				S::Not | S::Buffer => vec![(x - length, y)],
+				_ => {
+					attributes.extend([("size", String::from("50")), ("inputs", connections.len().to_string())]);
+					if matches!(kind, S::Xor | S::Xnor) {
+						attributes.push(("xor", String::from(match xor_type { XorType::One => "1", XorType::Odd => "odd" })));
+					}
+					gate_input_offsets(connections.len()).into_iter().map(|dy| (x - length, y + dy)).collect()
+				},
+			};
+			if *kind == S::Not {
+				attributes.push(("size", String::from("30")));
+			}
+			let negated: Vec<(String, String)> = inverted.iter().enumerate().filter(|(_, inverted)| **inverted)
+				.map(|(i, _)| (format!("negate{i}"), String::from("true"))).collect();
+			attributes.extend(negated.iter().map(|(name, value)| (&name[..], value.clone())));
+			Component { xml: comp(Some(1), at, name, &attributes), inputs, outputs: vec![at] }
+		},
+		ObjectInner::CustomGate { uuid, num_outputs, connections, .. } => Component {
+			xml: comp(None, at, names.get(&uuid[..]).map_or(uuid, |name| name), &[]),
+			inputs: (0..connections.len() as i64).map(|k| (x, y + custom_pin_spacing * k)).collect(),
+			outputs: (0..*num_outputs as i64).map(|k| (x + custom_width, y + custom_pin_spacing * k)).collect(),
+		},
+		ObjectInner::Input { kind, value, export_name } => {
+			let constant = match kind {
+				_ if export_name.is_some() => None,
+				InputType::True => Some(true),
+				InputType::False => Some(false),
+				// Unnamed switches in a custom circuit keep their saved value, as they cannot be changed from outside
+				_ => in_custom.then_some(*value),
+			};
+			let xml = match constant {
+				Some(value) => comp(Some(0), at, "Constant", &[("value", String::from(if value { "0x1" } else { "0x0" }))]),
+				None => comp(Some(0), at, "Pin", &label(object)),
+			};
+			Component { xml, inputs: vec![], outputs: vec![at] }
+		},
+		ObjectInner::Output { export_name: None, .. } if in_custom => Component { xml: comp(Some(0), at, "Probe", &[]), inputs: vec![at], outputs: vec![] },
+		ObjectInner::Output { .. } => {
+			let attributes = [vec![("facing", String::from("west")), ("output", String::from("true"))], label(object)].concat();
+			Component { xml: comp(Some(0), at, "Pin", &attributes), inputs: vec![at], outputs: vec![] }
+		},
+		ObjectInner::Label { text } => Component { xml: comp(Some(2), at, "Text", &[("text", text.replace('\n', " "))]), inputs: vec![], outputs: vec![] },
+	}
+}
+
+/// The appearance of a custom circuit: a box like the ones [`component`] expects, with the named inputs and outputs as its pins, in order.
+fn appearance(input_pins: &[Point], output_pins: &[Point]) -> String {
+	let (left, top) = (50, 50);
+	let rows = input_pins.len().max(output_pins.len()).max(1) as i64;
+	let mut out = format!("    <appear>\n      <rect fill=\"none\" height=\"{}\" stroke=\"#000000\" stroke-width=\"2\" width=\"{custom_width}\" x=\"{left}\" y=\"{top}\"/>\n", custom_pin_spacing * rows);
+	for (k, &pin) in input_pins.iter().enumerate() {
+		out += &format!("      <circ-port height=\"8\" pin=\"{}\" width=\"8\" x=\"{}\" y=\"{}\"/>\n", point(pin), left - 4, top + 6 + custom_pin_spacing * k as i64);
+	}
+	for (k, &pin) in output_pins.iter().enumerate() {
+		out += &format!("      <circ-port height=\"10\" pin=\"{}\" width=\"10\" x=\"{}\" y=\"{}\"/>\n", point(pin), left + custom_width - 5, top + 5 + custom_pin_spacing * k as i64);
+	}
+	// Instances are placed by their anchor, which is the first input
+	out += &format!("      <circ-anchor facing=\"east\" height=\"6\" width=\"6\" x=\"{}\" y=\"{}\"/>\n    </appear>\n", left - 3, top + 7);
+	out
+}
+
+/// One Logisim circuit with the objects of the top level or of a custom circuit.
+/// Objects are joined by tunnels on their pins, named after the object driving them, instead of by wires,
+/// as Logisim wires can only be horizontal or vertical and connect wherever they touch.
+fn circuit_xml(name: &str, objects: &[Object], names: &HashMap<&str, String>, in_custom: bool) -> String {
+	let mut out = format!("  <circuit name=\"{}\">\n    <a name=\"circuit\" val=\"{0}\"/>\n", escape(name));
+	let (min_x, min_y) = objects.iter().map(Object::position).fold((f64::INFINITY, f64::INFINITY), |(mx, my), (x, y)| (mx.min(x), my.min(y)));
+	let snap = |v: f64, min: f64| ((v - min) * scale / grid as f64).round() as i64 * grid + 100;
+	let mut taken: HashSet<Point> = HashSet::new();
+	let mut components = vec![];
+	for object in objects {
+		let (x, y) = object.position();
+		let mut at = (snap(x, min_x), snap(y, min_y));
+		// Move objects down until none of their pins touch a pin of another object, which would connect them
+		let component = loop {
+			let component = component(object, at, names, in_custom);
+			if component.inputs.iter().chain(&component.outputs).all(|pin| !taken.contains(pin)) { break component }
+			at.1 += 100;
+		};
+		taken.extend(component.inputs.iter().chain(&component.outputs));
+		components.push(component);
+	}
+	if in_custom {
+		let pins = |filter: fn(&Object) -> bool, side: fn(&Component) -> &Vec<Point>| -> Vec<Point> {
+			objects.iter().zip(&components).filter(|(o, _)| filter(o)).map(|(_, c)| side(c)[0]).collect()
+		};
+		out += "    <a name=\"appearance\" val=\"custom\"/>\n";
+		out += &appearance(&pins(Object::is_named_input, |c| &c.outputs), &pins(Object::is_named_output, |c| &c.inputs));
+	}
+	let tunnel = |at: Point, facing: &str, label: &str| comp(Some(0), at, "Tunnel", &[("facing", facing.to_string()), ("label", label.to_string())]);
+	let mut driven = HashSet::new();
+	// Each component is followed by the tunnels of the wires leading to it
+	let mut wires = wires(objects).peekable();
+	for (i, component) in components.iter().enumerate() {
+		out += &component.xml;
+		while let Some(Wire { source, output, input, .. }) = wires.next_if(|w| w.target == i) {
+			let (Some(&to), Some(&from)) = (component.inputs.get(input as usize), components[source].outputs.get(output as usize)) else { continue };
+			let label = match output {
+				0 => objects[source].uid().to_string(),
+				_ => format!("{}_{output}", objects[source].uid()),
+			};
+			out += &tunnel(to, "east", &label);
+			if driven.insert(from) {
+				out += &tunnel(from, "west", &label);
+			}
+		}
+	}
+	out + "  </circuit>\n"
+}
+
+impl Circuit {
+	/// Writes the circuit as a Logisim Evolution project, with the top level as the main circuit.
+	///
+	/// Gates, switches, light bulbs, constants, and labels become the matching Logisim components, at twice their distance apart.
+	/// Push buttons become input pins. Rotations are not kept, so every component faces right.
+	/// Each custom circuit becomes a subcircuit named by [`super::CustomCircuit::display_name`], with its named inputs on the left and its named outputs on the right.
+	/// Objects are joined by tunnels instead of wires.
+	pub fn to_logisim(&self) -> String {
+		let mut used: HashSet<String> = HashSet::from([String::from("main")]);
+		let names: HashMap<&str, String> = self.custom_library().iter().map(|custom| {
+			let mut name = custom.display_name().to_string();
+			if !used.insert(name.clone()) {
+				name = format!("{name} {}", custom.uid);
+				used.insert(name.clone());
+			}
+			(&custom.uid[..], name)
+		}).collect();
+		let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n<project source=\"3.8.0\" version=\"1.0\">\n");
+		out += "  This file is intended to be loaded by Logisim-evolution (https://github.com/logisim-evolution/).\n\n";
+		out += "  <lib desc=\"#Wiring\" name=\"0\"/>\n  <lib desc=\"#Gates\" name=\"1\"/>\n  <lib desc=\"#Base\" name=\"2\"/>\n  <main name=\"main\"/>\n";
+		out += &circuit_xml("main", &self.objects, &names, false);
+		for custom in self.custom_library() {
+			out += &circuit_xml(&names[&custom.uid[..]], &custom.objects, &names, true);
+		}
+		out + "</project>\n"
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::io::*;
+	use crate::util::fixtures;
+
+	/// The label of each tunnel, and how many tunnels have it.
+	fn tunnels(circ: &str) -> HashMap<String, usize> {
+		let mut count = HashMap::new();
+		for block in circ.split("<comp ").filter(|c| c.contains("name=\"Tunnel\"")) {
+			let label = block.split("name=\"label\" val=\"").nth(1).unwrap().split('"').next().unwrap();
+			*count.entry(label.to_string()).or_insert(0) += 1;
+		}
+		count
+	}
+
+	#[test]
+	fn logisim(){
+		let circ = parse_xml(&fixtures::gate_circuit("nand", 3, None)).unwrap().to_logisim();
+		// Every object of gate_circuit is at (0, 0), so all but the first are moved down to keep their pins apart
+		assert!(circ.contains("<comp lib=\"1\" loc=\"(100,400)\" name=\"NAND Gate\">\n      <a name=\"size\" val=\"50\"/>\n      <a name=\"inputs\" val=\"3\"/>\n"), "{circ}");
+		assert!(circ.contains("<comp lib=\"0\" loc=\"(40,400)\" name=\"Tunnel\">\n      <a name=\"facing\" val=\"east\"/>\n      <a name=\"label\" val=\"i1\"/>"), "{circ}");
+		assert!(circ.contains("<comp lib=\"0\" loc=\"(100,300)\" name=\"Pin\">\n      <a name=\"label\" val=\"i2\"/>"), "{circ}");
+		assert!(circ.contains("name=\"Pin\">\n      <a name=\"facing\" val=\"west\"/>\n      <a name=\"output\" val=\"true\"/>\n      <a name=\"label\" val=\"q\"/>"), "{circ}");
+		assert_eq!(tunnels(&circ), HashMap::from([("i0", 2), ("i1", 2), ("i2", 2), ("g", 2)].map(|(l, n)| (l.to_string(), n))));
+
+		let circ = parse_xml(fixtures::HALF_ADDER).unwrap().to_logisim();
+		assert_eq!(circ.matches("<circuit name=").count(), 2);
+		assert!(circ.contains("<circuit name=\"HA\">\n    <a name=\"circuit\" val=\"HA\"/>\n    <a name=\"appearance\" val=\"custom\"/>\n"), "{circ}");
+		assert_eq!(circ.matches("<circ-port").count(), 4);
+		assert!(circ.contains("name=\"HA\"/>\n"), "{circ}");
+		// Both outputs of the custom gate are used
+		assert!(tunnels(&circ).contains_key("ha_1"), "{circ}");
+		assert!(tunnels(&circ).values().all(|&n| n >= 2), "{circ}");
+		let mut reader = quick_xml::Reader::from_str(&circ);
+		while !matches!(reader.read_event().unwrap(), quick_xml::events::Event::Eof) {}
+	}
+}
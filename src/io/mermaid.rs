@@ -0,0 +1,72 @@
+//! Mermaid flowcharts of circuits, which GitHub and many documentation tools render from Markdown.
+
+use std::collections::HashMap;
+
+use super::{wires, Circuit, ObjectInner, Wire};
+
+/// Quotes a node or edge label, escaping the characters Mermaid would otherwise read as markup.
+fn quote(text: &str) -> String {
+	let escaped = text.replace('#', "#35;").replace('"', "#quot;").replace('<', "#lt;").replace('>', "#gt;");
+	format!("\"{escaped}\"")
+}
+
+impl Circuit {
+	/// Draws the top-level objects as a Mermaid `flowchart LR`.
+	/// Inputs are stadium-shaped and outputs are circles, both labeled with their export names.
+	/// Gates are boxes labeled with their type, and custom gates are subroutine-shaped boxes labeled with the circuit name.
+	/// Wires from custom gates are labeled with the output they come from, and inverted inputs end in a circle.
+	pub fn to_mermaid(&self) -> String {
+		let names: HashMap<&str, &str> = self.custom_library().iter().map(|c| (&c.uid[..], c.display_name())).collect();
+		let mut out = String::from("flowchart LR\n");
+		for (i, object) in self.objects.iter().enumerate() {
+			let node = match &object.inner {
+				ObjectInner::Input { export_name: Some(name), .. } => format!("([{}])", quote(name)),
+				ObjectInner::Input { kind, .. } => format!("([{}])", quote(&kind.to_string())),
+				ObjectInner::Output { export_name, .. } => format!("(({}))", quote(export_name.as_deref().unwrap_or("Output"))),
+				ObjectInner::SimpleGate { kind, .. } => format!("[{}]", quote(&kind.to_string())),
+				ObjectInner::CustomGate { uuid, .. } => format!("[[{}]]", quote(names.get(&uuid[..]).copied().unwrap_or(uuid))),
+				ObjectInner::Label { .. } => continue,
+			};
+			out += &format!("    n{i}{node}\n");
+		}
+		for Wire { source, output, target, inverted, .. } in wires(&self.objects) {
+			let arrow = if inverted { "--o" } else { "-->" };
+			let label = match self.objects[source].inner {
+				ObjectInner::CustomGate { .. } => format!("|{}|", quote(&output.to_string())),
+				_ => String::new(),
+			};
+			out += &format!("    n{source} {arrow}{label} n{target}\n");
+		}
+		out
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::io::*;
+	use crate::util::fixtures;
+
+	#[test]
+	fn mermaid(){
+		let half_adder = parse_xml(fixtures::HALF_ADDER).unwrap();
+		assert_eq!(half_adder.to_mermaid(), "\
+flowchart LR
+    n0([\"x\"])
+    n1([\"y\"])
+    n2[[\"HA\"]]
+    n3((\"sum\"))
+    n4((\"carry\"))
+    n0 --> n2
+    n1 --> n2
+    n2 -->|\"0\"| n3
+    n2 -->|\"1\"| n4
+");
+		let xml = fixtures::gate_circuit("and", 2, None).replace(r#"exportName="i0""#, r##"exportName="&lt;a&gt; &quot;#1&quot;""##);
+		let mut circuit = parse_xml(&xml).unwrap();
+		circuit.objects[2].set_input_inverted(1, true).unwrap();
+		let mermaid = circuit.to_mermaid();
+		assert!(mermaid.contains(r##"n0(["#lt;a#gt; #quot;#35;1#quot;"])"##), "{mermaid}");
+		assert!(mermaid.contains("n2[\"And\"]\n"), "{mermaid}");
+		assert!(mermaid.contains("n0 --> n2\n    n1 --o n2\n    n2 --> n3\n"), "{mermaid}");
+	}
+}
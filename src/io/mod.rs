@@ -1,147 +1,354 @@
-use std::collections::{HashMap, HashSet};
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use itertools::Itertools;
-use serde::{Deserialize};
+use serde::Serialize;
 use uuid::Uuid;
 
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod dsl;
+mod encoding;
+mod logisim;
+mod mermaid;
+mod schematic;
+mod svg;
+mod xml;
+pub use xml::{SourcePosition, Unknown};
 
-
-
-#[derive(Debug, Deserialize, PartialEq)]
-#[serde(rename = "logicly")]
+#[derive(Debug, PartialEq)]
 pub struct RawCircuit {
-	#[serde(rename = "@xmlns")]
 	xmlns: Option<String>,
-	#[serde(rename = "object")]
 	objects: Vec<RawObject>,
-	#[serde(rename = "connection")]
 	connections: Vec<RawConnection>,
-	#[serde(rename = "setting")]
 	settings: Vec<Setting>,
-	#[serde(rename = "custom")]
 	customs: Option<Vec<CustomCircuitWrapper>>,
+	unknown: Unknown,
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RawObject {
-	#[serde(rename = "@type")]
 	kind: String,
-	#[serde(rename = "@uid")]
 	uid: String,
-	#[serde(rename = "@x")]
 	x: f64,
-	#[serde(rename = "@y")]
 	y: f64,
-	#[serde(rename = "@rotation")]
 	rotation: u16,
-	#[serde(rename = "@exportName")]
 	export_name: Option<String>,
-	#[serde(rename = "@outputs")]
 	outputs: Option<String>,
-	#[serde(rename = "@inputs")]
 	inputs: Option<u32>,
-	#[serde(rename = "@text")]
 	text: Option<String>,
-	#[serde(rename = "@functionIndex")]
-	function_index: Option<u8>
+	function_index: Option<u8>,
+	/// Where the object was defined in the XML.
+	position: SourcePosition,
+	unknown: Unknown,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RawConnection {
-	#[serde(rename = "@inputUID")]
 	input_uid: String,
-	#[serde(rename = "@outputUID")]
 	output_uid: String,
-	#[serde(rename = "@inputIndex")]
 	input_index: u32,
-	#[serde(rename = "@outputIndex")]
 	output_index: u32,
-	#[serde(rename = "@points")]
-	points: Option<String>
+	points: Option<String>,
+	/// Where the connection was defined in the XML.
+	position: SourcePosition,
+	unknown: Unknown,
+}
+
+impl RawCircuit {
+	/// Gives every named input and output a unique export name within its circuit, by adding _2, _3... to later duplicates.
+	/// Returns the renamed objects as (custom circuit name, or None for the top level; uid; new name).
+	pub fn suffix_duplicate_names(&mut self) -> Vec<(Option<String>, String, String)> {
+		let mut renamed: Vec<_> = suffix_duplicate_names(&mut self.objects).into_iter().map(|(uid, name)| (None, uid, name)).collect();
+		for custom in self.customs.iter_mut().flatten() {
+			renamed.extend(suffix_duplicate_names(&mut custom.inner.objects).into_iter().map(|(uid, name)| (Some(custom.name.clone()), uid, name)));
+		}
+		renamed
+	}
+}
+fn suffix_duplicate_names(objects: &mut [RawObject]) -> Vec<(String, String)> {
+	let mut renamed = vec![];
+	let inputs = ["switch@logic.ly", "push_button@logic.ly", "constant_high@logic.ly", "constant_low@logic.ly"];
+	let outputs = ["light_bulb@logic.ly", "digit@logic.ly"];
+	for kinds in [&inputs[..], &outputs[..]] {
+		let mut taken: HashSet<String> = objects.iter().filter(|o| kinds.contains(&&o.kind[..])).filter_map(|o| o.export_name.clone()).collect();
+		let mut seen = HashSet::new();
+		for object in objects.iter_mut().filter(|o| kinds.contains(&&o.kind[..])) {
+			let Some(name) = &object.export_name else { continue };
+			if seen.insert(name.clone()) { continue }
+			let new_name = (2..).map(|i| format!("{name}_{i}")).find(|n| !taken.contains(n)).unwrap();
+			taken.insert(new_name.clone());
+			renamed.push((object.uid.clone(), new_name.clone()));
+			object.export_name = Some(new_name);
+		}
+	}
+	renamed
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub struct Setting {
-	#[serde(rename = "@name")]
 	name: String,
-	#[serde(rename = "@value")]
 	value: String,
+	unknown: Unknown,
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CustomCircuitWrapper {
-	#[serde(rename = "@name")]
 	name: String,
-	#[serde(rename = "@type")]
 	uid: String,
-	#[serde(rename = "@label")]
 	label: String,
-	#[serde(rename = "logicly")]
 	inner: RawCustomCircuit,
+	unknown: Unknown,
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RawCustomCircuit {
-	#[serde(rename = "object")]
 	objects: Vec<RawObject>,
-	#[serde(rename = "connection")]
 	connections: Vec<RawConnection>,
-	#[serde(rename = "location")]
 	locations: Vec<Location>,
+	unknown: Unknown,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Location {
-	#[serde(rename = "@id")]
 	id: String,
-	#[serde(rename = "@uids")]
 	uids: String,
+	unknown: Unknown,
 }
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Circuit {
 	pub objects: Vec<Object>,
 	/// If present, the circuits must be in a valid dependency order,
 	/// so that all circuits must come after their dependencies.
 	pub customs: Option<Vec<CustomCircuit>>,
+	/// Custom circuits that were structurally identical to an earlier one, as (duplicate uuid, canonical uuid).
+	/// Instances of the duplicates use the canonical definition instead.
+	pub merged_customs: Vec<(String, String)>,
+	/// Problems in the file that were worked around while parsing, such as dangling connections in [`ParseMode::Lenient`]
+	/// or a namespace from a newer version of Logicly.
+	pub warnings: Vec<String>,
+	/// The version of the Logicly file format, from the namespace of the root element.
+	/// None if the file has no namespace or it does not name a version.
+	pub schema_version: Option<u32>,
+	/// The bend points of top-level wires, keyed by the uid of the object each wire leads to and the input it connects to.
+	/// Only used for drawing the circuit.
+	pub wire_points: HashMap<(String, u32), Vec<(f64, f64)>>,
+}
+
+/// One wire of a circuit, from an output of one object to an input of another. Returned by [`Circuit::edges`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize)]
+pub struct Edge {
+	pub from_uid: String,
+	pub from_output_index: u32,
+	pub to_uid: String,
+	pub to_input_index: u32,
+}
+
+const logicly_namespace: &str = "http://logic.ly";
+/// The newest version of the file format that this crate knows about.
+pub const latest_schema_version: u32 = 2011;
+
+/// Reads the file format version out of a namespace like `http://logic.ly/2011`,
+/// warning about namespaces that are not Logicly's or are newer than [`latest_schema_version`].
+fn schema_version(xmlns: Option<&str>, warnings: &mut Vec<String>) -> Option<u32> {
+	let xmlns = xmlns?;
+	let Some(rest) = xmlns.strip_prefix(logicly_namespace) else {
+		warnings.push(format!("unknown namespace {xmlns}, reading it as a Logicly file anyway"));
+		return None;
+	};
+	let segment = rest.trim_start_matches('/').split('/').next().unwrap_or_default();
+	if segment.is_empty() { return None }
+	match segment.parse() {
+		Ok(version) if version > latest_schema_version => {
+			warnings.push(format!("file is from a newer version of Logicly (schema {version}, newest known is {latest_schema_version}), some parts may be missing or misread"));
+			Some(version)
+		},
+		Ok(version) => Some(version),
+		Err(_) => {
+			warnings.push(format!("unknown namespace {xmlns}, reading it as a Logicly file anyway"));
+			None
+		},
+	}
+}
+
+/// How to treat problems in a file that can be worked around.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ParseMode {
+	/// Fail on any problem.
+	#[default]
+	Strict,
+	/// Work around problems where possible, recording each one in [`Circuit::warnings`].
+	/// Connections to or from an object that does not exist are dropped.
+	Lenient,
 }
 impl Circuit {
+	/// Whether the file has no objects and no custom circuits, like a new Logicly file.
+	pub fn is_empty(&self) -> bool {
+		self.objects.is_empty() && self.custom_library().is_empty()
+	}
+	/// The custom circuits defined in this file, in dependency order.
+	pub fn custom_library(&self) -> &[CustomCircuit] {
+		self.customs.as_deref().unwrap_or_default()
+	}
+	/// Export names (or uids) of top-level outputs that have nothing connected to them.
+	/// These always read false, which usually means a wire was forgotten.
+	pub fn unconnected_outputs(&self) -> Vec<String> {
+		self.objects.iter()
+			.filter(|o| o.is_output() && o.connections().iter().all(Option::is_none))
+			.map(|o| o.export_name_or_uid().to_string())
+			.collect()
+	}
+	/// Every top-level wire, ordered by the object it leads to in file order and then by input.
+	pub fn edges(&self) -> Vec<Edge> {
+		wires(&self.objects).map(|wire| Edge {
+			from_uid: self.objects[wire.source].uid().to_string(),
+			from_output_index: wire.output,
+			to_uid: self.objects[wire.target].uid().to_string(),
+			to_input_index: wire.input,
+		}).collect()
+	}
+	/// The cone of influence of each named top-level output: the export names of the named inputs
+	/// that have a path to it through the wiring. Custom gates are treated as if every output depends on every input.
+	pub fn output_cones(&self) -> BTreeMap<String, BTreeSet<String>> {
+		self.objects.iter().filter(|o| o.is_named_output()).map(|output| {
+			let mut cone = BTreeSet::new();
+			let mut visited = HashSet::new();
+			let mut stack = vec![output];
+			while let Some(object) = stack.pop() {
+				if object.is_named_input() { cone.insert(object.export_name_or_uid().to_string()); }
+				for &(_, source) in object.connections().iter().flatten() {
+					if visited.insert(source) { stack.push(&self.objects[source]); }
+				}
+			}
+			(output.export_name_or_uid().to_string(), cone)
+		}).collect()
+	}
+	/// Describes each loop of top-level gates found by [`feedback_loops`], as the uids along it like `g1 -> g2 -> g1`.
+	/// These loops are the most common reason a circuit does not stabilize.
+	pub fn self_feedback_gates(&self) -> Vec<String> {
+		feedback_loops(&self.objects).into_iter().map(|path| {
+			path.iter().chain(path.first()).map(|&i| self.objects[i].uid()).join(" -> ")
+		}).collect()
+	}
+	/// The top-level objects for which `predicate` returns true, in file order.
+	pub fn find_objects(&self, predicate: impl Fn(&Object) -> bool) -> impl Iterator<Item = &Object> {
+		self.objects.iter().filter(move |o| predicate(o))
+	}
+	/// The top-level gates of type `kind`, in file order.
+	pub fn objects_of_kind(&self, kind: SimpleGateType) -> impl Iterator<Item = &Object> {
+		self.find_objects(move |o| matches!(o.inner, ObjectInner::SimpleGate { kind: k, .. } if k == kind))
+	}
+	/// The top-level instances of the custom circuit with uid `uuid`, in file order.
+	pub fn custom_instances<'a>(&'a self, uuid: &'a str) -> impl Iterator<Item = &'a Object> {
+		self.find_objects(move |o| matches!(&o.inner, ObjectInner::CustomGate { uuid: u, .. } if u == uuid))
+	}
+	/// Number of top-level named inputs, which is the number of input columns in the truth table.
+	pub fn named_input_count(&self) -> usize {
+		self.find_objects(Object::is_named_input).count()
+	}
+	/// Number of top-level named outputs, which is the number of output columns in the truth table.
+	pub fn named_output_count(&self) -> usize {
+		self.find_objects(Object::is_named_output).count()
+	}
+	/// Number of top-level instances of any custom circuit.
+	pub fn custom_instance_count(&self) -> usize {
+		self.find_objects(|o| matches!(o.inner, ObjectInner::CustomGate { .. })).count()
+	}
+	#[allow(clippy::ptr_arg)]
 	fn process_objects(
 		objects: Vec<RawObject>,
 		connections: Vec<RawConnection>,
-		customs: &Vec<CustomCircuit>
+		customs: &Vec<CustomCircuit>,
+		mode: ParseMode,
+		warnings: &mut Vec<String>,
 	) -> Result<Vec<Object>, String> {
 		let customs: HashMap<_, _> = customs.iter().map(|c| (c.uid.clone(), c)).collect();
 		let mut objects = objects.into_iter()
-			.map(|o| Object::try_from(o, &customs))
+			.map(|o| {
+				let position = o.position;
+				Object::try_from(o, &customs).map_err(|e| format!("object at {position}: {e}"))
+			})
 			.collect::<Result<Vec<_>, String>>()?;
 		let uid_to_index: HashMap::<String, usize> = objects.iter().enumerate().map(|(i, o)| (o.uid.clone(), i)).collect();
 		for obj in connections {
-			let output = *uid_to_index.get(&obj.output_uid)
-				.ok_or(String::from("UUID does not correspond to any known object"))?;
-			let input = *uid_to_index.get(&obj.input_uid)
-				.ok_or(String::from("UUID does not correspond to any known object"))?;
+			let position = obj.position;
+			let (output, input) = match (uid_to_index.get(&obj.output_uid), uid_to_index.get(&obj.input_uid)) {
+				(Some(&output), Some(&input)) => (output, input),
+				(output, _) => {
+					let missing = if output.is_none() { &obj.output_uid } else { &obj.input_uid };
+					let message = format!("connection at {position}: UUID {missing} does not correspond to any known object (output {} of {} to input {} of {})",
+						obj.output_index, obj.output_uid, obj.input_index, obj.input_uid);
+					match mode {
+						ParseMode::Strict => return Err(message),
+						ParseMode::Lenient => {
+							warnings.push(format!("{message}, dropped it"));
+							continue;
+						},
+					}
+				},
+			};
 			match &mut objects[input].inner {
 				ObjectInner::SimpleGate { connections, .. } | ObjectInner::CustomGate { connections, .. } | ObjectInner::Output { connections, .. } =>
-					connections[obj.input_index as usize] = Some((obj.output_index, output)),
+					match connections.get_mut(obj.input_index as usize) {
+						None => return Err(format!("connection at {position}: Invalid connection: {} has no input {} (it has {} inputs)", obj.input_uid, obj.input_index, connections.len())),
+						Some(Some(_)) => return Err(format!("connection at {position}: Invalid connection: input {} of {} is connected more than once", obj.input_index, obj.input_uid)),
+						Some(slot) => *slot = Some((obj.output_index, output)),
+					},
 				ObjectInner::Input {..} | ObjectInner::Label {..} =>
-					return Err(String::from("Invalid connection: cannot connect an output or a label to something else")),
+					return Err(format!("connection at {position}: Invalid connection: cannot connect an output or a label to something else")),
 			}
 		}
 		Ok(objects)
 	}
 }
 impl Display for Circuit {
+	/// Lists the top-level objects, with custom gates shown by [`CustomCircuit::display_name`] instead of uuid.
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		for (i, obj) in self.objects.iter().enumerate() {
-			writeln!(f, "({i}) {obj}")?;
+			match &obj.inner {
+				ObjectInner::CustomGate { uuid, connections, .. } => {
+					let name = self.custom_library().iter().find(|c| &c.uid == uuid).map_or(&uuid[..], |c| c.display_name());
+					writeln!(f, "({i}) CustomGate {name} [{}]", format_connections(connections))?;
+				},
+				_ => writeln!(f, "({i}) {obj}")?,
+			}
 		}
 		Ok(())
 	}
 }
 
-#[derive(Debug, PartialEq)]
+/// Makes uids for objects created by transformations, like `gen-0001`, `gen-0002`, and so on.
+/// Logicly writes random UUIDs, but accepts any unique string, so sequential uids keep generated circuits reproducible.
+/// Custom circuit definitions are still identified by UUIDs, which the generator never produces.
+#[derive(Debug, Clone)]
+pub struct UidGenerator {
+	prefix: String,
+	next: usize,
+	taken: HashSet<String>,
+}
+impl UidGenerator {
+	pub fn new(prefix: &str) -> UidGenerator {
+		UidGenerator { prefix: prefix.to_string(), next: 1, taken: HashSet::new() }
+	}
+	/// Like [`UidGenerator::new`], but skips the uids of `objects`, so that new objects can be added next to them.
+	pub fn avoiding(prefix: &str, objects: &[Object]) -> UidGenerator {
+		UidGenerator { taken: objects.iter().map(|o| o.uid.clone()).collect(), ..UidGenerator::new(prefix) }
+	}
+	pub fn next_uid(&mut self) -> String {
+		loop {
+			let uid = format!("{}-{:04}", self.prefix, self.next);
+			self.next += 1;
+			if !self.taken.contains(&uid) { return uid }
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct CustomCircuit {
 	pub objects: Vec<Object>,
 	pub name: String,
@@ -153,13 +360,34 @@ pub struct CustomCircuit {
 impl CustomCircuit {
 	fn try_from(CustomCircuitWrapper {
 		name, uid, label, inner: RawCustomCircuit {
-			objects, connections, locations
-		}
-	}: CustomCircuitWrapper, customs: &Vec<CustomCircuit>) -> Result<Self, String> {
-		Ok(Self {
-			name, uid, label, locations,
-			objects: Circuit::process_objects(objects, connections, customs)?,
-		})
+			objects, connections, locations, ..
+		}, ..
+	}: CustomCircuitWrapper, customs: &Vec<CustomCircuit>, mode: ParseMode, warnings: &mut Vec<String>) -> Result<Self, String> {
+		let mut custom_warnings = vec![];
+		let objects = Circuit::process_objects(objects, connections, customs, mode, &mut custom_warnings)
+			.map_err(|e| format!("in custom circuit {name}: {e}"))?;
+		warnings.extend(custom_warnings.into_iter().map(|w| format!("in custom circuit {name}: {w}")));
+		Ok(Self { name, uid, label, locations, objects })
+	}
+	/// The text to show for this circuit: its label, or its name if it has no label, or its uid if it has neither.
+	pub fn display_name(&self) -> &str {
+		[&self.label, &self.name].into_iter().find(|s| !s.is_empty()).unwrap_or(&self.uid)
+	}
+	/// Number of named inputs, which is the number of inputs of each instance.
+	pub fn named_input_count(&self) -> usize {
+		self.objects.iter().filter(|o| o.is_named_input()).count()
+	}
+	/// Number of named outputs, which is the number of outputs of each instance.
+	pub fn named_output_count(&self) -> usize {
+		self.objects.iter().filter(|o| o.is_named_output()).count()
+	}
+	/// Whether both circuits have the same objects in the same order, wired the same way.
+	/// Objects are compared by everything except their uid, position, and rotation, so export names and label text have to match.
+	/// The name, uid, label, and pin locations of the circuits themselves are ignored. Listing the same objects in another order
+	/// counts as a different structure.
+	pub fn same_structure(&self, other: &CustomCircuit) -> bool {
+		self.objects.len() == other.objects.len()
+			&& self.objects.iter().zip(&other.objects).all(|(a, b)| a.inner == b.inner)
 	}
 }
 
@@ -183,6 +411,16 @@ impl TryFrom<u16> for Rotation {
 		})
 	}
 }
+impl From<Rotation> for u16 {
+	fn from(value: Rotation) -> Self {
+		match value {
+			Rotation::Right => 0,
+			Rotation::Down => 90,
+			Rotation::Left => 180,
+			Rotation::Up => 270,
+		}
+	}
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Object {
@@ -193,6 +431,53 @@ pub struct Object {
 	pub inner: ObjectInner,
 }
 impl Object {
+	pub fn new(uid: String, x: f64, y: f64, rotation: Rotation, inner: ObjectInner) -> Self {
+		Self { uid, x, y, rotation, inner }
+	}
+	pub fn uid(&self) -> &str {
+		&self.uid
+	}
+	pub fn position(&self) -> (f64, f64) {
+		(self.x, self.y)
+	}
+	pub fn rotation(&self) -> Rotation {
+		self.rotation
+	}
+	/// The sources of this object's inputs, as (output index, object index).
+	/// Empty for objects without inputs.
+	pub fn connections(&self) -> &[Option<(u32, usize)>] {
+		match &self.inner {
+			ObjectInner::SimpleGate { connections, .. } | ObjectInner::CustomGate { connections, .. } | ObjectInner::Output { connections, .. } => connections,
+			ObjectInner::Input { .. } | ObjectInner::Label { .. } => &[],
+		}
+	}
+	pub fn connections_mut(&mut self) -> Option<&mut Vec<Option<(u32, usize)>>> {
+		match &mut self.inner {
+			ObjectInner::SimpleGate { connections, .. } | ObjectInner::CustomGate { connections, .. } | ObjectInner::Output { connections, .. } => Some(connections),
+			ObjectInner::Input { .. } | ObjectInner::Label { .. } => None,
+		}
+	}
+	/// Whether each input of this gate is inverted, in the same order as [`Self::connections`].
+	/// May be shorter than the connections, in which case the remaining inputs are not inverted.
+	pub fn inverted_inputs(&self) -> &[bool] {
+		match &self.inner {
+			ObjectInner::SimpleGate { inverted, .. } | ObjectInner::CustomGate { inverted, .. } => inverted,
+			_ => &[],
+		}
+	}
+	/// Inverts (or stops inverting) one input of a simple or custom gate.
+	pub fn set_input_inverted(&mut self, index: usize, value: bool) -> Result<(), String> {
+		let num_inputs = self.connections().len();
+		match &mut self.inner {
+			ObjectInner::SimpleGate { inverted, .. } | ObjectInner::CustomGate { inverted, .. } if index < num_inputs => {
+				if inverted.len() <= index { inverted.resize(index + 1, false); }
+				inverted[index] = value;
+				Ok(())
+			},
+			ObjectInner::SimpleGate { .. } | ObjectInner::CustomGate { .. } => Err(format!("{} has no input {index}", self.uid)),
+			_ => Err(format!("{} is not a gate, so its inputs cannot be inverted", self.uid)),
+		}
+	}
 	pub fn is_output(&self) -> bool {
 		matches!(self.inner, ObjectInner::Output { .. })
 	}
@@ -202,45 +487,48 @@ impl Object {
 	pub fn is_named_input(&self) -> bool {
 		matches!(self.inner, ObjectInner::Input { export_name: Some(_), .. })
 	}
-	/// Must be an Output or Input
+	/// The export name of an Output or Input if it has one, otherwise the uid. Other objects never have export names.
 	pub fn export_name_or_uid(&self) -> &str {
 		match &self.inner {
 			ObjectInner::Output { export_name, .. } | ObjectInner::Input { export_name, .. } => export_name.as_ref().unwrap_or(&self.uid),
-			_ => panic!("Not an Output or Input")
+			_ => &self.uid,
 		}
 	}
 }
+#[allow(clippy::useless_format)]
+fn format_connections(connections: &[Option<(u32, usize)>]) -> String {
+	connections.iter().map(|x| match x {
+		Some((ind, ptr)) if *ind == 0 => format!("{ptr}"),
+		Some((ind, ptr)) => format!("{ptr}#{ind}"),
+		None => format!("NUL")
+	}).collect::<Vec<_>>().join(", ")
+}
 impl Display for Object {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		fn print_connections(connections: &Vec<Option<(u32, usize)>>) -> String {
-			connections.iter().map(|x| match x {
-				Some((ind, ptr)) if *ind == 0 => format!("{ptr}"),
-				Some((ind, ptr)) => format!("{ptr}#{ind}"),
-				None => format!("NUL")
-			}).collect::<Vec<_>>().join(", ")
-		}
 		match &self.inner {
-			ObjectInner::SimpleGate { kind, connections, .. } => write!(f, "Gate {kind} [{}]", print_connections(connections)),
-			ObjectInner::CustomGate { uuid, connections, .. } => write!(f, "CustomGate {uuid} [{}]", print_connections(connections)),
-			ObjectInner::Output { export_name, connections } => write!(f, "Output({}) {}", export_name.clone().unwrap_or("?".to_string()), print_connections(connections)),
+			ObjectInner::SimpleGate { kind, connections, .. } => write!(f, "Gate {kind} [{}]", format_connections(connections)),
+			ObjectInner::CustomGate { uuid, connections, .. } => write!(f, "CustomGate {uuid} [{}]", format_connections(connections)),
+			ObjectInner::Output { export_name, connections } => write!(f, "Output({}) {}", export_name.clone().unwrap_or("?".to_string()), format_connections(connections)),
 			ObjectInner::Input { export_name, kind, value } => write!(f, "Input({}) {kind} {value}", export_name.clone().unwrap_or("?".to_string())),
 			ObjectInner::Label { text } => write!(f, "Label: {text}"),
 		}
 	}
 }
 impl Object {
+	#[allow(clippy::useless_format)]
 	fn try_from(value: RawObject, customs: &HashMap<String, &CustomCircuit>) -> Result<Self, String> {
 		Ok(match &value.kind[..] {
 			"switch@logic.ly" | "push_button@logic.ly" | "constant_high@logic.ly" | "constant_low@logic.ly" => match value {
-				RawObject { kind, uid, x, y, rotation, export_name, outputs, inputs: None, text: None, function_index: None } => Self {
+				RawObject { kind, uid, x, y, rotation, export_name, outputs, inputs: None, text: None, function_index: None, position: _, unknown: _ } => Self {
 					uid, x, y,
 					rotation: rotation.try_into()?,
 					inner: ObjectInner::Input {
 						export_name,
 						kind: kind[..].try_into()?,
 						value: match &outputs {
+							// Older versions of Logicly wrote switch states as 1 and 0
 							Some(str) => match &str[..] {
-								"false" => false, "true" => true,
+								"false" | "0" => false, "true" | "1" => true,
 								x => return Err(format!("invalid output field in object: expected 'true' or 'false', not {x}"))
 							},
 							None if matches!(&kind[..], "constant_high@logic.ly" | "constant_low@logic.ly") =>
@@ -252,7 +540,7 @@ impl Object {
 				_ => return Err(format!("Invalid gate: unexpected property")),
 			},
 			"light_bulb@logic.ly" | "digit@logic.ly" => match value {
-				RawObject { uid, x, y, rotation, export_name, outputs: None, inputs: None, text: None, function_index: None, kind: _ } => Self {
+				RawObject { uid, x, y, rotation, export_name, outputs: None, inputs: None, text: None, function_index: None, kind: _, position: _, unknown: _ } => Self {
 					uid, x, y,
 					rotation: rotation.try_into()?,
 					inner: ObjectInner::Output {
@@ -263,7 +551,7 @@ impl Object {
 				_ => return Err(format!("Invalid light bulb")),
 			},
 			"label@logic.ly" => match value {
-				RawObject { uid, x, y, rotation, export_name: None, outputs: None, inputs: None, text: Some(text), function_index: None, kind: _ } => Self {
+				RawObject { uid, x, y, rotation, export_name: None, outputs: None, inputs: None, text: Some(text), function_index: None, kind: _, position: _, unknown: _ } => Self {
 					uid, x, y,
 					rotation: rotation.try_into()?,
 					inner: ObjectInner::Label { text }
@@ -274,17 +562,22 @@ impl Object {
 			"and@logic.ly" | "nand@logic.ly" |
 			"or@logic.ly" | "nor@logic.ly" |
 			"xor@logic.ly" | "xnor@logic.ly" => match value {
-				RawObject { uid, x, y, kind, rotation, export_name: None, outputs: None, inputs: Some(inputs), text: None, function_index } => Self {
-					uid, x, y,
-					rotation: rotation.try_into()?,
+				RawObject { uid, x, y, kind, rotation, export_name: None, outputs: None, inputs: Some(inputs), text: None, function_index, position: _, unknown: _ } => Self {
 					inner: ObjectInner::SimpleGate {
 						connections: vec![None; inputs as usize],
-						kind: kind[..].try_into()?,
+						inverted: vec![],
+						kind: match kind[..].try_into()? {
+							kind @ (SimpleGateType::Buffer | SimpleGateType::Not) if inputs != 1 =>
+								return Err(format!("Invalid gate {uid}: {kind} gates must have exactly one input, found {inputs}")),
+							kind => kind,
+						},
 						xor_type: match function_index {
 							Some(1) => XorType::One,
 							_ => XorType::Odd,
 						},
-					}
+					},
+					uid, x, y,
+					rotation: rotation.try_into()?,
 				},
 				_ => return Err(format!("Invalid gate: attributes are invalid")),
 			},
@@ -292,11 +585,10 @@ impl Object {
 				RawObject { uid, x, y, rotation, export_name: None, outputs: None, inputs: None, text: None, .. } => Self {
 					inner: {
 						let gate = customs.get(uuid).ok_or(format!("Unknown custom circuit {uid}"))?;
-						let num_inputs = gate.objects.iter().filter(|o| o.is_named_input()).count();
-						let num_outputs = gate.objects.iter().filter(|o| o.is_named_output()).count() as u32;
 						ObjectInner::CustomGate {
-							connections: vec![None; num_inputs as usize],
-							num_outputs,
+							connections: vec![None; gate.named_input_count()],
+							inverted: vec![],
+							num_outputs: gate.named_output_count() as u32,
 							uuid: uuid.to_string(),
 						}
 					},
@@ -315,11 +607,15 @@ pub enum ObjectInner {
 		xor_type: XorType,
 		kind: SimpleGateType,
 		connections: Vec<Option<(u32, usize)>>,
+		/// Logicly files never invert gate inputs, so this is only set through [`Object::set_input_inverted`].
+		inverted: Vec<bool>,
 	},
 	CustomGate {
 		uuid: String,
 		num_outputs: u32,
 		connections: Vec<Option<(u32, usize)>>,
+		/// See [`Object::inverted_inputs`].
+		inverted: Vec<bool>,
 	},
 	Output {
 		export_name: Option<String>,
@@ -328,7 +624,7 @@ pub enum ObjectInner {
 	Input {
 		export_name: Option<String>,
 		kind: InputType,
-		/// unused
+		/// The position saved in the file. Only used if [`crate::simul::SimulationConfig::saved_inputs`] is set.
 		value: bool,
 	},
 	Label {
@@ -361,7 +657,12 @@ impl Display for InputType {
 		})
 	}
 }
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// Buffer and Not gates always have exactly one input, like in Logicly.
+/// Files that give them more are rejected rather than ignoring the extra wires.
+///
+/// The other gates can also be given a single input, in which case And, Or, and Xor act as a Buffer,
+/// and Nand, Nor, and Xnor act as a Not. This holds for both [`XorType`]s, as one true input is both an odd number and exactly one.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum SimpleGateType {
 	Buffer, Not,
 	And, Nand,
@@ -399,34 +700,178 @@ impl Display for SimpleGateType {
 		})
 	}
 }
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// How a Xor or Xnor gate combines its inputs, set by the `functionIndex` attribute.
+/// Xnor is always the negation of Xor with the same inputs and XorType.
+///
+/// The output depends only on the number of true inputs:
+///
+/// | true inputs | Xor, Odd | Xor, One | Xnor, Odd | Xnor, One |
+/// |-------------|----------|----------|-----------|-----------|
+/// | 0           | F        | F        | T         | T         |
+/// | 1           | T        | T        | F         | F         |
+/// | 2           | F        | F        | T         | T         |
+/// | 3           | T        | F        | F         | T         |
+/// | 4           | F        | F        | T         | T         |
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum XorType {
-	Odd, One
+	/// True if an odd number of inputs are true. This is the default.
+	Odd,
+	/// True if exactly one input is true. (`functionIndex="1"`)
+	One
 }
 impl TryFrom<RawCircuit> for Circuit {
 	type Error = String;
-	fn try_from(RawCircuit { connections, customs, objects, .. }: RawCircuit) -> Result<Self, Self::Error> {
+	fn try_from(raw: RawCircuit) -> Result<Self, Self::Error> {
+		Circuit::from_raw(raw, ParseMode::Strict)
+	}
+}
+impl Circuit {
+	pub fn from_raw(RawCircuit { xmlns, connections, customs, objects, .. }: RawCircuit, mode: ParseMode) -> Result<Self, String> {
+		let mut merged_customs: Vec<(String, String)> = vec![];
+		let mut warnings = vec![];
+		let schema_version = schema_version(xmlns.as_deref(), &mut warnings);
+		/// Makes instances of merged custom circuits use the canonical definition.
+		fn redirect(objects: &mut [RawObject], merged: &[(String, String)]) {
+			for obj in objects {
+				if let Some((_, canonical)) = merged.iter().find(|(duplicate, _)| *duplicate == obj.kind) {
+					obj.kind = canonical.clone();
+				}
+			}
+		}
 		let customs: Option<Vec<CustomCircuit>> = match customs {
 			Some(c) => {
 				let c = order_dependency_graph(c)?;
-				let mut customs = vec![];
-				for custom in c {
-					customs.push(CustomCircuit::try_from(custom, &customs)?);
+				let mut customs: Vec<CustomCircuit> = vec![];
+				for mut custom in c {
+					redirect(&mut custom.inner.objects, &merged_customs);
+					let custom = CustomCircuit::try_from(custom, &customs, mode, &mut warnings)?;
+					match customs.iter().find(|c| c.same_structure(&custom)) {
+						Some(canonical) => merged_customs.push((custom.uid, canonical.uid.clone())),
+						None => customs.push(custom),
+					}
 				}
 				Some(customs)
 			},
 			None => None,
 		};
+		let wire_points = connections.iter().filter_map(|c| {
+			Some(((c.input_uid.clone(), c.input_index), parse_points(c.points.as_deref()?)?))
+		}).collect();
+		let mut objects = objects;
+		redirect(&mut objects, &merged_customs);
 		let objects = Circuit::process_objects(
-			objects, connections, customs.as_ref().unwrap_or(&vec![])
+			objects, connections, customs.as_ref().unwrap_or(&vec![]), mode, &mut warnings
 		)?;
 		Ok(Self {
 			objects,
 			customs,
+			merged_customs,
+			warnings,
+			schema_version,
+			wire_points,
 		})
 	}
 }
 
+impl Circuit {
+	/// Converts the circuit back into the structure of a .logicly file, which [`RawCircuit::to_xml`] or [`save_raw_circuit`] can write out.
+	/// Attributes and elements that were not modeled when the circuit was read are lost.
+	/// Fails if a gate has an inverted input, which Logicly cannot represent.
+	pub fn to_raw(&self) -> Result<RawCircuit, String> {
+		let (objects, connections) = raw_objects(&self.objects, &self.wire_points)?;
+		let customs = self.customs.as_ref().map(|customs| customs.iter().map(|custom| {
+			let (objects, connections) = raw_objects(&custom.objects, &HashMap::new())
+				.map_err(|e| format!("in custom circuit {}: {e}", custom.name))?;
+			Ok(CustomCircuitWrapper {
+				name: custom.name.clone(),
+				uid: custom.uid.clone(),
+				label: custom.label.clone(),
+				inner: RawCustomCircuit { objects, connections, locations: custom.locations.clone(), unknown: Unknown::default() },
+				unknown: Unknown::default(),
+			})
+		}).collect::<Result<Vec<_>, String>>()).transpose()?;
+		Ok(RawCircuit {
+			xmlns: self.schema_version.map(|version| format!("{logicly_namespace}/{version}")),
+			objects,
+			connections,
+			settings: vec![],
+			customs,
+			unknown: Unknown::default(),
+		})
+	}
+}
+fn raw_objects(objects: &[Object], wire_points: &HashMap<(String, u32), Vec<(f64, f64)>>) -> Result<(Vec<RawObject>, Vec<RawConnection>), String> {
+	let raw_objects = objects.iter().map(|obj| {
+		if obj.inverted_inputs().contains(&true) {
+			return Err(format!("{} has an inverted input, which cannot be saved", obj.uid));
+		}
+		let mut raw = RawObject {
+			kind: String::new(),
+			uid: obj.uid.clone(),
+			x: obj.x,
+			y: obj.y,
+			rotation: obj.rotation.into(),
+			export_name: None,
+			outputs: None,
+			inputs: None,
+			text: None,
+			function_index: None,
+			position: SourcePosition::default(),
+			unknown: Unknown::default(),
+		};
+		match &obj.inner {
+			ObjectInner::SimpleGate { xor_type, kind, connections, .. } => {
+				raw.kind = format!("{}@logic.ly", kind.to_string().to_lowercase());
+				raw.inputs = Some(connections.len() as u32);
+				if *xor_type == XorType::One { raw.function_index = Some(1); }
+			},
+			ObjectInner::CustomGate { uuid, .. } => raw.kind = uuid.clone(),
+			ObjectInner::Output { export_name, connections } => {
+				raw.kind = String::from(if connections.len() == 4 { "digit@logic.ly" } else { "light_bulb@logic.ly" });
+				raw.export_name = export_name.clone();
+			},
+			ObjectInner::Input { export_name, kind, value } => {
+				raw.kind = String::from(match kind {
+					InputType::Switch => "switch@logic.ly",
+					InputType::Button => "push_button@logic.ly",
+					InputType::True => "constant_high@logic.ly",
+					InputType::False => "constant_low@logic.ly",
+				});
+				raw.export_name = export_name.clone();
+				if matches!(kind, InputType::Switch | InputType::Button) { raw.outputs = Some(value.to_string()); }
+			},
+			ObjectInner::Label { text } => {
+				raw.kind = String::from("label@logic.ly");
+				raw.text = Some(text.clone());
+			},
+		}
+		Ok(raw)
+	}).collect::<Result<Vec<_>, String>>()?;
+	let connections = wires(objects).map(|wire| {
+		let target = objects[wire.target].uid.clone();
+		RawConnection {
+			points: wire_points.get(&(target.clone(), wire.input))
+				.map(|points| points.iter().map(|(x, y)| format!("{x},{y}")).join(" ")),
+			input_uid: target,
+			output_uid: objects[wire.source].uid.clone(),
+			input_index: wire.input,
+			output_index: wire.output,
+			position: SourcePosition::default(),
+			unknown: Unknown::default(),
+		}
+	}).collect();
+	Ok((raw_objects, connections))
+}
+
+/// Reads the `points` of a connection, like `40,0 40,20`. Returns None if they are malformed, as they are only used for drawing.
+fn parse_points(points: &str) -> Option<Vec<(f64, f64)>> {
+	points.split_whitespace().map(|point| {
+		let (x, y) = point.split_once(',')?;
+		Some((x.parse().ok()?, y.parse().ok()?))
+	}).collect()
+}
+
+#[allow(clippy::collapsible_if, clippy::manual_flatten, clippy::useless_format)]
 pub fn order_dependency_graph(items: Vec<CustomCircuitWrapper>) -> Result<Vec<CustomCircuitWrapper>, String> {
 	let mut items_deps: Vec<_> = items.into_iter().map(|item|{
 		let deps: HashSet<_> = item.inner.objects.iter().filter_map(|o| match Uuid::try_parse(&o.kind) {
@@ -481,8 +926,11 @@ pub fn order_dependency_graph(items: Vec<CustomCircuitWrapper>) -> Result<Vec<Cu
 						}
 						i = next_i;
 					} else {
-						return Err(format!("Circuit contains a dependency cycle: failed to find it"));
+						let dep = deps.iter().next().unwrap();
+						return Err(format!("Custom circuit {} uses custom circuit {dep}, which is not defined", item.uid));
 					}
+				} else {
+					i += 1;
 				}
 				if i >= items_deps.len() {
 					if !updated {
@@ -497,9 +945,183 @@ pub fn order_dependency_graph(items: Vec<CustomCircuitWrapper>) -> Result<Vec<Cu
 	Ok(output)
 }
 
+/// A wire between two of a list of objects, by index in the list.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Wire {
+	pub source: usize,
+	pub output: u32,
+	pub target: usize,
+	pub input: u32,
+	/// Whether the target inverts this input.
+	pub inverted: bool,
+}
+
+/// The wires between `objects`, found from the connections of the object each one leads to.
+/// Ordered by target and then by input, which is the order the exporters draw them in.
+/// Also takes the objects of a simulation, which can be borrowed as the objects they were built from.
+pub(crate) fn wires<O: Borrow<Object>>(objects: &[O]) -> impl Iterator<Item = Wire> + '_ {
+	objects.iter().map(Borrow::borrow).enumerate().flat_map(|(target, object)| {
+		let inverted = object.inverted_inputs().iter().copied().chain(std::iter::repeat(false));
+		object.connections().iter().zip(inverted).enumerate().filter_map(move |(input, (connection, inverted))| {
+			let (output, source) = (*connection)?;
+			Some(Wire { source, output, target, input: input as u32, inverted })
+		})
+	})
+}
+
+/// Finds the groups of objects whose outputs feed back into their own inputs through other gates.
+/// Such a loop makes the circuit oscillate or hold state, as there is no clock to break it.
+/// Each group is given as the shortest loop through its first object in file order, as indices in signal order.
+/// Custom gates are treated as if every output depends on every input.
+pub fn feedback_loops(objects: &[Object]) -> Vec<Vec<usize>> {
+	let sources = |i: usize| objects[i].connections().iter().flatten().map(|&(_, source)| source);
+	let mut readers = vec![vec![]; objects.len()];
+	for i in 0..objects.len() {
+		for source in sources(i) { readers[source].push(i); }
+	}
+	// Kosaraju's algorithm: search along the wires, then against them in the reverse order that objects were finished,
+	// and each search in the second pass finds one strongly connected group.
+	let mut visited = vec![false; objects.len()];
+	let mut finished = Vec::with_capacity(objects.len());
+	for root in 0..objects.len() {
+		if visited[root] { continue }
+		visited[root] = true;
+		let mut stack = vec![(root, 0)];
+		while let Some((i, next)) = stack.last_mut() {
+			match readers[*i].get(*next) {
+				Some(&reader) => {
+					*next += 1;
+					if !visited[reader] {
+						visited[reader] = true;
+						stack.push((reader, 0));
+					}
+				},
+				None => {
+					finished.push(*i);
+					stack.pop();
+				},
+			}
+		}
+	}
+	let mut group = vec![None; objects.len()];
+	let mut num_groups = 0;
+	for &root in finished.iter().rev() {
+		if group[root].is_some() { continue }
+		group[root] = Some(num_groups);
+		let mut stack = vec![root];
+		while let Some(i) = stack.pop() {
+			for source in sources(i) {
+				if group[source].is_none() {
+					group[source] = Some(num_groups);
+					stack.push(source);
+				}
+			}
+		}
+		num_groups += 1;
+	}
+	let mut searched = vec![false; num_groups];
+	let mut loops = vec![];
+	for start in 0..objects.len() {
+		let start_group = group[start];
+		let Some(g) = start_group else { continue };
+		if searched[g] { continue }
+		searched[g] = true;
+		// Breadth first search for the shortest way back to the start, which only leaves the start if it is in a loop
+		let mut previous = HashMap::new();
+		let mut queue = VecDeque::from([start]);
+		'search: while let Some(i) = queue.pop_front() {
+			for &reader in &readers[i] {
+				if reader == start {
+					let mut path = vec![i];
+					while let Some(&before) = path.last().and_then(|last| previous.get(last)) { path.push(before); }
+					path.reverse();
+					loops.push(path);
+					break 'search;
+				}
+				if group[reader] == start_group && reader != start && !previous.contains_key(&reader) {
+					previous.insert(reader, i);
+					queue.push_back(reader);
+				}
+			}
+		}
+	}
+	loops
+}
+
+/// Reads the XML of a .logicly file without processing it.
+/// Attributes and elements that are not modeled are kept, so that [`RawCircuit::to_xml`] writes them back out.
+pub fn parse_raw_xml(input:&str) -> Result<RawCircuit> {
+	xml::read_raw_circuit(input.as_bytes()).map_err(|e| anyhow!(e))
+}
 pub fn parse_xml(input:&str) -> Result<Circuit> {
-	let raw: RawCircuit = serde_xml_rs::from_str(input)?;
-	Circuit::try_from(raw).map_err(|e| anyhow!(e))
+	parse_xml_with(input, ParseMode::Strict)
+}
+pub fn parse_xml_with(input:&str, mode: ParseMode) -> Result<Circuit> {
+	let raw = xml::read_raw_circuit(input.as_bytes()).map_err(|e| anyhow!(e))?;
+	Circuit::from_raw(raw, mode).map_err(|e| anyhow!(e))
+}
+
+/// Reads, decompresses, and parses a .logicly file.
+pub fn load_circuit(path: impl AsRef<Path>) -> Result<Circuit> {
+	load_circuit_with(path, ParseMode::Strict)
+}
+pub fn load_circuit_with(path: impl AsRef<Path>, mode: ParseMode) -> Result<Circuit> {
+	let file = File::open(path).context("Error reading file")?;
+	load_circuit_from_reader_with(file, mode)
+}
+
+/// Reads and decompresses a .logicly file without processing it. See [`parse_raw_xml`].
+pub fn load_raw_circuit(path: impl AsRef<Path>) -> Result<RawCircuit> {
+	let file = File::open(path).context("Error reading file")?;
+	xml::read_raw_circuit(decompress(file)?).map_err(|e| anyhow!(e))
+}
+/// Writes a circuit to a .logicly file, compressed the same way Logicly does.
+pub fn save_raw_circuit(raw: &RawCircuit, path: impl AsRef<Path>) -> Result<()> {
+	let file = File::create(path).context("Error writing file")?;
+	let mut encoder = flate2::write::DeflateEncoder::new(file, flate2::Compression::default());
+	encoder.write_all(raw.to_xml().as_bytes()).context("Error writing file")?;
+	encoder.finish().context("Error writing file")?;
+	Ok(())
+}
+
+/// Decompresses and parses the contents of a .logicly file as they are read,
+/// without holding the whole document in memory.
+pub fn load_circuit_from_reader(reader: impl Read) -> Result<Circuit> {
+	load_circuit_from_reader_with(reader, ParseMode::Strict)
+}
+pub fn load_circuit_from_reader_with(reader: impl Read, mode: ParseMode) -> Result<Circuit> {
+	let raw = xml::read_raw_circuit(decompress(reader)?).map_err(|e| anyhow!(e))?;
+	Circuit::from_raw(raw, mode).map_err(|e| anyhow!(e))
+}
+
+/// Decompresses a .logicly file, looking at its first bytes to explain what it is if it is not one.
+/// Gzip and zlib streams are accepted as well as the raw deflate streams that Logicly writes.
+fn decompress<'a>(reader: impl Read + 'a) -> Result<Box<dyn BufRead + 'a>> {
+	let mut reader = BufReader::new(reader);
+	let start = reader.fill_buf().context("Error reading file")?;
+	let start = start[..start.len().min(16)].to_vec();
+	let other_format = |format: &str| anyhow!("this is {format}, not a Logicly file");
+	let mut decompressed: Box<dyn BufRead> = match &start[..] {
+		[0x1f, 0x8b, ..] => Box::new(BufReader::new(flate2::bufread::GzDecoder::new(reader))),
+		// A raw deflate stream can only start with 0x78 if its first block is stored uncompressed, which Logicly does not do
+		[0x78, flags, ..] if u16::from_be_bytes([0x78, *flags]).is_multiple_of(31) => Box::new(BufReader::new(flate2::bufread::ZlibDecoder::new(reader))),
+		[b'P', b'K', 3 | 5 | 7, 4 | 6 | 8, ..] => return Err(other_format("a zip archive")),
+		[0x89, b'P', b'N', b'G', ..] => return Err(other_format("a PNG image")),
+		[0xff, 0xd8, 0xff, ..] => return Err(other_format("a JPEG image")),
+		[b'G', b'I', b'F', b'8', ..] => return Err(other_format("a GIF image")),
+		[b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => return Err(other_format("a WebP image")),
+		[b'%', b'P', b'D', b'F', ..] => return Err(other_format("a PDF document")),
+		_ => Box::new(BufReader::new(flate2::bufread::DeflateDecoder::new(reader))),
+	};
+	let contents = decompressed.fill_buf().map_err(|e|
+		anyhow!("this is not a Logicly file: it could not be decompressed ({e}), and starts with \"{}\"", start.escape_ascii())
+	)?;
+	// Whitespace, a byte order mark, or UTF-16 may come before the first element
+	if let Some(&first) = contents.iter().find(|b| !b.is_ascii_whitespace()) && !matches!(first, b'<' | 0 | 0xef | 0xfe | 0xff) {
+		let preview = contents[..contents.len().min(16)].escape_ascii();
+		return Err(anyhow!("this is not a Logicly file: it was decompressed, but does not contain XML (it starts with \"{preview}\")"));
+	}
+	Ok(decompressed)
 }
 
 #[cfg(test)]
@@ -512,6 +1134,7 @@ mod tests {
 		Uuid::from_bytes(name.try_into().unwrap())
 	}
 	fn make_circuit(name: &'static str, deps: Vec<&'static str>) -> CustomCircuitWrapper {
+		let mut uids = UidGenerator::new("gen");
 		CustomCircuitWrapper {
 			label: String::from(""),
 			uid: name_to_uuid(name).to_string(),
@@ -519,7 +1142,7 @@ mod tests {
 			inner: RawCustomCircuit {
 				objects: deps.into_iter().map(|s| RawObject {
 					kind: name_to_uuid(s).to_string(),
-					uid: Uuid::new_v4().to_string(),
+					uid: uids.next_uid(),
 					x: 0.,
 					y: 0.,
 					rotation: 0,
@@ -528,11 +1151,301 @@ mod tests {
 					inputs: None,
 					text: None,
 					function_index: None,
+					position: SourcePosition::default(),
+					unknown: Unknown::default(),
 				}).collect(),
 				connections: vec![],
-				locations: vec![]
-			}
+				locations: vec![],
+				unknown: Unknown::default(),
+			},
+			unknown: Unknown::default(),
+		}
+	}
+	/// Run with `cargo test --release bench_parse -- --ignored --nocapture`.
+	#[test]
+	#[ignore]
+	fn bench_parse(){
+		// About 50k objects, and twice as many connections
+		let xml = crate::util::fixtures::synthetic_circuit(10, 50000, 8);
+		let start = std::time::Instant::now();
+		let parsed = parse_xml(&xml).unwrap();
+		println!("{} objects, {} KB: {:?} from a string", parsed.objects.len(), xml.len() / 1024, start.elapsed());
+		let compressed = compress(xml.as_bytes());
+		let start = std::time::Instant::now();
+		let streamed = load_circuit_from_reader(&compressed[..]).unwrap();
+		println!("{} KB compressed: {:?} streamed from a reader", compressed.len() / 1024, start.elapsed());
+		assert_eq!(streamed, parsed);
+	}
+	#[test]
+	fn error_positions(){
+		use crate::util::fixtures::XOR;
+		let rotated = XOR.replace(r#"uid="g" x="80" y="20" rotation="0""#, r#"uid="g" x="80" y="20" rotation="45""#);
+		assert_eq!(parse_xml(&rotated).unwrap_err().to_string(), "object at line 4, column 2: Unsupported rotation 45");
+		let missing = XOR.replace(r#"<object type="light_bulb@logic.ly" uid="q" "#, r#"<object type="light_bulb@logic.ly" "#);
+		assert_eq!(parse_xml(&missing).unwrap_err().to_string(), "object at line 5, column 2: missing attribute uid");
+		let dangling = XOR.replace(r#"outputUID="b""#, r#"outputUID="c""#);
+		assert!(parse_xml(&dangling).unwrap_err().to_string().starts_with("connection at line 7, column 2: UUID c "));
+		let malformed = XOR.replace(r#"inputs="2"/>"#, r#"inputs="2">"#);
+		assert!(parse_xml(&malformed).unwrap_err().to_string().starts_with("invalid XML at line "));
+	}
+	#[test]
+	fn namespaced_attributes(){
+		use crate::util::fixtures::XOR;
+		let namespaced = XOR
+			.replace("<logicly>", r#"<logicly xmlns="http://logic.ly" xmlns:ex="http://example.com">"#)
+			.replace(r#"uid="q""#, r#"uid="q" ex:uid="other" ex:rotation="45""#);
+		let circuit = parse_xml(&namespaced).unwrap();
+		assert_eq!(circuit, parse_xml(XOR).unwrap());
+	}
+	fn compress(xml: &[u8]) -> Vec<u8> {
+		use std::io::Write;
+		let mut encoder = flate2::write::DeflateEncoder::new(vec![], flate2::Compression::default());
+		encoder.write_all(xml).unwrap();
+		encoder.finish().unwrap()
+	}
+	#[test]
+	fn load_from_reader(){
+		use crate::util::fixtures::*;
+		let synthetic = synthetic_circuit(10, 300, 8);
+		for xml in [XOR, MAJORITY, HALF_ADDER, REDUNDANT, RIPPLE_ADDER, INCREMENTER, AND_SUBTREES, &synthetic] {
+			assert_eq!(load_circuit_from_reader(&compress(xml.as_bytes())[..]).unwrap(), parse_xml(xml).unwrap());
+		}
+		let mut invalid = XOR.as_bytes().to_vec();
+		let q = XOR.find(r#"exportName="q""#).unwrap() + r#"exportName=""#.len();
+		invalid[q] = 0xff;
+		assert!(load_circuit_from_reader(&compress(&invalid)[..]).unwrap_err().to_string().contains("cannot decode input using UTF-8"));
+		let truncated = compress(XOR.as_bytes());
+		assert!(load_circuit_from_reader(&truncated[..truncated.len() / 2]).is_err());
+	}
+	#[test]
+	fn load_other_formats(){
+		use std::io::Write;
+		use crate::util::fixtures::XOR;
+		let expected = parse_xml(XOR).unwrap();
+		let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+		gzip.write_all(XOR.as_bytes()).unwrap();
+		assert_eq!(load_circuit_from_reader(&gzip.finish().unwrap()[..]).unwrap(), expected);
+		for level in [1, 6, 9] {
+			let mut zlib = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::new(level));
+			zlib.write_all(XOR.as_bytes()).unwrap();
+			assert_eq!(load_circuit_from_reader(&zlib.finish().unwrap()[..]).unwrap(), expected);
+		}
+
+		let error = |bytes: &[u8]| load_circuit_from_reader(bytes).unwrap_err().to_string();
+		assert_eq!(error(b"PK\x03\x04\x14\x00\x00\x00"), "this is a zip archive, not a Logicly file");
+		assert_eq!(error(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR"), "this is a PNG image, not a Logicly file");
+		assert_eq!(error(b"\xff\xd8\xff\xe0\x00\x10JFIF"), "this is a JPEG image, not a Logicly file");
+		assert_eq!(error(b"GIF89a\x01\x00\x01\x00"), "this is a GIF image, not a Logicly file");
+		assert_eq!(error(b"RIFF\x24\x00\x00\x00WEBPVP8 "), "this is a WebP image, not a Logicly file");
+		assert_eq!(error(b"%PDF-1.7\n"), "this is a PDF document, not a Logicly file");
+		assert_eq!(error(&compress(b"{\"objects\": []}")), r#"this is not a Logicly file: it was decompressed, but does not contain XML (it starts with "{\"objects\": []}")"#);
+		assert!(error(b"\x07\x00\x00\x00").starts_with(r#"this is not a Logicly file: it could not be decompressed ("#));
+		assert!(error(b"\x07\x00\x00\x00").ends_with(r#"and starts with "\x07\x00\x00\x00""#));
+	}
+	#[test]
+	fn load_encodings(){
+		use crate::util::fixtures::{XOR, XOR_BOM, XOR_UTF16LE};
+		let expected = parse_xml(XOR).unwrap();
+		assert_eq!(load_circuit_from_reader(&compress(XOR_BOM)[..]).unwrap(), expected);
+		assert_eq!(load_circuit_from_reader(&compress(XOR_UTF16LE)[..]).unwrap(), expected);
+		assert_eq!(parse_xml(std::str::from_utf8(XOR_BOM).unwrap()).unwrap(), expected);
+		let big_endian: Vec<u8> = XOR_UTF16LE.chunks(2).flat_map(|pair| [pair[1], pair[0]]).collect();
+		assert_eq!(load_circuit_from_reader(&compress(&big_endian)[..]).unwrap(), expected);
+		let latin1 = format!("<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>\n{XOR}");
+		assert_eq!(parse_xml(&latin1).unwrap_err().to_string(), "Unsupported encoding ISO-8859-1: only UTF-8 and UTF-16 are supported (at line 1, column 1)");
+		assert!(load_circuit_from_reader(&compress(&XOR_UTF16LE[..XOR_UTF16LE.len() - 1])[..]).is_err());
+	}
+	#[test]
+	fn special_characters(){
+		use crate::util::fixtures::XOR;
+		let text = |xml: &str| parse_xml(xml).unwrap().objects.into_iter().find_map(|o| match o.inner {
+			ObjectInner::Label { text } => Some(text),
+			_ => None,
+		}).unwrap();
+		let with_label = |attributes: &str| XOR.replacen("<connection", &format!("<object type=\"label@logic.ly\" uid=\"l\" x=\"0\" y=\"0\" rotation=\"0\" {attributes}/>\n\t<connection"), 1);
+		let expected = "a & b < c\n\"quoted\"";
+		assert_eq!(text(&with_label(r#"text="a &amp; b &lt; c&#10;&quot;quoted&quot;""#)), expected);
+		assert_eq!(text(&with_label("text=\"a &#38; b &#x3C; c\n&quot;quoted&quot;\"")), expected);
+		assert_eq!(text(&with_label("text='a &amp; b &lt; c\r\n\"quoted\"'")), expected);
+		assert_eq!(text(&with_label("text=\"  indented\tand&#13;kept  \"")), "  indented\tand\rkept  ");
+		let renamed = XOR.replace(r#"exportName="q""#, r#"exportName="q &amp; &apos;r&apos;""#);
+		assert_eq!(parse_xml(&renamed).unwrap().objects[3].export_name_or_uid(), "q & 'r'");
+		assert!(parse_xml(&with_label(r#"text="a & b""#)).unwrap_err().to_string().starts_with("invalid XML at line 6, column 2"));
+	}
+	#[test]
+	fn minimal_files(){
+		use crate::util::fixtures::{HALF_ADDER, XOR};
+		let empty = parse_xml("<logicly/>").unwrap();
+		assert!(empty.is_empty());
+		assert_eq!(empty.custom_library(), []);
+		assert_eq!(crate::simul::Simulation::from(empty).get_truth_table(100).unwrap().num_rows(), 1);
+
+		let objects_only: String = XOR.lines().filter(|l| l.contains("<object") || l.contains("logicly>")).collect();
+		let circuit = parse_xml(&objects_only).unwrap();
+		assert_eq!((circuit.objects.len(), circuit.unconnected_outputs()), (4, vec![String::from("q")]));
+		assert!(crate::simul::Simulation::from(circuit).get_truth_table(100).is_some());
+
+		let custom = &HALF_ADDER[HALF_ADDER.find("<custom").unwrap()..HALF_ADDER.find("</custom>").unwrap() + "</custom>".len()];
+		let customs_only = parse_xml(&format!("<logicly>{}</logicly>", custom.replace(r#"<location id="left" uids="a,b"/>"#, "").replace(r#"<location id="right" uids="s,c"/>"#, ""))).unwrap();
+		assert!(customs_only.objects.is_empty() && !customs_only.is_empty());
+		assert_eq!(customs_only.custom_library()[0].locations, []);
+		assert_eq!(crate::simul::Simulation::from_custom(&customs_only, "Half Adder").unwrap().get_truth_table(100).unwrap().minterms(1), vec![3]);
+	}
+	#[test]
+	fn schema_versions(){
+		use crate::util::fixtures::{STYLED, XOR, XOR_OLD};
+		let old = parse_xml(XOR_OLD).unwrap();
+		assert_eq!((old.schema_version, &old.warnings[..]), (Some(2010), &[][..]));
+		assert_eq!(old.objects.iter().map(Object::rotation).collect_vec(), [Rotation::Right, Rotation::Right, Rotation::Right, Rotation::Left]);
+		assert!(matches!(old.objects[0].inner, ObjectInner::Input { value: false, .. }));
+		assert!(matches!(old.objects[1].inner, ObjectInner::Input { value: true, .. }));
+		let table = |circuit: Circuit| crate::simul::Simulation::from(circuit).get_truth_table(100).unwrap();
+		assert_eq!(table(old), table(parse_xml(XOR).unwrap()));
+
+		assert_eq!(parse_xml(XOR).unwrap().schema_version, None);
+		assert_eq!(parse_xml(STYLED).unwrap().schema_version, Some(latest_schema_version));
+		let newer = parse_xml(&XOR.replace("<logicly>", r#"<logicly xmlns="http://logic.ly/2030">"#)).unwrap();
+		assert_eq!(newer.schema_version, Some(2030));
+		assert_eq!(newer.warnings, ["file is from a newer version of Logicly (schema 2030, newest known is 2011), some parts may be missing or misread"]);
+		let unknown = parse_xml(&XOR.replace("<logicly>", r#"<logicly xmlns="http://example.com/circuits">"#)).unwrap();
+		assert_eq!(unknown.schema_version, None);
+		assert_eq!(unknown.warnings, ["unknown namespace http://example.com/circuits, reading it as a Logicly file anyway"]);
+	}
+	#[test]
+	fn feedback_gates(){
+		let circuit = crate::circuit!{
+			in a, b;
+			g1 = and(a, g3); g2 = not(g1); g3 = or(b, g2);
+			h = xor(h, a);
+			out q = g3; out r = h; out s = not(g2);
+		};
+		assert_eq!(circuit.self_feedback_gates(), ["g1 -> g2 -> g3 -> g1", "h -> h"]);
+		assert!(parse_xml(crate::util::fixtures::RIPPLE_ADDER).unwrap().self_feedback_gates().is_empty());
+	}
+	#[test]
+	fn uid_generator(){
+		let mut uids = UidGenerator::new("gen");
+		assert_eq!([uids.next_uid(), uids.next_uid()], ["gen-0001", "gen-0002"]);
+		let mut circuit = crate::circuit!{ in a; out q = a; };
+		circuit.objects[1].uid = String::from("gen-0001");
+		let mut uids = UidGenerator::avoiding("gen", &circuit.objects);
+		assert_eq!(uids.next_uid(), "gen-0002");
+		// Generated uids are accepted by the parser like any other
+		let xml = crate::util::fixtures::gate_circuit("not", 1, None).replace(r#"uid="g""#, r#"uid="gen-0002""#).replace(r#"UID="g""#, r#"UID="gen-0002""#);
+		assert_eq!(parse_xml(&xml).unwrap().objects[1].uid(), "gen-0002");
+	}
+	#[test]
+	fn query_objects(){
+		let majority = parse_xml(crate::util::fixtures::MAJORITY).unwrap();
+		assert_eq!(majority.objects_of_kind(SimpleGateType::And).count(), 3);
+		assert_eq!(majority.objects_of_kind(SimpleGateType::Or).count(), 1);
+		assert_eq!(majority.objects_of_kind(SimpleGateType::Xor).count(), 0);
+		assert_eq!(majority.find_objects(Object::is_named_input).map(Object::export_name_or_uid).collect::<Vec<_>>(), ["a", "b", "c"]);
+		let half_adder = parse_xml(crate::util::fixtures::HALF_ADDER).unwrap();
+		let uuid = &half_adder.custom_library()[0].uid;
+		assert_eq!(half_adder.custom_instances(uuid).map(Object::uid).collect::<Vec<_>>(), ["ha"]);
+		assert_eq!(half_adder.custom_instances("nope").count(), 0);
+		assert_eq!((majority.named_input_count(), majority.named_output_count(), majority.custom_instance_count()), (3, 1, 0));
+		let adder = parse_xml(crate::util::fixtures::RIPPLE_ADDER).unwrap();
+		assert_eq!((adder.named_input_count(), adder.named_output_count(), adder.custom_instance_count()), (8, 5, 4));
+		assert_eq!((adder.custom_library()[0].named_input_count(), adder.custom_library()[0].named_output_count()), (3, 2));
+		let simul = crate::simul::Simulation::from(adder);
+		assert_eq!((simul.named_input_count(), simul.named_output_count(), simul.custom_instance_count()), (8, 5, 4));
+	}
+	#[test]
+	fn fix_duplicate_names(){
+		let mut raw = parse_raw_xml(crate::util::fixtures::MESSY).unwrap();
+		raw.objects.push(RawObject { uid: String::from("a3"), export_name: Some(String::from("a_2")), ..raw.objects[0].clone() });
+		raw.objects.push(RawObject { uid: String::from("a4"), ..raw.objects[0].clone() });
+		assert_eq!(raw.suffix_duplicate_names(), [
+			(None, String::from("a2"), String::from("a_3")),
+			(None, String::from("a4"), String::from("a_4")),
+		]);
+		let path = std::env::temp_dir().join(format!("logicly-rs-fix-{}.logicly", std::process::id()));
+		save_raw_circuit(&raw, &path).unwrap();
+		let loaded = load_raw_circuit(&path);
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(loaded.unwrap().to_xml(), raw.to_xml());
+	}
+	#[test]
+	fn circuit_to_raw(){
+		use crate::util::fixtures::{DIGIT, NESTED_ADDER, RIPPLE_ADDER, XOR};
+		for fixture in [XOR, DIGIT, RIPPLE_ADDER, NESTED_ADDER] {
+			let circuit = parse_xml(fixture).unwrap();
+			assert_eq!(parse_xml(&circuit.to_raw().unwrap().to_xml()).unwrap(), circuit);
 		}
+		let mut inverted = parse_xml(XOR).unwrap();
+		inverted.objects[2].set_input_inverted(0, true).unwrap();
+		assert_eq!(inverted.to_raw().unwrap_err(), "g has an inverted input, which cannot be saved");
+	}
+	#[test]
+	fn dangling_connections(){
+		use crate::util::fixtures::{DANGLING, HALF_ADDER};
+		let error = parse_xml(DANGLING).unwrap_err().to_string();
+		assert_eq!(error, "connection at line 7, column 2: UUID deleted does not correspond to any known object (output 0 of deleted to input 1 of g)");
+		let circuit = parse_xml_with(DANGLING, ParseMode::Lenient).unwrap();
+		assert_eq!(circuit.warnings, vec![format!("{error}, dropped it")]);
+		assert_eq!(circuit.objects[2].connections(), [Some((0, 0)), None]);
+
+		let inner = HALF_ADDER.replace(r#"<connection inputUID="a1" outputUID="b""#, r#"<connection inputUID="a1" outputUID="gone""#);
+		assert!(parse_xml(&inner).unwrap_err().to_string().starts_with("in custom circuit Half Adder: connection at line 23, column 4: UUID gone"));
+		let warnings = parse_xml_with(&inner, ParseMode::Lenient).unwrap().warnings;
+		assert_eq!(warnings.len(), 1);
+		assert!(warnings[0].starts_with("in custom circuit Half Adder: connection at line 23"));
+		assert!(parse_xml_with(crate::util::fixtures::XOR, ParseMode::Lenient).unwrap().warnings.is_empty());
+	}
+	#[test]
+	fn single_input_gates(){
+		use crate::util::fixtures::gate_circuit;
+		assert!(parse_xml(&gate_circuit("not", 1, None)).is_ok());
+		assert!(parse_xml(&gate_circuit("not", 2, None)).unwrap_err().to_string().contains("Not gates must have exactly one input, found 2"));
+		assert!(parse_xml(&gate_circuit("buffer", 2, None)).is_err());
+		let doubled = gate_circuit("and", 2, None).replace(r#"outputUID="i1" inputIndex="1""#, r#"outputUID="i1" inputIndex="0""#);
+		assert!(parse_xml(&doubled).unwrap_err().to_string().contains("input 0 of g is connected more than once"));
+		let missing = gate_circuit("and", 2, None).replace(r#"outputUID="i1" inputIndex="1""#, r#"outputUID="i1" inputIndex="2""#);
+		assert!(parse_xml(&missing).unwrap_err().to_string().contains("g has no input 2 (it has 2 inputs)"));
+		let beyond = gate_circuit("and", 2, None).replacen("<connection", r#"<connection inputUID="g" outputUID="i0" inputIndex="3" outputIndex="0"/><connection"#, 1);
+		let column = beyond.find("<connection").unwrap() + 1;
+		assert_eq!(parse_xml(&beyond).unwrap_err().to_string(), format!("connection at line 1, column {column}: Invalid connection: g has no input 3 (it has 2 inputs)"));
+	}
+	#[test]
+	fn merge_duplicate_customs(){
+		let original = "5f0b7c2e-8d7a-4c3e-9b61-2a1d0e4f3c10";
+		let copy = "0a9e3c1b-7d6f-4b2a-8e5c-1f3d2b4a6c80";
+		let custom = &crate::util::fixtures::HALF_ADDER[crate::util::fixtures::HALF_ADDER.find("<custom").unwrap()..];
+		let custom = &custom[..custom.find("</custom>").unwrap() + "</custom>".len()];
+		let xml = crate::util::fixtures::HALF_ADDER
+			.replacen(original, copy, 1)
+			.replace("</custom>", &format!("</custom>{}", custom.replace(original, copy).replace("Half Adder", "Half Adder (2)")));
+		let circuit = parse_xml(&xml).unwrap();
+		assert_eq!(circuit.custom_library().len(), 1);
+		assert_eq!(circuit.merged_customs, vec![(copy.to_string(), original.to_string())]);
+		assert!(matches!(&circuit.objects[2].inner, ObjectInner::CustomGate { uuid, .. } if uuid == original));
+		let mut simul: crate::simul::Simulation = circuit.into();
+		let mut expected: crate::simul::Simulation = parse_xml(crate::util::fixtures::HALF_ADDER).unwrap().into();
+		assert_eq!(simul.get_truth_table(100), expected.get_truth_table(100));
+	}
+	#[test]
+	fn edges(){
+		let edge = |from: &str, to: &str, input| Edge { from_uid: from.to_string(), from_output_index: 0, to_uid: to.to_string(), to_input_index: input };
+		let circuit = parse_xml(crate::util::fixtures::XOR).unwrap();
+		assert_eq!(circuit.edges(), vec![edge("a", "g", 0), edge("b", "g", 1), edge("g", "q", 0)]);
+		let mut circuit = parse_xml(crate::util::fixtures::HALF_ADDER).unwrap();
+		let edges = circuit.edges();
+		assert_eq!(edges.len(), circuit.objects.iter().map(|o| o.connections().iter().flatten().count()).sum::<usize>());
+		circuit.objects.iter_mut().for_each(|o| if let Some(c) = o.connections_mut() { c.clear() });
+		assert!(circuit.edges().is_empty());
+	}
+	#[test]
+	fn unconnected_outputs(){
+		let xml = crate::util::fixtures::gate_circuit("and", 2, None).replacen(
+			"<connection",
+			r#"<object type="light_bulb@logic.ly" uid="r" x="0" y="0" rotation="0" exportName="forgotten"/><connection"#,
+			1
+		);
+		let circuit = parse_xml(&xml).unwrap();
+		assert_eq!(circuit.unconnected_outputs(), vec!["forgotten"]);
+		assert!(parse_xml(crate::util::fixtures::XOR).unwrap().unconnected_outputs().is_empty());
 	}
 	#[test]
 	fn orderdeps_ordered_1(){
@@ -595,6 +1508,46 @@ mod tests {
 		assert_eq!(order_dependency_graph(deps.clone()), Err(format!("Circuit contains a dependency cycle: {} -> {} -> {}", a.uid, b.uid, a.uid)));
 	}
 	#[test]
+	fn orderdeps_undefined(){
+		// b is ordered before the cycle search starts, which used to loop forever on the slot it left
+		let a = make_circuit("a", vec!["b", "c"]);
+		let b = make_circuit("b", vec![]);
+		let deps = vec![b.clone(), a.clone()];
+		let error = order_dependency_graph(deps).unwrap_err();
+		assert_eq!(error, format!("Custom circuit {} uses custom circuit {}, which is not defined", a.uid, name_to_uuid("c")));
+	}
+	#[test]
+	fn malformed_files(){
+		// Each of these once made parsing panic or hang
+		let undefined_custom = crate::util::fixtures::HALF_ADDER.replace("</logicly>\n\t</custom>", r#"</logicly>
+		</custom>
+		<custom name="Broken" type="9d6c1e2a-1b3c-4d5e-8f70-a1b2c3d4e5f6">
+			<logicly><object type="0c0c0c0c-1b3c-4d5e-8f70-a1b2c3d4e5f6" uid="u" x="0" y="0" rotation="0"/></logicly>
+		</custom>"#);
+		assert!(undefined_custom.contains("Broken"));
+		let inputs: Vec<&[u8]> = vec![
+			undefined_custom.as_bytes(),
+			b"\x1f\x8b\x08\x00garbage",
+			b"\x78\x9cgarbage",
+		];
+		for input in inputs {
+			for mode in [ParseMode::Strict, ParseMode::Lenient] {
+				assert!(load_circuit_from_reader_with(input, mode).is_err(), "{}", String::from_utf8_lossy(input));
+			}
+		}
+	}
+	#[test]
+	fn custom_display_name(){
+		let circuit = parse_xml(crate::util::fixtures::HALF_ADDER).unwrap();
+		let mut custom = circuit.custom_library()[0].clone();
+		assert_eq!(custom.display_name(), "HA");
+		custom.label.clear();
+		assert_eq!(custom.display_name(), "Half Adder");
+		custom.name.clear();
+		assert_eq!(custom.display_name(), custom.uid);
+		assert!(circuit.to_string().contains("CustomGate HA [0, 1]"), "{circuit}");
+	}
+	#[test]
 	fn orderdeps_cycle_3(){
 		let a = make_circuit("a", vec!["b"]);
 		let b = make_circuit("b", vec!["c"]);
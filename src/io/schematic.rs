@@ -0,0 +1,192 @@
+//! Rough text drawings of circuits, for looking at them in a terminal.
+
+use std::collections::{HashMap, HashSet};
+
+use super::{Circuit, ObjectInner, SimpleGateType, Wire};
+
+/// Pixels in the file per column and row of the drawing. Rows are scaled more as characters are about twice as tall as they are wide.
+const column_width: f64 = 5.;
+const row_height: f64 = 10.;
+
+// Directions a wire leaves a cell in
+const left: u8 = 1;
+const right: u8 = 2;
+const up: u8 = 4;
+const down: u8 = 8;
+
+fn wire_char(directions: u8) -> char {
+	match directions {
+		0 => ' ',
+		1..=3 => '─',
+		4 | 8 | 12 => '│',
+		10 => '┌',
+		9 => '┐',
+		6 => '└',
+		5 => '┘',
+		11 => '┬',
+		7 => '┴',
+		14 => '├',
+		13 => '┤',
+		_ => '┼',
+	}
+}
+
+/// Where an object was drawn: the lines of its box, the row of its first line, and the column its lines start at.
+struct Placement {
+	lines: Vec<String>,
+	row: i64,
+	column: i64,
+}
+impl Placement {
+	fn width(&self) -> i64 {
+		self.lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as i64
+	}
+	/// The cells taken up by the box, and one cell of space on either side.
+	fn cells(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+		(self.row..self.row + self.lines.len() as i64).flat_map(|row| (self.column - 1..=self.column + self.width()).map(move |column| (row, column)))
+	}
+}
+
+impl Circuit {
+	/// Draws the top-level objects with box-drawing characters, placed on a grid scaled from their positions.
+	/// Gates are drawn as boxes with their IEC symbols, like `[&]` and `[≥1]`, followed by `o` if their output is negated.
+	/// Custom gates are boxes with the circuit name, inputs are `name>`, and outputs are `>name`.
+	/// Wires run from the right of their source to the left of the object they lead to, turning once halfway, and end in `o` if inverted.
+	/// The bend points saved in the file are not followed, and rotation is ignored.
+	/// Objects that would overlap are moved down until they fit, with a note below the drawing.
+	pub fn to_schematic(&self) -> String {
+		let names: HashMap<&str, &str> = self.custom_library().iter().map(|c| (&c.uid[..], c.display_name())).collect();
+		let mut placements: Vec<Placement> = vec![];
+		let mut taken = HashSet::new();
+		let mut notes = vec![];
+		for object in &self.objects {
+			let lines = match &object.inner {
+				ObjectInner::SimpleGate { kind, .. } => {
+					use SimpleGateType as S;
+					let symbol = match kind {
+						S::Buffer | S::Not => "1",
+						S::And | S::Nand => "&",
+						S::Or | S::Nor => "≥1",
+						S::Xor | S::Xnor => "=1",
+					};
+					let negated = matches!(kind, S::Not | S::Nand | S::Nor | S::Xnor);
+					vec![format!("[{symbol}]{}", if negated { "o" } else { "" })]
+				},
+				ObjectInner::CustomGate { uuid, .. } => vec![format!("[{}]", names.get(&uuid[..]).copied().unwrap_or(uuid))],
+				ObjectInner::Input { .. } => vec![format!("{}>", object.export_name_or_uid())],
+				ObjectInner::Output { .. } => vec![format!(">{}", object.export_name_or_uid())],
+				ObjectInner::Label { text } => text.lines().map(str::to_string).collect(),
+			};
+			let (x, y) = object.position();
+			let mut placement = Placement { lines, row: (y / row_height).round() as i64, column: 0 };
+			placement.column = (x / column_width).round() as i64 - placement.width() / 2;
+			let wanted = placement.row;
+			while placement.cells().any(|cell| taken.contains(&cell)) {
+				placement.row += 1;
+			}
+			if placement.row != wanted {
+				let moved = placement.row - wanted;
+				notes.push(format!("note: moved {} down {moved} {} to avoid overlapping another object", object.export_name_or_uid(), if moved == 1 { "row" } else { "rows" }));
+			}
+			taken.extend(placement.cells());
+			placements.push(placement);
+		}
+
+		// Each wire goes from the cell after its source to the cell before the object it leads to
+		let mut wires = vec![];
+		for Wire { source, target, inverted, .. } in super::wires(&self.objects) {
+			let from = (placements[source].row, placements[source].column + placements[source].width());
+			let to = (placements[target].row, placements[target].column - 1);
+			wires.push((from, to, inverted));
+		}
+		let min_row = placements.iter().map(|p| p.row).min().unwrap_or(0);
+		let min_column = placements.iter().map(|p| p.column - 1).min().unwrap_or(0);
+		let rows = placements.iter().map(|p| p.row + p.lines.len() as i64).max().unwrap_or(0) - min_row;
+		let columns = placements.iter().map(|p| p.column + p.width() + 1).max().unwrap_or(0) - min_column;
+		let mut directions = vec![vec![0u8; columns as usize]; rows as usize];
+		let mut mark = |(row, column): (i64, i64), direction: u8| directions[(row - min_row) as usize][(column - min_column) as usize] |= direction;
+		for &((from_row, from_column), (to_row, to_column), _) in &wires {
+			let middle = (from_column + to_column) / 2;
+			for (row, start, end) in [(from_row, from_column, middle), (to_row, middle, to_column)] {
+				for column in start.min(end)..=start.max(end) {
+					if column > start.min(end) { mark((row, column), left) }
+					if column < start.max(end) { mark((row, column), right) }
+				}
+			}
+			for row in from_row.min(to_row)..=from_row.max(to_row) {
+				if row > from_row.min(to_row) { mark((row, middle), up) }
+				if row < from_row.max(to_row) { mark((row, middle), down) }
+			}
+			mark((from_row, from_column), right);
+			mark((to_row, to_column), left);
+		}
+
+		let mut grid: Vec<Vec<char>> = directions.iter().map(|row| row.iter().map(|&d| wire_char(d)).collect()).collect();
+		for &(_, (row, column), inverted) in &wires {
+			if inverted {
+				grid[(row - min_row) as usize][(column - min_column) as usize] = 'o';
+			}
+		}
+		for placement in &placements {
+			for (k, line) in placement.lines.iter().enumerate() {
+				let row = &mut grid[(placement.row - min_row) as usize + k];
+				for (offset, c) in line.chars().enumerate() {
+					row[(placement.column - min_column) as usize + offset] = c;
+				}
+			}
+		}
+		let mut out = String::new();
+		for row in grid {
+			out += row.into_iter().collect::<String>().trim_end();
+			out += "\n";
+		}
+		for note in notes {
+			out += &note;
+			out += "\n";
+		}
+		out
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::io::*;
+	use crate::util::fixtures;
+
+	#[test]
+	fn schematic(){
+		let ripple_adder = parse_xml(fixtures::RIPPLE_ADDER).unwrap();
+		// The full adder has two xor, two and, and one or gate
+		let full_adder = Circuit { objects: ripple_adder.custom_library()[0].objects.clone(), ..ripple_adder.clone() };
+		assert_eq!(full_adder.to_schematic(), "  a>──────┬──────[=1]──────┬─────[=1]─────────────────────────────>s
+          │       │        │
+          │       │        │
+          │       │        │
+  b>──────┤       │        │
+          │       │        │
+          │       │        │
+          │       │        │
+ cin>─────┴───────[&]──────┴──────[&]────────────[≥1]────────────>cout
+");
+		let half_adder = parse_xml(fixtures::HALF_ADDER).unwrap();
+		assert_eq!(half_adder.to_schematic(), " x>──────┐               ┌──────>sum
+         │               │
+         ├──────[HA]─────┤
+         │               │
+ y>──────┘               └─────>carry
+");
+		let mut xor = parse_xml(fixtures::XOR).unwrap();
+		xor.objects[2].set_input_inverted(1, true).unwrap();
+		assert_eq!(xor.to_schematic(), " a>──────┐
+         │
+         ├─────o[=1]─────────────>q
+         │
+ b>──────┘
+");
+		// The switches for each pair of bits are at the same position
+		let schematic = ripple_adder.to_schematic();
+		assert!(schematic.starts_with(" a3>─────┬──────[FA]"), "{schematic}");
+		assert!(schematic.contains("\n b3>─────┘"), "{schematic}");
+		assert!(schematic.ends_with("note: moved b0 down 1 row to avoid overlapping another object\n"), "{schematic}");
+	}
+}
@@ -0,0 +1,196 @@
+//! SVG drawings of circuits, laid out using the positions and wire bend points saved in the file.
+
+use std::collections::HashMap;
+
+use quick_xml::escape::escape;
+
+use super::{Circuit, InputType, Object, ObjectInner, Rotation, SimpleGateType, Wire};
+
+/// Space left around the drawing.
+const margin: f64 = 40.;
+
+/// The shapes making up one object, drawn facing right and centered on (0, 0), and where its wires attach.
+struct Symbol {
+	body: String,
+	inputs: Vec<(f64, f64)>,
+	outputs: Vec<(f64, f64)>,
+}
+
+/// Positions of `count` pins spread evenly along the edge at `x`, which spans `height` centered on 0.
+fn pins(x: f64, count: usize, height: f64) -> Vec<(f64, f64)> {
+	(0..count).map(|k| (x, height * ((k as f64 + 0.5) / count as f64 - 0.5))).collect()
+}
+
+/// A text label at (`x`, `y`), with one line per line of `text`.
+fn text(x: f64, y: f64, text: &str) -> String {
+	let lines: Vec<&str> = text.lines().collect();
+	let first = -(lines.len() as f64 - 1.) / 2.;
+	let spans: String = lines.iter().enumerate().map(|(k, line)| {
+		format!("<tspan x=\"{x}\" dy=\"{}em\">{}</tspan>", if k == 0 { first } else { 1. }, escape(*line))
+	}).collect();
+	format!("<text x=\"{x}\" y=\"{y}\" text-anchor=\"middle\" dominant-baseline=\"middle\" fill=\"black\" stroke=\"none\">{spans}</text>")
+}
+
+impl Rotation {
+	fn degrees(self) -> f64 {
+		match self {
+			Rotation::Right => 0.,
+			Rotation::Down => 90.,
+			Rotation::Left => 180.,
+			Rotation::Up => 270.,
+		}
+	}
+}
+
+impl Object {
+	/// Converts a point on this object's symbol to a point in the circuit.
+	fn place(&self, (x, y): (f64, f64)) -> (f64, f64) {
+		let (ox, oy) = self.position();
+		let (x, y) = match self.rotation() {
+			Rotation::Right => (x, y),
+			Rotation::Down => (-y, x),
+			Rotation::Left => (-x, -y),
+			Rotation::Up => (y, -x),
+		};
+		(ox + x, oy + y)
+	}
+}
+
+impl Circuit {
+	fn symbol(&self, object: &Object, names: &HashMap<&str, &str>) -> Symbol {
+		match &object.inner {
+			ObjectInner::SimpleGate { kind, connections, .. } => {
+				use SimpleGateType as S;
+				let (shape, negated) = match kind {
+					S::Buffer | S::Not => ("<path d=\"M -20 -20 L 20 0 L -20 20 Z\"/>", *kind == S::Not),
+					S::And | S::Nand => ("<path d=\"M -20 -20 H 0 A 20 20 0 0 1 0 20 H -20 Z\"/>", *kind == S::Nand),
+					S::Or | S::Nor => ("<path d=\"M -20 -20 Q 5 -20 20 0 Q 5 20 -20 20 Q -10 0 -20 -20 Z\"/>", *kind == S::Nor),
+					S::Xor | S::Xnor => ("<path d=\"M -20 -20 Q 5 -20 20 0 Q 5 20 -20 20 Q -10 0 -20 -20 Z\"/><path d=\"M -26 -20 Q -16 0 -26 20\" fill=\"none\"/>", *kind == S::Xnor),
+				};
+				let mut body = shape.to_string();
+				if negated {
+					body += "<circle cx=\"24\" cy=\"0\" r=\"4\"/>";
+				}
+				Symbol { body, inputs: pins(-20., connections.len(), 30.), outputs: vec![(if negated { 28. } else { 20. }, 0.)] }
+			},
+			ObjectInner::CustomGate { uuid, num_outputs, connections, .. } => {
+				let height = 20. * connections.len().max(*num_outputs as usize).max(1) as f64;
+				let name = names.get(&uuid[..]).copied().unwrap_or(uuid);
+				let body = format!("<rect x=\"-30\" y=\"{}\" width=\"60\" height=\"{height}\"/>{}", -height / 2., text(0., 0., name));
+				Symbol { body, inputs: pins(-30., connections.len(), height), outputs: pins(30., *num_outputs as usize, height) }
+			},
+			ObjectInner::Input { kind, value, .. } => {
+				let (corner, value) = match kind {
+					InputType::Switch => (0, *value),
+					InputType::Button => (8, *value),
+					InputType::True => (0, true),
+					InputType::False => (0, false),
+				};
+				let body = format!("<rect x=\"-15\" y=\"-15\" width=\"30\" height=\"30\" rx=\"{corner}\"/>{}{}",
+					text(0., 0., if value { "1" } else { "0" }), text(0., 26., object.export_name_or_uid()));
+				Symbol { body, inputs: vec![], outputs: vec![(15., 0.)] }
+			},
+			ObjectInner::Output { connections, .. } => {
+				let body = format!("<circle cx=\"0\" cy=\"0\" r=\"15\"/>{}", text(0., 26., object.export_name_or_uid()));
+				Symbol { body, inputs: pins(-15., connections.len(), 30.), outputs: vec![] }
+			},
+			ObjectInner::Label { text: label } => Symbol { body: text(0., 0., label), inputs: vec![], outputs: vec![] },
+		}
+	}
+
+	/// Draws the top-level objects as an SVG image, at the positions and rotations saved in the file.
+	/// Gates are drawn with their usual symbols, custom gates as boxes labeled with the circuit name, switches as squares
+	/// showing their saved value, light bulbs as circles, and labels as text.
+	/// Wires are polylines through the bend points saved in the file.
+	pub fn to_svg(&self) -> String {
+		self.to_svg_with_values(|_, _| None)
+	}
+
+	/// Like [`Circuit::to_svg`], but colors each wire green or grey by its value.
+	/// `value(i, output)` gives the value of output `output` of object `i`, or None to draw its wires black.
+	pub fn to_svg_with_values(&self, value: impl Fn(usize, u32) -> Option<bool>) -> String {
+		let names: HashMap<&str, &str> = self.custom_library().iter().map(|c| (&c.uid[..], c.display_name())).collect();
+		let symbols: Vec<Symbol> = self.objects.iter().map(|o| self.symbol(o, &names)).collect();
+		let mut wires = String::new();
+		let mut points = vec![];
+		for Wire { source, output, target, input, .. } in super::wires(&self.objects) {
+			let (source_object, object) = (&self.objects[source], &self.objects[target]);
+			let start = symbols[source].outputs.get(output as usize).copied().unwrap_or_default();
+			let end = symbols[target].inputs[input as usize];
+			let bends = self.wire_points.get(&(object.uid().to_string(), input)).map(Vec::as_slice).unwrap_or_default();
+			let line: Vec<(f64, f64)> = [source_object.place(start)].into_iter().chain(bends.iter().copied()).chain([object.place(end)]).collect();
+			let color = match value(source, output) {
+				Some(true) => "green",
+				Some(false) => "grey",
+				None => "black",
+			};
+			let line_points = line.iter().map(|(x, y)| format!("{x},{y}")).collect::<Vec<_>>().join(" ");
+			wires += &format!("<polyline points=\"{line_points}\" fill=\"none\" stroke=\"{color}\" stroke-width=\"2\"/>\n");
+			points.extend(line);
+		}
+		points.extend(self.objects.iter().map(Object::position));
+		let min_x = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min) - margin;
+		let min_y = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min) - margin;
+		let max_x = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max) + margin;
+		let max_y = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max) + margin;
+		let (min_x, min_y, width, height) = if points.is_empty() { (0., 0., 0., 0.) } else { (min_x, min_y, max_x - min_x, max_y - min_y) };
+		let mut out = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{min_x} {min_y} {width} {height}\" font-family=\"sans-serif\" font-size=\"10\">\n");
+		out += &wires;
+		for (object, symbol) in self.objects.iter().zip(&symbols) {
+			let (x, y) = object.position();
+			out += &format!("<g class=\"symbol\" transform=\"translate({x} {y}) rotate({})\" fill=\"white\" stroke=\"black\">{}</g>\n", object.rotation().degrees(), symbol.body);
+		}
+		out + "</svg>\n"
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use quick_xml::{Reader, events::Event};
+
+	use crate::io::*;
+	use crate::util::fixtures;
+
+	/// Returns the name and attributes of every element in `svg`, failing if it is not well-formed XML.
+	fn elements(svg: &str) -> Vec<(String, Vec<(String, String)>)> {
+		let mut reader = Reader::from_str(svg);
+		reader.config_mut().check_end_names = true;
+		let mut elements = vec![];
+		loop {
+			match reader.read_event().unwrap() {
+				Event::Start(e) | Event::Empty(e) => {
+					let attributes = e.attributes().map(|a| {
+						let a = a.unwrap();
+						(a.key.into_inner().to_string(), quick_xml::escape::unescape(&a.value).unwrap().into_owned())
+					}).collect();
+					elements.push((e.name().into_inner().to_string(), attributes));
+				},
+				Event::Eof => break elements,
+				_ => {},
+			}
+		}
+	}
+	fn attribute<'a>(element: &'a (String, Vec<(String, String)>), name: &str) -> Option<&'a str> {
+		element.1.iter().find(|(key, _)| key == name).map(|(_, value)| &value[..])
+	}
+
+	#[test]
+	fn svg(){
+		let styled = parse_xml(fixtures::STYLED).unwrap();
+		let svg = styled.to_svg();
+		let parsed = elements(&svg);
+		let symbols: Vec<_> = parsed.iter().filter(|e| attribute(e, "class") == Some("symbol")).collect();
+		assert_eq!(symbols.len(), 5);
+		assert_eq!(attribute(symbols[2], "transform"), Some("translate(80 20) rotate(90)"));
+		let wires: Vec<_> = parsed.iter().filter(|e| e.0 == "polyline").collect();
+		assert_eq!(wires.len(), 3);
+		// The wire from a goes through its bend points to the first input of g, which is rotated to face down
+		assert_eq!(attribute(wires[0], "points"), Some("15,0 40,0 40,20 90,-10"));
+		assert!(svg.contains(">X</tspan>"), "{svg}");
+		assert!(svg.contains(">&quot;two&quot; &amp; three</tspan>"), "{svg}");
+
+		let svg = styled.to_svg_with_values(|i, _| (i == 0).then_some(true));
+		let colors: Vec<_> = elements(&svg).iter().filter(|e| e.0 == "polyline").map(|e| attribute(e, "stroke").unwrap().to_string()).collect();
+		assert_eq!(colors, ["green", "black", "black"]);
+	}
+}
@@ -0,0 +1,454 @@
+use std::fmt::{Display, Write};
+use std::io::{BufRead, Read};
+use std::str::FromStr;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use super::encoding;
+use super::{CustomCircuitWrapper, Location, RawCircuit, RawConnection, RawCustomCircuit, RawObject, Setting};
+
+/// A line and column in the XML source, both starting at 1.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SourcePosition {
+	pub line: usize,
+	pub column: usize,
+}
+impl Display for SourcePosition {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "line {}, column {}", self.line, self.column)
+	}
+}
+
+/// An element that this crate does not model, kept so that it can be written back out.
+/// Text content is not kept, as Logicly does not use it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct XmlElement {
+	pub name: String,
+	pub attributes: Vec<(String, String)>,
+	pub children: Vec<XmlElement>,
+}
+
+/// The attributes and child elements of a modeled element that this crate does not understand,
+/// such as styling or view settings, in the order they were written.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Unknown {
+	pub attributes: Vec<(String, String)>,
+	pub elements: Vec<XmlElement>,
+}
+
+/// Wraps a reader, remembering where each line starts so that byte offsets can be turned into positions.
+struct LineCounter<R> {
+	inner: R,
+	offset: u64,
+	line_starts: Vec<u64>,
+}
+impl<R: BufRead> LineCounter<R> {
+	fn new(inner: R) -> Self {
+		Self { inner, offset: 0, line_starts: vec![0] }
+	}
+	fn position(&self, offset: u64) -> SourcePosition {
+		let line = self.line_starts.partition_point(|&start| start <= offset);
+		SourcePosition { line, column: (offset - self.line_starts[line - 1]) as usize + 1 }
+	}
+}
+impl<R: BufRead> Read for LineCounter<R> {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let available = self.fill_buf()?;
+		let len = available.len().min(buf.len());
+		buf[..len].copy_from_slice(&available[..len]);
+		self.consume(len);
+		Ok(len)
+	}
+}
+impl<R: BufRead> BufRead for LineCounter<R> {
+	fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+		self.inner.fill_buf()
+	}
+	fn consume(&mut self, amount: usize) {
+		if let Ok(buf) = self.inner.fill_buf() {
+			for (i, _) in buf[..amount].iter().enumerate().filter(|(_, b)| **b == b'\n') {
+				self.line_starts.push(self.offset + i as u64 + 1);
+			}
+		}
+		self.offset += amount as u64;
+		self.inner.consume(amount);
+	}
+}
+
+/// Reads the elements of a .logicly file.
+/// Unknown elements and attributes are kept in [`Unknown`]. Attributes are matched by their full name, including any prefix.
+struct Parser<R> {
+	reader: Reader<LineCounter<R>>,
+	buf: Vec<u8>,
+}
+
+/// The attributes of one element, and where it starts.
+struct Element {
+	name: String,
+	position: SourcePosition,
+	/// In the order they were written.
+	attributes: Vec<(String, String)>,
+	/// False for empty elements like `<object/>`.
+	has_children: bool,
+}
+impl Element {
+	fn error(&self, message: impl Display) -> String {
+		format!("{} at {}: {message}", self.name, self.position)
+	}
+	fn optional<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+		self.attributes.iter().find(|(n, _)| n == name).map(|(_, value)| value.parse()
+			.map_err(|_| self.error(format!("invalid value for attribute {name}: {value}")))
+		).transpose()
+	}
+	fn required<T: FromStr>(&self, name: &str) -> Result<T, String> {
+		self.optional(name)?.ok_or_else(|| self.error(format!("missing attribute {name}")))
+	}
+}
+
+enum Item {
+	Start(Element),
+	End,
+	Eof,
+}
+
+impl<R: BufRead> Parser<R> {
+	fn new(input: R) -> Self {
+		Self {
+			reader: Reader::from_reader(LineCounter::new(input)),
+			buf: vec![],
+		}
+	}
+	fn position(&self, offset: u64) -> SourcePosition {
+		self.reader.get_ref().position(offset)
+	}
+	/// Reads until the next start tag, end tag, or the end of the file.
+	fn next(&mut self) -> Result<Item, String> {
+		loop {
+			let offset = self.reader.buffer_position();
+			self.buf.clear();
+			let event = match self.reader.read_event_into(&mut self.buf) {
+				Ok(event) => event,
+				Err(quick_xml::Error::Io(e)) => return Err(format!("Error reading input at {}: {e}", self.position(offset))),
+				Err(e) => return Err(format!("invalid XML at {}: {e}", self.position(self.reader.error_position()))),
+			};
+			let (start, has_children) = match event {
+				Event::Start(start) => (start, true),
+				Event::Empty(start) => (start, false),
+				Event::End(_) => return Ok(Item::End),
+				Event::Eof => return Ok(Item::Eof),
+				Event::Decl(decl) => {
+					if let Some(Ok(encoding)) = decl.encoding() {
+						encoding::check_declared_encoding(&encoding)
+							.map_err(|e| format!("{e} (at {})", self.reader.get_ref().position(offset)))?;
+					}
+					continue;
+				},
+				_ => continue,
+			};
+			let position = self.reader.get_ref().position(offset);
+			return Ok(Item::Start(Parser::<R>::element(&start, position, has_children)?));
+		}
+	}
+	fn element(start: &BytesStart, position: SourcePosition, has_children: bool) -> Result<Element, String> {
+		let name = start.name().into_inner().to_string();
+		let mut attributes = vec![];
+		for attribute in start.attributes() {
+			let attribute = attribute.map_err(|e| format!("invalid XML at {position}: {e}"))?;
+			// Whitespace is kept as written instead of being normalized to spaces, so that multi-line labels survive.
+			// Line endings are still normalized first, so a literal line break always reads as "\n".
+			let raw = attribute.value.replace("\r\n", "\n").replace('\r', "\n");
+			let value = quick_xml::escape::unescape(&raw)
+				.map_err(|e| format!("invalid XML at {position}: {e}"))?;
+			attributes.push((attribute.key.into_inner().to_string(), value.into_owned()));
+		}
+		Ok(Element { name, position, attributes, has_children })
+	}
+	/// Reads the children of an element that was just read, keeping it as an [`XmlElement`].
+	fn capture(&mut self, element: Element) -> Result<XmlElement, String> {
+		let mut children = vec![];
+		self.children(&element, |parser, child| {
+			children.push(parser.capture(child)?);
+			Ok(())
+		})?;
+		Ok(XmlElement { name: element.name, attributes: element.attributes, children })
+	}
+	/// Reads the children of an element that was just read, none of which are modeled,
+	/// and keeps them along with the attributes not listed in `known`.
+	fn unknown(&mut self, element: &Element, known: &[&str]) -> Result<Unknown, String> {
+		let mut elements = vec![];
+		self.children(element, |parser, child| {
+			elements.push(parser.capture(child)?);
+			Ok(())
+		})?;
+		Ok(Unknown { attributes: unknown_attributes(element, known), elements })
+	}
+	/// Calls `f` on each child of an element that was just read.
+	fn children(&mut self, element: &Element, mut f: impl FnMut(&mut Self, Element) -> Result<(), String>) -> Result<(), String> {
+		if !element.has_children { return Ok(()) }
+		loop {
+			match self.next()? {
+				Item::Start(child) => f(self, child)?,
+				Item::End => return Ok(()),
+				Item::Eof => return Err(element.error("unexpected end of file")),
+			}
+		}
+	}
+
+	fn circuit(&mut self) -> Result<RawCircuit, String> {
+		let root = loop {
+			match self.next()? {
+				Item::Start(element) if element.name == "logicly" => break element,
+				Item::Start(element) => return Err(element.error("expected a logicly element")),
+				Item::End => continue,
+				Item::Eof => return Err(format!("invalid XML at {}: no logicly element", self.position(self.reader.buffer_position()))),
+			}
+		};
+		let mut circuit = RawCircuit {
+			xmlns: root.optional("xmlns")?,
+			objects: vec![],
+			connections: vec![],
+			settings: vec![],
+			customs: None,
+			unknown: Unknown { attributes: unknown_attributes(&root, &["xmlns"]), elements: vec![] },
+		};
+		self.children(&root, |parser, element| {
+			match &element.name[..] {
+				"object" => circuit.objects.push(parser.object(&element)?),
+				"connection" => circuit.connections.push(parser.connection(&element)?),
+				"setting" => circuit.settings.push(Setting {
+					name: element.required("name")?,
+					value: element.required("value")?,
+					unknown: parser.unknown(&element, &["name", "value"])?,
+				}),
+				"custom" => {
+					let custom = parser.custom(&element)?;
+					circuit.customs.get_or_insert_default().push(custom);
+				},
+				_ => circuit.unknown.elements.push(parser.capture(element)?),
+			}
+			Ok(())
+		})?;
+		Ok(circuit)
+	}
+	fn custom(&mut self, element: &Element) -> Result<CustomCircuitWrapper, String> {
+		let mut inner = None;
+		let mut unknown = Unknown { attributes: unknown_attributes(element, &["name", "type", "label"]), elements: vec![] };
+		self.children(element, |parser, child| {
+			if child.name != "logicly" {
+				unknown.elements.push(parser.capture(child)?);
+				return Ok(());
+			}
+			let mut custom = RawCustomCircuit {
+				objects: vec![],
+				connections: vec![],
+				locations: vec![],
+				unknown: Unknown { attributes: unknown_attributes(&child, &[]), elements: vec![] },
+			};
+			parser.children(&child, |parser, element| {
+				match &element.name[..] {
+					"object" => custom.objects.push(parser.object(&element)?),
+					"connection" => custom.connections.push(parser.connection(&element)?),
+					"location" => custom.locations.push(Location {
+						id: element.required("id")?,
+						uids: element.required("uids")?,
+						unknown: parser.unknown(&element, &["id", "uids"])?,
+					}),
+					_ => custom.unknown.elements.push(parser.capture(element)?),
+				}
+				Ok(())
+			})?;
+			inner = Some(custom);
+			Ok(())
+		})?;
+		Ok(CustomCircuitWrapper {
+			name: element.required("name")?,
+			uid: element.required("type")?,
+			label: element.required("label")?,
+			inner: inner.ok_or_else(|| element.error("missing logicly element"))?,
+			unknown,
+		})
+	}
+	fn object(&mut self, element: &Element) -> Result<RawObject, String> {
+		Ok(RawObject {
+			kind: element.required("type")?,
+			uid: element.required("uid")?,
+			x: element.required("x")?,
+			y: element.required("y")?,
+			// Older versions of Logicly leave out the rotation of objects that are not rotated
+			rotation: element.optional("rotation")?.unwrap_or(0),
+			export_name: element.optional("exportName")?,
+			outputs: element.optional("outputs")?,
+			inputs: element.optional("inputs")?,
+			text: element.optional("text")?,
+			function_index: element.optional("functionIndex")?,
+			position: element.position,
+			unknown: self.unknown(element, object_attributes)?,
+		})
+	}
+	fn connection(&mut self, element: &Element) -> Result<RawConnection, String> {
+		Ok(RawConnection {
+			input_uid: element.required("inputUID")?,
+			output_uid: element.required("outputUID")?,
+			input_index: element.required("inputIndex")?,
+			output_index: element.required("outputIndex")?,
+			points: element.optional("points")?,
+			position: element.position,
+			unknown: self.unknown(element, connection_attributes)?,
+		})
+	}
+}
+
+const object_attributes: &[&str] = &["type", "uid", "x", "y", "rotation", "exportName", "outputs", "inputs", "text", "functionIndex"];
+const connection_attributes: &[&str] = &["inputUID", "outputUID", "inputIndex", "outputIndex", "points"];
+
+fn unknown_attributes(element: &Element, known: &[&str]) -> Vec<(String, String)> {
+	element.attributes.iter().filter(|(name, _)| !known.contains(&&name[..])).cloned().collect()
+}
+
+/// Reads the structure of a .logicly file, without checking that it makes sense.
+/// The input may be UTF-8 or UTF-16, with or without a byte order mark.
+pub fn read_raw_circuit<'a>(input: impl BufRead + 'a) -> Result<RawCircuit, String> {
+	Parser::new(encoding::utf8_reader(input)?).circuit()
+}
+
+impl RawCircuit {
+	/// Writes the circuit back out as the XML of a .logicly file, including any unknown attributes and elements.
+	/// Known attributes come first, in the order Logicly writes them, followed by unknown ones in their original order.
+	pub fn to_xml(&self) -> String {
+		let mut writer = Writer { out: String::new(), depth: 0 };
+		let root = self.xmlns.as_ref().map(|xmlns| ("xmlns", xmlns.clone()));
+		writer.element("logicly", root, &self.unknown.attributes, |writer| {
+			for object in &self.objects { writer.object(object) }
+			for connection in &self.connections { writer.connection(connection) }
+			for setting in &self.settings {
+				writer.element("setting", [("name", setting.name.clone()), ("value", setting.value.clone())], &setting.unknown.attributes, |writer| {
+					writer.elements(&setting.unknown.elements);
+				});
+			}
+			writer.elements(&self.unknown.elements);
+			for custom in self.customs.iter().flatten() {
+				let attributes = [("name", custom.name.clone()), ("type", custom.uid.clone()), ("label", custom.label.clone())];
+				writer.element("custom", attributes, &custom.unknown.attributes, |writer| {
+					let inner = &custom.inner;
+					writer.element("logicly", [], &inner.unknown.attributes, |writer| {
+						for object in &inner.objects { writer.object(object) }
+						for connection in &inner.connections { writer.connection(connection) }
+						for location in &inner.locations {
+							writer.element("location", [("id", location.id.clone()), ("uids", location.uids.clone())], &location.unknown.attributes, |writer| {
+								writer.elements(&location.unknown.elements);
+							});
+						}
+						writer.elements(&inner.unknown.elements);
+					});
+					writer.elements(&custom.unknown.elements);
+				});
+			}
+		});
+		writer.out
+	}
+}
+
+/// Writes elements indented with tabs, one per line.
+struct Writer {
+	out: String,
+	depth: usize,
+}
+impl Writer {
+	fn element<'a>(
+		&mut self, name: &str, attributes: impl IntoIterator<Item = (&'a str, String)>, unknown: &'a [(String, String)],
+		children: impl FnOnce(&mut Self),
+	){
+		let indent = "\t".repeat(self.depth);
+		write!(self.out, "{indent}<{name}").unwrap();
+		let unknown = unknown.iter().map(|(name, value)| (&name[..], value.clone()));
+		for (name, value) in attributes.into_iter().chain(unknown) {
+			write!(self.out, " {name}=\"{}\"", escape_attribute(&value)).unwrap();
+		}
+		let end = self.out.len();
+		self.out += ">\n";
+		self.depth += 1;
+		children(self);
+		self.depth -= 1;
+		if self.out.len() == end + 2 {
+			self.out.truncate(end);
+			self.out += "/>\n";
+		} else {
+			writeln!(self.out, "{indent}</{name}>").unwrap();
+		}
+	}
+	fn elements(&mut self, elements: &[XmlElement]) {
+		for element in elements {
+			self.element(&element.name, [], &element.attributes, |writer| writer.elements(&element.children));
+		}
+	}
+	fn object(&mut self, object: &RawObject) {
+		let attributes = [
+			Some(("type", object.kind.clone())),
+			Some(("uid", object.uid.clone())),
+			Some(("x", object.x.to_string())),
+			Some(("y", object.y.to_string())),
+			Some(("rotation", object.rotation.to_string())),
+			object.export_name.clone().map(|v| ("exportName", v)),
+			object.outputs.clone().map(|v| ("outputs", v)),
+			object.inputs.map(|v| ("inputs", v.to_string())),
+			object.text.clone().map(|v| ("text", v)),
+			object.function_index.map(|v| ("functionIndex", v.to_string())),
+		];
+		self.element("object", attributes.into_iter().flatten(), &object.unknown.attributes, |writer| {
+			writer.elements(&object.unknown.elements);
+		});
+	}
+	fn connection(&mut self, connection: &RawConnection) {
+		let attributes = [
+			Some(("inputUID", connection.input_uid.clone())),
+			Some(("outputUID", connection.output_uid.clone())),
+			Some(("inputIndex", connection.input_index.to_string())),
+			Some(("outputIndex", connection.output_index.to_string())),
+			connection.points.clone().map(|v| ("points", v)),
+		];
+		self.element("connection", attributes.into_iter().flatten(), &connection.unknown.attributes, |writer| {
+			writer.elements(&connection.unknown.elements);
+		});
+	}
+}
+
+/// Escapes an attribute value, including whitespace that a reader would otherwise normalize to spaces.
+fn escape_attribute(value: &str) -> String {
+	quick_xml::escape::escape(value).replace('\n', "&#10;").replace('\r', "&#13;").replace('\t', "&#9;")
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::io::xml::*;
+	use crate::io::parse_raw_xml;
+	use crate::util::fixtures;
+
+	/// Reads a whole document as a tree, with attributes and children sorted so that order is ignored.
+	fn tree(xml: &str) -> XmlElement {
+		fn sort(element: &mut XmlElement) {
+			element.attributes.sort();
+			element.children.iter_mut().for_each(sort);
+			element.children.sort_by(|a, b| (&a.name, &a.attributes).cmp(&(&b.name, &b.attributes)));
+		}
+		let mut parser = Parser::new(xml.as_bytes());
+		let Ok(Item::Start(root)) = parser.next() else { panic!("no root element") };
+		let mut root = parser.capture(root).unwrap();
+		sort(&mut root);
+		root
+	}
+
+	#[test]
+	fn round_trip(){
+		for xml in [fixtures::STYLED, fixtures::XOR, fixtures::HALF_ADDER, fixtures::RIPPLE_ADDER] {
+			let written = parse_raw_xml(xml).unwrap().to_xml();
+			assert_eq!(tree(&written), tree(xml), "{written}");
+			assert_eq!(parse_raw_xml(&written).unwrap().to_xml(), written);
+		}
+		let raw = parse_raw_xml(fixtures::STYLED).unwrap();
+		assert_eq!(raw.objects[4].text.as_deref(), Some("Line one\n\"two\" & three"));
+		assert_eq!(raw.unknown.attributes, [("version".to_string(), "4.2.1".to_string()), ("zoom".to_string(), "1.5".to_string())]);
+		assert_eq!(raw.objects[2].unknown.elements[0].name, "style");
+		assert!(crate::io::parse_xml(fixtures::STYLED).is_ok());
+	}
+}
@@ -0,0 +1,37 @@
+//! A parser and simulator for [Logicly](https://logic.ly/) files.
+//!
+//! ```no_run
+//! use logicly_rs::{io::load_circuit, simul::Simulation};
+//!
+//! let circuit = load_circuit("adder.logicly")?;
+//! let mut simulation: Simulation = circuit.into();
+//! let table = simulation.get_truth_table(1000).expect("circuit was unstable");
+//! println!("{} rows", table.num_rows());
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! # Features
+//!
+//! | Feature   | Default | Enables |
+//! |-----------|---------|---------|
+//! | `cli`     | yes     | The `logicly-rs` command, and its dependencies on clap, clap_complete, ctrlc, indicatif, and serde_json. Implies `zlib-ng`. |
+//! | `zlib-ng` | with `cli` | Decompresses files with zlib-ng, a C library, instead of the pure Rust backend of flate2. |
+//! | `archive` | no      | Reading circuits out of .zip archives, with `io::archive::read_circuit_from_archive`. |
+//! | `testing` | no      | The fixtures and circuit generators in `util::fixtures`, for testing code that uses this crate. |
+//!
+//! With `--no-default-features`, the library only depends on anyhow, flate2, itertools, quick-xml, serde, sha2, and uuid,
+//! and does not need a C compiler, so it can be built for targets like WebAssembly.
+#![allow(dead_code)]
+#![allow(non_upper_case_globals)]
+
+pub mod analyze;
+pub mod batch;
+pub mod compose;
+pub mod io;
+pub mod opt;
+pub mod service;
+pub mod simul;
+pub mod synth;
+pub mod util;
+pub mod validate;
+pub mod verify;
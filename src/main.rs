@@ -1,42 +1,699 @@
-#![allow(dead_code)]
-#![allow(non_upper_case_globals)]
+use std::{collections::HashMap, fs::File, io::IsTerminal, path::PathBuf, process::ExitCode, sync::Mutex};
+
 use anyhow::{Context, Result, anyhow};
-use std::{env::args, fs::File, io::Read};
-
-use crate::{io::parse_xml, simul::Simulation};
-
-mod io;
-mod simul;
-mod util;
-
-fn main() -> Result<()> {
-	let arg = args()
-		.nth(1)
-		.ok_or(anyhow!("Please specify the filename"))?;
-	let file = File::open(arg).context("Error reading file")?;
-	let mut decompressed = String::new();
-	flate2::read::DeflateDecoder::new(file)
-		.read_to_string(&mut decompressed)
-		.context("Error decompressing file")?;
-	// println!("{}", decompressed);
-	let parsed = parse_xml(&decompressed)?;
-	// println!("{parsed}");
-	let mut simul: Simulation = parsed.into();
-	// println!("{simul}");
-
-	// simul.get_outputs(HashMap::from_iter([("x", false), ("y", false)].into_iter()), 100);
-	simul.print_truth_table(1000);
-	// for (i, line) in simul.get_truth_table(1000).ok_or(anyhow!("circuit was unstable"))?.iter().enumerate() {
-	// 	let bits = int_to_bits(i, 8);
-	// 	let a = bits_to_int(bits[0..4].iter());
-	// 	let b = bits_to_int(bits[4..8].iter());
-	// 	let c = bits_to_int(line.iter().rev());
-	// 	if a + b == c {
-	// 		print!("✅");
-	// 	} else {
-	// 		println!("{a} + {b} != {c}");
-	// 	}
-	// }
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use itertools::Itertools;
+
+use logicly_rs::{batch, simul, io::{Circuit, Object, ParseMode, load_circuit_with, load_raw_circuit, save_raw_circuit}, validate::{LintConfig, Severity}, opt::optimize, simul::{BoolExpr, Breakpoint, CacheStats, CancellationToken, Cancelled, ConvergenceStats, ProgressCallback, ProgressEvent, Simulation, SimulationConfig, TruthTable, default_cache_dir, format_output_changes, format_state, run_until_break}, util::{bit_string, int_to_bits}, synth::{Technology, gate_count, map_expr, map_technology}, verify::{self, Bus}};
+
+/// Exit status after Ctrl+C stopped a computation, the same as shells use for a process killed by SIGINT.
+const CANCELLED_EXIT_CODE: u8 = 130;
+
+#[derive(Parser)]
+#[command(version, about = "A parser and simulator for logicly files", after_help = "Ctrl+C stops truth-table, verify, batch-check, and analyze at the next row, \
+prints what was computed so far, and exits with status 130. Press it again to exit at once.")]
+struct Cli {
+	#[command(subcommand)]
+	command: Command,
+	/// Drop connections to objects that do not exist instead of failing, and print a warning for each one
+	#[arg(long, global = true)]
+	lenient: bool,
+	/// Store the truth tables of custom circuits in this directory, and reuse them in later runs.
+	/// Without a directory, uses $XDG_CACHE_HOME/logicly-rs or ~/.cache/logicly-rs
+	#[arg(long, global = true, value_name = "DIR", num_args = 0..=1)]
+	cache_dir: Option<Option<PathBuf>>,
+	/// Fail instead of computing a truth table that would need more memory than this, in bytes. Does not apply to custom circuit tables
+	#[arg(long, global = true, value_name = "BYTES", default_value_t = 1 << 20)]
+	max_table_bytes: usize,
+}
+
+#[derive(Subcommand)]
+enum Command {
+	/// Prints the truth table of a circuit
+	TruthTable {
+		file: PathBuf,
+		#[arg(long, value_enum, default_value_t = TableFormat::Table)]
+		format: TableFormat,
+		/// Maximum number of terms listed per output by `--format minterms`
+		#[arg(long, default_value_t = 64)]
+		max_terms: usize,
+		/// Maximum number of update iterations per row
+		#[arg(long, default_value_t = 1000)]
+		limit: u128,
+		/// Append a column with the parity (XOR) of each row's outputs
+		#[arg(long)]
+		parity: bool,
+		/// Fail if any output has nothing connected to it
+		#[arg(long)]
+		strict: bool,
+		/// Also print the truth table of every custom circuit
+		#[arg(long)]
+		customs: bool,
+		/// Print a histogram of how many iterations each row took to stabilize
+		#[arg(long)]
+		stats: bool,
+		/// With --stats, also print the propagation sequence of the slowest row
+		#[arg(long, requires = "stats")]
+		trace_slowest: bool,
+		/// Also write the truth table to this file, in a binary format that `verify --against` can read
+		#[arg(long, value_name = "FILE")]
+		save_table: Option<PathBuf>,
+		/// Treat each row as stable once no output has changed for this many iterations, even if other gates are still changing.
+		/// Use more than the longest path from an input to an output, or some rows may be read too early
+		#[arg(long, value_name = "N")]
+		output_passes: Option<u32>,
+		/// Instead of the table, list the inputs of the rows where a condition on the outputs holds, like `carry==1 && sum0==0`
+		#[arg(long, value_name = "CONDITION")]
+		find: Option<String>,
+		/// Maximum number of rows listed by --find
+		#[arg(long, default_value_t = 10, requires = "find")]
+		max_rows: usize,
+	},
+	/// Checks that a circuit has the same truth table as one saved by `truth-table --save-table`,
+	/// or that a sequential circuit behaves as a counter or a register
+	Verify {
+		file: PathBuf,
+		#[arg(long, value_name = "FILE", required_unless_present_any = ["counter", "register"],
+			conflicts_with_all = ["modulus", "reset", "enable", "cycles", "clock"])]
+		against: Option<PathBuf>,
+		/// Check that the outputs PREFIX0, PREFIX1... count up by one every clock cycle, like `q:4` for q0 to q3
+		#[arg(long, value_name = "PREFIX:WIDTH", requires = "modulus", conflicts_with_all = ["against", "register"])]
+		counter: Option<Bus>,
+		/// With --counter, the number of values the counter goes through before going back to 0
+		#[arg(long)]
+		modulus: Option<u64>,
+		/// With --counter, an input that resets the counter to 0 on the next clock cycle
+		#[arg(long, value_name = "NAME")]
+		reset: Option<String>,
+		/// Check that the outputs Q load the inputs D every clock cycle, given as `D,Q`, like `d:4,q:4`
+		#[arg(long, value_name = "D,Q", conflicts_with = "against")]
+		register: Option<String>,
+		/// With --register, an input that must be high for the register to load
+		#[arg(long, value_name = "NAME")]
+		enable: Option<String>,
+		/// With --register, the number of clock cycles to run with random inputs
+		#[arg(long, default_value_t = 100)]
+		cycles: usize,
+		/// The clock input of the circuit, with --counter or --register
+		#[arg(long, value_name = "NAME", default_value = "clk")]
+		clock: String,
+		/// Maximum number of update iterations per row, or per clock edge
+		#[arg(long, default_value_t = 1000)]
+		limit: u128,
+	},
+	/// Checks many circuits against a truth table saved by `truth-table --save-table`, and writes a CSV summary.
+	/// Files that cannot be read or simulated are reported as failures instead of stopping the run
+	BatchCheck {
+		/// Files, directories, or patterns like `submissions/*.logicly`
+		#[arg(required = true)]
+		files: Vec<String>,
+		#[arg(long, value_name = "FILE")]
+		against: PathBuf,
+		/// Write the summary to this file instead of standard output
+		#[arg(long, value_name = "FILE")]
+		report: Option<PathBuf>,
+		/// Number of files to check at once
+		#[arg(long, default_value_t = 1)]
+		jobs: usize,
+		/// Maximum number of update iterations per row
+		#[arg(long, default_value_t = 1000)]
+		limit: u128,
+	},
+	/// Prints the estimated size and depth of a circuit
+	Synth {
+		file: PathBuf,
+		/// Rewrite the circuit using a single type of gate, and save the result to --output instead
+		#[arg(long, value_enum, requires = "output")]
+		tech: Option<Tech>,
+		/// The .logicly file to write the rewritten circuit to
+		#[arg(long, short, value_name = "FILE", requires = "tech")]
+		output: Option<PathBuf>,
+	},
+	/// Rewrites boolean expressions like `a & !b` using a single type of gate
+	Expr {
+		/// Expressions using `!`, `&`, `^`, `|`, and parentheses. Every name in them is an input
+		#[arg(required = true)]
+		expressions: Vec<String>,
+		#[arg(long, value_enum)]
+		tech: Tech,
+	},
+	/// Runs a circuit one update pass at a time, reading commands like `step` and `why NAME` from standard input
+	Debug {
+		file: PathBuf,
+		/// Maximum number of update iterations run by `continue`
+		#[arg(long, default_value_t = 1000)]
+		limit: u128,
+		/// After each command, list the named outputs that changed
+		#[arg(long)]
+		print_changes: bool,
+	},
+	/// Runs a circuit for a number of update passes, stopping at the first one that hits a breakpoint, and prints its state
+	Run {
+		file: PathBuf,
+		/// Maximum number of update passes
+		#[arg(long, default_value_t = 1000)]
+		ticks: u64,
+		/// Stop when this condition is met, like `q3==1`, `a & !b`, or `uid:g1 changes`
+		#[arg(long = "break", value_name = "COND")]
+		breaks: Vec<String>,
+		/// Set a named input before running, like `a=1`
+		#[arg(long, value_name = "NAME=VALUE")]
+		set: Vec<String>,
+		/// Also write the circuit to this file in Graphviz format, with each object colored by its value when the run stopped
+		#[arg(long, value_name = "FILE")]
+		dot: Option<PathBuf>,
+		/// After each tick, list the named outputs that changed
+		#[arg(long)]
+		print_changes: bool,
+	},
+	/// Prints the objects of a circuit and what they are connected to
+	Print {
+		file: PathBuf,
+		/// Draw the circuit with text instead, placing objects by their positions in the file
+		#[arg(long)]
+		schematic: bool,
+	},
+	/// Draws a circuit as an SVG image or a Mermaid flowchart, and prints it
+	Export {
+		file: PathBuf,
+		#[arg(long, value_enum, default_value_t = ExportFormat::Svg)]
+		format: ExportFormat,
+		/// With --format svg, color each wire by its value once the circuit stabilizes with the inputs saved in the file
+		#[arg(long)]
+		values: bool,
+	},
+	/// Prints a summary of a circuit file
+	Info {
+		file: PathBuf,
+		/// Also print a hash of the circuit's truth table, which is the same for any circuit that does the same thing
+		#[arg(long)]
+		fingerprint: bool,
+	},
+	/// Checks a circuit for likely mistakes, and fails if any errors are found
+	Lint {
+		file: PathBuf,
+		/// Report a rule as an error, which makes the command fail
+		#[arg(long, value_name = "RULE")]
+		deny: Vec<String>,
+		/// Do not report a rule
+		#[arg(long, value_name = "RULE")]
+		allow: Vec<String>,
+		/// Print the diagnostics as JSON
+		#[arg(long)]
+		json: bool,
+		/// Give duplicate export names a numbered suffix, write the result to this file, and lint that instead
+		#[arg(long, value_name = "OUTPUT")]
+		fix: Option<PathBuf>,
+	},
+	/// Runs the optimization passes on a circuit, and prints the result
+	Optimize {
+		file: PathBuf,
+		/// Print a report of the changes instead of the optimized circuit
+		#[arg(long, value_enum)]
+		report: Option<ReportFormat>,
+	},
+	/// Describes the structure of a circuit
+	Analyze {
+		file: PathBuf,
+		/// List the half adders, full adders, multiplexers, and latches built from simple gates
+		#[arg(long, required_unless_present_any = ["compare_outputs", "reachability"], conflicts_with_all = ["compare_outputs", "reachability"])]
+		patterns: bool,
+		/// List the rows where two named outputs differ, given as `A,B`, like a reference output and the one built to match it
+		#[arg(long, value_name = "A,B", conflicts_with = "reachability")]
+		compare_outputs: Option<String>,
+		/// List which states of a state machine can be reached from --reset, and how.
+		/// Each state bit is given as `INPUT:OUTPUT`, the input holding the current value and the output computing the next one
+		#[arg(long, value_name = "INPUT:OUTPUT,...", value_delimiter = ',')]
+		reachability: Vec<String>,
+		/// The state to start from with --reachability, like `000`, one digit per state bit. All zeros by default
+		#[arg(long, value_name = "BITS", requires = "reachability")]
+		reset: Option<String>,
+		/// Maximum number of update iterations per row, with --compare-outputs and --reachability
+		#[arg(long, default_value_t = 1000)]
+		limit: u128,
+		/// Print the results as JSON
+		#[arg(long)]
+		json: bool,
+	},
+	/// Prints a completion script for a shell.
+	/// The bash and fish scripts also complete input and output names, like after --set, from the circuit file on the command line
+	Completions {
+		#[arg(value_enum)]
+		shell: Shell,
+	},
+	/// Prints the names of the inputs or outputs of a circuit, one per line, for the completion scripts
+	#[command(name = "__complete-signals", hide = true)]
+	CompleteSignals {
+		file: PathBuf,
+		#[arg(long, value_enum, default_value_t = SignalKind::All)]
+		kind: SignalKind,
+	},
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+	Text,
+	Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+	Svg,
+	Mermaid,
+	/// A Logisim Evolution project (.circ)
+	Logisim,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Tech {
+	Nand,
+	Nor,
+}
+impl Tech {
+	fn technology(self) -> Technology {
+		match self {
+			Tech::Nand => Technology::Nand,
+			Tech::Nor => Technology::Nor,
+		}
+	}
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TableFormat {
+	/// One row per input combination
+	Table,
+	/// Σm / ΠM listing for each output
+	Minterms,
+}
 
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SignalKind {
+	Inputs,
+	Outputs,
+	All,
+}
+
+/// Options that take the name of an input or output, which the completion scripts complete from the circuit file,
+/// followed by a suffix added to each name.
+const SIGNAL_OPTIONS: &[(&str, SignalKind, &str)] = &[
+	("set", SignalKind::Inputs, "="),
+	("clock", SignalKind::Inputs, ""),
+	("reset", SignalKind::Inputs, ""),
+	("enable", SignalKind::Inputs, ""),
+	("find", SignalKind::Outputs, "=="),
+	("compare-outputs", SignalKind::Outputs, ""),
+	("break", SignalKind::All, "=="),
+];
+
+fn main() -> Result<ExitCode> {
+	let cli = Cli::parse();
+	let cancel = CancellationToken::new();
+	// Other commands, like the debugger, do not check the token, so Ctrl+C keeps its usual meaning for them
+	if matches!(cli.command, Command::TruthTable { .. } | Command::Verify { .. } | Command::BatchCheck { .. } | Command::Analyze { .. }) {
+		let handler = cancel.clone();
+		ctrlc::set_handler(move || match handler.is_cancelled() {
+			true => std::process::exit(CANCELLED_EXIT_CODE.into()),
+			false => handler.cancel(),
+		}).context("could not install the Ctrl+C handler")?;
+	}
+	match run(cli, cancel.clone()) {
+		Err(error) if error.is::<Cancelled>() => eprintln!("{error}"),
+		Err(error) => return Err(error),
+		Ok(()) if cancel.is_cancelled() => eprintln!("Cancelled"),
+		Ok(()) => return Ok(ExitCode::SUCCESS),
+	}
+	Ok(ExitCode::from(CANCELLED_EXIT_CODE))
+}
+
+fn run(cli: Cli, cancel: CancellationToken) -> Result<()> {
+	let mode = if cli.lenient { ParseMode::Lenient } else { ParseMode::Strict };
+	let cache_dir = match cli.cache_dir {
+		Some(Some(dir)) => Some(dir),
+		Some(None) => Some(default_cache_dir().ok_or(anyhow!("could not find a cache directory, set XDG_CACHE_HOME or HOME"))?),
+		None => None,
+	};
+	let mut config = SimulationConfig::default().cache_dir(cache_dir).table_memory_limit(cli.max_table_bytes).cancellation(cancel);
+	if matches!(cli.command, Command::TruthTable { .. } | Command::Verify { .. } | Command::BatchCheck { .. }) && std::io::stderr().is_terminal() {
+		config = config.progress(progress_bars());
+	}
+	match cli.command {
+		Command::TruthTable { file, format, max_terms, limit, parity, strict, customs, stats, trace_slowest, save_table, output_passes, find, max_rows } => {
+			let circuit = load(file, mode)?;
+			let mut simul = simulate(circuit, config.strict(strict).output_convergence(output_passes))?;
+			if let Some(condition) = find {
+				let table = simul.try_get_truth_table(limit)?;
+				print!("{}", table.format_matches(&condition, max_rows)?);
+				return Ok(());
+			}
+			// The table computed for the stats is printed too, rather than simulating every row twice
+			let stats = match stats {
+				true => Some(simul.try_get_truth_table_with_stats(limit)?),
+				false => None,
+			};
+			let extra = parity.then_some(("parity", &simul::parity as simul::Reducer));
+			match (format, &stats) {
+				(TableFormat::Table, Some((table, _))) => print!("{}", table.format_table(extra)),
+				(TableFormat::Table, None) => simul.write_truth_table_with(&mut std::io::stdout().lock(), limit, extra)?,
+				(TableFormat::Minterms, Some((table, _))) => print!("{}", table.format_minterms(max_terms)),
+				(TableFormat::Minterms, None) => {
+					let table = simul.try_get_truth_table(limit)?;
+					print!("{}", table.format_minterms(max_terms));
+				},
+			}
+			if customs {
+				println!();
+				simul.print_all_custom_tables();
+			}
+			if let Some((table, stats)) = stats {
+				print_stats(&table, &stats);
+				if trace_slowest {
+					for (i, changed) in simul.trace_row(stats.slowest_row(), limit).iter().enumerate() {
+						let changed = changed.iter().map(|(name, values)| format!("{name}={}", bit_string(values)));
+						println!("  pass {}: {}", i + 1, changed.format(", "));
+					}
+				}
+			}
+			if let Some(path) = save_table {
+				let table = simul.try_get_truth_table(limit)?;
+				table.write_binary(File::create(&path).with_context(|| format!("Error creating {}", path.display()))?)?;
+			}
+		},
+		Command::Verify { file, against: None, counter, modulus, reset, register, enable, cycles, clock, limit } => {
+			let mut simul = simulate(load(file, mode)?, config)?;
+			if let (Some(q), Some(modulus)) = (counter, modulus) {
+				let cycles = verify::counter(&mut simul, &q, &clock, reset.as_deref(), modulus, limit)?;
+				println!("{q} counts modulo {modulus} ({cycles} clock cycles checked)");
+			}
+			if let Some(buses) = register {
+				let (d, q) = buses.split_once(',').ok_or_else(|| anyhow!("expected two buses separated by a comma, found {buses}"))?;
+				let (d, q): (Bus, Bus) = (d.parse()?, q.parse()?);
+				verify::register(&mut simul, &d, &q, &clock, enable.as_deref(), cycles, limit)?;
+				println!("{q} loads {d} as expected ({cycles} clock cycles checked)");
+			}
+		},
+		Command::Verify { file, against: Some(against), limit, .. } => {
+			let expected = TruthTable::read_binary(File::open(&against).with_context(|| format!("Error opening {}", against.display()))?)?;
+			let mut simul = simulate(load(file, mode)?, config)?;
+			let table = simul.try_get_truth_table(limit)?;
+			table.assert_matches(&expected)?;
+			println!("Truth table matches ({} rows)", table.num_rows());
+		},
+		Command::BatchCheck { files, against, report, jobs, limit } => {
+			let expected = TruthTable::read_binary(File::open(&against).with_context(|| format!("Error opening {}", against.display()))?)?;
+			let files = batch::expand_patterns(&files)?;
+			let results = batch::check_files(&files, &expected, mode, &config, limit, jobs);
+			match report {
+				Some(path) => batch::write_csv(&results, File::create(&path).with_context(|| format!("Error creating {}", path.display()))?)?,
+				None => batch::write_csv(&results, std::io::stdout().lock())?,
+			}
+			let failed = results.iter().filter(|r| !r.passed).count();
+			eprintln!("{} of {} files passed", results.len() - failed, results.len());
+			if failed > 0 {
+				return Err(anyhow!("{failed} file(s) failed"));
+			}
+		},
+		Command::Synth { file, tech, output } => {
+			let circuit = load(file, mode)?;
+			let (Some(tech), Some(output)) = (tech, output) else {
+				print!("{}", circuit.synthesis_report());
+				return Ok(());
+			};
+			let tech = tech.technology();
+			let mapped = map_technology(&circuit, tech);
+			save_raw_circuit(&mapped.to_raw().map_err(|e| anyhow!(e))?, &output)?;
+			println!("{tech:?}-only circuit: {} gates (originally {})", gate_count(&mapped), gate_count(&circuit));
+		},
+		Command::Expr { expressions, tech } => {
+			let tech = tech.technology();
+			for source in &expressions {
+				let names = source.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+					.filter(|name| name.starts_with(|c: char| !c.is_ascii_digit()))
+					.unique()
+					.collect_vec();
+				let mapped = map_expr(&BoolExpr::parse(source, &names)?, tech);
+				println!("{source} = {} ({} {tech:?} gates)", mapped.format(tech, &names), mapped.gate_count());
+			}
+		},
+		Command::Debug { file, limit, print_changes } => {
+			let mut simul = simulate(load(file, mode)?, config)?;
+			eprintln!("Type help for a list of commands");
+			simul::debug_session(&mut simul, std::io::stdin().lock(), std::io::stdout(), limit, print_changes)?;
+		},
+		Command::Run { file, ticks, breaks, set, dot, print_changes } => {
+			let mut simul = simulate(load(file, mode)?, config)?;
+			for assignment in &set {
+				let (name, value) = match assignment.split_once('=') {
+					Some((name, "0")) => (name, false),
+					Some((name, "1")) => (name, true),
+					_ => return Err(anyhow!("expected NAME=0 or NAME=1, found {assignment}")),
+				};
+				**simul.get_inputs_mut().get_mut(name).ok_or_else(|| anyhow!("No input named {name}"))? = value;
+			}
+			let mut breakpoints = breaks.iter().map(|source| Breakpoint::parse(source, &simul)).collect::<Result<Vec<_>>>()?;
+			let print_tick = |simul: &mut Simulation, tick| {
+				let changes = simul.take_output_changes();
+				if print_changes && !changes.is_empty() {
+					println!("tick {tick}: {}", format_output_changes(&changes));
+				}
+			};
+			match run_until_break(&mut simul, &mut breakpoints, ticks, print_tick) {
+				Some((tick, hit)) => println!("Breakpoint {} hit at tick {tick}: {}", hit + 1, breakpoints[hit].source),
+				None if breakpoints.is_empty() => println!("Ran {ticks} ticks"),
+				None => println!("No breakpoint hit in {ticks} ticks"),
+			}
+			print!("{}", format_state(&simul));
+			if let Some(path) = dot {
+				std::fs::write(&path, simul.to_dot()).with_context(|| format!("Error writing {}", path.display()))?;
+			}
+		},
+		Command::Print { file, schematic } => {
+			let circuit = load(file, mode)?;
+			match schematic {
+				true => print!("{}", circuit.to_schematic()),
+				false => print!("{circuit}"),
+			}
+		},
+		Command::Export { file, format, values } => {
+			let circuit = load(file, mode)?;
+			match format {
+				ExportFormat::Svg if values => {
+					let mut simul = simulate(circuit.clone(), config.saved_inputs(true))?;
+					simul.reset_state();
+					if !simul.update_until_done(1000) {
+						return Err(anyhow!("circuit did not stabilize"));
+					}
+					print!("{}", circuit.to_svg_with_values(|i, output| simul.object_values(i).get(output as usize).copied()));
+				},
+				ExportFormat::Svg => print!("{}", circuit.to_svg()),
+				ExportFormat::Mermaid => print!("{}", circuit.to_mermaid()),
+				ExportFormat::Logisim => print!("{}", circuit.to_logisim()),
+			}
+		},
+		Command::Info { file, fingerprint } => {
+			let circuit = load(file, mode)?;
+			match circuit.schema_version {
+				Some(version) => println!("Schema version: {version}"),
+				None => println!("Schema version: unknown"),
+			}
+			println!("Objects: {}", circuit.objects.len());
+			println!("Custom circuits: {}", circuit.custom_library().len());
+			println!("Custom circuit instances: {}", circuit.custom_instance_count());
+			if !circuit.custom_library().is_empty() {
+				println!("Custom circuit names: {}", circuit.custom_library().iter().map(|c| c.display_name()).join(", "));
+			}
+			let names = |filter: fn(&Object) -> bool| circuit.objects.iter().filter(|o| filter(o)).map(|o| o.export_name_or_uid()).join(", ");
+			println!("Inputs: {}", names(Object::is_named_input));
+			println!("Outputs: {}", names(Object::is_named_output));
+			if fingerprint {
+				let mut simul = simulate(circuit, config)?;
+				println!("Fingerprint: {}", simul.fingerprint(1000)?.iter().map(|b| format!("{b:02x}")).join(""));
+			}
+		},
+		Command::Lint { file, deny, allow, json, fix } => {
+			let circuit = match fix {
+				Some(output) => {
+					let mut raw = load_raw_circuit(&file)?;
+					for (custom, uid, name) in raw.suffix_duplicate_names() {
+						let location = custom.map(|c| format!(" in custom circuit {c}")).unwrap_or_default();
+						eprintln!("Renamed {uid}{location} to {name}");
+					}
+					save_raw_circuit(&raw, &output)?;
+					Circuit::from_raw(raw, mode).map_err(|e| anyhow!(e))?
+				},
+				None => load(file, mode)?,
+			};
+			let report = circuit.lint(&LintConfig { allow, deny })?;
+			match json {
+				true => println!("{}", serde_json::to_string_pretty(&report)?),
+				false => print!("{}", report.format_by_severity()),
+			}
+			if report.has_errors() {
+				let errors = report.diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+				return Err(anyhow!("found {errors} error(s)"));
+			}
+		},
+		Command::Optimize { file, report: format } => {
+			let (optimized, report) = optimize(&load(file, mode)?);
+			match format {
+				None => print!("{optimized}"),
+				Some(ReportFormat::Text) => print!("{report}"),
+				Some(ReportFormat::Json) => println!("{}", serde_json::to_string_pretty(&report)?),
+			}
+		},
+		Command::Analyze { file, patterns: _, compare_outputs, reachability, reset, limit, json } => {
+			let circuit = load(file, mode)?;
+			if !reachability.is_empty() {
+				let state = reachability.iter()
+					.map(|bit| bit.split_once(':').ok_or_else(|| anyhow!("expected INPUT:OUTPUT, found {bit}")))
+					.collect::<Result<Vec<_>>>()?;
+				let reset = match reset {
+					Some(bits) => bits.chars().map(|c| match c {
+						'0' => Ok(false),
+						'1' => Ok(true),
+						_ => Err(anyhow!("expected a reset state made of 0 and 1, found {bits}")),
+					}).collect::<Result<Vec<_>>>()?,
+					None => vec![false; state.len()],
+				};
+				let report = simulate(circuit, config)?.state_reachability(&state, &reset, limit)?;
+				match json {
+					true => println!("{}", serde_json::to_string_pretty(&report)?),
+					false => print!("{report}"),
+				}
+				return Ok(());
+			}
+			if let Some(outputs) = compare_outputs {
+				let (a, b) = outputs.split_once(',').ok_or_else(|| anyhow!("expected two outputs separated by a comma, found {outputs}"))?;
+				let comparison = simulate(circuit, config)?.compare_outputs(a, b, limit)?;
+				match json {
+					true => println!("{}", serde_json::to_string_pretty(&comparison)?),
+					false => print!("{comparison}"),
+				}
+				return Ok(());
+			}
+			let report = circuit.find_patterns();
+			match json {
+				true => println!("{}", serde_json::to_string_pretty(&report)?),
+				false => print!("{report}"),
+			}
+		},
+		Command::Completions { shell } => print!("{}", completion_script(shell)),
+		Command::CompleteSignals { file, kind } => {
+			// Completion should never print errors into the command line being typed
+			let Ok(circuit) = load_circuit_with(file, mode) else { return Ok(()) };
+			for object in &circuit.objects {
+				let wanted = match kind {
+					SignalKind::Inputs => object.is_named_input(),
+					SignalKind::Outputs => object.is_named_output(),
+					SignalKind::All => object.is_named_input() || object.is_named_output(),
+				};
+				if wanted { println!("{}", object.export_name_or_uid()); }
+			}
+		},
+	}
 	Ok(())
 }
+
+/// Loads a circuit, failing with a readable message if there is nothing in it.
+fn load(file: PathBuf, mode: ParseMode) -> Result<Circuit> {
+	let circuit = load_circuit_with(file, mode)?;
+	for warning in &circuit.warnings {
+		eprintln!("Warning: {warning}");
+	}
+	if circuit.is_empty() {
+		return Err(anyhow!("circuit has no objects"));
+	}
+	Ok(circuit)
+}
+
+/// Simulates a circuit, printing how many custom circuit tables were found in the cache if it was used.
+fn simulate(circuit: Circuit, config: SimulationConfig) -> Result<Simulation> {
+	let simul = Simulation::with_config(circuit, config)?;
+	let CacheStats { hits, misses } = simul.cache_stats();
+	if hits + misses > 0 {
+		eprintln!("Table cache: {hits} hit(s), {misses} miss(es)");
+	}
+	Ok(simul)
+}
+
+fn print_stats(table: &TruthTable, stats: &ConvergenceStats) {
+	let histogram = stats.histogram();
+	let max_count = histogram.values().copied().max().unwrap_or(0);
+	println!("Iterations to stabilize:");
+	for (iterations, count) in histogram {
+		println!("{iterations:>6}: {:<40} {count}", "#".repeat((count * 40).div_ceil(max_count)));
+	}
+	let slowest = stats.slowest_row();
+	let bits = int_to_bits(slowest, table.inputs().len() as u8);
+	let assignment = table.inputs().iter().zip(bits).map(|(name, bit)| format!("{name}={}", bit as u8));
+	println!("Slowest row: {slowest} ({}), {} iterations", assignment.format(", "), stats.iterations[slowest]);
+}
+
+/// Shows a progress bar on standard error for each truth table or batch of files being computed, removed once it is done.
+fn progress_bars() -> ProgressCallback {
+	let bars = MultiProgress::new();
+	let style = ProgressStyle::with_template("{msg} [{bar:40}] {pos}/{len} ({per_sec}, {eta} left)").expect("valid template").progress_chars("=> ");
+	let running = Mutex::new(HashMap::new());
+	Box::new(move |event: ProgressEvent| {
+		let mut running = running.lock().unwrap_or_else(|e| e.into_inner());
+		let bar = running.entry(event.task.clone()).or_insert_with(|| {
+			bars.add(ProgressBar::new(event.total as u64).with_style(style.clone()).with_message(event.task.to_string()))
+		});
+		bar.set_position(event.done as u64);
+		if event.done == event.total {
+			bar.finish_and_clear();
+			running.remove(&event.task);
+		}
+	})
+}
+
+/// Generates the completion script for a shell from the definition of the command line.
+/// For bash and fish, appends completion of input and output names for the options in [`SIGNAL_OPTIONS`],
+/// which runs `__complete-signals` on the first existing file on the command line.
+fn completion_script(shell: Shell) -> String {
+	let mut script = Vec::new();
+	clap_complete::generate(shell, &mut Cli::command(), "logicly-rs", &mut script);
+	let mut script = String::from_utf8(script).expect("completion script should be UTF-8");
+	// clap_complete names the bash function after the binary, with `-` replaced by `__`
+	let kind_name = |kind: SignalKind| kind.to_possible_value().expect("no skipped kinds").get_name().to_string();
+	match shell {
+		Shell::Bash => {
+			let cases = SIGNAL_OPTIONS.iter().map(|(option, kind, suffix)| {
+				format!("        --{option}) kind={} suffix='{suffix}' ;;\n", kind_name(*kind))
+			}).join("");
+			script += &format!(r#"
+_logicly__rs_signals() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}" kind suffix word file
+    case "$prev" in
+{cases}        *) _logicly__rs "$@"; return ;;
+    esac
+    for word in "${{COMP_WORDS[@]:1:COMP_CWORD-2}}"; do
+        if [[ -f "$word" ]]; then file="$word"; break; fi
+    done
+    if [[ -z "$file" ]]; then _logicly__rs "$@"; return; fi
+    [[ -n "$suffix" ]] && compopt -o nospace
+    COMPREPLY=($(compgen -W "$(logicly-rs __complete-signals "$file" --kind $kind 2>/dev/null)" -S "$suffix" -- "$cur"))
+}}
+complete -F _logicly__rs_signals -o bashdefault -o default logicly-rs
+"#);
+		},
+		Shell::Fish => {
+			script += r#"
+function __logicly_rs_signals
+    for word in (commandline -opc)[2..-1]
+        if test -f $word
+            logicly-rs __complete-signals $word --kind $argv[1] 2>/dev/null | string replace -r '$' "$argv[2]"
+            return
+        end
+    end
+end
+"#;
+			for (option, kind, suffix) in SIGNAL_OPTIONS {
+				script += &format!("complete -c logicly-rs -l {option} -f -a '(__logicly_rs_signals {} \"{suffix}\")'\n", kind_name(*kind));
+			}
+		},
+		_ => {},
+	}
+	script
+}
@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::io::{Circuit, CustomCircuit, Object, ObjectInner};
+
+/// A single change made by an optimization pass.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Change {
+	/// Name of the rule that made the change.
+	pub rule: &'static str,
+	pub kind: ChangeKind,
+	/// Uid of the affected object.
+	pub uid: String,
+}
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+	Removed, Rewritten
+}
+
+/// An optimization pass, which returns the optimized objects and a record of what it changed.
+type Pass = fn(Vec<Object>) -> (Vec<Object>, Vec<Change>);
+const passes: &[Pass] = &[remove_dead_logic, merge_common_subexpressions];
+
+/// Runs all optimization passes until none of them make any changes,
+/// on the top level and on each custom circuit definition.
+pub fn optimize(circuit: &Circuit) -> (Circuit, OptimizationReport) {
+	let mut changes = vec![];
+	let mut run = |objects: &[Object]| {
+		let mut objects = objects.to_vec();
+		loop {
+			let mut changed = false;
+			for pass in passes {
+				let (optimized, pass_changes) = pass(objects);
+				objects = optimized;
+				changed |= !pass_changes.is_empty();
+				changes.extend(pass_changes);
+			}
+			if !changed { break objects }
+		}
+	};
+	let objects = run(&circuit.objects);
+	let customs = circuit.customs.as_ref().map(|customs| customs.iter().map(|c| CustomCircuit {
+		objects: run(&c.objects),
+		..c.clone()
+	}).collect());
+	let optimized = Circuit { objects, customs, merged_customs: circuit.merged_customs.clone(), warnings: circuit.warnings.clone(), schema_version: circuit.schema_version, wire_points: circuit.wire_points.clone() };
+	let report = OptimizationReport::new(circuit, &optimized, changes);
+	(optimized, report)
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct OptimizationReport {
+	/// Number of gates of each type, over the top level and all custom circuit definitions.
+	pub gates_before: BTreeMap<String, usize>,
+	pub gates_after: BTreeMap<String, usize>,
+	/// Logic depth of the top level circuit.
+	pub depth_before: usize,
+	pub depth_after: usize,
+	/// Number of changes made by each rule.
+	pub applications: BTreeMap<&'static str, usize>,
+	/// Uids of removed objects.
+	pub removed: Vec<String>,
+	/// Uids of objects that were replaced with something else.
+	pub rewritten: Vec<String>,
+}
+impl OptimizationReport {
+	fn new(before: &Circuit, after: &Circuit, changes: Vec<Change>) -> Self {
+		let mut report = Self {
+			gates_before: gate_counts(before),
+			gates_after: gate_counts(after),
+			depth_before: logic_depth(&before.objects),
+			depth_after: logic_depth(&after.objects),
+			..Default::default()
+		};
+		for change in changes {
+			*report.applications.entry(change.rule).or_default() += 1;
+			match change.kind {
+				ChangeKind::Removed => report.removed.push(change.uid),
+				ChangeKind::Rewritten => report.rewritten.push(change.uid),
+			}
+		}
+		report
+	}
+}
+impl Display for OptimizationReport {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(f, "Gates:")?;
+		for kind in self.gates_before.keys().chain(self.gates_after.keys()).unique().sorted() {
+			let before = self.gates_before.get(kind).copied().unwrap_or(0);
+			let after = self.gates_after.get(kind).copied().unwrap_or(0);
+			writeln!(f, "  {kind}: {before} -> {after}")?;
+		}
+		writeln!(f, "  total: {} -> {}", self.gates_before.values().sum::<usize>(), self.gates_after.values().sum::<usize>())?;
+		writeln!(f, "Depth: {} -> {}", self.depth_before, self.depth_after)?;
+		writeln!(f, "Rules applied:")?;
+		for (rule, count) in &self.applications {
+			writeln!(f, "  {rule}: {count}")?;
+		}
+		writeln!(f, "Removed: {}", self.removed.join(", "))?;
+		writeln!(f, "Rewritten: {}", self.rewritten.join(", "))
+	}
+}
+
+/// Number of gates of each type, over the top level and all custom circuit definitions.
+/// Custom gate instances are counted as "Custom".
+pub fn gate_counts(circuit: &Circuit) -> BTreeMap<String, usize> {
+	let mut counts = BTreeMap::new();
+	for obj in circuit.customs.iter().flatten().map(|c| &c.objects).chain([&circuit.objects]).flatten() {
+		let kind = match &obj.inner {
+			ObjectInner::SimpleGate { kind, .. } => kind.to_string(),
+			ObjectInner::CustomGate { .. } => String::from("Custom"),
+			_ => continue,
+		};
+		*counts.entry(kind).or_default() += 1;
+	}
+	counts
+}
+
+/// Length of the longest chain of gates from an input to an output.
+/// Custom gates count as one level. Feedback loops are cut where they are first found.
+pub fn logic_depth(objects: &[Object]) -> usize {
+	logic_depth_with(objects, |obj| match obj.inner {
+		ObjectInner::SimpleGate { .. } | ObjectInner::CustomGate { .. } => 1,
+		_ => 0,
+	})
+}
+
+/// Like [`logic_depth`], but each object adds `cost(object)` levels instead.
+pub fn logic_depth_with(objects: &[Object], cost: impl Fn(&Object) -> usize) -> usize {
+	fn depth(i: usize, objects: &[Object], cost: &dyn Fn(&Object) -> usize, memo: &mut Vec<Option<usize>>, visiting: &mut Vec<bool>) -> usize {
+		if let Some(d) = memo[i] { return d }
+		if visiting[i] { return 0 }
+		visiting[i] = true;
+		let d = cost(&objects[i]) + objects[i].connections().iter().flatten()
+			.map(|&(_, src)| depth(src, objects, cost, memo, visiting))
+			.max().unwrap_or(0);
+		visiting[i] = false;
+		memo[i] = Some(d);
+		d
+	}
+	let mut memo = vec![None; objects.len()];
+	let mut visiting = vec![false; objects.len()];
+	(0..objects.len()).filter(|&i| objects[i].is_output())
+		.map(|i| depth(i, objects, &cost, &mut memo, &mut visiting))
+		.max().unwrap_or(0)
+}
+
+/// Removes the objects at the given indices, updating connections to point at the new indices.
+/// Connections from removed objects are disconnected.
+fn remove_objects(objects: Vec<Object>, remove: &HashSet<usize>) -> Vec<Object> {
+	let mut new_index = vec![None; objects.len()];
+	let mut next = 0;
+	for (i, index) in new_index.iter_mut().enumerate() {
+		if !remove.contains(&i) {
+			*index = Some(next);
+			next += 1;
+		}
+	}
+	objects.into_iter().enumerate().filter(|(i, _)| !remove.contains(i)).map(|(_, mut obj)| {
+		for c in obj.connections_mut().into_iter().flatten() {
+			*c = c.and_then(|(idx, ptr)| new_index[ptr].map(|ptr| (idx, ptr)));
+		}
+		obj
+	}).collect()
+}
+
+/// Removes gates whose outputs never reach an output object.
+fn remove_dead_logic(objects: Vec<Object>) -> (Vec<Object>, Vec<Change>) {
+	let mut live = vec![false; objects.len()];
+	let mut stack: Vec<usize> = (0..objects.len()).filter(|&i| objects[i].is_output()).collect();
+	while let Some(i) = stack.pop() {
+		if live[i] { continue }
+		live[i] = true;
+		stack.extend(objects[i].connections().iter().flatten().map(|&(_, src)| src));
+	}
+	let dead: HashSet<usize> = objects.iter().enumerate()
+		.filter(|&(i, o)| !live[i] && matches!(o.inner, ObjectInner::SimpleGate { .. } | ObjectInner::CustomGate { .. }))
+		.map(|(i, _)| i)
+		.collect();
+	let changes = dead.iter().sorted().map(|&i| Change {
+		rule: "dead-logic",
+		kind: ChangeKind::Removed,
+		uid: objects[i].uid().to_string(),
+	}).collect();
+	(remove_objects(objects, &dead), changes)
+}
+
+/// Merges simple gates of the same type and XorType that read the same inputs, keeping the first one.
+/// All gate types with more than one input are symmetric, so input order is ignored,
+/// but an inverted input only matches another inverted input.
+fn merge_common_subexpressions(mut objects: Vec<Object>) -> (Vec<Object>, Vec<Change>) {
+	let mut seen = HashMap::new();
+	let mut replacement = HashMap::new();
+	for (i, obj) in objects.iter().enumerate() {
+		if let ObjectInner::SimpleGate { kind, xor_type, connections, .. } = &obj.inner {
+			let inverted = obj.inverted_inputs().iter().chain(std::iter::repeat(&false));
+			let inputs: Vec<_> = connections.iter().zip(inverted).sorted().collect();
+			let key = (*kind, *xor_type, inputs);
+			match seen.get(&key) {
+				Some(&first) => { replacement.insert(i, first); },
+				None => { seen.insert(key, i); },
+			}
+		}
+	}
+	for obj in &mut objects {
+		for (_, src) in obj.connections_mut().into_iter().flatten().flatten() {
+			if let Some(&first) = replacement.get(src) { *src = first; }
+		}
+	}
+	let merged: HashSet<usize> = replacement.keys().copied().collect();
+	let changes = merged.iter().sorted().map(|&i| Change {
+		rule: "common-subexpression",
+		kind: ChangeKind::Removed,
+		uid: objects[i].uid().to_string(),
+	}).collect();
+	(remove_objects(objects, &merged), changes)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::io::parse_xml;
+	use crate::opt::*;
+	use crate::simul::Simulation;
+	use crate::util::fixtures;
+
+	#[test]
+	fn optimize_redundant(){
+		let circuit = parse_xml(fixtures::REDUNDANT).unwrap();
+		let (optimized, report) = optimize(&circuit);
+		assert_eq!(report.gates_before, BTreeMap::from([("And".to_string(), 1), ("Not".to_string(), 2), ("Or".to_string(), 1)]));
+		assert_eq!(report.gates_after, BTreeMap::from([("And".to_string(), 1), ("Not".to_string(), 1)]));
+		assert_eq!((report.depth_before, report.depth_after), (2, 2));
+		assert_eq!(report.applications, BTreeMap::from([("dead-logic", 2)]));
+		assert_eq!(report.removed, vec!["dead_or", "dead_not"]);
+		assert!(report.rewritten.is_empty());
+		assert_eq!(
+			Simulation::from(optimized).get_truth_table(100),
+			Simulation::from(circuit).get_truth_table(100)
+		);
+	}
+	#[test]
+	fn optimize_and_subtrees(){
+		let circuit = parse_xml(fixtures::AND_SUBTREES).unwrap();
+		let (optimized, report) = optimize(&circuit);
+		assert_eq!(report.gates_before, BTreeMap::from([("And".to_string(), 6)]));
+		assert_eq!(report.gates_after, BTreeMap::from([("And".to_string(), 2)]));
+		assert_eq!(report.applications, BTreeMap::from([("common-subexpression", 4)]));
+		assert_eq!(report.removed, vec!["ab1", "ab2", "abc1", "abc2"]);
+		assert_eq!(
+			Simulation::from(optimized).get_truth_table(100),
+			Simulation::from(circuit).get_truth_table(100)
+		);
+	}
+	#[test]
+	fn optimize_report_json(){
+		let (_, report) = optimize(&parse_xml(fixtures::REDUNDANT).unwrap());
+		let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+		assert_eq!(json["applications"]["dead-logic"], 2);
+		assert_eq!(json["removed"][1], "dead_not");
+	}
+}
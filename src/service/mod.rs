@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::simul::Simulation;
+
+/// Something that can evaluate a circuit from the values of its named inputs.
+/// Implementations must be usable from many threads at once.
+pub trait CircuitService: Send + Sync {
+	/// Returns the value of every named output, starting from the reset state.
+	/// Named inputs that are missing from `inputs` are false.
+	fn evaluate(&self, inputs: &HashMap<String, bool>) -> HashMap<String, bool>;
+}
+
+/// Serves one loaded circuit to concurrent callers.
+///
+/// Each evaluation borrows an idle simulation from a pool, cloning the original one if there are none,
+/// so concurrent requests never wait for each other to finish simulating.
+pub struct SharedSimulation {
+	template: Simulation,
+	idle: Mutex<Vec<Simulation>>,
+	limit: u128,
+}
+impl SharedSimulation {
+	/// `limit` is the maximum number of update iterations per evaluation.
+	pub fn new(simulation: Simulation, limit: u128) -> Self {
+		Self {
+			template: simulation,
+			idle: Mutex::new(vec![]),
+			limit,
+		}
+	}
+	/// Evaluates with a lookup table if the circuit is small and combinational, see [`Simulation::use_lookup_table`].
+	/// Evaluations that use the table ignore `limit`, and use the cycle limit of the simulation's config instead.
+	pub fn with_lookup_table(mut self) -> Self {
+		self.template.use_lookup_table();
+		self
+	}
+}
+impl CircuitService for SharedSimulation {
+	fn evaluate(&self, inputs: &HashMap<String, bool>) -> HashMap<String, bool> {
+		let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+		let mut simulation = idle.unwrap_or_else(|| self.template.clone());
+		let inputs = inputs.iter().map(|(name, &value)| (&name[..], value)).collect();
+		let outputs = simulation.get_outputs(&inputs, self.limit);
+		self.idle.lock().unwrap_or_else(|e| e.into_inner()).push(simulation);
+		outputs
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::io::parse_xml;
+	use crate::service::*;
+	use crate::util::fixtures;
+
+	#[test]
+	fn concurrent_evaluation(){
+		let xor = || SharedSimulation::new(parse_xml(fixtures::XOR).unwrap().into(), 100);
+		let services: [Box<dyn CircuitService>; 2] = [Box::new(xor()), Box::new(xor().with_lookup_table())];
+		for service in &services {
+			std::thread::scope(|scope| {
+				for thread in 0..8 {
+					scope.spawn(move || for i in 0..100 {
+						let (a, b) = ((i + thread) % 2 == 1, i % 3 == 0);
+						let inputs = HashMap::from([(String::from("a"), a), (String::from("b"), b)]);
+						assert_eq!(service.evaluate(&inputs), HashMap::from([(String::from("q"), a != b)]));
+					});
+				}
+			});
+		}
+	}
+}
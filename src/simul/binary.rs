@@ -0,0 +1,162 @@
+//! A compact file format for truth tables, so that large tables do not need to be recomputed.
+//!
+//! All integers are little-endian. A file contains, in order:
+//! - the magic bytes `LGTT`
+//! - the format version, as a `u8`: 2 if some output has more than one column, like a digit display, otherwise 1
+//! - the number of inputs and the number of output columns, each as a `u16`
+//! - the input names, then the output column names, each as a `u16` byte length followed by UTF-8
+//! - in version 2 only, the number of output objects as a `u16`, followed by the number of columns of each one as a `u8`
+//! - the outputs of every row in row order, one bit per value, packed least significant bit first and padded to a whole byte
+//! - the 32-bit FNV-1a hash of everything before it
+
+use std::io::{Read, Write};
+
+use anyhow::{anyhow, Context, Result};
+
+use super::TruthTable;
+
+const magic: &[u8; 4] = b"LGTT";
+/// The version written for tables where every output has one column, which older versions of logicly-rs can read.
+const version: u8 = 1;
+const widths_version: u8 = 2;
+/// Tables with more inputs than this are rejected, rather than trying to allocate space for their rows.
+const max_inputs: usize = 32;
+
+fn checksum(bytes: &[u8]) -> u32 {
+	bytes.iter().fold(0x811c9dc5, |hash, &b| (hash ^ b as u32).wrapping_mul(0x01000193))
+}
+
+impl TruthTable {
+	/// Writes this table in the compact binary format described in `simul/binary.rs`.
+	pub fn write_binary(&self, mut w: impl Write) -> Result<()> {
+		let mut out = magic.to_vec();
+		out.push(if self.widths.is_empty() { version } else { widths_version });
+		for names in [&self.inputs, &self.outputs] {
+			out.extend(u16::try_from(names.len()).context("Too many columns")?.to_le_bytes());
+		}
+		for name in self.inputs.iter().chain(&self.outputs) {
+			out.extend(u16::try_from(name.len()).with_context(|| format!("Name {name} is too long"))?.to_le_bytes());
+			out.extend(name.as_bytes());
+		}
+		if !self.widths.is_empty() {
+			out.extend(u16::try_from(self.widths.len()).context("Too many outputs")?.to_le_bytes());
+			out.extend(&self.widths);
+		}
+		out.extend(self.data.chunks(8).map(|bits| bits.iter().rev().fold(0u8, |byte, &bit| byte << 1 | bit as u8)));
+		out.extend(checksum(&out).to_le_bytes());
+		w.write_all(&out).context("Error writing truth table")
+	}
+	/// Reads a table written by [`TruthTable::write_binary`].
+	pub fn read_binary(mut r: impl Read) -> Result<TruthTable> {
+		let mut bytes = vec![];
+		r.read_to_end(&mut bytes).context("Error reading truth table")?;
+		let mut reader = Reader { bytes: &bytes, position: 0 };
+		if reader.take(magic.len(), "magic").ok() != Some(&magic[..]) {
+			return Err(anyhow!("Not a truth table file"));
+		}
+		let file_version = reader.take(1, "version")?[0];
+		if file_version != version && file_version != widths_version {
+			return Err(anyhow!("Unsupported truth table version {file_version}, expected {version} or {widths_version}"));
+		}
+		let num_inputs = reader.u16("number of inputs")? as usize;
+		let num_outputs = reader.u16("number of outputs")? as usize;
+		if num_inputs > max_inputs {
+			return Err(anyhow!("Truth table has {num_inputs} inputs, the most supported is {max_inputs}"));
+		}
+		let mut names = (0..num_inputs + num_outputs).map(|_| {
+			let len = reader.u16("name length")? as usize;
+			String::from_utf8(reader.take(len, "name")?.to_vec()).context("Truth table contains a name that is not valid UTF-8")
+		}).collect::<Result<Vec<_>>>()?;
+		let outputs = names.split_off(num_inputs);
+		let widths = match file_version == widths_version {
+			true => {
+				let count = reader.u16("number of output objects")? as usize;
+				reader.take(count, "output widths")?.to_vec()
+			},
+			false => vec![],
+		};
+		if !widths.is_empty() && widths.iter().map(|&w| w as usize).sum::<usize>() != num_outputs {
+			return Err(anyhow!("Truth table output widths do not add up to {num_outputs} columns"));
+		}
+		let len = num_outputs << num_inputs;
+		let packed = reader.take(len.div_ceil(8), "rows")?;
+		let expected = checksum(&bytes[..reader.position]);
+		if reader.take(4, "checksum")? != expected.to_le_bytes() {
+			return Err(anyhow!("Truth table is corrupt: checksum does not match"));
+		}
+		if reader.position != bytes.len() {
+			return Err(anyhow!("Truth table has {} unexpected bytes after the checksum", bytes.len() - reader.position));
+		}
+		Ok(TruthTable {
+			data: (0..len).map(|i| packed[i / 8] >> (i % 8) & 1 == 1).collect(),
+			row_size: num_outputs,
+			inputs: names,
+			outputs,
+			widths,
+		})
+	}
+}
+
+struct Reader<'a> {
+	bytes: &'a [u8],
+	position: usize,
+}
+impl<'a> Reader<'a> {
+	/// Takes the next `len` bytes, which hold the part of the file named `what`.
+	fn take(&mut self, len: usize, what: &str) -> Result<&'a [u8]> {
+		let taken = self.bytes.get(self.position..self.position + len).ok_or_else(|| anyhow!("Truth table is truncated: missing the {what}"))?;
+		self.position += len;
+		Ok(taken)
+	}
+	fn u16(&mut self, what: &str) -> Result<u16> {
+		let bytes = self.take(2, what)?;
+		Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::simul::*;
+	use crate::util::fixtures;
+
+	fn table(xml: &str) -> TruthTable {
+		let mut simul: Simulation = parse_xml(xml).unwrap().into();
+		simul.get_truth_table(100).unwrap()
+	}
+	fn bytes(table: &TruthTable) -> Vec<u8> {
+		let mut bytes = vec![];
+		table.write_binary(&mut bytes).unwrap();
+		bytes
+	}
+
+	#[test]
+	fn binary_round_trip(){
+		for xml in [fixtures::XOR, fixtures::HALF_ADDER, fixtures::RIPPLE_ADDER, fixtures::DIGIT] {
+			let table = table(xml);
+			assert_eq!(TruthTable::read_binary(bytes(&table).as_slice()).unwrap(), table);
+		}
+		// Only tables with a digit display need the newer version
+		assert_eq!(bytes(&table(fixtures::XOR))[4], 1);
+		assert_eq!(bytes(&table(fixtures::DIGIT))[4], 2);
+		let table = table(fixtures::HALF_ADDER);
+		let bytes = bytes(&table);
+		// Magic and version, counts, names (x, y, sum, carry) with their lengths, 4 rows of 2 bits, and the checksum
+		assert_eq!(bytes.len(), 5 + 4 + (4 * 2 + 1 + 1 + 3 + 5) + 1 + 4);
+	}
+	#[test]
+	fn binary_errors(){
+		let bytes = bytes(&table(fixtures::HALF_ADDER));
+		let error = |bytes: &[u8]| TruthTable::read_binary(bytes).unwrap_err().to_string();
+		let mut corrupt = bytes.clone();
+		corrupt[bytes.len() - 5] ^= 1;
+		assert_eq!(error(&corrupt), "Truth table is corrupt: checksum does not match");
+		assert_eq!(error(&bytes[..bytes.len() - 2]), "Truth table is truncated: missing the checksum");
+		assert_eq!(error(&bytes[..14]), "Truth table is truncated: missing the name");
+		assert_eq!(error(&bytes[..5]), "Truth table is truncated: missing the number of inputs");
+		assert_eq!(error(b"<logicly>"), "Not a truth table file");
+		let mut newer = bytes.clone();
+		newer[4] = 3;
+		assert_eq!(error(&newer), "Unsupported truth table version 3, expected 1 or 2");
+		assert_eq!(error(&[&bytes[..], &[0]].concat()), "Truth table has 1 unexpected bytes after the checksum");
+	}
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::io::{CustomCircuit, ObjectInner, XorType};
+use crate::util::bit_string;
+
+use super::TruthTable;
+
+/// How many custom circuit truth tables were found in the cache directory, and how many had to be computed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+	pub hits: usize,
+	pub misses: usize,
+}
+
+/// The default cache directory: `$XDG_CACHE_HOME/logicly-rs`, or `~/.cache/logicly-rs`.
+pub fn default_cache_dir() -> Option<PathBuf> {
+	let base = std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+		.filter(|path| path.is_absolute())
+		.or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+	Some(base.join("logicly-rs"))
+}
+
+/// Hashes everything about a custom circuit that affects its truth table: its objects and their connections,
+/// and the definitions of the custom circuits it uses, whose hashes must be in `hashes` (keyed by uid).
+/// Positions, uids, label text, and saved switch positions are ignored.
+///
+/// Each object is written as one line of space separated fields, starting with its kind. Names are prefixed with their length,
+/// so that no name can be mistaken for other fields.
+pub fn structural_hash(custom: &CustomCircuit, hashes: &HashMap<String, [u8; 32]>) -> [u8; 32] {
+	let mut text = format!("logicly-rs {} custom circuit\n", env!("CARGO_PKG_VERSION"));
+	for object in &custom.objects {
+		let line = match &object.inner {
+			ObjectInner::SimpleGate { xor_type, kind, connections, inverted } => {
+				let xor_type = match xor_type { XorType::Odd => "odd", XorType::One => "one" };
+				format!("gate {kind} {xor_type} {} {}", encode_connections(connections), encode_bits(inverted))
+			},
+			ObjectInner::CustomGate { uuid, num_outputs, connections, inverted } => {
+				let definition = hashes.get(uuid).map(|hash| hex(hash)).unwrap_or_else(|| encode_name(uuid));
+				format!("custom {definition} {num_outputs} {} {}", encode_connections(connections), encode_bits(inverted))
+			},
+			ObjectInner::Output { export_name, connections } =>
+				format!("output {} {}", encode_export_name(export_name), encode_connections(connections)),
+			ObjectInner::Input { export_name, kind, .. } =>
+				format!("input {kind} {}", encode_export_name(export_name)),
+			ObjectInner::Label { .. } => String::from("label"),
+		};
+		text += &line;
+		text += "\n";
+	}
+	Sha256::digest(text.as_bytes()).into()
+}
+
+/// Each input as `output@object`, or `-` if it is not connected, separated by commas.
+fn encode_connections(connections: &[Option<(u32, usize)>]) -> String {
+	let encoded = connections.iter().map(|c| match c {
+		Some((output, object)) => format!("{output}@{object}"),
+		None => String::from("-"),
+	}).collect::<Vec<_>>().join(",");
+	format!("[{encoded}]")
+}
+
+fn encode_bits(bits: &[bool]) -> String {
+	format!("[{}]", bit_string(bits))
+}
+
+fn encode_name(name: &str) -> String {
+	format!("{}:{name}", name.len())
+}
+
+fn encode_export_name(export_name: &Option<String>) -> String {
+	export_name.as_deref().map(encode_name).unwrap_or_else(|| String::from("-"))
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn table_path(dir: &Path, hash: &[u8; 32]) -> PathBuf {
+	dir.join(format!("{}.table", hex(hash)))
+}
+
+/// Reads a cached table. Missing, unreadable, and corrupt tables all count as not cached.
+pub fn read(dir: &Path, hash: &[u8; 32]) -> Option<TruthTable> {
+	TruthTable::read_binary(File::open(table_path(dir, hash)).ok()?).ok()
+}
+
+/// Stores a table in the cache. The table is written to a temporary file and then renamed,
+/// so other processes using the same directory never see a partly written table.
+pub fn write(dir: &Path, hash: &[u8; 32], table: &TruthTable) -> Result<()> {
+	fs::create_dir_all(dir).with_context(|| format!("Error creating cache directory {}", dir.display()))?;
+	let path = table_path(dir, hash);
+	static next_temp: AtomicUsize = AtomicUsize::new(0);
+	let temp = path.with_extension(format!("{}.{}.tmp", std::process::id(), next_temp.fetch_add(1, Ordering::Relaxed)));
+	table.write_binary(File::create(&temp).with_context(|| format!("Error creating {}", temp.display()))?)?;
+	fs::rename(&temp, &path).with_context(|| format!("Error writing {}", path.display()))
+}
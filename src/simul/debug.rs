@@ -0,0 +1,370 @@
+//! An interactive debugger that runs a simulation one update pass at a time, used by the `debug` command,
+//! and the breakpoints used by it and the `run` command.
+
+use std::io::{BufRead, Write};
+
+use anyhow::{anyhow, Context, Result};
+use itertools::Itertools;
+
+use crate::util::bit_string;
+
+use super::{BoolExpr, Simulation};
+
+const help: &str = "\
+set NAME 0|1   set a named input
+step           run one update pass, and list the objects that changed
+steps N        run N update passes, stopping early at a breakpoint
+continue       run update passes until nothing changes or a breakpoint is hit
+show [NAME]    print the named inputs and outputs, or one object by export name or uid
+why NAME       print the inputs of an object
+break COND     stop when COND becomes true, like `carry==1`, `a & !b`, or `uid:g1 changes`
+breaks         list the breakpoints and how many times each was hit
+delete N       remove breakpoint N
+quit           stop debugging
+";
+
+/// A condition checked after every update pass.
+#[derive(Clone, Debug)]
+pub struct Breakpoint {
+	/// The condition as it was written.
+	pub source: String,
+	condition: Condition,
+	/// Number of update passes after which the breakpoint was hit.
+	pub hits: usize,
+}
+#[derive(Clone, Debug)]
+enum Condition {
+	/// Hit when the expression becomes true. `probes[i]` is the object index of input `i` of the expression.
+	Holds { expr: BoolExpr, probes: Vec<usize>, held: bool },
+	/// Hit whenever the values of an object change.
+	Changes { probe: usize, last: Vec<bool> },
+}
+impl Breakpoint {
+	/// Reads a condition, which is either `NAME==0`, `NAME==1`, `NAME changes`, or a [`BoolExpr`] over object names.
+	/// Names are export names, or failing that uids. Writing `uid:UID` only looks for an object with that uid,
+	/// which also allows uids that are not valid names, like hyphenated UUIDs.
+	pub fn parse(source: &str, simul: &Simulation) -> Result<Breakpoint> {
+		let source = source.trim();
+		let find = |name: &str| match name.trim().strip_prefix("uid:") {
+			Some(uid) => simul.find_uid(uid).ok_or_else(|| anyhow!("No object with uid {uid}")),
+			None => simul.find_probe(name.trim()).ok_or_else(|| anyhow!("No object named {}", name.trim())),
+		};
+		let condition = if let Some(name) = source.strip_suffix("changes") && name.ends_with(char::is_whitespace) {
+			let probe = find(name)?;
+			Condition::Changes { probe, last: simul.values(probe).to_vec() }
+		} else {
+			let expr_source = match source.split_once("==").map(|(name, value)| (name, value.trim())) {
+				Some((name, "1")) => name.to_string(),
+				Some((name, "0")) => format!("!{name}"),
+				Some((_, value)) => return Err(anyhow!("expected 0 or 1 after ==, found {value}")),
+				None => source.to_string(),
+			};
+			let (expr_source, uids) = replace_uids(&expr_source);
+			let names: Vec<&str> = expr_source.split(|c: char| !(c.is_alphanumeric() || c == '_')).filter(|name| !name.is_empty()).unique().collect();
+			let probes = names.iter().map(|name| {
+				let probe = match uids.iter().find(|(placeholder, _)| placeholder == name) {
+					Some((_, uid)) => simul.find_uid(uid).ok_or_else(|| anyhow!("No object with uid {uid}"))?,
+					None => find(name)?,
+				};
+				match simul.values(probe).len() {
+					1 => Ok(probe),
+					len => Err(anyhow!("{name} has {len} values, so it cannot be used in a condition")),
+				}
+			}).collect::<Result<Vec<_>>>()?;
+			Condition::Holds { expr: BoolExpr::parse(&expr_source, &names)?, probes, held: false }
+		};
+		Ok(Breakpoint { source: source.to_string(), condition, hits: 0 })
+	}
+	/// Checks the condition after an update pass, and counts a hit if it was met.
+	fn check(&mut self, simul: &Simulation) -> bool {
+		let hit = match &mut self.condition {
+			Condition::Holds { expr, probes, held } => {
+				let values: Vec<bool> = probes.iter().map(|&probe| simul.values(probe)[0]).collect();
+				let was_held = std::mem::replace(held, expr.eval(&values));
+				*held && !was_held
+			},
+			Condition::Changes { probe, last } => {
+				let values = simul.values(*probe);
+				let changed = values != &last[..];
+				if changed { *last = values.to_vec(); }
+				changed
+			},
+		};
+		if hit { self.hits += 1; }
+		hit
+	}
+}
+
+/// Replaces each `uid:UID` in an expression with a placeholder name that [`BoolExpr::parse`] accepts.
+/// A uid runs until whitespace or an operator. Returns the new expression, and each placeholder with the uid it stands for.
+fn replace_uids(source: &str) -> (String, Vec<(String, String)>) {
+	let mut out = String::new();
+	let mut uids: Vec<(String, String)> = vec![];
+	let mut rest = source;
+	while let Some(start) = rest.find("uid:") {
+		let (before, after) = (&rest[..start], &rest[start + "uid:".len()..]);
+		out += before;
+		// `uid:` only starts a uid at the start of a name
+		if before.ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+			out += "uid:";
+			rest = after;
+			continue;
+		}
+		let len = after.find(|c: char| c.is_whitespace() || "&|^!()=".contains(c)).unwrap_or(after.len());
+		let uid = &after[..len];
+		let placeholder = match uids.iter().find(|(_, u)| u == uid) {
+			Some((placeholder, _)) => placeholder.clone(),
+			None => {
+				let placeholder = (0..).map(|i| format!("uid_{i}")).find(|p| !source.contains(p) && !uids.iter().any(|(q, _)| q == p)).expect("there are infinitely many placeholders");
+				uids.push((placeholder.clone(), uid.to_string()));
+				placeholder
+			},
+		};
+		out += &placeholder;
+		rest = &after[len..];
+	}
+	out += rest;
+	(out, uids)
+}
+
+/// Runs up to `ticks` update passes, stopping after the first one that hits a breakpoint.
+/// Returns the tick (as counted by [`Simulation::step`]) and the index of the first breakpoint that was hit.
+/// `on_tick` is called with the tick after each update pass, before the breakpoints are checked.
+pub fn run_until_break(simul: &mut Simulation, breakpoints: &mut [Breakpoint], ticks: u64, mut on_tick: impl FnMut(&mut Simulation, u64)) -> Option<(u64, usize)> {
+	for _ in 0..ticks {
+		let tick = simul.step().iteration;
+		on_tick(simul, tick);
+		if let Some(hit) = check_breakpoints(simul, breakpoints) {
+			return Some((tick, hit));
+		}
+	}
+	None
+}
+
+/// Checks every breakpoint, so that each one counts its hits, and returns the index of the first one that was hit.
+fn check_breakpoints(simul: &Simulation, breakpoints: &mut [Breakpoint]) -> Option<usize> {
+	breakpoints.iter_mut().map(|breakpoint| breakpoint.check(simul)).collect::<Vec<_>>().into_iter().position(|hit| hit)
+}
+
+/// The values of the named inputs and outputs, one per line.
+pub fn format_state(simul: &Simulation) -> String {
+	simul.input_names().into_iter().chain(simul.output_names()).map(|name| {
+		let i = simul.find_probe(name).expect("named inputs and outputs can be found by name");
+		format!("{name} = {}\n", bit_string(simul.values(i)))
+	}).collect()
+}
+
+/// Lists changes from [`Simulation::take_output_changes`] like `q0 0 -> 1, q1 1 -> 0`.
+pub fn format_output_changes(changes: &[(String, bool, bool)]) -> String {
+	changes.iter().map(|(name, old, new)| format!("{name} {} -> {}", *old as u8, *new as u8)).join(", ")
+}
+
+/// Reads debugger commands from `input` until it ends or a `quit` command, and writes the results to `output`.
+/// `limit` is the most update passes run by `continue`. Mistakes in commands are reported and do not stop the session.
+/// If `print_changes` is set, the named outputs that changed are listed after each command.
+pub fn debug_session(simul: &mut Simulation, input: impl BufRead, mut output: impl Write, limit: u128, print_changes: bool) -> Result<()> {
+	let mut debugger = Debugger { breakpoints: vec![], ids: vec![], next_id: 1 };
+	simul.take_output_changes();
+	for line in input.lines() {
+		let line = line.context("Error reading command")?;
+		let words: Vec<&str> = line.split_whitespace().collect();
+		if let ["quit" | "exit"] = words[..] { break }
+		if let Err(err) = debugger.run_command(simul, &line, &words, &mut output, limit) {
+			writeln!(output, "error: {err}")?;
+		}
+		let changes = simul.take_output_changes();
+		if print_changes && !changes.is_empty() {
+			writeln!(output, "outputs changed: {}", format_output_changes(&changes))?;
+		}
+	}
+	Ok(())
+}
+
+struct Debugger {
+	breakpoints: Vec<Breakpoint>,
+	/// The number shown for each breakpoint, which does not change when earlier ones are deleted.
+	ids: Vec<usize>,
+	next_id: usize,
+}
+/// What happened in one step.
+#[derive(PartialEq)]
+enum Step {
+	Changed,
+	Stable,
+	Breakpoint,
+}
+impl Debugger {
+	fn run_command(&mut self, simul: &mut Simulation, line: &str, words: &[&str], out: &mut impl Write, limit: u128) -> Result<()> {
+		match *words {
+			[] => {},
+			["help"] => write!(out, "{help}")?,
+			["set", name, value] => {
+				let value = match value {
+					"0" | "false" => false,
+					"1" | "true" => true,
+					_ => return Err(anyhow!("expected 0 or 1, found {value}")),
+				};
+				**simul.get_inputs_mut().get_mut(name).ok_or_else(|| anyhow!("No input named {name}"))? = value;
+			},
+			["step"] => { self.step(simul, out)?; },
+			["steps", count] => {
+				let count: u64 = count.parse().map_err(|_| anyhow!("expected a number of steps, found {count}"))?;
+				for _ in 0..count {
+					if self.step(simul, out)? == Step::Breakpoint { break }
+				}
+			},
+			["continue"] => {
+				for _ in 0..limit {
+					if self.step(simul, out)? != Step::Changed { return Ok(()) }
+				}
+				return Err(anyhow!("the circuit did not stabilize within {limit} steps"));
+			},
+			["show"] => write!(out, "{}", format_state(simul))?,
+			["show", name] => {
+				let values = simul.get_probes(&[name]).remove(name).ok_or_else(|| anyhow!("No object named {name}"))?;
+				writeln!(out, "{name} = {}", bit_string(&values))?;
+			},
+			["why", name] => write!(out, "{}", simul.explain(name)?)?,
+			["break", ..] => {
+				let condition = line.trim_start().strip_prefix("break").expect("the first word is break");
+				let breakpoint = Breakpoint::parse(condition, simul)?;
+				writeln!(out, "breakpoint {}: {}", self.next_id, breakpoint.source)?;
+				self.breakpoints.push(breakpoint);
+				self.ids.push(self.next_id);
+				self.next_id += 1;
+			},
+			["breaks"] => for (id, breakpoint) in self.ids.iter().zip(&self.breakpoints) {
+				writeln!(out, "{id}: {} ({} hits)", breakpoint.source, breakpoint.hits)?;
+			},
+			["delete", id] => {
+				let index = id.parse().ok().and_then(|id: usize| self.ids.iter().position(|&i| i == id))
+					.ok_or_else(|| anyhow!("No breakpoint {id}"))?;
+				self.ids.remove(index);
+				self.breakpoints.remove(index);
+			},
+			_ => return Err(anyhow!("unknown command `{}`, try help", words.join(" "))),
+		}
+		Ok(())
+	}
+	/// Runs one update pass, prints what changed, and checks the breakpoints.
+	fn step(&mut self, simul: &mut Simulation, out: &mut impl Write) -> Result<Step> {
+		let report = simul.step();
+		let mut result = if report.changes.is_empty() {
+			writeln!(out, "step {}: no changes, the circuit is stable", report.iteration)?;
+			Step::Stable
+		} else {
+			let changes = report.changes.iter().map(|change| format!("{} {} -> {}", change.name, bit_string(&change.old), bit_string(&change.new)));
+			writeln!(out, "step {}: {}", report.iteration, changes.format(", "))?;
+			Step::Changed
+		};
+		if let Some(hit) = check_breakpoints(simul, &mut self.breakpoints) {
+			writeln!(out, "breakpoint {} hit: {}", self.ids[hit], self.breakpoints[hit].source)?;
+			result = Step::Breakpoint;
+		}
+		Ok(result)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::simul::*;
+	use crate::util::fixtures;
+
+	fn session(xml: &str, script: &str) -> String {
+		let mut simul: Simulation = parse_xml(xml).unwrap().into();
+		let mut output = vec![];
+		debug_session(&mut simul, script.as_bytes(), &mut output, 100, false).unwrap();
+		String::from_utf8(output).unwrap()
+	}
+
+	#[test]
+	fn debug_ripple_carry(){
+		// The full adders are listed from fa3 down to fa0, so the carry moves one stage per step
+		let output = session(fixtures::RIPPLE_ADDER, "set a0 1\nset a1 1\nset a2 1\nset a3 1\ncontinue\nset b0 1\nsteps 5\nshow cout\nwhy fa1\n");
+		assert_eq!(output, "\
+step 1: fa3 00 -> 10, fa2 00 -> 10, fa1 00 -> 10, fa0 00 -> 10, s0 0 -> 1, s1 0 -> 1, s2 0 -> 1, s3 0 -> 1
+step 2: no changes, the circuit is stable
+step 3: fa0 10 -> 01, s0 1 -> 0
+step 4: fa1 10 -> 01, s1 1 -> 0
+step 5: fa2 10 -> 01, s2 1 -> 0
+step 6: fa3 10 -> 01, s3 1 -> 0, cout 0 -> 1
+step 7: no changes, the circuit is stable
+cout = 1
+fa1 = 01 (custom circuit FA)
+  input 0: a1 = 1
+  input 1: b1 = 0
+  input 2: fa0[1] = 1
+");
+	}
+	/// A 4-bit counter that counts once per update pass, because each bit reads the carry left by the previous pass.
+	fn counter() -> Simulation {
+		crate::circuit!{
+			c3 = xor(c3, t3); c2 = xor(c2, t2); c1 = xor(c1, c0); c0 = not(c0);
+			t2 = and(c1, c0); t3 = and(t2, c2);
+			out q0 = c0; out q1 = c1; out q2 = c2; out q3 = c3;
+		}.into()
+	}
+	#[test]
+	fn breakpoints(){
+		let mut simul = counter();
+		let mut breakpoints = vec![Breakpoint::parse("q3==1", &simul).unwrap()];
+		let mut changes = vec![];
+		assert_eq!(run_until_break(&mut simul, &mut breakpoints, 1000, |simul, tick| changes.push((tick, format_output_changes(&simul.take_output_changes())))), Some((8, 0)));
+		assert_eq!(changes[..3], [(1, String::from("q0 0 -> 1")), (2, String::from("q0 1 -> 0, q1 0 -> 1")), (3, String::from("q0 0 -> 1"))]);
+		assert_eq!(format_state(&simul), "q0 = 0\nq1 = 0\nq2 = 0\nq3 = 1\n");
+		let mut simul = counter();
+		let mut output = vec![];
+		let script = "break q3==1\nbreak uid:c0 changes\ndelete 2\nbreak q0 & q1\nsteps 10\ncontinue\ncontinue\nbreaks\ndelete 9\nbreak q0==2\nbreak z\n";
+		debug_session(&mut simul, script.as_bytes(), &mut output, 100, false).unwrap();
+		let output = String::from_utf8(output).unwrap();
+		assert_eq!(output.lines().filter(|line| !line.starts_with("step")).collect::<Vec<_>>(), [
+			"breakpoint 1: q3==1",
+			"breakpoint 2: uid:c0 changes",
+			"breakpoint 3: q0 & q1",
+			"breakpoint 3 hit: q0 & q1",
+			"breakpoint 3 hit: q0 & q1",
+			"breakpoint 1 hit: q3==1",
+			"1: q3==1 (1 hits)",
+			"3: q0 & q1 (2 hits)",
+			"error: No breakpoint 9",
+			"error: expected 0 or 1 after ==, found 2",
+			"error: No object named z",
+		]);
+		// steps stops at the first breakpoint, and continue at the next ones
+		assert_eq!(output.lines().filter(|line| line.starts_with("step")).count(), 8);
+	}
+	#[test]
+	fn uid_breakpoints(){
+		let uuid = "0b5e2d1c-7f3a-4e9b-8c6d-1a2b3c4d5e6f";
+		let mut simul: Simulation = parse_xml(&fixtures::XOR.replace(r#""g""#, &format!(r#""{uuid}""#))).unwrap().into();
+		**simul.get_inputs_mut().get_mut("a").unwrap() = true;
+		let mut breakpoints = [format!("uid:{uuid}==1"), format!("!b&uid:{uuid}"), format!("uid:{uuid} changes")]
+			.iter().map(|source| Breakpoint::parse(source, &simul).unwrap()).collect::<Vec<_>>();
+		assert_eq!(run_until_break(&mut simul, &mut breakpoints, 10, |_, _| {}), Some((1, 0)));
+		assert_eq!(breakpoints.iter().map(|b| b.hits).collect::<Vec<_>>(), [1, 1, 1]);
+		assert_eq!(Breakpoint::parse("uid:a-b | a", &simul).unwrap_err().to_string(), "No object with uid a-b");
+		assert_eq!(Breakpoint::parse("uid:z changes", &simul).unwrap_err().to_string(), "No object with uid z");
+	}
+	#[test]
+	fn debug_print_changes(){
+		let mut simul: Simulation = parse_xml(fixtures::XOR).unwrap().into();
+		let mut output = vec![];
+		debug_session(&mut simul, "set a 1\ncontinue\nset b 1\nshow\nstep\n".as_bytes(), &mut output, 100, true).unwrap();
+		assert_eq!(String::from_utf8(output).unwrap().lines().filter(|line| line.starts_with("outputs")).collect::<Vec<_>>(), [
+			"outputs changed: q 0 -> 1",
+			"outputs changed: q 1 -> 0",
+		]);
+	}
+	#[test]
+	fn debug_errors(){
+		let output = session(fixtures::XOR, "set z 1\nset a 2\nsteps x\nfly\nquit\nstep\n");
+		assert_eq!(output, "\
+error: No input named z
+error: expected 0 or 1, found 2
+error: expected a number of steps, found x
+error: unknown command `fly`, try help
+");
+		let latch = fixtures::gate_circuit("nor", 1, None).replace(r#"outputUID="i0""#, r#"outputUID="g""#);
+		assert!(session(&latch, "continue\n").ends_with("error: the circuit did not stabilize within 100 steps\n"));
+	}
+}
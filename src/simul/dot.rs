@@ -0,0 +1,71 @@
+//! Graphviz output showing the current values of a simulation.
+
+use crate::io::{wires, ObjectInner, Wire};
+use crate::util::bit_string;
+
+use super::{Op, Simulation};
+
+/// Quotes a string for use as a DOT label.
+fn quote(text: &str) -> String {
+	format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+impl Simulation {
+	/// Draws the circuit as a Graphviz `digraph`, with each object filled in by its current values:
+	/// green if they are all true, grey if they are all false, and yellow for custom gates with some of each.
+	/// Inputs and outputs are labeled with their names and values, and each wire with the bit it carries before any inversion.
+	/// Inverted inputs have a circle at the end of the wire.
+	pub fn to_dot(&self) -> String {
+		let mut out = String::from("digraph circuit {\n\trankdir=LR;\n\tnode [style=filled];\n");
+		for (i, object) in self.objects.iter().enumerate() {
+			let values = self.values(i);
+			if values.is_empty() { continue }
+			let color = if values.iter().all(|&v| v) { "green" } else if values.iter().all(|&v| !v) { "grey" } else { "yellow" };
+			let (label, shape) = match (&object.inner, &self.ops[i]) {
+				(ObjectInner::Input { .. } | ObjectInner::Output { .. }, _) => (format!("{} = {}", object.export_name_or_uid(), bit_string(values)), "box"),
+				(_, Op::Custom(uuid)) => (self.customs[uuid].name.clone(), "component"),
+				_ => (self.kind_name(i), "ellipse"),
+			};
+			out += &format!("\tn{i} [label={}, shape={shape}, fillcolor={color}];\n", quote(&label));
+		}
+		for Wire { source, output, target, inverted, .. } in wires(&self.objects) {
+			let value = self.values(source)[output as usize];
+			let arrowhead = if inverted { ", arrowhead=odot" } else { "" };
+			out += &format!("\tn{source} -> n{target} [label=\"{}\"{arrowhead}];\n", value as u8);
+		}
+		out + "}\n"
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use crate::simul::*;
+	use crate::util::fixtures;
+
+	#[test]
+	fn dot_values(){
+		let mut simul: Simulation = parse_xml(fixtures::XOR).unwrap().into();
+		simul.get_outputs(&HashMap::from([("a", true)]), 100);
+		assert_eq!(simul.to_dot(), "\
+digraph circuit {
+	rankdir=LR;
+	node [style=filled];
+	n0 [label=\"a = 1\", shape=box, fillcolor=green];
+	n1 [label=\"b = 0\", shape=box, fillcolor=grey];
+	n2 [label=\"Xor\", shape=ellipse, fillcolor=green];
+	n3 [label=\"q = 1\", shape=box, fillcolor=green];
+	n0 -> n2 [label=\"1\"];
+	n1 -> n2 [label=\"0\"];
+	n2 -> n3 [label=\"1\"];
+}
+");
+		let mut simul: Simulation = parse_xml(&fixtures::HALF_ADDER.replace(r#"exportName="x""#, r#"exportName="say &quot;hi\""#)).unwrap().into();
+		simul.get_outputs(&HashMap::from([("say \"hi\\", true), ("y", true)]), 100);
+		let dot = simul.to_dot();
+		assert!(dot.contains(r#"n0 [label="say \"hi\\ = 1", shape=box, fillcolor=green];"#), "{dot}");
+		// sum is false and carry is true
+		assert!(dot.contains(r#"n2 [label="HA", shape=component, fillcolor=yellow];"#), "{dot}");
+	}
+}
@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Result};
+
+/// A boolean expression over the inputs of a circuit, as read by [`BoolExpr::parse`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BoolExpr {
+	/// The value of an input, by index.
+	Input(usize),
+	Not(Box<BoolExpr>),
+	And(Box<BoolExpr>, Box<BoolExpr>),
+	Xor(Box<BoolExpr>, Box<BoolExpr>),
+	Or(Box<BoolExpr>, Box<BoolExpr>),
+}
+impl BoolExpr {
+	/// Parses an expression written with the names in `inputs`, `!` (not), `&` (and), `^` (xor), `|` (or), and parentheses.
+	/// The operators bind in that order, from tightest to loosest, so `!a & b | c` is `((!a) & b) | c`.
+	/// `&&` and `||` are the same as `&` and `|`, and a name can be compared to a value, as in `a==1 && b==0`.
+	pub fn parse(source: &str, inputs: &[&str]) -> Result<BoolExpr> {
+		let mut parser = Parser { source, position: 0, inputs };
+		let expr = parser.or()?;
+		parser.skip_whitespace();
+		match parser.peek() {
+			None => Ok(expr),
+			Some(c) => Err(parser.error(&format!("unexpected {c:?}"))),
+		}
+	}
+	/// Evaluates the expression, given the value of each input.
+	pub fn eval(&self, inputs: &[bool]) -> bool {
+		match self {
+			BoolExpr::Input(i) => inputs[*i],
+			BoolExpr::Not(a) => !a.eval(inputs),
+			BoolExpr::And(a, b) => a.eval(inputs) & b.eval(inputs),
+			BoolExpr::Xor(a, b) => a.eval(inputs) ^ b.eval(inputs),
+			BoolExpr::Or(a, b) => a.eval(inputs) | b.eval(inputs),
+		}
+	}
+}
+
+struct Parser<'a> {
+	source: &'a str,
+	/// Byte offset of the next character to read.
+	position: usize,
+	inputs: &'a [&'a str],
+}
+impl Parser<'_> {
+	fn error(&self, message: &str) -> anyhow::Error {
+		anyhow!("{message} at column {} of `{}`", self.source[..self.position].chars().count() + 1, self.source)
+	}
+	fn skip_whitespace(&mut self) {
+		let rest = &self.source[self.position..];
+		self.position += rest.len() - rest.trim_start().len();
+	}
+	fn peek(&self) -> Option<char> {
+		self.source[self.position..].chars().next()
+	}
+	/// Skips whitespace, then consumes `c` if it is next.
+	fn eat(&mut self, c: char) -> bool {
+		self.skip_whitespace();
+		let found = self.peek() == Some(c);
+		if found { self.position += c.len_utf8(); }
+		found
+	}
+	/// Reads operands separated by `op`, combining them from the left.
+	fn binary(&mut self, op: char, operand: fn(&mut Self) -> Result<BoolExpr>, combine: fn(Box<BoolExpr>, Box<BoolExpr>) -> BoolExpr) -> Result<BoolExpr> {
+		let mut expr = operand(self)?;
+		while self.eat(op) {
+			if self.peek() == Some(op) && op != '^' { self.position += op.len_utf8(); }
+			expr = combine(Box::new(expr), Box::new(operand(self)?));
+		}
+		Ok(expr)
+	}
+	fn or(&mut self) -> Result<BoolExpr> {
+		self.binary('|', Self::xor, BoolExpr::Or)
+	}
+	fn xor(&mut self) -> Result<BoolExpr> {
+		self.binary('^', Self::and, BoolExpr::Xor)
+	}
+	fn and(&mut self) -> Result<BoolExpr> {
+		self.binary('&', Self::unary, BoolExpr::And)
+	}
+	fn unary(&mut self) -> Result<BoolExpr> {
+		if self.eat('!') {
+			return Ok(BoolExpr::Not(Box::new(self.unary()?)));
+		}
+		if self.eat('(') {
+			let expr = self.or()?;
+			if !self.eat(')') { return Err(self.error("expected )")) }
+			return Ok(expr);
+		}
+		let rest = &self.source[self.position..];
+		let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.')).unwrap_or(rest.len());
+		if len == 0 {
+			return Err(self.error(&match self.peek() {
+				Some(c) => format!("expected an input name, found {c:?}"),
+				None => String::from("expected an input name, found the end"),
+			}));
+		}
+		let name = &rest[..len];
+		let index = self.inputs.iter().position(|&input| input == name)
+			.ok_or_else(|| self.error(&format!("unknown input {name}, expected one of {}", self.inputs.join(", "))))?;
+		self.position += len;
+		self.skip_whitespace();
+		if !self.source[self.position..].starts_with("==") { return Ok(BoolExpr::Input(index)) }
+		self.position += 2;
+		self.skip_whitespace();
+		match self.peek() {
+			Some('1') => { self.position += 1; Ok(BoolExpr::Input(index)) },
+			Some('0') => { self.position += 1; Ok(BoolExpr::Not(Box::new(BoolExpr::Input(index)))) },
+			_ => Err(self.error("expected 0 or 1 after ==")),
+		}
+	}
+}
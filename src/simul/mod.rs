@@ -1,10 +1,39 @@
-use std::{collections::HashMap, fmt::Display, ops::{Deref, Index}};
-use crate::{io::{Circuit, InputType, Object, ObjectInner, SimpleGateType, XorType}, util::*};
+use std::{borrow::Cow, collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque}, fmt::Display, io::Write, ops::{Deref, Index}, path::PathBuf, sync::Arc};
+use itertools::Itertools;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::{io::{parse_xml, Circuit, CustomCircuit, InputType, Object, ObjectInner, SimpleGateType, XorType}, util::*};
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+mod binary;
+mod cache;
+mod debug;
+mod dot;
+mod expr;
+mod observe;
+mod progress;
+mod sources;
+pub use cache::{default_cache_dir, CacheStats};
+pub use debug::{debug_session, format_output_changes, format_state, run_until_break, Breakpoint};
+pub use expr::BoolExpr;
+pub use observe::{ChangeEvent, ProbeTarget, SubscriptionId};
+pub use progress::{CancellationToken, Cancelled, ProgressCallback, ProgressEvent, ProgressTask};
+pub(crate) use progress::ProgressHook;
+use observe::{ChangeCallback, Observers};
+use sources::InputSources;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TruthTable {
 	data: Vec<bool>,
-	row_size: usize
+	row_size: usize,
+	/// Export names of the inputs, most significant bit first.
+	inputs: Vec<String>,
+	/// Export names of the outputs, in column order. Outputs with more than one column, like digit displays,
+	/// have a column for each of their inputs, named like `d.0` to `d.3` for inputs 0 to 3 of an output named `d`.
+	outputs: Vec<String>,
+	/// Number of columns of each output object, in column order, or empty if every output has one column.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	widths: Vec<u8>,
 }
 impl Index<usize> for TruthTable {
 	type Output = [bool];
@@ -12,66 +41,1180 @@ impl Index<usize> for TruthTable {
 		&self.data[row * self.row_size..(row+1) * self.row_size]
 	}
 }
-type CustomCircuitMap = HashMap<String, (Simulation, Option<TruthTable>)>;
+impl TruthTable {
+	pub fn inputs(&self) -> &[String] {
+		&self.inputs
+	}
+	pub fn outputs(&self) -> &[String] {
+		&self.outputs
+	}
+	/// Number of columns of each output object, in column order: 4 for a digit display, and 1 for a light bulb.
+	/// Adds up to the length of [`Self::outputs`].
+	pub fn output_widths(&self) -> Vec<u8> {
+		match self.widths.is_empty() {
+			true => vec![1; self.outputs.len()],
+			false => self.widths.clone(),
+		}
+	}
+	pub fn num_rows(&self) -> usize {
+		1 << self.inputs.len()
+	}
+	/// One value per output object in row `row`: the first column of an output with several columns,
+	/// which is what a digit display passes on as a custom gate output, and what [`Simulation::get_outputs`] gives for it.
+	pub fn output_values(&self, row: usize) -> Cow<'_, [bool]> {
+		match self.widths.is_empty() {
+			true => Cow::Borrowed(&self[row]),
+			false => Cow::Owned(first_columns(&self[row], &self.widths)),
+		}
+	}
+	/// The output values of row `row`, or None if there is no such row. Indexing the table panics instead.
+	pub fn try_get_row(&self, row: usize) -> Option<&[bool]> {
+		(row < self.num_rows()).then(|| &self[row])
+	}
+	/// The output values of each row, in row order.
+	pub fn rows(&self) -> impl Iterator<Item = &[bool]> {
+		(0..self.num_rows()).map(|row| &self[row])
+	}
+	/// Checks that this table has the same inputs and outputs as `expected`, and the same value in every row.
+	/// Otherwise, describes the first difference.
+	pub fn assert_matches(&self, expected: &TruthTable) -> Result<(), Mismatch> {
+		if self.inputs != expected.inputs {
+			return Err(Mismatch::Inputs { expected: expected.inputs.clone(), found: self.inputs.clone() });
+		}
+		if self.outputs != expected.outputs {
+			return Err(Mismatch::Outputs { expected: expected.outputs.clone(), found: self.outputs.clone() });
+		}
+		match self.rows().zip(expected.rows()).position(|(found, expected)| found != expected) {
+			Some(row) => Err(Mismatch::Row {
+				row,
+				inputs: int_to_bits(row, self.inputs.len() as u8),
+				expected: expected[row].to_vec(),
+				found: self[row].to_vec(),
+			}),
+			None => Ok(()),
+		}
+	}
+	/// Returns the indices of the rows where the output in column `output` is true.
+	pub fn minterms(&self, output: usize) -> Vec<usize> {
+		(0..self.num_rows()).filter(|&row| self[row][output]).collect()
+	}
+	/// Returns the indices of the rows where the output in column `output` is false.
+	pub fn maxterms(&self, output: usize) -> Vec<usize> {
+		(0..self.num_rows()).filter(|&row| !self[row][output]).collect()
+	}
+	/// Finds every pair of outputs whose columns are identical or complementary across the whole table.
+	pub fn redundant_outputs(&self) -> Vec<(String, String, OutputRelation)> {
+		let column = |output: usize| (0..self.num_rows()).map(move |row| self[row][output]);
+		(0..self.outputs.len()).tuple_combinations().filter_map(|(a, b)| {
+			let relation = if column(a).eq(column(b)) {
+				OutputRelation::Equal
+			} else if column(a).zip(column(b)).all(|(x, y)| x != y) {
+				OutputRelation::Complement
+			} else { return None };
+			Some((self.outputs[a].clone(), self.outputs[b].clone(), relation))
+		}).collect()
+	}
+	/// Counts the outputs that compute different functions, so that outputs with identical columns count once.
+	/// With `merge_complements`, outputs whose columns are complementary also count once.
+	pub fn distinct_outputs(&self, merge_complements: bool) -> usize {
+		(0..self.outputs.len()).map(|output| {
+			// Complementary columns are the same once the column starting with true is inverted
+			let invert = merge_complements && self.num_rows() > 0 && self[0][output];
+			(0..self.num_rows()).map(|row| self[row][output] != invert).collect::<Vec<_>>()
+		}).collect::<HashSet<_>>().len()
+	}
+	/// Drops the input columns that no output depends on, merging rows that only differed in them.
+	/// Returns the smaller table and the names of the removed inputs, in column order.
+	///
+	/// Inputs outside the cone of every output in `output_cones` (see [`Circuit::output_cones`]) are removed without looking at the rows,
+	/// so the cones must come from the circuit this table was computed from. Other inputs are removed if flipping them never changes a row.
+	pub fn minimize_inputs(&self, output_cones: &BTreeMap<String, BTreeSet<String>>) -> (TruthTable, Vec<String>) {
+		let len = self.inputs.len();
+		let bit = |input: usize| 1 << (len - input - 1);
+		let (removed, kept): (Vec<usize>, Vec<usize>) = (0..len).partition(|&input| {
+			!output_cones.values().any(|cone| cone.contains(&self.inputs[input]))
+				|| (0..self.num_rows()).all(|row| self[row] == self[row ^ bit(input)])
+		});
+		(self.keep_inputs(&kept), removed.into_iter().map(|i| self.inputs[i].clone()).collect())
+	}
+	/// Returns the table with only the input columns `kept`, which are indices in column order, using the rows where the other inputs are false.
+	fn keep_inputs(&self, kept: &[usize]) -> TruthTable {
+		let bit = |input: usize| 1 << (self.inputs.len() - input - 1);
+		let mut data = Vec::with_capacity(self.row_size << kept.len());
+		for row in 0..1 << kept.len() {
+			// Removed inputs are false in the row that is kept
+			let old_row = kept.iter().rev().enumerate()
+				.filter(|(i, _)| (row >> i) & 1 == 1)
+				.fold(0, |acc, (_, &input)| acc | bit(input));
+			data.extend_from_slice(&self[old_row]);
+		}
+		TruthTable {
+			data,
+			row_size: self.row_size,
+			inputs: kept.iter().map(|&i| self.inputs[i].clone()).collect(),
+			outputs: self.outputs.clone(),
+			widths: self.widths.clone(),
+		}
+	}
+	/// Returns a table with only the given output columns, in the given order. The inputs and rows are unchanged.
+	/// Each column is its own output in the new table, even if it was one of the columns of a digit display.
+	/// Panics if an output is not in this table.
+	pub fn project(&self, outputs: &[&str]) -> TruthTable {
+		let columns: Vec<usize> = outputs.iter()
+			.map(|&name| self.outputs.iter().position(|o| o == name).unwrap_or_else(|| panic!("No output named {name}")))
+			.collect();
+		TruthTable {
+			data: self.rows().flat_map(|row| columns.iter().map(|&c| row[c])).collect(),
+			row_size: columns.len(),
+			inputs: self.inputs.clone(),
+			outputs: columns.iter().map(|&c| self.outputs[c].clone()).collect(),
+			widths: vec![],
+		}
+	}
+	/// Removes the given input columns, if no output depends on them: flipping any of them must never change an output.
+	/// Otherwise, fails with the outputs that do depend on them. Panics if an input is not in this table.
+	pub fn drop_inputs(&self, inputs: &[&str]) -> Result<TruthTable, NotIndependent> {
+		let len = self.inputs.len();
+		let dropped: Vec<usize> = inputs.iter()
+			.map(|&name| self.inputs.iter().position(|i| i == name).unwrap_or_else(|| panic!("No input named {name}")))
+			.collect();
+		let bit = |input: usize| 1 << (len - input - 1);
+		let dependent: Vec<String> = (0..self.outputs.len())
+			.filter(|&output| dropped.iter().any(|&input| (0..self.num_rows()).any(|row| self[row][output] != self[row ^ bit(input)][output])))
+			.map(|output| self.outputs[output].clone())
+			.collect();
+		if !dependent.is_empty() {
+			return Err(NotIndependent { inputs: inputs.iter().map(|s| s.to_string()).collect(), outputs: dependent });
+		}
+		let kept: Vec<usize> = (0..len).filter(|i| !dropped.contains(i)).collect();
+		Ok(self.keep_inputs(&kept))
+	}
+	/// The rows where `predicate` holds, in row order.
+	/// Rows are checked as the iterator is advanced, so taking the first few matches of a large table is cheap.
+	pub fn find<'a>(&'a self, predicate: impl Fn(&TableRow<'a>) -> bool + 'a) -> impl Iterator<Item = TableRow<'a>> + 'a {
+		(0..self.num_rows()).map(move |index| TableRow { table: self, index }).filter(move |row| predicate(row))
+	}
+	/// The rows where the output named `output` is `value`. Panics if there is no such output.
+	pub fn rows_where(&self, output: &str, value: bool) -> impl Iterator<Item = TableRow<'_>> {
+		let column = self.outputs.iter().position(|o| o == output).unwrap_or_else(|| panic!("No output named {output}"));
+		self.find(move |row| row.outputs()[column] == value)
+	}
+	/// The rows where `condition` holds, written with the output names as in [`BoolExpr::parse`], like `carry==1 && sum0==0`.
+	pub fn find_where(&self, condition: &str) -> Result<impl Iterator<Item = TableRow<'_>>> {
+		let names: Vec<&str> = self.outputs.iter().map(|o| &o[..]).collect();
+		let expr = BoolExpr::parse(condition, &names)?;
+		Ok(self.find(move |row| expr.eval(row.outputs())))
+	}
+	/// Draws the table with `T` and `F` for each value, as [`Simulation::format_truth_table`] does.
+	/// Inputs and outputs are sorted by name in reverse, and the rows follow the inputs in that order.
+	/// Outputs with several columns keep their columns together, in order, like `d.0|d.1|d.2|d.3`.
+	///
+	/// `extra` optionally appends a column named `extra.0`, computed by calling `extra.1` on each row's output columns
+	/// in the order they are printed. Rows missing from the end of a partial table, such as a cancelled one, are left out.
+	pub fn format_table(&self, extra: Option<(&str, Reducer)>) -> String {
+		let rows_present = self.data.len() / self.row_size.max(1);
+		let mut out = vec![];
+		self.write_table(&mut out, extra, |row| Ok((row < rows_present).then(|| self[row].to_vec())))
+			.expect("writing to a Vec does not fail");
+		String::from_utf8(out).expect("the table is UTF-8")
+	}
+	/// Writes the table as [`Self::format_table`] does, one line at a time, with the columns of this table but the values from `row_values`.
+	/// `row_values` is called with the index of each row in the order they are printed, and returns its values in column order,
+	/// or None to stop without the remaining rows.
+	fn write_table(&self, out: &mut impl Write, extra: Option<(&str, Reducer)>, mut row_values: impl FnMut(usize) -> Result<Option<Vec<bool>>>) -> Result<()> {
+		let inputs: Vec<usize> = (0..self.inputs.len()).sorted_by(|&a, &b| self.inputs[b].cmp(&self.inputs[a])).collect();
+		let mut groups = vec![];
+		let mut start = 0;
+		for width in self.output_widths().into_iter().map(usize::from) {
+			let first = &self.outputs[start];
+			let name = if width == 1 { &first[..] } else { first.strip_suffix(".0").unwrap_or(first) };
+			groups.push((name, start..start + width));
+			start += width;
+		}
+		groups.sort_by(|(a, _), (b, _)| b.cmp(a));
+		let columns: Vec<usize> = groups.into_iter().flat_map(|(_, columns)| columns).collect();
+		let header_inp = inputs.iter().map(|&i| &self.inputs[i][..]).collect::<Vec<_>>();
+		let header_inp_str = header_inp.join("|");
+		let header_out = columns.iter().map(|&i| &self.outputs[i][..]).collect::<Vec<_>>();
+		let header_out_str = header_out.join("|");
+		let header_extra_str = extra.map(|(name, _)| format!("||{name}")).unwrap_or_default();
+		writeln!(out, "{}||{}{}", header_inp_str, header_out_str, header_extra_str)?;
+		writeln!(out, "{}", "-".repeat(header_inp_str.len() + 2 + header_out_str.len() + header_extra_str.len()))?;
+		let cell = |value: bool, name: &str| format!("{:^width$}", if value { "T" } else { "F" }, width = name.len());
+		let len = inputs.len();
+		for i in 0..self.num_rows() {
+			let values = int_to_bits(i, len as u8);
+			let row = inputs.iter().zip(&values).filter(|&(_, &value)| value).fold(0, |acc, (&input, _)| acc | 1 << (len - input - 1));
+			let Some(row_values) = row_values(row)? else { break };
+			let outputs: Vec<bool> = columns.iter().map(|&column| row_values[column]).collect();
+			let line_inp = values.iter().zip(&header_inp).map(|(&value, name)| cell(value, name)).join("|");
+			let line_out = outputs.iter().zip(&header_out).map(|(&value, name)| cell(value, name)).join("|");
+			let line_extra = extra.map(|(name, reducer)| format!("||{}", cell(reducer(&outputs), name))).unwrap_or_default();
+			writeln!(out, "{line_inp}||{line_out}{line_extra}")?;
+		}
+		Ok(())
+	}
+	/// Lists the inputs of the first `max_rows` rows where `condition` holds (see [`TruthTable::find_where`]), one row per line,
+	/// and how many more rows were left out.
+	pub fn format_matches(&self, condition: &str, max_rows: usize) -> Result<String> {
+		let mut out = String::new();
+		let mut found = self.find_where(condition)?;
+		for row in found.by_ref().take(max_rows) {
+			out += &format!("{}\n", row.format_inputs());
+		}
+		match found.count() {
+			0 if out.is_empty() => out += &format!("No rows where {condition}\n"),
+			0 => {},
+			more => out += &format!("... and {more} more\n"),
+		}
+		Ok(out)
+	}
+	/// Returns this table with the inputs and the output columns each sorted by name, each column being its own output.
+	fn sorted_columns(&self) -> TruthTable {
+		let inputs: Vec<usize> = (0..self.inputs.len()).sorted_by_key(|&i| &self.inputs[i]).collect();
+		let outputs: Vec<usize> = (0..self.outputs.len()).sorted_by_key(|&i| &self.outputs[i]).collect();
+		let bit = |input: usize| 1 << (inputs.len() - input - 1);
+		let data = (0..self.num_rows()).flat_map(|row| {
+			let old_row = inputs.iter().enumerate()
+				.filter(|&(i, _)| row & bit(i) != 0)
+				.fold(0, |acc, (_, &input)| acc | bit(input));
+			outputs.iter().map(move |&output| self[old_row][output])
+		}).collect();
+		TruthTable {
+			data,
+			row_size: self.row_size,
+			inputs: inputs.iter().map(|&i| self.inputs[i].clone()).collect(),
+			outputs: outputs.iter().map(|&i| self.outputs[i].clone()).collect(),
+			widths: vec![],
+		}
+	}
+	/// Checks that this table has the given inputs and outputs, and one full row for every combination of inputs.
+	fn check_shape(&self, inputs: &[&str], outputs: &[String]) -> Result<()> {
+		if self.inputs != inputs || self.outputs != outputs {
+			return Err(anyhow!("expected inputs [{}] and outputs [{}], found inputs [{}] and outputs [{}]",
+				inputs.join(", "), outputs.join(", "), self.inputs.join(", "), self.outputs.join(", ")));
+		}
+		if self.row_size != outputs.len() || self.data.len() != self.num_rows() * self.row_size {
+			return Err(anyhow!("expected {} rows of {} values, found {} values", self.num_rows(), outputs.len(), self.data.len()));
+		}
+		Ok(())
+	}
+	/// Formats each output as a sum of minterms and a product of maxterms.
+	/// Listings with more than `max_terms` terms are replaced by a count.
+	pub fn format_minterms(&self, max_terms: usize) -> String {
+		fn format_terms(symbol: &str, terms: &[usize], max_terms: usize) -> String {
+			if terms.len() > max_terms {
+				format!("{symbol}(...) [{} terms, truncated]", terms.len())
+			} else {
+				format!("{symbol}({})", terms.iter().join(", "))
+			}
+		}
+		let mut out = format!("inputs (MSB first): {}\n", self.inputs.join(", "));
+		for (i, name) in self.outputs.iter().enumerate() {
+			let minterms = self.minterms(i);
+			let maxterms = self.maxterms(i);
+			out += &format!("{name} = {} = {} ({} of {} rows true)\n",
+				format_terms("Σm", &minterms, max_terms),
+				format_terms("ΠM", &maxterms, max_terms),
+				minterms.len(), self.num_rows()
+			);
+		}
+		out
+	}
+}
+/// The outputs that stop [`TruthTable::drop_inputs`] from removing some inputs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NotIndependent {
+	/// The inputs that were to be removed.
+	pub inputs: Vec<String>,
+	/// The outputs that depend on at least one of them, in column order.
+	pub outputs: Vec<String>,
+}
+impl Display for NotIndependent {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Cannot remove inputs [{}]: outputs [{}] depend on them", self.inputs.join(", "), self.outputs.join(", "))
+	}
+}
+impl std::error::Error for NotIndependent {}
+/// A truth table that would need more memory than [`SimulationConfig::table_memory_limit`] allows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableTooLarge {
+	pub inputs: usize,
+	pub outputs: usize,
+	/// Estimated size of the table in bytes, or None if it does not fit in a `usize`.
+	pub bytes: Option<usize>,
+	pub limit: usize,
+}
+impl Display for TableTooLarge {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let bytes = self.bytes.map_or("more than usize::MAX".to_string(), |b| format!("about {b}"));
+		write!(f, "The truth table for {} inputs and {} outputs needs {bytes} bytes, over the limit of {} bytes", self.inputs, self.outputs, self.limit)
+	}
+}
+impl std::error::Error for TableTooLarge {}
+/// The first of each output's columns, given the number of columns of each output.
+fn first_columns(columns: &[bool], widths: &[u8]) -> Vec<bool> {
+	widths.iter().scan(0, |start, &width| {
+		let value = columns[*start];
+		*start += width as usize;
+		Some(value)
+	}).collect()
+}
+/// One row of a [`TruthTable`], as returned by [`TruthTable::find`].
+#[derive(Clone, Copy, Debug)]
+pub struct TableRow<'a> {
+	table: &'a TruthTable,
+	index: usize,
+}
+impl<'a> TableRow<'a> {
+	pub fn index(&self) -> usize {
+		self.index
+	}
+	/// The values of the inputs, most significant bit first.
+	pub fn inputs(&self) -> Vec<bool> {
+		int_to_bits(self.index, self.table.inputs.len() as u8)
+	}
+	/// The values of the outputs, in column order.
+	pub fn outputs(&self) -> &'a [bool] {
+		&self.table[self.index]
+	}
+	/// The value of the input named `name`. Panics if there is no such input.
+	pub fn input(&self, name: &str) -> bool {
+		let column = self.table.inputs.iter().position(|i| i == name).unwrap_or_else(|| panic!("No input named {name}"));
+		self.index >> (self.table.inputs.len() - column - 1) & 1 == 1
+	}
+	/// The value of the output column named `name`. Panics if there is no such column.
+	pub fn output(&self, name: &str) -> bool {
+		let column = self.table.outputs.iter().position(|o| o == name).unwrap_or_else(|| panic!("No output named {name}"));
+		self.outputs()[column]
+	}
+	/// The value of the output object named `name` as a number, with its first column as the least significant bit,
+	/// like the digit shown by a digit display. Panics if there is no such output.
+	pub fn output_value(&self, name: &str) -> u64 {
+		let mut start = 0;
+		for width in self.table.output_widths().into_iter().map(usize::from) {
+			let first = &self.table.outputs[start];
+			if width == 1 && first == name || width > 1 && first.strip_suffix(".0") == Some(name) {
+				return self.outputs()[start..start + width].iter().rev().fold(0, |value, &bit| value << 1 | bit as u64);
+			}
+			start += width;
+		}
+		panic!("No output named {name}")
+	}
+	/// Formats the inputs as `name=value`, separated by commas. See [`format_assignment`].
+	pub fn format_inputs(&self) -> String {
+		format_assignment(&self.table.inputs, &self.inputs())
+	}
+}
+/// Formats the value of each named input as `name=value`, separated by commas.
+/// Inputs named like `a0`, `a1`, `a2` are treated as the bits of one number `a`, and shown in decimal.
+pub fn format_assignment(names: &[String], values: &[bool]) -> String {
+	fn bit_of(name: &str) -> Option<(&str, u32)> {
+		let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+		let bit = name[prefix.len()..].parse::<u32>().ok().filter(|&bit| bit < 64)?;
+		(!prefix.is_empty()).then_some((prefix, bit))
+	}
+	let bus_len = |prefix: &str| names.iter().filter(|n| bit_of(n).is_some_and(|(p, _)| p == prefix)).count();
+	// (name, value, whether it is a group of inputs)
+	let mut fields: Vec<(&str, u64, bool)> = vec![];
+	for (name, &value) in names.iter().zip(values) {
+		match bit_of(name).filter(|&(prefix, _)| bus_len(prefix) >= 2) {
+			Some((prefix, bit)) => match fields.iter_mut().find(|(n, _, bus)| *bus && *n == prefix) {
+				Some((_, total, _)) => *total |= (value as u64) << bit,
+				None => fields.push((prefix, (value as u64) << bit, true)),
+			},
+			None => fields.push((name, value as u64, false)),
+		}
+	}
+	fields.iter().map(|(name, value, _)| format!("{name}={value}")).join(", ")
+}
+/// The first difference between a truth table and the expected one. See [`TruthTable::assert_matches`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+	/// The tables have different inputs, or the same inputs in a different order.
+	Inputs { expected: Vec<String>, found: Vec<String> },
+	/// The tables have different outputs, or the same outputs in a different order.
+	Outputs { expected: Vec<String>, found: Vec<String> },
+	/// The outputs differ in this row. `inputs` are the values of the inputs, most significant bit first.
+	Row { row: usize, inputs: Vec<bool>, expected: Vec<bool>, found: Vec<bool> },
+}
+impl Display for Mismatch {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let bits = |values: &[bool]| values.iter().map(|&b| if b { '1' } else { '0' }).collect::<String>();
+		match self {
+			Mismatch::Inputs { expected, found } => write!(f, "Expected inputs [{}], found [{}]", expected.join(", "), found.join(", ")),
+			Mismatch::Outputs { expected, found } => write!(f, "Expected outputs [{}], found [{}]", expected.join(", "), found.join(", ")),
+			Mismatch::Row { row, inputs, expected, found } =>
+				write!(f, "Row {row} (inputs {}): expected outputs {}, found {}", bits(inputs), bits(expected), bits(found)),
+		}
+	}
+}
+impl std::error::Error for Mismatch {}
+/// The objects that changed in one update pass, with their old and new values. See [`Simulation::step`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepReport {
+	/// Number of update passes run so far with [`Simulation::step`], including this one.
+	pub iteration: u64,
+	/// Empty if the circuit was already stable.
+	pub changes: Vec<ObjectChange>,
+}
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectChange {
+	/// The export name or uid of the object.
+	pub name: String,
+	pub old: Vec<bool>,
+	pub new: Vec<bool>,
+}
+/// The objects that changed in one update pass. See [`Simulation::step_event`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepEvent {
+	/// Number of update passes run so far with [`Simulation::step`] or [`Simulation::step_event`], including this one.
+	pub tick: u64,
+	/// Export name or uid, and new values, of each object that changed.
+	pub changed: Vec<(String, Vec<bool>)>,
+}
+/// The named outputs changed by [`Simulation::apply_inputs`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputDelta {
+	/// Export name and new value of each output that changed, in file order.
+	pub changed: Vec<(String, bool)>,
+}
+/// Statistics about how a circuit stabilized while computing a truth table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConvergenceStats {
+	/// Number of update passes that made changes before each row stabilized, indexed by row.
+	pub iterations: Vec<u128>,
+}
+impl ConvergenceStats {
+	/// Maps a number of iterations to the number of rows that took that many iterations to stabilize.
+	pub fn histogram(&self) -> BTreeMap<u128, usize> {
+		let mut histogram = BTreeMap::new();
+		for &n in &self.iterations {
+			*histogram.entry(n).or_default() += 1;
+		}
+		histogram
+	}
+	/// Index of the first row that took the most iterations to stabilize.
+	pub fn slowest_row(&self) -> usize {
+		self.iterations.iter().enumerate()
+			.max_by_key(|&(i, n)| (n, std::cmp::Reverse(i)))
+			.map_or(0, |(i, _)| i)
+	}
+}
+/// Which gates were seen both low and high by a set of test vectors. See [`Simulation::toggle_coverage`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoverageReport {
+	/// Number of gates, including custom gates, in the top level of the circuit.
+	pub gates: usize,
+	/// Uids of the gates with an output that was never false or never true, in file order.
+	pub untoggled: Vec<String>,
+}
+impl CoverageReport {
+	/// The percentage of gates that were toggled, or 100 if there are no gates.
+	pub fn percentage(&self) -> f64 {
+		if self.gates == 0 { return 100.; }
+		100. * (self.gates - self.untoggled.len()) as f64 / self.gates as f64
+	}
+}
+/// The rows where two named outputs of one circuit differ. See [`Simulation::compare_outputs`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct OutputComparison {
+	/// The two outputs that were compared.
+	pub outputs: [String; 2],
+	/// Export names of the inputs, most significant bit first.
+	pub inputs: Vec<String>,
+	/// Number of rows checked, which is every combination of the inputs.
+	pub rows: usize,
+	/// The rows where the outputs differ, in row order, with the value of the first output in that row.
+	pub differences: Vec<(usize, bool)>,
+}
+impl Display for OutputComparison {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let [a, b] = &self.outputs;
+		if self.differences.is_empty() {
+			return writeln!(f, "{a} and {b} are identical over {} rows", self.rows);
+		}
+		writeln!(f, "{a} and {b} differ in {} of {} rows:", self.differences.len(), self.rows)?;
+		for &(row, value) in &self.differences {
+			let inputs = format_assignment(&self.inputs, &int_to_bits(row, self.inputs.len() as u8));
+			writeln!(f, "  {inputs}: {a}={}, {b}={}", value as u8, !value as u8)?;
+		}
+		Ok(())
+	}
+}
+/// Which encodings of the state bits of a finite state machine can be reached from reset, and how.
+/// See [`Simulation::state_reachability`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ReachabilityReport {
+	/// Export names of the inputs holding the current state, most significant bit first.
+	pub state: Vec<String>,
+	/// Export names of the other named inputs, most significant bit first, which are set at each step.
+	pub inputs: Vec<String>,
+	/// The encoding the machine starts in.
+	pub reset: usize,
+	/// For each state encoding, the number of steps on the shortest path to it from reset, or None if it is unreachable.
+	pub distances: Vec<Option<usize>>,
+	/// For each reachable state other than reset, the state before it on a shortest path and the inputs that lead from there to it.
+	pub previous: Vec<Option<(usize, usize)>>,
+}
+impl ReachabilityReport {
+	/// The encodings that cannot be reached from reset, in increasing order.
+	pub fn unreachable(&self) -> Vec<usize> {
+		(0..self.distances.len()).filter(|&state| self.distances[state].is_none()).collect()
+	}
+	/// The values of [`Self::inputs`] at each step of a shortest path from reset to `state`, or None if it is unreachable.
+	pub fn shortest_path(&self, state: usize) -> Option<Vec<usize>> {
+		self.distances[state]?;
+		let mut path = vec![];
+		let mut state = state;
+		while let Some((before, inputs)) = self.previous[state] {
+			path.push(inputs);
+			state = before;
+		}
+		path.reverse();
+		Some(path)
+	}
+}
+impl Display for ReachabilityReport {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let width = self.state.len() as u8;
+		let header = format!("state ({})", self.state.join(" "));
+		writeln!(f, "{header} | reachable | steps | inputs")?;
+		for (state, distance) in self.distances.iter().enumerate() {
+			let encoding = bit_string(&int_to_bits(state, width));
+			match (distance, self.shortest_path(state)) {
+				(Some(0), _) => writeln!(f, "{encoding:<w$} | yes       | 0     | (reset)", w = header.len())?,
+				(Some(distance), Some(path)) => {
+					let path = path.iter().map(|&inputs| format_assignment(&self.inputs, &int_to_bits(inputs, self.inputs.len() as u8))).join("; ");
+					writeln!(f, "{encoding:<w$} | yes       | {distance:<5} | {path}", w = header.len())?;
+				},
+				_ => writeln!(f, "{encoding:<w$} | no        |       |", w = header.len())?,
+			}
+		}
+		let unreachable = self.unreachable();
+		writeln!(f, "{} of {} states are unreachable", unreachable.len(), self.distances.len())
+	}
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputRelation {
+	/// The outputs are always equal.
+	Equal,
+	/// One output is always the negation of the other.
+	Complement,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CustomSimulation {
+	/// See [`CustomCircuit::display_name`].
+	name: String,
+	/// The parsed circuit, kept to tell whether a new version of the file changed it.
+	circuit: CustomCircuit,
+	simulation: Simulation,
+	/// None if the circuit has too many inputs, or is unstable.
+	table: Option<TruthTable>,
+}
+impl CustomSimulation {
+	/// Computes the truth table, or finds it in the cache directory set in `config`, unless the circuit is too large.
+	/// If a table was given in advance, checks it instead. `hash` is the [`cache::structural_hash`] of the custom circuit.
+	fn compute_table(&mut self, hash: &[u8; 32], config: &SimulationConfig) -> Result<CacheStats> {
+		let simulation = &mut self.simulation;
+		if let Some(table) = &self.table {
+			table.check_shape(&simulation.input_names(), &simulation.output_columns())
+				.map_err(|e| anyhow!("Invalid truth table for custom circuit {}: {e}", self.name))?;
+			return Ok(CacheStats::default());
+		}
+		if simulation.named_input_count() > config.custom_table_max_inputs { return Ok(CacheStats::default()) }
+		let task = || ProgressTask::CustomTable(self.name.clone());
+		// Tables found by output convergence could differ from the ones other runs expect to find
+		let Some(dir) = config.cache_dir.as_ref().filter(|_| config.output_convergence.is_none()) else {
+			self.table = Self::table_unless_cancelled(simulation.compute_truth_table(config.cycle_limit, false, task))?;
+			return Ok(CacheStats::default());
+		};
+		self.table = cache::read(dir, hash)
+			.filter(|table| table.check_shape(&simulation.input_names(), &simulation.output_columns()).is_ok());
+		if self.table.is_some() { return Ok(CacheStats { hits: 1, misses: 0 }) }
+		self.table = Self::table_unless_cancelled(simulation.compute_truth_table(config.cycle_limit, false, task))?;
+		if let Some(table) = &self.table { cache::write(dir, hash, table)?; }
+		Ok(CacheStats { hits: 0, misses: 1 })
+	}
+	/// Custom circuits without a table are simulated live, but a cancelled computation has to stop.
+	fn table_unless_cancelled(result: Result<(TruthTable, ConvergenceStats)>) -> Result<Option<TruthTable>> {
+		match result {
+			Ok((table, _)) => Ok(Some(table)),
+			Err(error) if error.is::<Cancelled>() => Err(error),
+			Err(_) => Ok(None),
+		}
+	}
+}
+/// The custom circuits available to a simulation, keyed by uid. Each custom circuit is built once, and shared by every simulation
+/// that uses it, including the simulations of other custom circuits.
+type CustomCircuitMap = HashMap<String, Arc<CustomSimulation>>;
+
+/// How an object computes its values during an update pass.
+#[derive(Debug, Clone, PartialEq)]
+enum Op {
+	/// Inputs and labels do not change themselves.
+	Fixed,
+	Gate(SimpleGateType, XorType),
+	/// A custom gate, identified by the uuid of its definition.
+	Custom(String),
+	Output,
+}
+/// An input of an object, as the index of the value driving it in the state buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Wire {
+	/// None if the input is unconnected, which reads as false.
+	source: Option<usize>,
+	inverted: bool,
+}
+
+/// Options for building and running a [`Simulation`], set with builder methods on [`SimulationConfig::default`].
+/// The defaults match [`Simulation`]'s `From<Circuit>` implementation.
+///
+/// ```ignore
+/// let config = SimulationConfig::default().cycle_limit(100).live_customs(true);
+/// let simulation = Simulation::with_config(circuit, config)?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulationConfig {
+	custom_table_max_inputs: usize,
+	cycle_limit: u128,
+	lookup_table_max_inputs: usize,
+	live_customs: bool,
+	strict: bool,
+	saved_inputs: bool,
+	cache_dir: Option<PathBuf>,
+	threads: usize,
+	output_convergence: Option<u32>,
+	table_memory_limit: usize,
+	pub(crate) progress: ProgressHook,
+	cancel: Option<CancellationToken>,
+}
+impl Default for SimulationConfig {
+	fn default() -> Self {
+		Self {
+			custom_table_max_inputs: 24, //max 16Mb per output
+			cycle_limit: 1000,
+			lookup_table_max_inputs: 16, //max 64Kb for the top level table
+			live_customs: false,
+			strict: false,
+			saved_inputs: false,
+			cache_dir: None,
+			threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+			output_convergence: None,
+			table_memory_limit: 1 << 20,
+			progress: ProgressHook::default(),
+			cancel: None,
+		}
+	}
+}
+impl SimulationConfig {
+	/// Custom circuits with more named inputs than this are simulated live instead of using a truth table. Defaults to 24,
+	/// which allows tables of up to 16 MiB per output column. This is the only limit on the size of custom circuit tables:
+	/// [`Self::table_memory_limit`] does not apply to them.
+	pub fn custom_table_max_inputs(mut self, max: usize) -> Self {
+		self.custom_table_max_inputs = max;
+		self
+	}
+	/// Maximum number of update passes when computing the truth tables of custom circuits and the lookup table,
+	/// when stepping live custom gates, and in [`Simulation::as_fn`]. Defaults to 1000.
+	pub fn cycle_limit(mut self, limit: u128) -> Self {
+		self.cycle_limit = limit;
+		self
+	}
+	/// [`Simulation::use_lookup_table`] does nothing for circuits with more named inputs than this. Defaults to 16.
+	pub fn lookup_table_max_inputs(mut self, max: usize) -> Self {
+		self.lookup_table_max_inputs = max;
+		self
+	}
+	/// Simulate every custom gate instance live, as if [`Simulation::use_live_customs`] was called. Defaults to false.
+	pub fn live_customs(mut self, live: bool) -> Self {
+		self.live_customs = live;
+		self
+	}
+	/// Fail to build the simulation if any output has nothing connected to it, like [`Simulation::from_strict`]. Defaults to false.
+	pub fn strict(mut self, strict: bool) -> Self {
+		self.strict = strict;
+		self
+	}
+	/// When resetting the simulation, as [`Simulation::get_outputs`] does, put switches in the position saved in the file instead of off.
+	/// Defaults to false. Truth tables are not affected, as they set every input.
+	pub fn saved_inputs(mut self, saved: bool) -> Self {
+		self.saved_inputs = saved;
+		self
+	}
+	/// Look for the truth tables of custom circuits in this directory before computing them, and store them there after.
+	/// Tables are found by a hash of the custom circuit's definition, so editing a custom circuit never reuses its old table.
+	/// Defaults to None, which does not use a cache. See [`default_cache_dir`].
+	pub fn cache_dir(mut self, dir: Option<PathBuf>) -> Self {
+		self.cache_dir = dir;
+		self
+	}
+	/// Number of threads used to compute the truth tables of custom circuits that do not use each other.
+	/// Defaults to the number of threads the system can run in parallel.
+	pub fn threads(mut self, threads: usize) -> Self {
+		self.threads = threads;
+		self
+	}
+	/// Treat the circuit as stable once no output has changed for this many update passes in a row,
+	/// even if other objects are still changing. Defaults to None, which waits until nothing changes.
+	///
+	/// This gives truth tables for circuits with oscillators whose values never reach an output, but it can stop too early:
+	/// a change still travelling towards an output is missed if it takes longer than `passes` update passes to get there.
+	/// Use more passes than the longest path from an input to an output. The cache directory is not used in this mode.
+	pub fn output_convergence(mut self, passes: Option<u32>) -> Self {
+		self.output_convergence = passes;
+		self
+	}
+	/// Refuse to compute truth tables that would need more than this many bytes, counting the 16 bytes per row
+	/// kept by [`Simulation::get_truth_table_with_stats`]. Defaults to 1 MiB, which is 2^16 rows of 16 outputs.
+	/// See [`Simulation::try_get_truth_table`]. This applies to the tables of whole circuits, including the lookup table,
+	/// but not to custom circuit tables, which are limited by [`Self::custom_table_max_inputs`] instead.
+	pub fn table_memory_limit(mut self, bytes: usize) -> Self {
+		self.table_memory_limit = bytes;
+		self
+	}
+	/// Call `callback` every 1024 rows, and after the last row, while computing a truth table,
+	/// including the tables of custom circuits and the rows printed by [`Simulation::format_truth_table`].
+	/// [`crate::batch::check_files`] calls it after each file instead. Defaults to no callback, which costs nothing.
+	/// The callback is called from the threads computing tables, and clones of this config share it.
+	pub fn progress(mut self, callback: ProgressCallback) -> Self {
+		self.progress = ProgressHook::new(callback);
+		self
+	}
+	/// Stop computing truth tables, including those of custom circuits, and comparing outputs, once `token` is cancelled.
+	/// They fail with a [`Cancelled`] error at the next row, and [`Simulation::format_truth_table`] returns the rows so far.
+	/// Defaults to None, which cannot be cancelled.
+	pub fn cancellation(mut self, token: CancellationToken) -> Self {
+		self.cancel = Some(token);
+		self
+	}
+	pub(crate) fn is_cancelled(&self) -> bool {
+		self.cancel.as_ref().is_some_and(CancellationToken::is_cancelled)
+	}
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Simulation {
+	/// The parsed objects, which are only used to look up names.
 	objects: Vec<SObject>,
+	/// The index of the object with each uid, interned once so lookups by uid do not scan [`Self::objects`].
+	uids: HashMap<String, usize>,
+	ops: Vec<Op>,
+	/// The values of all objects. Object `i` owns `state[slots[i]..slots[i + 1]]`.
+	state: Vec<bool>,
+	slots: Vec<usize>,
+	/// The inputs of all objects. Object `i` reads the `len` wires starting at `wires[start]`, where `wire_ranges[i] = (start, len)`.
+	wires: Vec<Wire>,
+	wire_ranges: Vec<(usize, usize)>,
 	customs: CustomCircuitMap,
+	/// If set, the truth table of the whole circuit, which is used instead of simulating. See [`Self::use_lookup_table`].
+	lookup: Option<TruthTable>,
+	config: SimulationConfig,
+	cache_stats: CacheStats,
+	/// Number of update passes run by [`Self::step`].
+	tick: u64,
+	/// The named output values last returned by [`Self::take_output_changes`]. None means all false, as after a reset.
+	reported_outputs: Option<Vec<bool>>,
+	observers: Observers,
+	input_sources: InputSources,
 }
 impl From<Circuit> for Simulation {
 	fn from(value: Circuit) -> Self {
-		let customs_list = value.customs.unwrap_or_else(|| vec![]);
-		let mut customs:CustomCircuitMap = HashMap::with_capacity(customs_list.len());
-		for custom in customs_list {
-			let mut simulation = Simulation::from(custom.objects, customs.clone());
-			let truth_table = if simulation.inputs_mut().count() > Simulation::truth_table_max_length { None }
-			else { simulation.get_truth_table(Simulation::truth_table_max_iterations) };
-			customs.insert(custom.uid, (simulation, truth_table));
-		}
-		Self {
-			objects: value.objects.into_iter().map(SObject::from).collect(),
-			customs
-		}
+		Simulation::with_config(value, SimulationConfig::default()).expect("the default config is not strict")
 	}
 }
 impl Simulation {
-	const truth_table_max_length: usize = 24; //max 1Mb per table
-	const truth_table_max_iterations: u128 = 1000; //max 1000 iterations per table
-	fn from(objects: Vec<Object>, customs: CustomCircuitMap) -> Self {
-		Self {
+	/// Simulates a circuit with the options in `config`.
+	pub fn with_config(circuit: Circuit, config: SimulationConfig) -> Result<Simulation> {
+		Simulation::with_tables_and_config(circuit, HashMap::new(), config)
+	}
+	/// Like [`Self::with_config`], but uses the truth tables in `tables` (keyed by custom circuit uid) instead of computing them.
+	fn with_tables_and_config(circuit: Circuit, tables: HashMap<String, TruthTable>, config: SimulationConfig) -> Result<Simulation> {
+		if config.strict {
+			let unconnected = circuit.unconnected_outputs();
+			if !unconnected.is_empty() {
+				return Err(anyhow!("Unconnected outputs: {}", unconnected.join(", ")));
+			}
+		}
+		let (customs, cache_stats) = Simulation::build_customs_with(circuit.customs.unwrap_or_default(), tables, &config)?;
+		let mut simulation = Simulation::from(circuit.objects, customs, config);
+		simulation.cache_stats = cache_stats;
+		if simulation.config.live_customs { simulation.use_live_customs(); }
+		Ok(simulation)
+	}
+	fn from(objects: Vec<Object>, customs: CustomCircuitMap, config: SimulationConfig) -> Self {
+		let mut state = vec![];
+		let mut slots = Vec::with_capacity(objects.len() + 1);
+		for obj in &objects {
+			slots.push(state.len());
+			let (count, value) = match &obj.inner {
+				// For now all gates have only 1 output
+				ObjectInner::SimpleGate { .. } => (1, false),
+				ObjectInner::CustomGate { num_outputs, .. } => (*num_outputs as usize, false),
+				ObjectInner::Output { connections, .. } => (connections.len(), false),
+				&ObjectInner::Input { value, .. } => (1, value),
+				ObjectInner::Label { .. } => (0, false),
+			};
+			state.extend(std::iter::repeat_n(value, count));
+		}
+		slots.push(state.len());
+		let mut wires = vec![];
+		let mut wire_ranges = Vec::with_capacity(objects.len());
+		for obj in &objects {
+			wire_ranges.push((wires.len(), obj.connections().len()));
+			let inverted = obj.inverted_inputs().iter().chain(std::iter::repeat(&false));
+			wires.extend(obj.connections().iter().zip(inverted).map(|(c, &inverted)| Wire {
+				source: c.map(|(idx, ptr)| slots[ptr] + idx as usize),
+				inverted,
+			}));
+		}
+		let ops = objects.iter().map(|obj| match &obj.inner {
+			&ObjectInner::SimpleGate { kind, xor_type, .. } => Op::Gate(kind, xor_type),
+			ObjectInner::CustomGate { uuid, .. } => Op::Custom(uuid.clone()),
+			ObjectInner::Output { .. } => Op::Output,
+			ObjectInner::Input { .. } | ObjectInner::Label { .. } => Op::Fixed,
+		}).collect();
+		let mut uids = HashMap::with_capacity(objects.len());
+		for (i, obj) in objects.iter().enumerate() {
+			uids.entry(obj.uid().to_string()).or_insert(i);
+		}
+		let mut simulation = Self {
 			objects: objects.into_iter().map(SObject::from).collect(),
+			uids,
+			ops, state, slots, wires, wire_ranges,
 			customs,
+			lookup: None,
+			config,
+			cache_stats: CacheStats::default(),
+			tick: 0,
+			reported_outputs: None,
+			observers: Observers::default(),
+			input_sources: InputSources::default(),
+		};
+		simulation.attach_live_instances(false);
+		simulation
+	}
+	/// Gives custom gate instances their own nested simulation, instead of looking up their output in a cached truth table.
+	/// This is always done for custom circuits that have no truth table; `all` also does it for every other instance.
+	fn attach_live_instances(&mut self, all: bool) {
+		for obj in &mut self.objects {
+			if let ObjectInner::CustomGate { uuid, .. } = &obj.object.inner {
+				let custom = &self.customs[uuid];
+				if all || custom.table.is_none() {
+					let mut live = custom.simulation.clone();
+					if all { live.attach_live_instances(true); }
+					obj.live = Some(Box::new(live));
+				}
+			}
+		}
+	}
+	/// Simulates every custom gate instance live, including nested ones, so their internal nodes can be read with [`Self::peek_custom`].
+	/// This is slower than using cached truth tables, but allows custom circuits to hold state.
+	pub fn use_live_customs(&mut self) {
+		self.attach_live_instances(true);
+		self.lookup = None;
+	}
+	/// If the circuit is combinational and has at most 16 named inputs, computes its truth table,
+	/// so that [`Self::get_outputs`] and [`Self::get_outputs_wide`] look up their result instead of simulating.
+	/// Returns whether a lookup table is in use.
+	///
+	/// This is the same caching that is done for custom circuits. Building the table costs about one simulation per row,
+	/// so it pays for itself once there are more calls than rows: for 2000 gates and 12 inputs, a lookup is about 2000 times faster
+	/// than a simulation (see `bench_lookup_table`). Lookups do not update the values of any objects.
+	///
+	/// The table is dropped when inputs are set with [`Self::get_inputs_mut`] or [`Self::inputs_mut`], or driven with
+	/// [`Self::set_input_source`], and it is not built while any input is driven by a source.
+	pub fn use_lookup_table(&mut self) -> bool {
+		if self.lookup.is_none() && self.input_sources.is_empty() && self.named_input_count() <= self.config.lookup_table_max_inputs && self.is_combinational() {
+			self.lookup = self.compute_truth_table(self.config.cycle_limit, false, || ProgressTask::TruthTable).ok().map(|(table, _)| table);
+		}
+		self.lookup.is_some()
+	}
+	/// Computes a hash of what the circuit does, which is the same for any two circuits with the same inputs and outputs
+	/// and the same truth table, however they are laid out or built.
+	///
+	/// This is the SHA-256 hash of the truth table written by [`TruthTable::write_binary`],
+	/// with the inputs and outputs each sorted by name instead of in the usual order.
+	/// Fails if the circuit is sequential or unstable, as its outputs would not only depend on its inputs,
+	/// or if its table would be over [`SimulationConfig::table_memory_limit`].
+	pub fn fingerprint(&mut self, cycle_limit: u128) -> Result<[u8; 32]> {
+		if !self.is_combinational() {
+			return Err(anyhow!("Cannot fingerprint a sequential circuit"));
+		}
+		let table = self.try_get_truth_table(cycle_limit)?;
+		let mut bytes = vec![];
+		table.sorted_columns().write_binary(&mut bytes)?;
+		Ok(Sha256::digest(&bytes).into())
+	}
+	/// Whether the circuit has no feedback loops, and no custom gates that are simulated live.
+	fn is_combinational(&self) -> bool {
+		if self.objects.iter().any(|o| o.live.is_some()) { return false }
+		// The object that owns a value in the state buffer.
+		let owner = |slot: usize| self.slots.partition_point(|&start| start <= slot) - 1;
+		let sources = |i: usize| {
+			let (start, len) = self.wire_ranges[i];
+			self.wires[start..start + len].iter().filter_map(|w| w.source).map(owner)
+		};
+		#[derive(Clone, Copy, PartialEq)]
+		enum Visit { New, Active, Done }
+		let mut visits = vec![Visit::New; self.objects.len()];
+		for root in 0..self.objects.len() {
+			if visits[root] != Visit::New { continue }
+			visits[root] = Visit::Active;
+			let mut stack = vec![(root, sources(root))];
+			while let Some((i, iter)) = stack.last_mut() {
+				match iter.next() {
+					Some(next) if visits[next] == Visit::Active => return false,
+					Some(next) if visits[next] == Visit::New => {
+						visits[next] = Visit::Active;
+						stack.push((next, sources(next)));
+					},
+					Some(_) => {},
+					None => {
+						visits[*i] = Visit::Done;
+						stack.pop();
+					},
+				}
+			}
+		}
+		true
+	}
+	/// Reads the values of the object `inner_uid` inside the custom gate instance `instance_uid`.
+	///
+	/// Only works for live instances: returns None for instances that use a cached truth table,
+	/// unless [`Self::use_live_customs`] was called.
+	pub fn peek_custom(&self, instance_uid: &str, inner_uid: &str) -> Option<&[bool]> {
+		let live = self.objects[*self.uids.get(instance_uid)?].live.as_ref()?;
+		live.uids.get(inner_uid).map(|&i| live.values(i))
+	}
+	/// The values of the object at index `i`.
+	fn values(&self, i: usize) -> &[bool] {
+		&self.state[self.slots[i]..self.slots[i + 1]]
+	}
+	/// Indices in the state buffer of the named inputs, in file order.
+	fn input_slots(&self) -> Vec<usize> {
+		(0..self.objects.len()).filter(|&i| self.objects[i].is_named_input()).map(|i| self.slots[i]).collect()
+	}
+	/// Values of the named outputs, in file order.
+	fn output_values(&self) -> Vec<bool> {
+		(0..self.objects.len()).filter(|&i| self.objects[i].is_named_output()).map(|i| self.state[self.slots[i]]).collect()
+	}
+	/// Values of every input of the named outputs, in file order, so a digit display has 4 values.
+	fn output_bits(&self) -> Vec<bool> {
+		(0..self.objects.len()).filter(|&i| self.objects[i].is_named_output()).flat_map(|i| self.values(i).to_vec()).collect()
+	}
+	/// Number of values of each named output in [`Self::output_bits`], in file order.
+	fn output_widths(&self) -> Vec<u8> {
+		(0..self.objects.len()).filter(|&i| self.objects[i].is_named_output()).map(|i| self.values(i).len() as u8).collect()
+	}
+	/// Sets the named inputs without resetting the state, then returns the named outputs once stable.
+	/// Fails with the outputs reached so far if the circuit did not stabilize within [`SimulationConfig::cycle_limit`] update passes.
+	fn step_live(&mut self, inputs: &[bool]) -> Result<Vec<bool>, Vec<bool>> {
+		for (slot, &val) in self.input_slots().into_iter().zip(inputs) {
+			self.state[slot] = val;
+		}
+		let stable = self.update_until_done(self.config.cycle_limit);
+		if stable { Ok(self.output_values()) } else { Err(self.output_values()) }
+	}
+	/// The custom circuits must be in dependency order.
+	fn build_customs(customs_list: Vec<CustomCircuit>, config: &SimulationConfig) -> CustomCircuitMap {
+		let (customs, _) = Simulation::build_customs_with(customs_list, HashMap::new(), &config.clone().cache_dir(None))
+			.expect("there are no precomputed tables to check, and no cache to write to");
+		customs
+	}
+	/// Like [`Self::build_customs`], but uses the tables in `precomputed` (keyed by custom circuit uid) instead of computing them,
+	/// and uses the cache directory set in `config`.
+	fn build_customs_with(customs_list: Vec<CustomCircuit>, mut precomputed: HashMap<String, TruthTable>, config: &SimulationConfig) -> Result<(CustomCircuitMap, CacheStats)> {
+		let mut customs:CustomCircuitMap = HashMap::with_capacity(customs_list.len());
+		let mut hashes = HashMap::new();
+		let mut stats = CacheStats::default();
+		let mut errors = vec![];
+		// Custom circuits in the same layer do not use each other, so their tables can be computed in parallel
+		for layer in Simulation::dependency_layers(customs_list) {
+			let mut layer: Vec<_> = layer.into_iter().map(|custom| {
+				let hash = cache::structural_hash(&custom, &hashes);
+				let simulation = CustomSimulation {
+					name: custom.display_name().to_string(),
+					// Custom circuit tables are limited by custom_table_max_inputs, not by the memory limit
+					simulation: Simulation::from(custom.objects.clone(), customs.clone(), config.clone().table_memory_limit(usize::MAX)),
+					table: precomputed.remove(&custom.uid),
+					circuit: custom,
+				};
+				(simulation.circuit.uid.clone(), hash, simulation)
+			}).collect();
+			let results = parallel_map(&mut layer, config.threads, |(_, hash, custom)| custom.compute_table(hash, config));
+			for ((uid, hash, custom), result) in layer.into_iter().zip(results) {
+				match result {
+					Ok(custom_stats) => {
+						stats.hits += custom_stats.hits;
+						stats.misses += custom_stats.misses;
+					},
+					Err(error) => errors.push(error),
+				}
+				hashes.insert(uid.clone(), hash);
+				customs.insert(uid, Arc::new(custom));
+			}
+		}
+		if let Some(uid) = precomputed.keys().next() {
+			errors.push(anyhow!("Truth table for unknown custom circuit {uid}"));
+		}
+		if let Some(index) = errors.iter().position(|e| e.is::<Cancelled>()) {
+			return Err(errors.swap_remove(index));
+		}
+		match errors.len() {
+			0 => Ok((customs, stats)),
+			1 => Err(errors.remove(0)),
+			_ => Err(anyhow!("{}", errors.iter().join("\n"))),
+		}
+	}
+	/// Groups custom circuits so that each one only uses custom circuits from earlier groups.
+	/// The custom circuits must be in dependency order.
+	fn dependency_layers(customs_list: Vec<CustomCircuit>) -> Vec<Vec<CustomCircuit>> {
+		let mut layer_of: HashMap<String, usize> = HashMap::new();
+		let mut layers: Vec<Vec<CustomCircuit>> = vec![];
+		for custom in customs_list {
+			let layer = custom.objects.iter().filter_map(|o| match &o.inner {
+				ObjectInner::CustomGate { uuid, .. } => layer_of.get(uuid).map(|l| l + 1),
+				_ => None,
+			}).max().unwrap_or(0);
+			layer_of.insert(custom.uid.clone(), layer);
+			if layers.len() <= layer { layers.resize_with(layer + 1, Vec::new); }
+			layers[layer].push(custom);
+		}
+		layers
+	}
+	/// Like `Simulation::from`, but uses truth tables from [`Self::export_tables`] instead of computing them.
+	/// Custom circuits without a table in `tables` are handled as usual.
+	pub fn from_tables(circuit: Circuit, tables: HashMap<String, TruthTable>) -> Result<Simulation> {
+		Simulation::with_tables_and_config(circuit, tables, SimulationConfig::default())
+	}
+	/// Parses a new version of the circuit this simulation was built from, and simulates it,
+	/// reusing the truth tables of custom circuits that have not changed. This makes reloading a file after each save fast
+	/// when it has a large custom circuit library.
+	pub fn reparse_incremental(&self, new_xml: &str) -> Result<Simulation> {
+		let circuit = parse_xml(new_xml)?;
+		let tables = self.reusable_tables(circuit.custom_library());
+		Simulation::with_tables_and_config(circuit, tables, self.config.clone())
+	}
+	/// The truth tables of this simulation that are still correct for `customs`: those of custom circuits
+	/// with the same structure (see [`CustomCircuit::same_structure`]), and that only use custom circuits that are also unchanged.
+	fn reusable_tables(&self, customs: &[CustomCircuit]) -> HashMap<String, TruthTable> {
+		let mut unchanged = HashSet::new();
+		let mut tables = HashMap::new();
+		for custom in customs {
+			let Some(old) = self.customs.get(&custom.uid) else { continue };
+			let same = old.circuit.same_structure(custom)
+				&& custom.objects.iter().all(|o| match &o.inner {
+					ObjectInner::CustomGate { uuid, .. } => unchanged.contains(uuid),
+					_ => true,
+				});
+			if same {
+				unchanged.insert(&custom.uid);
+				if let Some(table) = &old.table {
+					tables.insert(custom.uid.clone(), table.clone());
+				}
+			}
 		}
+		tables
+	}
+	/// How many custom circuit truth tables were read from the cache directory, if one was set in [`SimulationConfig::cache_dir`].
+	pub fn cache_stats(&self) -> CacheStats {
+		self.cache_stats
+	}
+	/// The truth tables computed for custom circuits, keyed by custom circuit uid.
+	/// Custom circuits that are too large or unstable have no table.
+	pub fn export_tables(&self) -> HashMap<String, TruthTable> {
+		self.customs.iter()
+			.filter_map(|(uid, custom)| Some((uid.clone(), custom.table.clone()?)))
+			.collect()
+	}
+	/// Like `Simulation::from`, but inputs that are not given to [`Self::get_outputs`] keep the position saved in the file,
+	/// so the outputs match what Logicly shows when the file is opened.
+	pub fn from_saved_state(circuit: Circuit) -> Simulation {
+		Simulation::with_config(circuit, SimulationConfig::default().saved_inputs(true)).expect("the config is not strict")
+	}
+	/// Like `Simulation::from`, but fails if any output is unconnected, naming the outputs as [`Circuit::unconnected_outputs`] does.
+	/// The same check can be combined with other settings through [`SimulationConfig::strict`].
+	pub fn from_strict(circuit: Circuit) -> Result<Simulation> {
+		Simulation::with_config(circuit, SimulationConfig::default().strict(true))
+	}
+	/// Creates a simulation of one of the circuit's custom circuits, identified by name or uid.
+	/// The custom circuit's named inputs and outputs become the simulation's inputs and outputs.
+	pub fn from_custom(circuit: &Circuit, name: &str) -> Result<Simulation> {
+		let library = circuit.custom_library();
+		let custom = library.iter().find(|c| c.name == name || c.uid == name)
+			.ok_or_else(|| anyhow!("No custom circuit named {name}"))?;
+		let config = SimulationConfig::default();
+		let dependencies = Simulation::dependency_closure(library, &custom.objects);
+		Ok(Simulation::from(custom.objects.clone(), Simulation::build_customs(dependencies, &config), config))
+	}
+	/// The custom circuits of `library` that `objects` use, directly or through other custom circuits, in dependency order.
+	/// The library must be in dependency order.
+	fn dependency_closure(library: &[CustomCircuit], objects: &[Object]) -> Vec<CustomCircuit> {
+		let uses = |objects: &[Object]| objects.iter().filter_map(|o| match &o.inner {
+			ObjectInner::CustomGate { uuid, .. } => Some(uuid.clone()),
+			_ => None,
+		}).collect::<Vec<_>>();
+		let mut needed: HashSet<String> = uses(objects).into_iter().collect();
+		let mut closure: Vec<CustomCircuit> = library.iter().rev().filter(|custom| {
+			if !needed.contains(&custom.uid) { return false }
+			needed.extend(uses(&custom.objects));
+			true
+		}).cloned().collect();
+		closure.reverse();
+		closure
 	}
 	pub fn print_outputs(&self){
-		for obj in &self.objects {
+		for (i, obj) in self.objects.iter().enumerate() {
 			if obj.is_output() || matches!(obj.object.inner, ObjectInner::Input { .. }) {
-				println!("{}: {:?}", obj.export_name_or_uid(), obj.values)
+				println!("{}: {:?}", obj.export_name_or_uid(), self.values(i))
 			}
 		}
 	}
 	/// Returns a mutable reference to all inputs with an export name, in the form of a hash map.
-	/// Panics if multiple inputs have the same export name.
+	/// If multiple inputs have the same export name, only one of them is included.
+	/// Drops the lookup table, see [`Self::use_lookup_table`].
 	pub fn get_inputs_mut(&mut self) -> HashMap<&str, &mut bool> {
-		let mut map = HashMap::new();
-		for obj in &mut self.objects {
-			match &mut obj.object.inner {
-				ObjectInner::Input {
-					export_name: Some(name),
-					kind: InputType::Button | InputType::Switch,
-					..
-				} => { map.insert(&name[..], obj.values.get_mut(0).unwrap()); },
-				_ => {}
-			}
-		}
-		map
+		self.lookup = None;
+		let Self { objects, state, slots, .. } = self;
+		let names: HashMap<usize, &str> = objects.iter().enumerate().flat_map(|(i, o)| match &o.object.inner {
+			ObjectInner::Input {
+				export_name: Some(name),
+				kind: InputType::Button | InputType::Switch,
+				..
+			} => Some((slots[i], &name[..])),
+			_ => None,
+		}).collect();
+		state.iter_mut().enumerate().flat_map(|(slot, val)| names.get(&slot).map(|name| (*name, val))).collect()
 	}
-	pub fn inputs_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut SObject> {
-		self.objects.iter_mut().flat_map(|o| match &mut o.object.inner {
-			ObjectInner::Input { export_name: Some(_), .. } => Some(o),
-			_ => None
-		})
+	/// Returns a mutable reference to the value of each named input, in the order of [`Self::input_names`].
+	/// Drops the lookup table, like [`Self::get_inputs_mut`].
+	pub fn inputs_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut bool> {
+		self.lookup = None;
+		let slots: HashSet<usize> = self.input_slots().into_iter().collect();
+		self.state.iter_mut().enumerate().filter(move |(slot, _)| slots.contains(slot)).map(|(_, val)| val)
+	}
+	/// Makes the named switch or button take the value returned by `source` at the start of each update pass.
+	/// `source` is given the number of update passes run so far by [`Self::step`], so it returns the same value
+	/// while other methods update the circuit until it is stable. The value overrides any set with [`Self::get_inputs_mut`] or [`Self::get_outputs`],
+	/// and replaces any earlier source for the same input.
+	pub fn set_input_source(&mut self, name: &str, source: Box<dyn FnMut(u64) -> bool + Send + Sync>) -> Result<()> {
+		let index = self.objects.iter().position(|o| matches!(&o.object.inner, ObjectInner::Input {
+			export_name: Some(export_name),
+			kind: InputType::Button | InputType::Switch,
+			..
+		} if export_name == name)).ok_or_else(|| anyhow!("No input named {name}"))?;
+		self.input_sources.set(index, source);
+		self.lookup = None;
+		Ok(())
+	}
+	/// Stops driving the named input with a callback. Returns false if it was not driven by one.
+	pub fn clear_input_source(&mut self, name: &str) -> bool {
+		self.lookup = None;
+		self.objects.iter().enumerate()
+			.filter(|(_, o)| o.is_named_input() && o.export_name_or_uid() == name)
+			.any(|(i, _)| self.input_sources.remove(i))
 	}
 	pub fn outputs(&self) -> impl Iterator<Item = &SObject> {
 		self.objects.iter().flat_map(|o| match &o.object.inner {
@@ -81,129 +1224,790 @@ impl Simulation {
 	}
 	/// Returns if any changes were made.
 	pub fn update_all_once(&mut self) -> bool {
+		self.update_pass(|_| {})
+	}
+	/// Updates every object once, calling `on_change` with the index of each object whose value changed.
+	/// Returns if any changes were made.
+	fn update_pass(&mut self, mut on_change: impl FnMut(usize)) -> bool {
+		let Self { objects, uids: _, ops, state, slots, wires, wire_ranges, customs, lookup: _, config: _, cache_stats: _, tick, reported_outputs: _, observers: _, input_sources } = self;
 		let mut changed = false;
-		for i in 0..self.objects.len() {
-			let obj = &self.objects[i];
-			if let Some(new_val) = obj.get_new_value(&self.objects, &mut self.customs) {
-				if new_val != self.objects[i].values { changed = true }
-				self.objects[i].values = new_val;
+		for i in input_sources.update(state, slots, *tick) {
+			changed = true;
+			on_change(i);
+		}
+		let mut inputs = vec![];
+		for (i, op) in ops.iter().enumerate() {
+			if *op == Op::Fixed { continue }
+			let (start, len) = wire_ranges[i];
+			inputs.clear();
+			inputs.extend(wires[start..start + len].iter().map(|w| w.source.is_some_and(|s| state[s]) != w.inverted));
+			let live_outputs;
+			let table_outputs;
+			let new_val: &[bool] = match op {
+				Op::Fixed => unreachable!(),
+				&Op::Gate(kind, xor_type) => &[evaluate_gate(kind, xor_type, &inputs)],
+				Op::Output => &inputs,
+				Op::Custom(uuid) => match &mut objects[i].live {
+					Some(live) => {
+						// An instance that has not settled counts as a change, so the whole circuit is only stable once it has
+						live_outputs = live.step_live(&inputs).unwrap_or_else(|outputs| { changed = true; outputs });
+						&live_outputs
+					},
+					None => {
+						let table = customs[uuid].table.as_ref().expect("custom gates without a truth table are simulated live");
+						table_outputs = table.output_values(bits_to_int(inputs.iter()));
+						&table_outputs
+					},
+				},
+			};
+			let values = &mut state[slots[i]..slots[i + 1]];
+			if values != new_val {
+				values.copy_from_slice(new_val);
+				changed = true;
+				on_change(i);
 			}
 		}
 		changed
 	}
 	/// Returns true if the update was successful, and false if the limit was reached.
 	pub fn update_until_done(&mut self, limit: u128) -> bool {
-		for _ in 1..limit {
-			if !self.update_all_once() { return true; }
+		self.update_until_stable(limit).is_some()
+	}
+	/// Returns the number of update passes that made changes before the circuit stabilized,
+	/// or None if the limit was reached.
+	///
+	/// With [`SimulationConfig::output_convergence`], the circuit is also stable once the outputs stop changing,
+	/// and the passes after the last change to an output are not counted.
+	fn update_until_stable(&mut self, limit: u128) -> Option<u128> {
+		let outputs: Vec<usize> = (0..self.objects.len()).filter(|&i| matches!(self.objects[i].inner, ObjectInner::Output { .. })).collect();
+		let output_values = |simul: &Simulation| outputs.iter().flat_map(|&i| simul.values(i).to_vec()).collect::<Vec<_>>();
+		let mut last_outputs = output_values(self);
+		let mut unchanged: u32 = 0;
+		let mut passes = None;
+		for i in 1..limit {
+			let changed = self.update_all_once();
+			if !changed {
+				passes = Some(i - 1);
+				break;
+			}
+			self.notify_observers(false);
+			let Some(needed) = self.config.output_convergence else { continue };
+			let current = output_values(self);
+			if current == last_outputs {
+				unchanged += 1;
+			} else {
+				unchanged = 0;
+				last_outputs = current;
+			}
+			if unchanged >= needed {
+				passes = Some(i - u128::from(needed));
+				break;
+			}
 		}
-		false
+		self.notify_observers(true);
+		passes
+	}
+	fn notify_observers(&mut self, settled: bool) {
+		let Self { observers, state, slots, .. } = self;
+		observers.notify(state, slots, settled);
+	}
+	/// Calls `callback` whenever the target's value differs from the last time, once the circuit has finished updating,
+	/// or after each [`Self::step_event`]. Resetting or restoring the state does not call it by itself.
+	pub fn on_change(&mut self, target: ProbeTarget, callback: Box<dyn FnMut(&ChangeEvent) + Send + Sync>) -> Result<SubscriptionId> {
+		self.subscribe(target, false, callback)
+	}
+	/// Like [`Self::on_change`], but also calls `callback` for values that only last until the next update pass.
+	pub fn on_every_change(&mut self, target: ProbeTarget, callback: Box<dyn FnMut(&ChangeEvent) + Send + Sync>) -> Result<SubscriptionId> {
+		self.subscribe(target, true, callback)
+	}
+	fn subscribe(&mut self, target: ProbeTarget, glitches: bool, callback: ChangeCallback) -> Result<SubscriptionId> {
+		let index = match &target {
+			ProbeTarget::Name(name) => self.objects.iter().position(|o| (o.is_named_input() || o.is_named_output()) && o.export_name_or_uid() == name)
+				.ok_or_else(|| anyhow!("No input or output named {name}"))?,
+			ProbeTarget::Uid(uid) => self.uids.get(uid).copied()
+				.ok_or_else(|| anyhow!("No object with uid {uid}"))?,
+		};
+		let name = self.objects[index].export_name_or_uid().to_string();
+		Ok(self.observers.add(index, name, self.values(index).to_vec(), glitches, callback))
 	}
-	/// Sets all non-constant objects to false.
+	/// Removes a callback. Returns false if it was already removed.
+	pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+		self.observers.remove(id)
+	}
+	/// Sets all non-constant objects to false, or switches to their saved position if [`SimulationConfig::saved_inputs`] is set.
 	pub fn reset_state(&mut self){
-		for obj in &mut self.objects {
-			match obj.inner {
-				ObjectInner::Input { kind: InputType::Button | InputType::Switch, .. }
-				| ObjectInner::SimpleGate { .. } | ObjectInner::Output { .. } => {
-					for val in &mut obj.values { *val = false; }
+		for (i, obj) in self.objects.iter_mut().enumerate() {
+			let value = match obj.inner {
+				ObjectInner::Input { kind: InputType::Switch, value, .. } => self.config.saved_inputs && value,
+				// Constants are set too, in case a restored state overwrote them
+				ObjectInner::Input { kind: InputType::True, .. } => true,
+				ObjectInner::Input { kind: InputType::False, .. }
+				| ObjectInner::Input { kind: InputType::Button, .. }
+				| ObjectInner::SimpleGate { .. } | ObjectInner::Output { .. } => false,
+				// Custom gate outputs are kept, but live instances start over
+				ObjectInner::CustomGate { .. } => {
+					if let Some(live) = &mut obj.live { live.reset_state(); }
+					continue;
 				},
 				_ => continue,
-			}
+			};
+			self.state[self.slots[i]..self.slots[i + 1]].fill(value);
+		}
+		self.reported_outputs = None;
+	}
+	const state_magic: &[u8; 4] = b"LGS2";
+	/// Serializes the value of every top-level object and the tick count, so the simulation can be paused and resumed later,
+	/// with input sources continuing from the same tick. The internal state of live custom gate instances is not included.
+	///
+	/// Format: the magic `LGS2`, the tick count as a little-endian u64, the object count as a little-endian u32,
+	/// the number of values of each object as a little-endian u32, then all values packed 8 per byte, LSB first.
+	pub fn save_state(&self) -> Vec<u8> {
+		let mut out = Simulation::state_magic.to_vec();
+		out.extend(self.tick.to_le_bytes());
+		out.extend((self.objects.len() as u32).to_le_bytes());
+		out.extend(self.slots.windows(2).flat_map(|slot| ((slot[1] - slot[0]) as u32).to_le_bytes()));
+		for chunk in &self.state.iter().chunks(8) {
+			out.push(chunk.enumerate().fold(0, |byte, (i, val)| byte | ((*val as u8) << i)));
+		}
+		out
+	}
+	/// Restores a state produced by `save_state`. Fails without modifying anything if the state is for a different circuit.
+	pub fn restore_state(&mut self, state: &[u8]) -> Result<()> {
+		let rest = state.strip_prefix(Simulation::state_magic).ok_or(anyhow!("Invalid simulation state: bad magic"))?;
+		let (tick, rest) = rest.split_first_chunk::<8>().ok_or(anyhow!("Invalid simulation state: truncated"))?;
+		let (count, rest) = rest.split_first_chunk::<4>().ok_or(anyhow!("Invalid simulation state: truncated"))?;
+		if u32::from_le_bytes(*count) as usize != self.objects.len() {
+			return Err(anyhow!("Simulation state does not match this circuit: expected {} objects, found {}", self.objects.len(), u32::from_le_bytes(*count)));
 		}
+		let (shape, packed) = rest.split_at_checked(self.objects.len() * 4).ok_or(anyhow!("Invalid simulation state: truncated"))?;
+		let shape = shape.chunks_exact(4).map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize);
+		if self.slots.windows(2).zip(shape).any(|(slot, len)| slot[1] - slot[0] != len) {
+			return Err(anyhow!("Simulation state does not match this circuit: object shapes differ"));
+		}
+		let total = self.state.len();
+		if packed.len() != total.div_ceil(8) {
+			return Err(anyhow!("Invalid simulation state: expected {} bytes of values, found {}", total.div_ceil(8), packed.len()));
+		}
+		let mut bits = packed.iter().flat_map(|byte| (0..8).map(move |i| byte & (1 << i) != 0));
+		for val in &mut self.state {
+			*val = bits.next().expect("length was checked");
+		}
+		self.tick = u64::from_le_bytes(*tick);
+		self.reported_outputs = None;
+		Ok(())
+	}
+	/// The value `inputs` gives input `i`. Inputs are found by export name, or by uid if no input has that export name.
+	fn given_input(&self, inputs: &HashMap<&str, bool>, i: usize) -> Option<bool> {
+		let object = &self.objects[i];
+		let by_name = match &object.inner {
+			ObjectInner::Input { export_name: Some(name), .. } => inputs.get(&name[..]).copied(),
+			_ => None,
+		};
+		by_name.or_else(|| inputs.get(object.uid()).copied()
+			.filter(|_| !self.objects.iter().any(|o| o.is_named_input() && o.export_name_or_uid() == object.uid())))
 	}
 	/// Resets the state, then finds the outputs of this simulation given some inputs.
+	/// Inputs are keyed by export name, or by uid for inputs without one, and inputs that are left out are reset.
+	///
+	/// If a lookup table is in use (see [`Self::use_lookup_table`]) and only named inputs are given, the outputs are read from it instead:
+	/// `limit` is ignored, as the table was built with [`SimulationConfig::cycle_limit`], and the values of objects are left as they were.
 	pub fn get_outputs(&mut self, inputs: &HashMap<&str, bool>, limit: u128) -> HashMap<String, bool> {
+		self.try_get_outputs(inputs, limit).unwrap_or_else(|outputs| outputs)
+	}
+	/// Like [`Self::get_outputs`], but fails with the outputs reached so far if the circuit did not stabilize within `limit` update passes.
+	fn try_get_outputs(&mut self, inputs: &HashMap<&str, bool>, limit: u128) -> Result<HashMap<String, bool>, HashMap<String, bool>> {
+		// The lookup table only covers named inputs
+		let drives_unnamed = (0..self.objects.len()).any(|i| drivable(&self.objects[i]) && !self.objects[i].is_named_input() && self.given_input(inputs, i).is_some());
+		if let Some(table) = &self.lookup && !drives_unnamed {
+			let row = self.objects.iter().enumerate().filter(|(_, o)| o.is_named_input()).map(|(i, o)| match o.inner {
+				ObjectInner::Input { kind: kind @ (InputType::Button | InputType::Switch), value, .. } =>
+					self.given_input(inputs, i).unwrap_or(self.config.saved_inputs && kind == InputType::Switch && value),
+				_ => self.state[self.slots[i]],
+			}).collect::<Vec<_>>();
+			let values = table.output_values(bits_to_int(row.iter()));
+			return Ok(self.output_names().into_iter().map(String::from).zip(values.iter().copied()).collect());
+		}
+		let stable = self.settle_with(inputs, limit);
+		let outputs = self.objects.iter().enumerate().flat_map(|(i, f)| match &f.inner {
+			ObjectInner::Output { export_name: Some(name), .. } => Some((name.clone(), self.state[self.slots[i]])),
+			_ => None
+		}).collect();
+		if stable { Ok(outputs) } else { Err(outputs) }
+	}
+	/// Like [`Self::get_outputs`], but inputs given as None are don't-cares: every combination of their values is tried.
+	/// Each output is Some if it has the same value in every combination, or None if it depends on the don't-cares.
+	/// This runs the circuit once per combination, so it takes twice as long for each extra don't-care.
+	///
+	/// Fails if a name matches no switch or button, if there are too many don't-cares to count the combinations,
+	/// or if the circuit does not stabilize within `limit` update passes for some combination.
+	pub fn get_outputs_partial(&mut self, inputs: &HashMap<&str, Option<bool>>, limit: u128) -> Result<HashMap<String, Option<bool>>> {
+		if let Some(name) = inputs.keys().find(|&&name| !self.objects.iter().any(|o| drivable(o) && (o.export_name_or_uid() == name || o.uid() == name))) {
+			return Err(anyhow!("No input named {name}"));
+		}
+		let free: Vec<&str> = inputs.iter().filter(|(_, value)| value.is_none()).map(|(&name, _)| name).collect();
+		let rows = 1usize.checked_shl(free.len() as u32).ok_or_else(|| anyhow!("Too many don't-cares: {}", free.len()))?;
+		let mut given: HashMap<&str, bool> = inputs.iter().filter_map(|(&name, value)| Some((name, (*value)?))).collect();
+		let mut outputs: HashMap<String, Option<bool>> = HashMap::new();
+		for row in 0..rows {
+			for (bit, name) in free.iter().enumerate() {
+				given.insert(name, (row >> bit) & 1 == 1);
+			}
+			let found = self.try_get_outputs(&given, limit).map_err(|_| anyhow!("circuit did not stabilize within {limit} update passes"))?;
+			for (name, value) in found {
+				let known = outputs.entry(name).or_insert(Some(value));
+				if *known != Some(value) { *known = None; }
+			}
+			if row > 0 && outputs.values().all(Option::is_none) { break }
+		}
+		Ok(outputs)
+	}
+	/// Resets the state, sets the given inputs as in [`Self::get_outputs`], and updates until stable, without using the lookup table.
+	/// Returns false if the limit was reached.
+	fn settle_with(&mut self, inputs: &HashMap<&str, bool>, limit: u128) -> bool {
 		self.reset_state();
-		for obj in &mut self.objects {
-			match &mut obj.object.inner {
-				ObjectInner::Input {
-					export_name: Some(name),
-					kind: InputType::Button | InputType::Switch,
-					..
-				} => {
-					if let Some(&val) = inputs.get(&name[..]) {
-						obj.values[0] = val;
-					}
+		for i in 0..self.objects.len() {
+			if drivable(&self.objects[i]) && let Some(val) = self.given_input(inputs, i) {
+				self.state[self.slots[i]] = val;
+			}
+		}
+		self.update_until_done(limit)
+	}
+	/// Runs each test vector from a reset state, and reports which gates never had an output both false and true.
+	/// Each vector gives a value for every input in `input_order`, which are export names, or uids for inputs without one.
+	/// Values are only looked at once a vector has stabilized, so glitches while the circuit settles do not count.
+	/// Custom gates count as one gate, which is toggled once each of its outputs has been both false and true.
+	///
+	/// Fails if an input is not found, a vector has the wrong length, or the circuit does not stabilize within `limit` update passes.
+	pub fn toggle_coverage(&mut self, vectors: &[Vec<bool>], input_order: &[&str], limit: u128) -> Result<CoverageReport> {
+		if let Some(name) = input_order.iter().find(|&&name| !self.objects.iter().any(|o| drivable(o) && (o.export_name_or_uid() == name || o.uid() == name))) {
+			return Err(anyhow!("No input named {name}"));
+		}
+		let gates: Vec<usize> = (0..self.objects.len())
+			.filter(|&i| matches!(self.objects[i].inner, ObjectInner::SimpleGate { .. } | ObjectInner::CustomGate { .. }))
+			.collect();
+		// Whether each value of each gate has been seen false, and true
+		let mut seen: Vec<Vec<[bool; 2]>> = gates.iter().map(|&i| vec![[false; 2]; self.values(i).len()]).collect();
+		for (n, vector) in vectors.iter().enumerate() {
+			if vector.len() != input_order.len() {
+				return Err(anyhow!("Vector {n} has {} values, expected {}", vector.len(), input_order.len()));
+			}
+			let inputs = input_order.iter().copied().zip(vector.iter().copied()).collect();
+			if !self.settle_with(&inputs, limit) {
+				return Err(anyhow!("circuit did not stabilize within {limit} update passes on vector {n}"));
+			}
+			for (&i, seen) in gates.iter().zip(&mut seen) {
+				for (seen, &value) in seen.iter_mut().zip(self.values(i)) {
+					seen[value as usize] = true;
+				}
+			}
+		}
+		let untoggled = gates.iter().zip(&seen)
+			.filter(|(_, seen)| seen.iter().any(|&[low, high]| !(low && high)))
+			.map(|(&i, _)| self.objects[i].uid().to_string())
+			.collect();
+		Ok(CoverageReport { gates: gates.len(), untoggled })
+	}
+	/// Export names of the named inputs, in the order they appear in the file.
+	/// This is the input order used by [`Self::get_outputs_wide`] and truth tables.
+	pub fn input_names(&self) -> Vec<&str> {
+		self.objects.iter().filter(|o| o.is_named_input()).map(|o| o.export_name_or_uid()).collect()
+	}
+	/// Export names of the named outputs, in the order they appear in the file.
+	/// This is the output order used by [`Self::get_outputs_wide`] and truth tables.
+	pub fn output_names(&self) -> Vec<&str> {
+		self.outputs().map(|o| o.export_name_or_uid()).collect()
+	}
+	/// Number of named inputs, the same as the length of [`Self::input_names`].
+	pub fn named_input_count(&self) -> usize {
+		self.objects.iter().filter(|o| o.is_named_input()).count()
+	}
+	/// Number of named outputs, the same as the length of [`Self::output_names`].
+	pub fn named_output_count(&self) -> usize {
+		self.outputs().count()
+	}
+	/// Number of top-level custom gates, whether they are simulated with a truth table or live.
+	pub fn custom_instance_count(&self) -> usize {
+		self.objects.iter().filter(|o| matches!(o.inner, ObjectInner::CustomGate { .. })).count()
+	}
+	/// Resets the state, then finds the outputs of this simulation given the value of every named input.
+	/// Inputs and outputs are ordered as in [`Self::input_names`] and [`Self::output_names`].
+	/// Panics if `inputs` does not contain exactly one value per named input.
+	/// With a lookup table, `limit` is ignored and objects keep their values, as in [`Self::get_outputs`].
+	pub fn get_outputs_wide(&mut self, inputs: &[bool], limit: u128) -> Vec<bool> {
+		self.try_get_outputs_wide(inputs, limit).unwrap_or_else(|outputs| outputs)
+	}
+	/// Like [`Self::get_outputs_wide`], but fails with the outputs reached so far if the circuit did not stabilize within `limit` update passes.
+	fn try_get_outputs_wide(&mut self, inputs: &[bool], limit: u128) -> Result<Vec<bool>, Vec<bool>> {
+		if let Some(table) = &self.lookup {
+			assert_eq!(inputs.len(), table.inputs().len(), "expected one value per named input");
+			return Ok(table.output_values(bits_to_int(inputs.iter())).into_owned());
+		}
+		let slots = self.input_slots();
+		assert_eq!(inputs.len(), slots.len(), "expected one value per named input");
+		self.reset_state();
+		for (slot, &val) in slots.into_iter().zip(inputs) {
+			self.state[slot] = val;
+		}
+		let stable = self.update_until_done(limit);
+		let outputs = self.output_values();
+		if stable { Ok(outputs) } else { Err(outputs) }
+	}
+	/// Sets the given named inputs, leaving every other input and all internal state as it was, and updates until stable.
+	/// Returns the named outputs that changed, which makes this much cheaper than [`Self::get_outputs`] when toggling a few inputs.
+	///
+	/// As nothing is reset, circuits with feedback loops or live custom gates keep their state from earlier calls,
+	/// so the outputs can differ from those of [`Self::get_outputs`] with the same inputs. The lookup table is not used.
+	pub fn apply_inputs(&mut self, changes: &HashMap<&str, bool>, limit: u128) -> Result<OutputDelta> {
+		let before = self.output_values();
+		let mut inputs = self.get_inputs_mut();
+		if let Some(name) = changes.keys().find(|name| !inputs.contains_key(*name)) {
+			return Err(anyhow!("No input named {name}"));
+		}
+		for (name, &value) in changes {
+			**inputs.get_mut(name).expect("checked above") = value;
+		}
+		if !self.update_until_done(limit) {
+			return Err(anyhow!("circuit did not stabilize within {limit} update passes"));
+		}
+		let changed = self.output_names().into_iter().zip(before).zip(self.output_values())
+			.filter(|((_, before), after)| before != after)
+			.map(|((name, _), after)| (name.to_string(), after))
+			.collect();
+		Ok(OutputDelta { changed })
+	}
+	/// Returns `(name, old, new)` for each named output that changed since the last call, in file order.
+	/// After a reset or [`Self::restore_state`], changes are reported relative to all outputs being false.
+	pub fn take_output_changes(&mut self) -> Vec<(String, bool, bool)> {
+		let current = self.output_values();
+		let previous = self.reported_outputs.replace(current.clone()).unwrap_or_else(|| vec![false; current.len()]);
+		self.output_names().into_iter().zip(previous).zip(current)
+			.filter(|((_, old), new)| old != new)
+			.map(|((name, old), new)| (name.to_string(), old, new))
+			.collect()
+	}
+	/// Checks whether the named output equals `expr` for every combination of inputs.
+	/// `expr` is written with the export names of the inputs, as described in [`BoolExpr::parse`], like `(a & b) | c`.
+	pub fn matches_expression(&mut self, output_name: &str, expr: &str, limit: u128) -> Result<bool> {
+		let expr = BoolExpr::parse(expr, &self.input_names())?;
+		let column = self.output_names().iter().position(|&name| name == output_name)
+			.ok_or_else(|| anyhow!("No output named {output_name}"))?;
+		let (table, _) = self.compute_truth_table(limit, false, || ProgressTask::TruthTable)?;
+		let num_inputs = table.inputs().len() as u8;
+		Ok((0..table.num_rows()).all(|row| table.output_values(row)[column] == expr.eval(&int_to_bits(row, num_inputs))))
+	}
+	/// Returns a closure that evaluates this circuit as a pure function of its inputs.
+	/// See [`Self::get_outputs_wide`] for the argument and return value ordering.
+	/// The closure returns None for inputs where the circuit does not stabilize within [`SimulationConfig::cycle_limit`] update passes.
+	pub fn as_fn(&mut self) -> impl FnMut(&[bool]) -> Option<Vec<bool>> {
+		move |inputs| self.try_get_outputs_wide(inputs, self.config.cycle_limit).ok()
+	}
+	/// Finds every state of a finite state machine that can be reached from `reset_state`, including `reset_state` itself.
+	///
+	/// The circuit computes the next state: each state bit is given as (named input holding the current value, named output computing the next value).
+	/// Every combination of the other named inputs is applied in every state, in breadth first order.
+	/// States are ordered as in `state_inputs`. See [`Self::get_outputs_wide`] for `limit`.
+	///
+	/// Fails if a name is not a named input or output, if `reset_state` does not contain one value per state bit,
+	/// or if the circuit does not stabilize within `limit` update passes in some state.
+	pub fn reachable_states(&mut self, state_inputs: &[(&str, &str)], reset_state: &[bool], limit: u128) -> Result<HashSet<Vec<bool>>> {
+		let (current, next, free) = self.state_machine_inputs(state_inputs, reset_state)?;
+		let mut inputs = vec![false; self.named_input_count()];
+		let mut reached = HashSet::from([reset_state.to_vec()]);
+		let mut queue = VecDeque::from([reset_state.to_vec()]);
+		while let Some(state) = queue.pop_front() {
+			for (&i, &value) in current.iter().zip(&state) {
+				inputs[i] = value;
+			}
+			for combination in 0..1usize << free.len() {
+				for (bit, &i) in free.iter().rev().enumerate() {
+					inputs[i] = (combination >> bit) & 1 == 1;
+				}
+				let outputs = self.try_get_outputs_wide(&inputs, limit)
+					.map_err(|_| anyhow!("circuit was unstable in state {}", bit_string(&state)))?;
+				let next_state: Vec<bool> = next.iter().map(|&i| outputs[i]).collect();
+				if reached.insert(next_state.clone()) {
+					queue.push_back(next_state);
+				}
+			}
+		}
+		Ok(reached)
+	}
+	/// Checks the arguments of [`Self::reachable_states`] and [`Self::state_reachability`], and returns the positions
+	/// of the inputs holding the state, of the outputs computing the next state, and of the other inputs, in [`Self::input_names`] and [`Self::output_names`].
+	fn state_machine_inputs(&self, state_inputs: &[(&str, &str)], reset_state: &[bool]) -> Result<(Vec<usize>, Vec<usize>, Vec<usize>)> {
+		if reset_state.len() != state_inputs.len() {
+			return Err(anyhow!("expected {} reset values, one per state bit, found {}", state_inputs.len(), reset_state.len()));
+		}
+		let (input_names, output_names) = (self.input_names(), self.output_names());
+		let find = |names: &[&str], name: &str, kind: &str| names.iter().position(|n| *n == name)
+			.ok_or_else(|| anyhow!("No {kind} named {name}"));
+		let current = state_inputs.iter().map(|(input, _)| find(&input_names, input, "input")).collect::<Result<Vec<_>>>()?;
+		let next = state_inputs.iter().map(|(_, output)| find(&output_names, output, "output")).collect::<Result<Vec<_>>>()?;
+		let free: Vec<usize> = (0..input_names.len()).filter(|i| !current.contains(i)).collect();
+		if free.len() >= usize::BITS as usize {
+			return Err(anyhow!("Too many inputs to try in each state: {}", free.len()));
+		}
+		Ok((current, next, free))
+	}
+	/// Most state bits [`Self::state_reachability`] accepts, as it keeps a distance and a path step for every encoding.
+	pub const max_reachability_bits: usize = 20;
+	/// Like [`Self::reachable_states`], but reports every encoding of the state bits, whether it can be reached from `reset_state`,
+	/// and a shortest sequence of the other inputs that reaches it. Useful for checking that a one-hot machine cannot enter an invalid state.
+	///
+	/// Fails if a name is not a named input or output, if there are more than [`Self::max_reachability_bits`] state bits,
+	/// or if the circuit does not stabilize within `limit` update passes in some state.
+	pub fn state_reachability(&mut self, state_inputs: &[(&str, &str)], reset_state: &[bool], limit: u128) -> Result<ReachabilityReport> {
+		if state_inputs.len() > Simulation::max_reachability_bits {
+			return Err(anyhow!("Too many state bits to check every state: {}, the most is {}", state_inputs.len(), Simulation::max_reachability_bits));
+		}
+		let (current, next, free) = self.state_machine_inputs(state_inputs, reset_state)?;
+		let input_names = self.input_names();
+		let state_names: Vec<String> = current.iter().map(|&i| input_names[i].to_string()).collect();
+		let free_names: Vec<String> = free.iter().map(|&i| input_names[i].to_string()).collect();
+		let reset = bits_to_int(reset_state.iter());
+		let mut distances = vec![None; 1 << state_inputs.len()];
+		let mut previous = vec![None; 1 << state_inputs.len()];
+		distances[reset] = Some(0);
+		let mut inputs = vec![false; input_names.len()];
+		let mut queue = VecDeque::from([reset]);
+		let mut explored = 0;
+		while let Some(state) = queue.pop_front() {
+			if self.config.is_cancelled() {
+				return Err(Cancelled { task: ProgressTask::States, done: explored, total: distances.len() }.into());
+			}
+			explored += 1;
+			for (&i, value) in current.iter().zip(int_to_bits(state, state_inputs.len() as u8)) {
+				inputs[i] = value;
+			}
+			for combination in 0..1usize << free.len() {
+				for (bit, &i) in free.iter().rev().enumerate() {
+					inputs[i] = (combination >> bit) & 1 == 1;
+				}
+				let outputs = self.try_get_outputs_wide(&inputs, limit)
+					.map_err(|_| anyhow!("circuit was unstable in state {}", bit_string(&int_to_bits(state, state_inputs.len() as u8))))?;
+				let reached = bits_to_int(next.iter().map(|&i| &outputs[i]));
+				if distances[reached].is_none() {
+					distances[reached] = Some(distances[state].unwrap() + 1);
+					previous[reached] = Some((state, combination));
+					queue.push_back(reached);
+				}
+			}
+		}
+		Ok(ReachabilityReport { state: state_names, inputs: free_names, reset, distances, previous })
+	}
+	/// Sets the outputs of custom gates that are not simulated live to false, which [`Self::reset_state`] leaves as they are.
+	/// Rows then take the same number of update passes to stabilize, whichever row was simulated before.
+	fn clear_custom_outputs(&mut self) {
+		for i in 0..self.objects.len() {
+			if matches!(self.objects[i].inner, ObjectInner::CustomGate { .. }) && self.objects[i].live.is_none() {
+				self.state[self.slots[i]..self.slots[i + 1]].fill(false);
+			}
+		}
+	}
+	/// Resets the state, then sets the named inputs to the bits of a truth table row index.
+	fn set_row_inputs(&mut self, row_index: usize) {
+		self.reset_state();
+		for (bit, slot) in self.input_slots().into_iter().rev().enumerate() {
+			self.state[slot] = (row_index >> bit) & 1 == 1;
+		}
+	}
+	/// Returns None if the circuit fails to stabilize for any combination of inputs,
+	/// or if the table would be larger than [`SimulationConfig::table_memory_limit`].
+	///
+	/// Inputs and outputs are ordered as in [`Self::input_names`] and [`Self::output_names`].
+	/// The first input is the most significant bit of the row index.
+	pub fn get_truth_table(&mut self, cycle_limit: u128) -> Option<TruthTable> {
+		self.try_get_truth_table(cycle_limit).ok()
+	}
+	/// Like [`Self::get_truth_table`], but says why there is no table.
+	/// Fails with a [`TableTooLarge`] before allocating anything if the table would be over the memory limit.
+	pub fn try_get_truth_table(&mut self, cycle_limit: u128) -> Result<TruthTable> {
+		self.compute_truth_table(cycle_limit, false, || ProgressTask::TruthTable).map(|(table, _)| table)
+	}
+	/// Finds the rows where the named outputs `a` and `b` differ, like a reference output and one built next to it.
+	/// Each row is simulated and checked in turn, so unlike [`Self::get_truth_table`], the whole table is never held in memory.
+	pub fn compare_outputs(&mut self, a: &str, b: &str, limit: u128) -> Result<OutputComparison> {
+		let outputs = self.output_names();
+		let column = |name: &str| outputs.iter().position(|&o| o == name).ok_or_else(|| anyhow!("No output named {name}"));
+		let (column_a, column_b) = (column(a)?, column(b)?);
+		let len = self.named_input_count();
+		if len >= usize::BITS as usize {
+			return Err(anyhow!("Too many inputs to check every row: {len}"));
+		}
+		let mut differences = vec![];
+		for row in 0..1usize << len {
+			if self.config.is_cancelled() {
+				return Err(Cancelled { task: ProgressTask::TruthTable, done: row, total: 1 << len }.into());
+			}
+			let values = match &self.lookup {
+				Some(table) => table.output_values(row).into_owned(),
+				None => {
+					self.set_row_inputs(row);
+					self.update_until_stable(limit).ok_or_else(|| anyhow!("circuit was unstable in row {row}"))?;
+					self.output_values()
 				},
-				_ => {}
+			};
+			if values[column_a] != values[column_b] {
+				differences.push((row, values[column_a]));
 			}
 		}
-		self.update_until_done(limit);
-		self.objects.iter().flat_map(|f| match &f.inner {
-			ObjectInner::Output { export_name: Some(name), .. } => Some((name.clone(), f.values[0])),
-			_ => None
+		Ok(OutputComparison {
+			outputs: [a.to_string(), b.to_string()],
+			inputs: self.input_names().into_iter().map(String::from).collect(),
+			rows: 1 << len,
+			differences,
+		})
+	}
+	/// Pairs of named outputs that are always equal or always complementary. See [`TruthTable::redundant_outputs`].
+	pub fn redundant_outputs(&mut self, cycle_limit: u128) -> Result<Vec<(String, String, OutputRelation)>> {
+		let table = self.try_get_truth_table(cycle_limit)?;
+		Ok(table.redundant_outputs())
+	}
+	/// How many different functions the named outputs compute. See [`TruthTable::distinct_outputs`].
+	pub fn distinct_output_functions(&mut self, cycle_limit: u128, merge_complements: bool) -> Result<usize> {
+		let table = self.try_get_truth_table(cycle_limit)?;
+		Ok(table.distinct_outputs(merge_complements))
+	}
+	/// Same as [`Self::get_truth_table`], but also records how long each row took to stabilize.
+	pub fn get_truth_table_with_stats(&mut self, cycle_limit: u128) -> Option<(TruthTable, ConvergenceStats)> {
+		self.try_get_truth_table_with_stats(cycle_limit).ok()
+	}
+	/// Same as [`Self::try_get_truth_table`], but also records how long each row took to stabilize.
+	/// Each row starts with the outputs of custom gates cleared, so that the counts do not depend on the row before.
+	pub fn try_get_truth_table_with_stats(&mut self, cycle_limit: u128) -> Result<(TruthTable, ConvergenceStats)> {
+		self.compute_truth_table(cycle_limit, true, || ProgressTask::TruthTable)
+	}
+	/// Simulates every row, after checking that the table, and the stats if `with_stats` is set, fit in the memory limit.
+	/// Without `with_stats`, the returned stats are empty. Progress and cancellation are reported as `task`.
+	/// Digit displays get a column for each of their inputs, in every table: those returned to callers,
+	/// those of custom circuits, and the lookup table. See [`TruthTable::output_values`] for one value per output.
+	fn compute_truth_table(&mut self, cycle_limit: u128, with_stats: bool, task: impl FnOnce() -> ProgressTask) -> Result<(TruthTable, ConvergenceStats)> {
+		let len = self.named_input_count();
+		let widths = self.output_widths();
+		let row_len = widths.iter().map(|&width| width as usize).sum();
+		let row_bytes = row_len * size_of::<bool>() + if with_stats { size_of::<u128>() } else { 0 };
+		let rows = 1usize.checked_shl(len as u32).filter(|_| len < usize::BITS as usize);
+		let bytes = rows.and_then(|rows| rows.checked_mul(row_bytes));
+		let limit = self.config.table_memory_limit;
+		let Some(rows) = rows.filter(|_| bytes.is_some_and(|b| b <= limit)) else {
+			return Err(TableTooLarge { inputs: len, outputs: row_len, bytes, limit }.into());
+		};
+		let mut buf: Vec<bool> = Vec::with_capacity(row_len * rows);
+		let mut iterations = Vec::with_capacity(if with_stats { rows } else { 0 });
+		let task = task();
+		let progress = self.config.progress.start(|| task.clone(), rows);
+		for row_index in 0..rows {
+			if self.config.is_cancelled() {
+				return Err(Cancelled { task, done: row_index, total: rows }.into());
+			}
+			self.set_row_inputs(row_index);
+			if with_stats { self.clear_custom_outputs(); }
+			let passes = self.update_until_stable(cycle_limit).ok_or_else(|| anyhow!("circuit was unstable in row {row_index}"))?;
+			if with_stats { iterations.push(passes); }
+			buf.extend(self.output_bits());
+			progress.rows_done(row_index + 1);
+		}
+		Ok((self.table_of(buf), ConvergenceStats { iterations }))
+	}
+	/// A table with this simulation's inputs and output columns, holding `data`.
+	fn table_of(&self, data: Vec<bool>) -> TruthTable {
+		let widths = self.output_widths();
+		TruthTable {
+			data,
+			row_size: widths.iter().map(|&width| width as usize).sum(),
+			inputs: self.input_names().into_iter().map(String::from).collect(),
+			outputs: self.output_columns(),
+			widths: if widths.iter().all(|&width| width == 1) { vec![] } else { widths },
+		}
+	}
+	/// Names of the columns of [`Self::output_bits`], like those of [`TruthTable::outputs`].
+	fn output_columns(&self) -> Vec<String> {
+		self.output_names().into_iter().zip(self.output_widths()).flat_map(|(name, width)| match width {
+			1 => vec![name.to_string()],
+			_ => (0..width).map(|i| format!("{name}.{i}")).collect(),
 		}).collect()
 	}
-	/// Returns None if the circuit fails to stabilize for any combination of inputs.
-	pub fn get_truth_table(&mut self, cycle_limit: u128) -> Option<TruthTable> {
-		let len = self.inputs_mut().count();
-		let row_len = self.objects.iter().flat_map(|f| match &f.inner {
-			ObjectInner::Output { export_name: Some(_), .. } => Some(()),
-			_ => None
-		}).count();
-		let mut buf: Vec<bool> = Vec::with_capacity(row_len * 2usize.pow(len as u32));
-		for row_index in 0..2u32.pow(len as u32) {
-			self.reset_state();
-			for (bit, obj) in self.inputs_mut().rev().enumerate() {
-				obj.values[0] = (row_index >> bit) & 1 == 1;
-			}
-			if !self.update_until_done(cycle_limit) { return None }
-			buf.extend(
-				self.objects.iter().flat_map(|f| match &f.inner {
-					ObjectInner::Output { export_name: Some(_), .. } => Some(f.values[0]),
-					_ => None
-				})
-			);
+	/// Simulates a single truth table row from the reset state, and returns the objects that changed in each update pass,
+	/// as (export name or uid, new value).
+	/// Custom gate outputs are cleared first, as in [`Self::try_get_truth_table_with_stats`].
+	pub fn trace_row(&mut self, row_index: usize, limit: u128) -> Vec<Vec<(String, Vec<bool>)>> {
+		self.set_row_inputs(row_index);
+		self.clear_custom_outputs();
+		let mut trace = vec![];
+		for _ in 1..limit {
+			let mut changed = vec![];
+			if !self.update_pass(|i| changed.push(i)) { break }
+			trace.push(self.named_values(changed));
 		}
-		Some(TruthTable { data: buf, row_size: row_len })
+		trace
 	}
-	pub fn print_truth_table(&mut self, limit: u128){
-		let mut input_names: Vec<_> = self.objects.iter().flat_map(|o| match &o.inner {
-			ObjectInner::Input { export_name: Some(name), .. } => Some(name.clone()),
-			_ => None,
+	/// Runs one update pass from the current state, and reports the objects that changed.
+	pub fn step(&mut self) -> StepReport {
+		let old = self.state.clone();
+		let mut changed = vec![];
+		self.update_pass(|i| changed.push(i));
+		self.notify_observers(true);
+		self.tick += 1;
+		let changes = changed.into_iter().map(|i| ObjectChange {
+			name: self.objects[i].export_name_or_uid().to_string(),
+			old: old[self.slots[i]..self.slots[i + 1]].to_vec(),
+			new: self.values(i).to_vec(),
 		}).collect();
-		input_names.sort_by(|a, b| b.cmp(a));
-		let mut output_names: Vec<_> = self.objects.iter().flat_map(|o| match &o.inner {
-			ObjectInner::Output { export_name: Some(name), .. } => Some(name.clone()),
-			_ => None,
+		StepReport { iteration: self.tick, changes }
+	}
+	/// Like [`Self::step`], but without the old values, for streaming to a front end.
+	pub fn step_event(&mut self) -> StepEvent {
+		let report = self.step();
+		StepEvent { tick: report.iteration, changed: report.changes.into_iter().map(|change| (change.name, change.new)).collect() }
+	}
+	/// Describes the current value of an object and each of its inputs, for finding out why it has that value.
+	/// Probes are export names or uids.
+	pub fn explain(&self, probe: &str) -> Result<String> {
+		let i = self.find_probe(probe).ok_or_else(|| anyhow!("No object named {probe}"))?;
+		let mut out = format!("{} = {} ({})\n", self.objects[i].export_name_or_uid(), bit_string(self.values(i)), self.kind_name(i));
+		let (start, len) = self.wire_ranges[i];
+		for (input, wire) in self.wires[start..start + len].iter().enumerate() {
+			let Some(slot) = wire.source else {
+				out += &format!("  input {input}: unconnected = 0\n");
+				continue;
+			};
+			let source = self.slot_owner(slot);
+			let name = match self.slots[source + 1] - self.slots[source] {
+				1 => self.objects[source].export_name_or_uid().to_string(),
+				_ => format!("{}[{}]", self.objects[source].export_name_or_uid(), slot - self.slots[source]),
+			};
+			let value = self.state[slot] as u8;
+			out += &match wire.inverted {
+				false => format!("  input {input}: {name} = {value}\n"),
+				true => format!("  input {input}: {name} = {value}, inverted to {}\n", 1 - value),
+			};
+		}
+		Ok(out)
+	}
+	/// What kind of object object `i` is, like `And` or `custom circuit FA`.
+	fn kind_name(&self, i: usize) -> String {
+		match &self.ops[i] {
+			Op::Gate(kind, _) => kind.to_string(),
+			Op::Custom(uuid) => format!("custom circuit {}", self.customs[uuid].name),
+			Op::Output => String::from("output"),
+			Op::Fixed => String::from("input"),
+		}
+	}
+	/// The index of the object whose values include `state[slot]`.
+	fn slot_owner(&self, slot: usize) -> usize {
+		self.slots.partition_point(|&s| s <= slot) - 1
+	}
+	/// The export name or uid and the values of each object.
+	fn named_values(&self, indices: Vec<usize>) -> Vec<(String, Vec<bool>)> {
+		indices.into_iter().map(|i| (self.objects[i].export_name_or_uid().to_string(), self.values(i).to_vec())).collect()
+	}
+	/// Finds the object with export name `probe`, or failing that, the object with uid `probe`.
+	fn find_probe(&self, probe: &str) -> Option<usize> {
+		self.objects.iter().position(|o| o.export_name_or_uid() == probe)
+			.or_else(|| self.find_uid(probe))
+	}
+	fn find_uid(&self, uid: &str) -> Option<usize> {
+		self.uids.get(uid).copied()
+	}
+	/// The current values of the outputs of object `index`, in the order of [`Circuit::objects`].
+	pub fn object_values(&self, index: usize) -> &[bool] {
+		self.values(index)
+	}
+	/// Returns the current values of the probed objects, keyed by probe. Probes are export names or uids.
+	/// Probes that match no object are left out.
+	pub fn get_probes(&self, probes: &[&str]) -> HashMap<String, Vec<bool>> {
+		probes.iter().flat_map(|&probe| self.find_probe(probe).map(|i| (probe.to_string(), self.values(i).to_vec()))).collect()
+	}
+	/// Draws `ticks` columns of the values of each probed object as a row of `_` (false) and `‾` (true).
+	/// The first column is the current state, and the simulation is advanced with [`Self::step`] before each later column,
+	/// so input sources see the same ticks as when stepping. Probes are export names or uids.
+	/// Objects with several values, like custom gates with several outputs, get a row per value, named like `name[1]`.
+	pub fn format_waveform(&mut self, ticks: usize, probes: &[&str]) -> Result<String> {
+		let indices = probes.iter().map(|&probe| self.find_probe(probe).ok_or_else(|| anyhow!("No object named {probe}")))
+			.collect::<Result<Vec<_>>>()?;
+		let mut rows: Vec<(String, String)> = indices.iter().zip(probes).flat_map(|(&i, probe)| {
+			let len = self.values(i).len();
+			(0..len).map(move |bit| (if len == 1 { probe.to_string() } else { format!("{probe}[{bit}]") }, String::new()))
 		}).collect();
-		output_names.sort_by(|a, b| b.cmp(a));
-		let mut inputs: HashMap<_, _> = input_names.iter().map(|w| (&w[..], false)).collect();
-		let header_inp = input_names.iter().map(|s| &s[..]).collect::<Vec<_>>();
-		let header_inp_str = header_inp.join("|");
-		let header_out = output_names.iter().map(|s| &s[..]).collect::<Vec<_>>();
-		let header_out_str = header_out.join("|");
-		println!("{}||{}", header_inp_str, header_out_str);
-		println!("{}", "-".repeat(header_inp_str.len() + 2 + header_out_str.len()));
-		for i in 0..2u32.pow(input_names.len() as u32) {
-			for (bit_n, input) in input_names.iter().rev().enumerate() {
-				let value = (i >> bit_n) & 1 == 1;
-				inputs.insert(&input[..], value);	
-			}
-			let outputs = self.get_outputs(&inputs, limit);
-			let line_inp = input_names.iter().map(|inp| inputs.get(&inp[..]).unwrap())
-				.enumerate().map(|(i, val)| format!("{:^width$}", match val {
-					true => "T",
-					false => "F"
-				}, width = header_inp[i].len())).collect::<Vec<_>>().join("|");
-			let line_out = output_names.iter().map(|out| outputs.get(&out[..]).unwrap())
-				.enumerate().map(|(i, val)| format!("{:^width$}", match val {
-					true => "T",
-					false => "F"
-				}, width = header_out[i].len())).collect::<Vec<_>>().join("|");
-			println!("{line_inp}||{line_out}");
-		}
-	}
-	fn get_values(connections: &Vec<Option<(u32, usize)>>, objects: &Vec<SObject>) -> Vec<bool> {
-		connections.iter().map(|c| match c {
-			&Some((idx, ptr)) => objects[ptr].values[idx as usize],
-			None => false,
-		}).collect()
+		for tick in 0..ticks {
+			if tick > 0 { self.step(); }
+			let values = indices.iter().flat_map(|&i| self.values(i).iter());
+			for ((_, wave), &value) in rows.iter_mut().zip(values) {
+				wave.push(if value { '‾' } else { '_' });
+			}
+		}
+		let width = rows.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+		Ok(rows.into_iter().map(|(name, wave)| format!("{name:>width$} {wave}\n")).collect())
+	}
+	pub fn print_waveform(&mut self, ticks: usize, probes: &[&str]) -> Result<()> {
+		print!("{}", self.format_waveform(ticks, probes)?);
+		Ok(())
+	}
+	/// Prints the truth table to stdout as it is computed, like [`Self::write_truth_table_with`].
+	pub fn print_truth_table(&mut self, limit: u128) -> Result<()> {
+		self.write_truth_table_with(&mut std::io::stdout().lock(), limit, None)
+	}
+	/// Like [`Self::print_truth_table`], but returns the text. The whole table is held in memory, so this is meant for small tables.
+	pub fn format_truth_table(&mut self, limit: u128) -> Result<String> {
+		self.format_truth_table_with(limit, None)
+	}
+	/// Like [`Self::format_truth_table`], but optionally appends a column named `extra.0`, as in [`TruthTable::format_table`].
+	pub fn format_truth_table_with(&mut self, limit: u128, extra: Option<(&str, Reducer)>) -> Result<String> {
+		let mut out = vec![];
+		self.write_truth_table_with(&mut out, limit, extra)?;
+		Ok(String::from_utf8(out).expect("the table is UTF-8"))
+	}
+	/// Writes the truth table to `out` as [`TruthTable::format_table`] draws it, simulating each row just before it is written,
+	/// so only one row is held in memory at a time.
+	///
+	/// Unlike [`Self::get_truth_table`], rows that do not stabilize within `limit` update passes are shown as they are then,
+	/// and there is no memory limit. Fails if there are too many inputs to count the rows in a `usize`.
+	pub fn write_truth_table_with(&mut self, out: &mut impl Write, limit: u128, extra: Option<(&str, Reducer)>) -> Result<()> {
+		if let Some(table) = &self.lookup {
+			return table.write_table(out, extra, |row| Ok(Some(table[row].to_vec())));
+		}
+		let len = self.named_input_count();
+		if len >= usize::BITS as usize {
+			return Err(anyhow!("Too many inputs to print every row: {len}"));
+		}
+		let layout = self.table_of(vec![]);
+		let progress = self.config.progress.start(|| ProgressTask::TruthTable, 1 << len);
+		let mut done = 0;
+		layout.write_table(out, extra, |row| {
+			if self.config.is_cancelled() { return Ok(None) }
+			self.set_row_inputs(row);
+			self.update_until_done(limit);
+			done += 1;
+			progress.rows_done(done);
+			Ok(Some(self.output_bits()))
+		})
+	}
+	/// Prints the truth table of every custom circuit, headed by its label or name.
+	pub fn print_all_custom_tables(&self){
+		print!("{}", self.format_all_custom_tables());
+	}
+	/// Like [`Self::print_all_custom_tables`], but returns the text. The tables are the ones computed when the simulation was built.
+	pub fn format_all_custom_tables(&self) -> String {
+		let mut out = String::new();
+		for custom in self.customs.values().sorted_by(|a, b| a.name.cmp(&b.name)) {
+			out += &format!("{}\n", custom.name);
+			let inputs = custom.simulation.named_input_count();
+			if let Some(table) = &custom.table {
+				out += &table.format_table(None);
+				out += "\n";
+			} else if inputs > self.config.custom_table_max_inputs {
+				out += &format!("(too large for a truth table: {inputs} inputs)\n\n");
+			} else {
+				out += "(unstable)\n\n";
+			}
+		}
+		out
 	}
 }
 impl Display for Simulation {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		for (i, obj) in self.objects.iter().enumerate() {
-			writeln!(f, "({i}) {} | {:?}", obj.object, obj.values)?;
+			writeln!(f, "({i}) {} | {:?}", obj.object, self.values(i))?;
 		}
 		Ok(())
 	}
@@ -211,69 +2015,1048 @@ impl Display for Simulation {
 #[derive(Debug, Clone, PartialEq)]
 pub struct SObject {
 	object: Object,
-	values: Vec<bool>,
+	/// For custom gates simulated live, the state of this instance.
+	live: Option<Box<Simulation>>,
 }
 impl From<Object> for SObject {
 	fn from(object: Object) -> Self {
-		let values = match &object.inner {
-			// For now all gates have only 1 output
-			ObjectInner::SimpleGate { .. } => 1,
-			ObjectInner::CustomGate { num_outputs, .. } => *num_outputs as usize,
-			ObjectInner::Output { .. } => 1,
-			ObjectInner::Input { .. } => 1,
-			ObjectInner::Label { .. } => 0,
-		};
-		let value = match &object.inner {
-			&ObjectInner::Input { value, .. } => value,
-			_ => false,
-		};
-		Self {
-			object,
-			values: vec![value; values],
-		}
-	}
-}
-impl SObject {
-	/// Returns None if the object does not support updating.
-	fn get_new_value(&self, objects: &Vec<SObject>, customs:&mut CustomCircuitMap) -> Option<Vec<bool>> {
-		use SimpleGateType as S;
-		return match &self.object.inner {
-			ObjectInner::SimpleGate { xor_type, kind, connections } => {
-				let inputs = Simulation::get_values(connections, objects);
-				Some(vec![match kind {
-					S::Buffer => inputs[0],
-					S::Not => !inputs[0],
-					S::And => inputs.iter().all(|x| *x),
-					S::Nand => !inputs.iter().all(|x| *x),
-					S::Or => inputs.iter().any(|x| *x),
-					S::Nor => !inputs.iter().any(|x| *x),
-					S::Xor | S::Xnor => (match xor_type {
-						XorType::Odd => inputs.iter().filter(|x| **x).count() % 2 == 1,
-						XorType::One => inputs.iter().filter(|x| **x).count() == 1,
-					} == (*kind == S::Xor)),
-				}])
-			},
-			ObjectInner::CustomGate { uuid, connections, .. } => Some({
-				let inputs = Simulation::get_values(connections, objects);
-				let (custom, table) = customs.get_mut(uuid).expect("unreachable, the uuid was checked to determine num outputs");
-				match table {
-					Some(table) => {
-						let packed_inputs = bits_to_int(inputs.iter());
-						table[packed_inputs].to_vec()
-					},
-					None => todo!(),
-				}
-			}),
-			crate::io::ObjectInner::Output { connections, .. } =>
-				Some(Simulation::get_values(connections, objects)),
-			ObjectInner::Input { .. } => None, // Inputs do not change themselves
-			ObjectInner::Label { .. } => None,
-		};
+		Self { object, live: None }
 	}
 }
+fn evaluate_gate(kind: SimpleGateType, xor_type: XorType, inputs: &[bool]) -> bool {
+	use SimpleGateType as S;
+	match kind {
+		S::Buffer => inputs[0],
+		S::Not => !inputs[0],
+		S::And => inputs.iter().all(|x| *x),
+		S::Nand => !inputs.iter().all(|x| *x),
+		S::Or => inputs.iter().any(|x| *x),
+		S::Nor => !inputs.iter().any(|x| *x),
+		S::Xor | S::Xnor => {
+			let ones = inputs.iter().filter(|x| **x).count();
+			let xor = match xor_type {
+				XorType::Odd => ones % 2 == 1,
+				XorType::One => ones == 1,
+			};
+			xor != (kind == S::Xnor)
+		},
+	}
+}
+/// Whether the object is an input that can be set from outside, so not a constant.
+fn drivable(object: &SObject) -> bool {
+	matches!(object.inner, ObjectInner::Input { kind: InputType::Button | InputType::Switch, .. })
+}
+/// Combines a row of outputs into the value of an extra truth table column.
+pub type Reducer<'a> = &'a dyn Fn(&[bool]) -> bool;
+/// The XOR of all values, for use as an extra truth table column.
+pub fn parity(values: &[bool]) -> bool {
+	values.iter().filter(|x| **x).count() % 2 == 1
+}
 impl Deref for SObject {
 	type Target = Object;
 	fn deref(&self) -> &Self::Target {
 		&self.object
 	}
-}
\ No newline at end of file
+}
+impl std::borrow::Borrow<Object> for SObject {
+	fn borrow(&self) -> &Object {
+		&self.object
+	}
+}
+#[cfg(test)]
+mod tests {
+	use crate::io::parse_xml;
+	use crate::simul::*;
+	use crate::util::fixtures;
+
+	fn load(xml: &str) -> Simulation {
+		parse_xml(xml).unwrap().into()
+	}
+
+	#[test]
+	fn minterms_xor(){
+		let table = load(fixtures::XOR).get_truth_table(100).unwrap();
+		assert_eq!(table.minterms(0), vec![1, 2]);
+		assert_eq!(table.maxterms(0), vec![0, 3]);
+		assert_eq!(table.format_minterms(64), "inputs (MSB first): a, b\nq = Σm(1, 2) = ΠM(0, 3) (2 of 4 rows true)\n");
+	}
+	#[test]
+	fn minterms_majority(){
+		let table = load(fixtures::MAJORITY).get_truth_table(100).unwrap();
+		assert_eq!(table.minterms(0), vec![3, 5, 6, 7]);
+		assert_eq!(table.maxterms(0), vec![0, 1, 2, 4]);
+		assert_eq!(table.format_minterms(3), "inputs (MSB first): a, b, c\nm = Σm(...) [4 terms, truncated] = ΠM(...) [4 terms, truncated] (4 of 8 rows true)\n");
+	}
+	#[test]
+	fn as_fn_xor(){
+		let mut simul = load(fixtures::XOR);
+		assert_eq!(simul.input_names(), vec!["a", "b"]);
+		assert_eq!(simul.output_names(), vec!["q"]);
+		let mut xor = simul.as_fn();
+		assert_eq!(xor(&[false, false]), Some(vec![false]));
+		assert_eq!(xor(&[false, true]), Some(vec![true]));
+		assert_eq!(xor(&[true, false]), Some(vec![true]));
+		assert_eq!(xor(&[true, true]), Some(vec![false]));
+		// A nand gate feeding back into itself oscillates while its other input is on
+		let ring = fixtures::gate_circuit("nand", 2, None).replace(r#"outputUID="i1" inputIndex="1""#, r#"outputUID="g" inputIndex="1""#);
+		let mut ring = load(&ring);
+		let mut ring = ring.as_fn();
+		assert_eq!(ring(&[false, false]), Some(vec![true]));
+		assert_eq!(ring(&[true, false]), None);
+	}
+
+	#[test]
+	fn reachable_states(){
+		let bits = |states: &[[u8; 2]]| states.iter().map(|s| s.iter().map(|&b| b == 1).collect()).collect::<HashSet<Vec<bool>>>();
+		// A 2 bit counter that counts up while en is on
+		let mut counter: Simulation = crate::circuit!{
+			in s1, s0, en;
+			out n1 = xor(s1, and(s0, en));
+			out n0 = xor(s0, en);
+		}.into();
+		let state = [("s1", "n1"), ("s0", "n0")];
+		assert_eq!(counter.reachable_states(&state, &[false, false], 100).unwrap(), bits(&[[0, 0], [0, 1], [1, 0], [1, 1]]));
+		assert_eq!(counter.reachable_states(&state, &[false], 100).unwrap_err().to_string(), "expected 2 reset values, one per state bit, found 1");
+		assert_eq!(counter.reachable_states(&[("s1", "n1"), ("s0", "q")], &[false, false], 100).unwrap_err().to_string(), "No output named q");
+		// Counts 00, 01, 10, then back to 00, so 11 is unreachable unless the machine starts there
+		let mut mod3: Simulation = crate::circuit!{
+			in s1, s0;
+			out n1 = s0;
+			out n0 = nor(s1, s0);
+		}.into();
+		assert_eq!(mod3.reachable_states(&state, &[false, false], 100).unwrap(), bits(&[[0, 0], [0, 1], [1, 0]]));
+		assert_eq!(mod3.reachable_states(&state, &[true, true], 100).unwrap(), bits(&[[1, 1], [1, 0], [0, 0], [0, 1]]));
+		// The ring oscillates while i0 is on, which is not a next state
+		let ring = fixtures::gate_circuit("nand", 2, None).replace(r#"outputUID="i1" inputIndex="1""#, r#"outputUID="g" inputIndex="1""#);
+		assert_eq!(load(&ring).reachable_states(&[("i1", "q")], &[false], 100).unwrap_err().to_string(), "circuit was unstable in state 0");
+	}
+	#[test]
+	fn state_reachability(){
+		// A 3 bit counter that steps 000, 001, 010, 100, 101, then back to 000 while en is on, skipping 011, 110, and 111
+		let mut counter: Simulation = crate::circuit!{
+			in s2, s1, s0, en;
+			out n2 = or(and(en, nor(s0, nor(s1, s2))), and(not(en), s2));
+			out n1 = or(and(en, and(s0, nor(s2, s1))), and(not(en), s1));
+			out n0 = or(and(en, nor(s1, s0)), and(not(en), s0));
+		}.into();
+		let state = [("s2", "n2"), ("s1", "n1"), ("s0", "n0")];
+		let report = counter.state_reachability(&state, &[false; 3], 100).unwrap();
+		assert_eq!(report.unreachable(), [0b011, 0b110, 0b111]);
+		assert_eq!(report.distances, [Some(0), Some(1), Some(2), None, Some(3), Some(4), None, None]);
+		assert_eq!(report.shortest_path(0b100), Some(vec![1, 1, 1]));
+		assert_eq!(report.shortest_path(0b011), None);
+		assert_eq!(report.to_string(), "\
+state (s2 s1 s0) | reachable | steps | inputs
+000              | yes       | 0     | (reset)
+001              | yes       | 1     | en=1
+010              | yes       | 2     | en=1; en=1
+011              | no        |       |
+100              | yes       | 3     | en=1; en=1; en=1
+101              | yes       | 4     | en=1; en=1; en=1; en=1
+110              | no        |       |
+111              | no        |       |
+3 of 8 states are unreachable
+");
+		// Starting in an unused state, the machine falls back into the cycle
+		let report = counter.state_reachability(&state, &[true, true, true], 100).unwrap();
+		assert_eq!(report.unreachable(), [0b011, 0b110]);
+		assert!(counter.state_reachability(&state, &[false; 2], 100).is_err());
+		assert!(counter.state_reachability(&[("s2", "nope")], &[false], 100).is_err());
+		let wide = vec![("s0", "n0"); Simulation::max_reachability_bits + 1];
+		assert!(counter.state_reachability(&wide, &vec![false; wide.len()], 100).unwrap_err().to_string().starts_with("Too many state bits"));
+		let token = CancellationToken::new();
+		token.cancel();
+		counter.config = counter.config.clone().cancellation(token);
+		let error = counter.state_reachability(&state, &[false; 3], 100).unwrap_err();
+		assert_eq!(error.downcast_ref(), Some(&Cancelled { task: ProgressTask::States, done: 0, total: 8 }));
+		assert_eq!(error.to_string(), "State search cancelled after 0 of 8");
+	}
+	#[test]
+	fn step_reports(){
+		let mut simul: Simulation = crate::circuit!{ in a; n2 = not(n1); n1 = not(a); out q = n2; }.into();
+		simul.update_until_done(100);
+		**simul.get_inputs_mut().get_mut("a").unwrap() = true;
+		let change = |name: &str, old, new| ObjectChange { name: name.into(), old: vec![old], new: vec![new] };
+		let reports: Vec<_> = (0..3).map(|_| simul.step()).collect();
+		assert_eq!(reports[0].changes, [change("n1", true, false)]);
+		assert_eq!(reports[1].changes, [change("n2", false, true), change("q", false, true)]);
+		assert_eq!(reports[2].changes, []);
+		assert_eq!(reports.iter().map(|r| r.iteration).collect::<Vec<_>>(), [1, 2, 3]);
+		assert_eq!(simul.step_event().tick, 4);
+		assert_eq!(simul.explain("n2").unwrap(), "n2 = 1 (Not)\n  input 0: n1 = 0\n");
+	}
+	#[test]
+	fn step_events(){
+		// n2 is updated before n1, so a change to a takes two passes to reach q
+		let mut simul: Simulation = crate::circuit!{ in a; n2 = not(n1); n1 = not(a); out q = n2; }.into();
+		simul.update_until_done(100);
+		**simul.get_inputs_mut().get_mut("a").unwrap() = true;
+		let events: Vec<_> = (0..3).map(|_| simul.step_event()).collect();
+		assert_eq!(serde_json::to_string(&events).unwrap(), r#"[{"tick":1,"changed":[["n1",[false]]]},{"tick":2,"changed":[["n2",[true]],["q",[true]]]},{"tick":3,"changed":[]}]"#);
+	}
+	#[test]
+	fn inputs_mut(){
+		let mut simul = load(fixtures::MAJORITY);
+		assert_eq!(simul.inputs_mut().count(), simul.named_input_count());
+		for (val, bit) in simul.inputs_mut().rev().zip([true, true, false]) {
+			*val = bit;
+		}
+		simul.update_until_done(100);
+		assert_eq!(simul.output_values(), [true]);
+		assert_eq!(simul.inputs_mut().map(|val| *val).collect::<Vec<_>>(), [false, true, true]);
+	}
+	#[test]
+	fn waveform(){
+		let mut simul: Simulation = crate::circuit!{ in a; n1 = not(a); n2 = not(n1); out long_name = n2; }.into();
+		simul.update_until_done(100);
+		**simul.get_inputs_mut().get_mut("a").unwrap() = true;
+		assert_eq!(simul.format_waveform(5, &["a", "n1", "n2", "long_name"]).unwrap(), [
+			"        a ‾‾‾‾‾\n",
+			"       n1 ‾____\n",
+			"       n2 _‾‾‾‾\n",
+			"long_name _‾‾‾‾\n",
+		].concat());
+		assert_eq!(simul.format_waveform(1, &["b"]).unwrap_err().to_string(), "No object named b");
+		// A clock driven by an input source keeps counting across waveforms
+		let mut simul: Simulation = crate::circuit!{ in clk; n = not(clk); out q = n; }.into();
+		simul.set_input_source("clk", Box::new(|tick| tick % 2 == 1)).unwrap();
+		assert_eq!(simul.format_waveform(5, &["clk", "q"]).unwrap(), "clk __‾_‾\n  q _‾_‾_\n");
+		assert_eq!(simul.tick, 4);
+		assert_eq!(simul.format_waveform(3, &["clk"]).unwrap(), "clk ‾_‾\n");
+	}
+	#[test]
+	fn fingerprint(){
+		let xor = load(fixtures::XOR).fingerprint(100).unwrap();
+		let mut nands: Simulation = crate::circuit!{ in b, a; n = nand(a, b); out q = nand(nand(a, n), nand(n, b)); }.into();
+		assert_eq!(nands.fingerprint(100).unwrap(), xor);
+		let mut xnor: Simulation = crate::circuit!{ in a, b; out q = xnor(a, b); }.into();
+		assert_ne!(xnor.fingerprint(100).unwrap(), xor);
+		let mut renamed: Simulation = crate::circuit!{ in a, c; out q = xor(a, c); }.into();
+		assert_ne!(renamed.fingerprint(100).unwrap(), xor);
+		// Stable across runs and platforms
+		assert_eq!(xor.iter().map(|b| format!("{b:02x}")).collect::<String>(), "693ba799cbdc7dae9e990f352fab4cb6094a614cae99e93033898333fc444932");
+		let latch = fixtures::gate_circuit("or", 2, None).replace(r#"outputUID="i1" inputIndex="1""#, r#"outputUID="g" inputIndex="1""#);
+		assert_eq!(load(&latch).fingerprint(100).unwrap_err().to_string(), "Cannot fingerprint a sequential circuit");
+	}
+	#[test]
+	fn apply_inputs(){
+		let mut simul = load(fixtures::NESTED_ADDER);
+		let mut inputs = HashMap::from([("x0", true), ("y0", true)]);
+		simul.get_outputs(&inputs, 100);
+		// 3 + 1 = 4: the carry turns on, s1 turns off, and s0 stays off
+		let delta = simul.apply_inputs(&HashMap::from([("x1", true)]), 100).unwrap();
+		assert_eq!(delta.changed, [(String::from("c"), true), (String::from("s1"), false)]);
+		inputs.insert("x1", true);
+		let outputs = simul.get_outputs(&inputs, 100);
+		assert_eq!(outputs, load(fixtures::NESTED_ADDER).get_outputs(&inputs, 100));
+		assert_eq!(simul.apply_inputs(&HashMap::from([("x1", true)]), 100).unwrap().changed, []);
+		assert_eq!(simul.apply_inputs(&HashMap::from([("z", true)]), 100).unwrap_err().to_string(), "No input named z");
+	}
+	#[test]
+	fn output_changes(){
+		let mut simul = load(fixtures::HALF_ADDER);
+		assert_eq!(simul.take_output_changes(), []);
+		simul.apply_inputs(&HashMap::from([("x", true)]), 100).unwrap();
+		assert_eq!(simul.take_output_changes(), [(String::from("sum"), false, true)]);
+		assert_eq!(simul.take_output_changes(), []);
+		simul.apply_inputs(&HashMap::from([("y", true)]), 100).unwrap();
+		assert_eq!(simul.take_output_changes(), [(String::from("sum"), true, false), (String::from("carry"), false, true)]);
+		simul.apply_inputs(&HashMap::from([("x", true)]), 100).unwrap();
+		assert_eq!(simul.take_output_changes(), []);
+		let saved = simul.save_state();
+		simul.reset_state();
+		assert_eq!(simul.take_output_changes(), []);
+		simul.restore_state(&saved).unwrap();
+		assert_eq!(simul.take_output_changes(), [(String::from("carry"), false, true)]);
+	}
+	#[test]
+	fn probes(){
+		let mut simul = load(fixtures::HALF_ADDER);
+		simul.get_outputs(&HashMap::from([("x", true), ("y", true)]), 100);
+		let sum_uid = simul.objects.iter().find(|o| o.export_name_or_uid() == "sum").unwrap().uid().to_string();
+		let gate = simul.objects.iter().find(|o| matches!(o.inner, ObjectInner::CustomGate { .. })).unwrap().uid().to_string();
+		let probes = simul.get_probes(&["carry", &sum_uid, &gate, "missing"]);
+		assert_eq!(probes.len(), 3);
+		assert_eq!(probes["carry"], [true]);
+		assert_eq!(probes[&sum_uid], [false]);
+		assert_eq!(probes[&gate].len(), 2);
+	}
+	#[test]
+	fn matches_expression(){
+		let mut majority = load(fixtures::MAJORITY);
+		assert!(majority.matches_expression("m", "a & b | a & c | b & c", 100).unwrap());
+		assert!(majority.matches_expression("m", "(a | b) & (a | c) & (b | c)", 100).unwrap());
+		assert!(!majority.matches_expression("m", "a ^ b ^ c", 100).unwrap());
+		let mut half_adder = load(fixtures::HALF_ADDER);
+		assert!(half_adder.matches_expression("sum", "x&!y | !x&y", 100).unwrap());
+		assert!(half_adder.matches_expression("carry", "!(!x | !y)", 100).unwrap());
+		let mut error = |expr: &str| majority.matches_expression("m", expr, 100).unwrap_err().to_string();
+		assert_eq!(error("a & (b"), "expected ) at column 7 of `a & (b`");
+		assert_eq!(error("a & d"), "unknown input d, expected one of a, b, c at column 5 of `a & d`");
+		assert_eq!(error("a &"), "expected an input name, found the end at column 4 of `a &`");
+		assert_eq!(error("a b"), "unexpected 'b' at column 3 of `a b`");
+		assert_eq!(majority.matches_expression("z", "a", 100).unwrap_err().to_string(), "No output named z");
+	}
+	#[test]
+	fn minimize_inputs(){
+		// d is not connected to anything, and r is always false whatever c is
+		let circuit = crate::circuit!{
+			in a, d, b, c;
+			out q = xor(a, b);
+			out r = and(c, not(c));
+		};
+		let cones = circuit.output_cones();
+		assert_eq!(cones["q"], BTreeSet::from(["a".to_string(), "b".to_string()]));
+		assert_eq!(cones["r"], BTreeSet::from(["c".to_string()]));
+		let mut simul: Simulation = circuit.into();
+		let table = simul.get_truth_table(100).unwrap();
+		let (minimized, removed) = table.minimize_inputs(&cones);
+		assert_eq!(removed, vec!["d", "c"]);
+		assert_eq!(minimized.inputs(), ["a", "b"]);
+		assert_eq!(minimized.rows().collect::<Vec<_>>(), [[false, false], [true, false], [true, false], [false, false]]);
+		let (_, removed) = load(fixtures::MAJORITY).get_truth_table(100).unwrap().minimize_inputs(&parse_xml(fixtures::MAJORITY).unwrap().output_cones());
+		assert!(removed.is_empty());
+	}
+	#[test]
+	fn project_and_drop_inputs(){
+		let mut simul: Simulation = crate::circuit!{ in a, d, b; out q = xor(a, b); out r = and(a, d); out s = or(a, b); }.into();
+		let table = simul.get_truth_table(100).unwrap();
+		let projected = table.project(&["s", "q"]);
+		assert_eq!(projected.outputs(), ["s", "q"]);
+		assert_eq!(projected.num_rows(), table.num_rows());
+		assert_eq!(projected.drop_inputs(&["d"]).unwrap().inputs(), ["a", "b"]);
+		assert_eq!(table.drop_inputs(&["d", "b"]).unwrap_err().to_string(), "Cannot remove inputs [d, b]: outputs [q, r, s] depend on them");
+		assert_eq!(table.drop_inputs(&["d"]).unwrap_err().outputs, ["r"]);
+		// The projection matches the table of a circuit with only those outputs
+		let mut expected: Simulation = crate::circuit!{ in a, b; out s = or(a, b); out q = xor(a, b); }.into();
+		let expected = expected.get_truth_table(100).unwrap();
+		assert_eq!(projected.drop_inputs(&["d"]).unwrap().assert_matches(&expected), Ok(()));
+		assert_eq!(projected.assert_matches(&expected).unwrap_err().to_string(), "Expected inputs [a, b], found [a, d, b]");
+		assert_eq!(table.project(&["r"]).drop_inputs(&["b"]).unwrap().rows().collect::<Vec<_>>(), [[false], [false], [false], [true]]);
+	}
+
+	#[test]
+	fn find_rows(){
+		let mut simul: Simulation = crate::circuit!{ in a, b, cin; x = xor(a, b); out sum = xor(x, cin); out carry = or(and(a, b), and(x, cin)); }.into();
+		let table = simul.get_truth_table(100).unwrap();
+		let found: Vec<usize> = table.find_where("carry==1 && sum==0").unwrap().map(|row| row.index()).collect();
+		assert_eq!(found, [3, 5, 6]);
+		assert_eq!(table.rows_where("carry", true).map(|row| row.index()).collect::<Vec<_>>(), [3, 5, 6, 7]);
+		let row = table.find(|row| row.output("sum") && !row.input("a")).next().unwrap();
+		assert_eq!((row.index(), row.inputs(), row.outputs()), (1, vec![false, false, true], &[true, false][..]));
+		assert_eq!(table.format_matches("carry == 1 && sum == 0", 2).unwrap(), "a=0, b=1, cin=1\na=1, b=0, cin=1\n... and 1 more\n");
+		assert_eq!(table.format_matches("carry==1 & !carry", 2).unwrap(), "No rows where carry==1 & !carry\n");
+		assert!(table.find_where("carry==2").is_err());
+		// Inputs named like a0, a1 are shown as one number
+		let mut simul: Simulation = crate::circuit!{ in a1, a0, c; out q = and(a1, a0); }.into();
+		let table = simul.get_truth_table(100).unwrap();
+		assert_eq!(table.format_matches("q", 10).unwrap(), "a=3, c=0\na=3, c=1\n");
+	}
+
+	#[test]
+	fn compare_outputs(){
+		let mut simul = load(fixtures::CHECKED_ADDER);
+		let same = simul.compare_outputs("spec_sum", "impl_sum", 100).unwrap();
+		assert_eq!((same.rows, same.differences.len()), (8, 0));
+		assert_eq!(same.to_string(), "spec_sum and impl_sum are identical over 8 rows\n");
+		let different = simul.compare_outputs("spec_cout", "impl_cout", 100).unwrap();
+		assert_eq!(different.differences, [(3, true)]);
+		assert_eq!(different.to_string(), "spec_cout and impl_cout differ in 1 of 8 rows:\n  a=0, b=1, cin=1: spec_cout=1, impl_cout=0\n");
+		assert_eq!(simul.compare_outputs("spec_sum", "nope", 100).unwrap_err().to_string(), "No output named nope");
+		// The same rows are found without the lookup table
+		let mut simul = Simulation::with_config(parse_xml(fixtures::CHECKED_ADDER).unwrap(), SimulationConfig::default().lookup_table_max_inputs(0)).unwrap();
+		assert_eq!(simul.compare_outputs("spec_cout", "impl_cout", 100).unwrap(), different);
+	}
+	#[test]
+	fn outputs_with_dont_cares(){
+		let mut majority = load(fixtures::MAJORITY);
+		let outputs = |simul: &mut Simulation, inputs: &[(&str, Option<bool>)]| simul.get_outputs_partial(&inputs.iter().copied().collect(), 100).unwrap()["m"];
+		assert_eq!(outputs(&mut majority, &[("a", Some(true)), ("b", None), ("c", None)]), None);
+		assert_eq!(outputs(&mut majority, &[("a", Some(true)), ("b", Some(true)), ("c", None)]), Some(true));
+		assert_eq!(outputs(&mut majority, &[("a", Some(false)), ("b", None), ("c", Some(false))]), Some(false));
+		// Inputs that are left out are reset, as in get_outputs
+		assert_eq!(outputs(&mut majority, &[("a", None)]), Some(false));
+		assert_eq!(outputs(&mut majority, &[("a", Some(true)), ("b", Some(true)), ("c", Some(false))]), Some(true));
+		let mut half_adder: Simulation = crate::circuit!{ in a, b; out s = xor(a, b); out c = and(a, b); }.into();
+		let found = half_adder.get_outputs_partial(&HashMap::from([("a", Some(false)), ("b", None)]), 100).unwrap();
+		assert_eq!(found, HashMap::from([("s".to_string(), None), ("c".to_string(), Some(false))]));
+		let error = |simul: &mut Simulation, inputs: HashMap<&str, Option<bool>>| simul.get_outputs_partial(&inputs, 100).unwrap_err().to_string();
+		assert_eq!(error(&mut half_adder, HashMap::from([("a", None), ("z", None)])), "No input named z");
+		let names: Vec<String> = (0..64).map(|i| format!("i{i}")).collect();
+		let mut wide = load(&fixtures::gate_circuit("and", 64, None));
+		assert_eq!(error(&mut wide, names.iter().map(|name| (&name[..], None)).collect()), "Too many don't-cares: 64");
+		let mut latch = load(&fixtures::gate_circuit("nor", 1, None).replace(r#"outputUID="i0""#, r#"outputUID="g""#));
+		assert_eq!(error(&mut latch, HashMap::new()), "circuit did not stabilize within 100 update passes");
+	}
+	#[test]
+	fn constant_inputs_only(){
+		let xml = r#"<logicly>
+			<object type="constant_high@logic.ly" uid="h" x="0" y="0" rotation="0"/>
+			<object type="constant_low@logic.ly" uid="l" x="0" y="40" rotation="0"/>
+			<object type="nand@logic.ly" uid="g" x="80" y="20" rotation="0" inputs="2"/>
+			<object type="light_bulb@logic.ly" uid="q" x="160" y="0" rotation="0" exportName="q"/>
+			<object type="light_bulb@logic.ly" uid="r" x="160" y="40" rotation="0" exportName="r"/>
+			<connection inputUID="g" outputUID="h" inputIndex="0" outputIndex="0"/>
+			<connection inputUID="g" outputUID="l" inputIndex="1" outputIndex="0"/>
+			<connection inputUID="q" outputUID="g" inputIndex="0" outputIndex="0"/>
+			<connection inputUID="r" outputUID="h" inputIndex="0" outputIndex="0"/>
+		</logicly>"#;
+		let expected = HashMap::from([("q".to_string(), true), ("r".to_string(), true)]);
+		for lookup in [0, 16] {
+			let mut simul = Simulation::with_config(parse_xml(xml).unwrap(), SimulationConfig::default().lookup_table_max_inputs(lookup)).unwrap();
+			assert_eq!(simul.get_outputs(&HashMap::new(), 100), expected);
+			let table = simul.get_truth_table(100).unwrap();
+			assert_eq!(table.rows().collect::<Vec<_>>(), [[true, true]]);
+			// A state where the constants were overwritten, as restored from another circuit with the same shape
+			simul.restore_state(&{ let mut other = simul.clone(); other.state.fill(false); other.save_state() }).unwrap();
+			assert_eq!(simul.get_outputs(&HashMap::new(), 100), expected);
+			assert_eq!(simul.get_outputs_wide(&[], 100), [true, true]);
+		}
+	}
+	#[test]
+	fn redundant_outputs(){
+		let mut simul = load(r#"<logicly>
+			<object type="switch@logic.ly" uid="a" x="0" y="0" rotation="0" exportName="a" outputs="false"/>
+			<object type="switch@logic.ly" uid="b" x="0" y="40" rotation="0" exportName="b" outputs="false"/>
+			<object type="not@logic.ly" uid="n" x="80" y="0" rotation="0" inputs="1"/>
+			<object type="light_bulb@logic.ly" uid="p" x="160" y="0" rotation="0" exportName="p"/>
+			<object type="light_bulb@logic.ly" uid="q" x="160" y="40" rotation="0" exportName="q"/>
+			<object type="light_bulb@logic.ly" uid="r" x="160" y="80" rotation="0" exportName="r"/>
+			<object type="light_bulb@logic.ly" uid="s" x="160" y="120" rotation="0" exportName="s"/>
+			<connection inputUID="n" outputUID="a" inputIndex="0" outputIndex="0"/>
+			<connection inputUID="p" outputUID="a" inputIndex="0" outputIndex="0"/>
+			<connection inputUID="q" outputUID="a" inputIndex="0" outputIndex="0"/>
+			<connection inputUID="r" outputUID="n" inputIndex="0" outputIndex="0"/>
+			<connection inputUID="s" outputUID="b" inputIndex="0" outputIndex="0"/>
+			<setting name="gridSize" value="10"/>
+		</logicly>"#);
+		let pair = |a: &str, b: &str, relation| (a.to_string(), b.to_string(), relation);
+		assert_eq!(simul.redundant_outputs(100).unwrap(), vec![
+			pair("p", "q", OutputRelation::Equal),
+			pair("p", "r", OutputRelation::Complement),
+			pair("q", "r", OutputRelation::Complement),
+		]);
+		assert_eq!(load(fixtures::HALF_ADDER).redundant_outputs(100).unwrap(), vec![]);
+	}
+	#[test]
+	fn try_get_row(){
+		let table = load(fixtures::HALF_ADDER).get_truth_table(100).unwrap();
+		assert_eq!(table.try_get_row(3), Some(&[false, true][..]));
+		assert_eq!(table.try_get_row(4), None);
+		assert_eq!(table.try_get_row(usize::MAX), None);
+	}
+	#[test]
+	fn inputs_by_uid(){
+		// b has no export name
+		let mut simul = load(&fixtures::XOR.replace(r#" exportName="b""#, ""));
+		assert!(!simul.get_outputs(&HashMap::from([("a", true), ("b", true)]), 100)["q"]);
+		assert!(simul.get_outputs(&HashMap::from([("b", true)]), 100)["q"]);
+		simul.use_lookup_table();
+		assert!(!simul.get_outputs(&HashMap::from([("a", true), ("b", true)]), 100)["q"]);
+		assert!(simul.get_outputs(&HashMap::from([("a", true)]), 100)["q"]);
+		// Export names take precedence over uids
+		let mut simul = load(&fixtures::XOR.replace(r#" exportName="b""#, "").replace(r#"exportName="a""#, r#"exportName="b""#));
+		assert!(simul.get_outputs(&HashMap::from([("b", true)]), 100)["q"]);
+		assert!(simul.get_outputs(&HashMap::from([("a", true), ("b", true)]), 100)["q"]);
+	}
+	#[test]
+	fn distinct_output_functions(){
+		let mut simul: Simulation = crate::circuit!{ in a, b; out p = and(a, b); out q = and(b, a); out r = nand(a, b); out s = xor(a, b); }.into();
+		assert_eq!(simul.distinct_output_functions(100, false).unwrap(), 3);
+		assert_eq!(simul.distinct_output_functions(100, true).unwrap(), 2);
+		assert_eq!(load(fixtures::AND_SUBTREES).distinct_output_functions(100, false).unwrap(), 1);
+		assert_eq!(load(fixtures::HALF_ADDER).distinct_output_functions(100, true).unwrap(), 2);
+		let ring = fixtures::gate_circuit("nand", 2, None).replace(r#"outputUID="i1" inputIndex="1""#, r#"outputUID="g" inputIndex="1""#);
+		assert_eq!(load(&ring).distinct_output_functions(100, false).unwrap_err().to_string(), "circuit was unstable in row 2");
+	}
+	#[test]
+	fn output_convergence(){
+		// A not gate feeding itself, which never settles but does not reach the output
+		let xml = fixtures::XOR.replace(r#"<setting"#, r#"<object type="not@logic.ly" uid="osc" x="80" y="80" rotation="0" inputs="1"/>
+	<connection inputUID="osc" outputUID="osc" inputIndex="0" outputIndex="0"/>
+	<setting"#);
+		assert_eq!(load(&xml).get_truth_table(100), None);
+		let mut converging = Simulation::with_config(parse_xml(&xml).unwrap(), SimulationConfig::default().output_convergence(Some(3))).unwrap();
+		assert_eq!(converging.get_truth_table(100), load(fixtures::XOR).get_truth_table(100));
+		// Without the oscillator, the circuit is stable before the outputs have been unchanged for long enough
+		let mut stable = Simulation::with_config(parse_xml(fixtures::XOR).unwrap(), SimulationConfig::default().output_convergence(Some(50))).unwrap();
+		assert_eq!(stable.get_truth_table(100), load(fixtures::XOR).get_truth_table(100));
+	}
+	#[test]
+	fn table_memory_limit(){
+		let mut wide = load(&fixtures::gate_circuit("and", 40, None));
+		let error = wide.try_get_truth_table(100).unwrap_err();
+		assert_eq!(error.downcast_ref(), Some(&TableTooLarge { inputs: 40, outputs: 1, bytes: Some(1 << 40), limit: 1 << 20 }));
+		assert_eq!(wide.get_truth_table(100), None);
+		let mut widest = load(&fixtures::gate_circuit("or", 64, None));
+		assert_eq!(widest.try_get_truth_table(100).unwrap_err().to_string(), "The truth table for 64 inputs and 1 outputs needs more than usize::MAX bytes, over the limit of 1048576 bytes");
+		// Printed tables are not held in memory, but still need a row count
+		assert_eq!(widest.format_truth_table(100).unwrap_err().to_string(), "Too many inputs to print every row: 64");
+		// 4 rows of 1 output, plus 16 bytes per row for the stats
+		let mut xor = Simulation::with_config(parse_xml(fixtures::XOR).unwrap(), SimulationConfig::default().table_memory_limit(4)).unwrap();
+		assert_eq!(xor.try_get_truth_table(100).unwrap(), load(fixtures::XOR).get_truth_table(100).unwrap());
+		assert_eq!(xor.try_get_truth_table_with_stats(100).unwrap_err().to_string(), "The truth table for 2 inputs and 1 outputs needs about 68 bytes, over the limit of 4 bytes");
+		// Custom circuit tables are not limited, and callers that only need a table report the limit instead of instability
+		let mut half_adder = Simulation::with_config(parse_xml(fixtures::HALF_ADDER).unwrap(), SimulationConfig::default().table_memory_limit(2)).unwrap();
+		assert_eq!(half_adder.export_tables().len(), 1);
+		assert!(half_adder.redundant_outputs(100).unwrap_err().is::<TableTooLarge>());
+		assert!(half_adder.distinct_output_functions(100, false).unwrap_err().is::<TableTooLarge>());
+	}
+	#[test]
+	fn progress_events(){
+		let events = Arc::new(std::sync::Mutex::new(vec![]));
+		let recorded = events.clone();
+		let config = SimulationConfig::default().progress(Box::new(move |event| recorded.lock().unwrap().push(event)));
+		let mut simul = Simulation::with_config(parse_xml(&fixtures::gate_circuit("and", 12, None)).unwrap(), config.clone()).unwrap();
+		simul.get_truth_table(100).unwrap();
+		let table_events = std::mem::take(&mut *events.lock().unwrap());
+		assert_eq!(table_events.iter().map(|e| (&e.task, e.done, e.total)).collect::<Vec<_>>(), [1024, 2048, 3072, 4096].map(|done| (&ProgressTask::TruthTable, done, 4096)));
+		assert!(table_events.windows(2).all(|pair| pair[0].elapsed <= pair[1].elapsed));
+		// Printed tables are reported the same way, with a single event for small tables
+		Simulation::with_config(parse_xml(fixtures::XOR).unwrap(), config.clone()).unwrap().format_truth_table(100).unwrap();
+		assert_eq!(std::mem::take(&mut *events.lock().unwrap()).iter().map(|e| e.done).collect::<Vec<_>>(), [4]);
+		// Custom circuit tables are computed when the simulation is built
+		Simulation::with_config(parse_xml(fixtures::HALF_ADDER).unwrap(), config).unwrap();
+		assert!(events.lock().unwrap().iter().any(|e| matches!(&e.task, ProgressTask::CustomTable(name) if !name.is_empty())), "{:?}", events.lock().unwrap());
+	}
+	#[test]
+	fn cancellation(){
+		let token = CancellationToken::new();
+		let (reached, wait) = (std::sync::mpsc::channel(), std::sync::mpsc::channel::<()>());
+		let (reached_tx, wait_rx) = (std::sync::Mutex::new(reached.0), std::sync::Mutex::new(wait.1));
+		// The progress callback pauses the sweep after 1024 rows until the other thread has cancelled it
+		let config = SimulationConfig::default().cancellation(token.clone()).progress(Box::new(move |event| {
+			if event.done == 1024 {
+				reached_tx.lock().unwrap().send(()).unwrap();
+				wait_rx.lock().unwrap().recv().unwrap();
+			}
+		}));
+		let mut simul = Simulation::with_config(parse_xml(&fixtures::gate_circuit("and", 12, None)).unwrap(), config).unwrap();
+		let sweep = std::thread::spawn(move || simul.try_get_truth_table(100));
+		reached.1.recv().unwrap();
+		token.cancel();
+		wait.0.send(()).unwrap();
+		let error = sweep.join().unwrap().unwrap_err();
+		assert_eq!(error.downcast_ref(), Some(&Cancelled { task: ProgressTask::TruthTable, done: 1024, total: 4096 }));
+		assert_eq!(error.to_string(), "Truth table cancelled after 1024 of 4096");
+		// Once cancelled, building a simulation fails while computing its custom circuit tables
+		let cancelled = SimulationConfig::default().cancellation(token);
+		let error = Simulation::with_config(parse_xml(fixtures::HALF_ADDER).unwrap(), cancelled.clone()).unwrap_err();
+		assert!(matches!(error.downcast_ref(), Some(Cancelled { task: ProgressTask::CustomTable(_), done: 0, .. })), "{error}");
+		let mut checked = Simulation::with_config(parse_xml(fixtures::CHECKED_ADDER).unwrap(), cancelled.clone()).unwrap();
+		assert!(checked.compare_outputs("spec_cout", "impl_cout", 100).unwrap_err().is::<Cancelled>());
+		assert_eq!(checked.format_truth_table(100).unwrap().lines().count(), 2);
+	}
+	#[test]
+	fn digit_display_columns(){
+		let mut simul = load(fixtures::DIGIT);
+		let table = simul.get_truth_table(100).unwrap();
+		assert_eq!(table.outputs(), ["d.0", "d.1", "d.2", "d.3", "q"]);
+		assert_eq!(table.output_widths(), [4, 1]);
+		assert_eq!(&table[3], [true, true, false, true, true]);
+		assert_eq!(table.find(|_| true).map(|row| (row.output_value("d"), row.output_value("q"))).collect::<Vec<_>>(), [(0, 0), (2, 0), (1, 0), (11, 1)]);
+		assert_eq!(table.find_where("d.3==1 && q==1").unwrap().count(), 1);
+		// Simulating one row still gives one value per output, with or without a lookup table
+		assert_eq!(simul.get_outputs_wide(&[false, true], 100), [false, false]);
+		assert!(simul.use_lookup_table());
+		assert_eq!(simul.get_outputs_wide(&[true, true], 100), [true, true]);
+		assert!(simul.matches_expression("q", "a & b", 100).unwrap());
+		assert_eq!(load(fixtures::XOR).get_truth_table(100).unwrap().output_widths(), [1]);
+		// Printed tables, the lookup table, and custom circuit tables all have the same columns
+		let printed = "b|a||q|d.0|d.1|d.2|d.3\n----------------------\nF|F||F| F | F | F | F \nF|T||F| T | F | F | F \nT|F||F| F | T | F | F \nT|T||T| T | T | F | T \n";
+		assert_eq!(simul.lookup.as_ref().unwrap().outputs(), table.outputs());
+		assert_eq!(simul.format_truth_table(100).unwrap(), printed);
+		assert_eq!(load(fixtures::DIGIT).format_truth_table(100).unwrap(), printed);
+		let body = fixtures::DIGIT.lines().filter(|line| line.contains("<object") || line.contains("<connection")).join("\n");
+		let uuid = "9d3c2b1a-0f4e-4d5c-8b7a-6e5f4d3c2b1a";
+		let nested = format!(r#"<logicly>
+			<object type="switch@logic.ly" uid="x" x="0" y="0" rotation="0" exportName="x" outputs="false"/>
+			<object type="switch@logic.ly" uid="y" x="0" y="40" rotation="0" exportName="y" outputs="false"/>
+			<object type="{uuid}" uid="c" x="80" y="0" rotation="0"/>
+			<object type="light_bulb@logic.ly" uid="o0" x="160" y="0" rotation="0" exportName="o0"/>
+			<object type="light_bulb@logic.ly" uid="o1" x="160" y="40" rotation="0" exportName="o1"/>
+			<connection inputUID="c" outputUID="x" inputIndex="0" outputIndex="0"/>
+			<connection inputUID="c" outputUID="y" inputIndex="1" outputIndex="0"/>
+			<connection inputUID="o0" outputUID="c" inputIndex="0" outputIndex="0"/>
+			<connection inputUID="o1" outputUID="c" inputIndex="0" outputIndex="1"/>
+			<custom name="Digit" type="{uuid}" label="D"><logicly>{body}</logicly></custom>
+		</logicly>"#);
+		let mut nested = load(&nested);
+		assert_eq!(nested.export_tables()[uuid].outputs(), table.outputs());
+		assert_eq!(nested.format_all_custom_tables(), format!("D\n{printed}\n"));
+		// A digit display passes on the value of its first input
+		assert_eq!(nested.get_truth_table(100).unwrap().rows().collect::<Vec<_>>(), [[false, false], [false, false], [true, false], [true, true]]);
+	}
+	#[test]
+	fn toggle_coverage(){
+		let mut xor = load(fixtures::XOR);
+		let report = xor.toggle_coverage(&[vec![false, false], vec![true, true]], &["a", "b"], 100).unwrap();
+		assert_eq!(report, CoverageReport { gates: 1, untoggled: vec!["g".into()] });
+		assert_eq!(report.percentage(), 0.);
+		assert_eq!(xor.toggle_coverage(&[vec![false], vec![true]], &["b"], 100).unwrap().percentage(), 100.);
+		assert_eq!(xor.toggle_coverage(&[vec![true]], &["c"], 100).unwrap_err().to_string(), "No input named c");
+		assert_eq!(xor.toggle_coverage(&[vec![true]], &["a", "b"], 100).unwrap_err().to_string(), "Vector 0 has 1 values, expected 2");
+		let mut adder = load(fixtures::RIPPLE_ADDER);
+		let inputs = adder.input_names().into_iter().map(String::from).collect::<Vec<_>>();
+		let inputs = inputs.iter().map(|s| &s[..]).collect::<Vec<_>>();
+		let vectors = [vec![false; inputs.len()], vec![true; inputs.len()]];
+		let report = adder.toggle_coverage(&vectors, &inputs, 100).unwrap();
+		// The lowest full adder has no carry in, so adding 1 and 1 never sets its sum
+		assert_eq!(report.untoggled, ["fa0"]);
+		assert_eq!(report.percentage(), 75.);
+	}
+	#[test]
+	fn truth_table_parity_column(){
+		let mut simul = load(fixtures::HALF_ADDER);
+		assert_eq!(simul.format_truth_table_with(100, Some(("parity", &parity))).unwrap(), "\
+y|x||sum|carry||parity
+----------------------
+F|F|| F |  F  ||  F   
+F|T|| T |  F  ||  T   
+T|F|| T |  F  ||  T   
+T|T|| F |  T  ||  T   
+");
+		assert_eq!(simul.format_truth_table_with(100, None).unwrap(), simul.format_truth_table(100).unwrap());
+	}
+	#[test]
+	fn observers(){
+		let events = Arc::new(std::sync::Mutex::new(vec![]));
+		let record = || {
+			let events = events.clone();
+			Box::new(move |event: &ChangeEvent| events.lock().unwrap().push((event.name.clone(), event.new.clone())))
+		};
+		let mut simul = load(fixtures::HALF_ADDER);
+		let sum = simul.on_change(ProbeTarget::Name("sum".into()), record()).unwrap();
+		simul.on_change(ProbeTarget::Name("carry".into()), record()).unwrap();
+		assert_eq!(simul.on_change(ProbeTarget::Uid("nope".into()), record()).unwrap_err().to_string(), "No object with uid nope");
+		simul.apply_inputs(&HashMap::from([("x", true)]), 100).unwrap();
+		simul.apply_inputs(&HashMap::from([("y", true)]), 100).unwrap();
+		// Same inputs as before, so the outputs settle to the same values after the reset
+		simul.get_outputs(&HashMap::from([("x", true), ("y", true)]), 100);
+		assert!(simul.unsubscribe(sum));
+		assert!(!simul.unsubscribe(sum));
+		simul.apply_inputs(&HashMap::from([("y", false)]), 100).unwrap();
+		assert_eq!(*events.lock().unwrap(), [
+			(String::from("sum"), vec![true]),
+			(String::from("sum"), vec![false]),
+			(String::from("carry"), vec![true]),
+			(String::from("carry"), vec![false]),
+		]);
+		// q is briefly true while g sees the new a and the old n
+		let mut simul: Simulation = crate::circuit!{ in a; g = and(a, n); n = not(a); out q = g; }.into();
+		simul.update_until_done(100);
+		events.lock().unwrap().clear();
+		simul.on_change(ProbeTarget::Name("q".into()), record()).unwrap();
+		simul.on_every_change(ProbeTarget::Name("q".into()), record()).unwrap();
+		simul.apply_inputs(&HashMap::from([("a", true)]), 100).unwrap();
+		assert_eq!(*events.lock().unwrap(), [(String::from("q"), vec![true]), (String::from("q"), vec![false])]);
+	}
+	#[test]
+	fn input_sources(){
+		let pattern = [true, true, false, true, false, false];
+		let mut simul = load(fixtures::XOR);
+		**simul.get_inputs_mut().get_mut("b").unwrap() = true;
+		simul.set_input_source("a", Box::new(move |tick| pattern[tick as usize % pattern.len()])).unwrap();
+		assert_eq!(simul.set_input_source("q", Box::new(|_| true)).unwrap_err().to_string(), "No input named q");
+		let q: Vec<bool> = (0..8).map(|_| { simul.step(); simul.get_probes(&["q"])["q"][0] }).collect();
+		assert_eq!(q, [false, false, true, false, true, true, false, false]);
+		// The source is consulted once per pass, so updating until stable keeps the value for the current step
+		simul.update_until_done(100);
+		assert_eq!(simul.get_probes(&["a"])["a"], [false]);
+		assert!(simul.clear_input_source("a"));
+		assert!(!simul.clear_input_source("a"));
+		**simul.get_inputs_mut().get_mut("a").unwrap() = true;
+		simul.step();
+		assert_eq!(simul.get_probes(&["q"])["q"], [false]);
+	}
+	#[test]
+	fn one_input_gates(){
+		for (kind, inverts) in [("buffer", false), ("not", true), ("and", false), ("nand", true), ("or", false), ("nor", true), ("xor", false), ("xnor", true)] {
+			for function_index in [None, Some(1)] {
+				let mut simul = load(&fixtures::gate_circuit(kind, 1, function_index));
+				let mut gate = simul.as_fn();
+				assert_eq!(gate(&[false]), Some(vec![inverts]), "{kind} {function_index:?}");
+				assert_eq!(gate(&[true]), Some(vec![!inverts]), "{kind} {function_index:?}");
+			}
+		}
+	}
+	#[test]
+	fn inverted_input(){
+		let mut circuit = parse_xml(&fixtures::gate_circuit("and", 2, None)).unwrap();
+		circuit.objects[2].set_input_inverted(1, true).unwrap();
+		assert!(circuit.objects[2].set_input_inverted(2, true).is_err());
+		assert!(circuit.objects[0].set_input_inverted(0, true).is_err());
+		let mut simul: Simulation = circuit.into();
+		let mut and_not = simul.as_fn();
+		assert_eq!(and_not(&[false, false]), Some(vec![false]));
+		assert_eq!(and_not(&[false, true]), Some(vec![false]));
+		assert_eq!(and_not(&[true, false]), Some(vec![true]));
+		assert_eq!(and_not(&[true, true]), Some(vec![false]));
+	}
+	/// Run with `cargo test --release bench_lookup_table -- --ignored --nocapture`.
+	#[test]
+	#[ignore]
+	fn bench_lookup_table(){
+		let mut simul = load(&fixtures::synthetic_circuit(12, 2000, 8));
+		let rows: Vec<Vec<bool>> = (0..4096).map(|row| int_to_bits(row, 12)).collect();
+		let start = std::time::Instant::now();
+		let simulated: Vec<_> = rows.iter().map(|row| simul.get_outputs_wide(row, 100)).collect();
+		println!("simulated 4096 calls: {:?}", start.elapsed());
+		let start = std::time::Instant::now();
+		assert!(simul.use_lookup_table());
+		println!("built lookup table: {:?}", start.elapsed());
+		let start = std::time::Instant::now();
+		let looked_up: Vec<_> = rows.iter().map(|row| simul.get_outputs_wide(row, 100)).collect();
+		println!("looked up 4096 calls: {:?}", start.elapsed());
+		assert_eq!(simulated, looked_up);
+	}
+	/// Run with `cargo test --release bench_parallel_customs -- --ignored --nocapture`.
+	/// Thread counts above the number of cores are still run, but cannot be any faster.
+	#[test]
+	#[ignore]
+	fn bench_parallel_customs(){
+		let circuit = parse_xml(&fixtures::sibling_customs(16, 14, 400)).unwrap();
+		let build = |threads: usize| {
+			let start = std::time::Instant::now();
+			let simul = Simulation::with_config(circuit.clone(), SimulationConfig::default().threads(threads)).unwrap();
+			println!("{threads} thread(s): {:?}", start.elapsed());
+			simul.export_tables()
+		};
+		println!("{} core(s) available", std::thread::available_parallelism().map_or(1, |n| n.get()));
+		let sequential = build(1);
+		for threads in [2, 4, 8] {
+			assert_eq!(build(threads), sequential);
+		}
+	}
+	#[test]
+	fn parallel_customs(){
+		let circuit = parse_xml(&fixtures::sibling_customs(6, 4, 20)).unwrap();
+		let build = |threads: usize| Simulation::with_config(circuit.clone(), SimulationConfig::default().threads(threads)).unwrap();
+		let tables = build(1).export_tables();
+		assert_eq!(tables.len(), 6);
+		for threads in [2, 4, 16] {
+			assert_eq!(build(threads).export_tables(), tables);
+		}
+		let mut layered = Simulation::with_config(parse_xml(fixtures::RIPPLE_ADDER).unwrap(), SimulationConfig::default().threads(4)).unwrap();
+		assert_eq!(layered.get_truth_table(100), load(fixtures::RIPPLE_ADDER).get_truth_table(100));
+		// Every invalid table is reported, not just the first
+		let xor = load(fixtures::XOR).get_truth_table(100).unwrap();
+		let wrong = tables.keys().take(2).map(|uid| (uid.clone(), xor.clone())).collect();
+		let error = Simulation::from_tables(circuit, wrong).unwrap_err().to_string();
+		assert_eq!(error.lines().filter(|line| line.starts_with("Invalid truth table for custom circuit")).count(), 2, "{error}");
+	}
+	#[test]
+	fn lookup_table(){
+		let mut simul = load(fixtures::MAJORITY);
+		let expected: Vec<_> = (0..8).map(|row| simul.get_outputs_wide(&int_to_bits(row, 3), 100)).collect();
+		assert!(simul.use_lookup_table());
+		assert_eq!((0..8).map(|row| simul.get_outputs_wide(&int_to_bits(row, 3), 100)).collect::<Vec<_>>(), expected);
+		let inputs = HashMap::from([("a", true), ("c", true)]);
+		assert_eq!(simul.get_outputs(&inputs, 100), load(fixtures::MAJORITY).get_outputs(&inputs, 100));
+		// Setting or driving inputs drops the table, and it is not rebuilt while an input has a source
+		simul.inputs_mut().for_each(|val| *val = true);
+		assert!(simul.lookup.is_none());
+		simul.set_input_source("a", Box::new(|_| true)).unwrap();
+		assert!(!simul.use_lookup_table());
+		simul.clear_input_source("a");
+		assert!(simul.use_lookup_table());
+		// A feedback loop makes the circuit sequential
+		let latch = fixtures::gate_circuit("or", 2, None).replace(r#"outputUID="i1" inputIndex="1""#, r#"outputUID="g" inputIndex="1""#);
+		assert!(!load(&latch).use_lookup_table());
+		let mut live = load(fixtures::HALF_ADDER);
+		assert!(live.use_lookup_table());
+		live.use_live_customs();
+		assert!(!live.use_lookup_table());
+	}
+	#[test]
+	fn unstable_live_custom(){
+		let uuid = "3c1d2e4f-5a6b-4c7d-8e9f-0a1b2c3d4e5f";
+		let xml = format!(r#"<logicly>
+			<object type="switch@logic.ly" uid="x" x="0" y="0" rotation="0" exportName="x" outputs="false"/>
+			<object type="{uuid}" uid="c" x="80" y="0" rotation="0"/>
+			<object type="light_bulb@logic.ly" uid="o" x="160" y="0" rotation="0" exportName="o"/>
+			<connection inputUID="c" outputUID="x" inputIndex="0" outputIndex="0"/>
+			<connection inputUID="o" outputUID="c" inputIndex="0" outputIndex="0"/>
+			<custom name="Ring" type="{uuid}" label="R"><logicly>
+				<object type="switch@logic.ly" uid="a" x="0" y="0" rotation="0" exportName="a" outputs="false"/>
+				<object type="nand@logic.ly" uid="g" x="80" y="0" rotation="0" inputs="2"/>
+				<object type="light_bulb@logic.ly" uid="q" x="160" y="0" rotation="0" exportName="q"/>
+				<connection inputUID="g" outputUID="a" inputIndex="0" outputIndex="0"/>
+				<connection inputUID="g" outputUID="g" inputIndex="1" outputIndex="0"/>
+				<connection inputUID="q" outputUID="g" inputIndex="0" outputIndex="0"/>
+			</logicly></custom>
+		</logicly>"#);
+		// The ring oscillates while a is on, so it has no table and is simulated live.
+		// Each step runs an even number of passes, so the instance's output looks settled from outside.
+		let mut simul = Simulation::with_config(parse_xml(&xml).unwrap(), SimulationConfig::default().cycle_limit(101)).unwrap();
+		assert!(simul.export_tables().is_empty());
+		assert_eq!(simul.get_outputs_wide(&[false], 100), [true]);
+		assert!(simul.try_get_outputs_wide(&[false], 100).is_ok());
+		assert!(simul.try_get_outputs_wide(&[true], 100).is_err());
+		assert!(simul.get_truth_table(100).is_none());
+	}
+	#[test]
+	fn peek_custom_live(){
+		let mut simul = load(fixtures::HALF_ADDER);
+		assert_eq!(simul.peek_custom("ha", "a1"), None);
+		simul.use_live_customs();
+		for (_, val) in simul.get_inputs_mut() { *val = true; }
+		simul.update_until_done(100);
+		assert_eq!(simul.peek_custom("ha", "a1"), Some(&[true][..]));
+		assert_eq!(simul.peek_custom("ha", "x1"), Some(&[false][..]));
+		assert_eq!(simul.peek_custom("ha", "missing"), None);
+		assert_eq!(simul.get_truth_table(100), load(fixtures::HALF_ADDER).get_truth_table(100));
+	}
+	#[test]
+	fn simulation_config(){
+		let with_config = |xml: &str, config: SimulationConfig| Simulation::with_config(parse_xml(xml).unwrap(), config).unwrap();
+		assert_eq!(with_config(fixtures::HALF_ADDER, SimulationConfig::default()), load(fixtures::HALF_ADDER));
+		let mut live = with_config(fixtures::HALF_ADDER, SimulationConfig::default().live_customs(true));
+		assert_eq!(live.peek_custom("ha", "x1"), Some(&[false][..]));
+		assert_eq!(live.get_truth_table(100), load(fixtures::HALF_ADDER).get_truth_table(100));
+		let mut untabled = with_config(fixtures::HALF_ADDER, SimulationConfig::default().custom_table_max_inputs(1));
+		assert!(untabled.export_tables().is_empty());
+		assert_eq!(untabled.get_truth_table(100), load(fixtures::HALF_ADDER).get_truth_table(100));
+		assert!(!with_config(fixtures::MAJORITY, SimulationConfig::default().lookup_table_max_inputs(2)).use_lookup_table());
+		let xml = fixtures::gate_circuit("or", 2, None).replace(r#"<connection inputUID="q" outputUID="g" inputIndex="0" outputIndex="0"/>"#, "");
+		assert!(Simulation::with_config(parse_xml(&xml).unwrap(), SimulationConfig::default().strict(true)).is_err());
+	}
+	#[test]
+	fn table_cache(){
+		let dir = std::env::temp_dir().join(format!("logicly-rs-cache-{}", std::process::id()));
+		let config = SimulationConfig::default().cache_dir(Some(dir.clone()));
+		let simulate = |xml: &str| Simulation::with_config(parse_xml(xml).unwrap(), config.clone()).unwrap();
+		let cold = simulate(fixtures::HALF_ADDER);
+		assert_eq!(cold.cache_stats(), CacheStats { hits: 0, misses: 1 });
+		let mut warm = simulate(fixtures::HALF_ADDER);
+		assert_eq!(warm.cache_stats(), CacheStats { hits: 1, misses: 0 });
+		assert_eq!(warm.get_truth_table(100), load(fixtures::HALF_ADDER).get_truth_table(100));
+		// Moving objects does not change the table, but changing a gate does
+		let moved = fixtures::HALF_ADDER.replace(r#"uid="x1" x="80""#, r#"uid="x1" x="100""#);
+		assert_eq!(simulate(&moved).cache_stats(), CacheStats { hits: 1, misses: 0 });
+		let edited = fixtures::HALF_ADDER.replace(r#"type="xor@logic.ly" uid="x1""#, r#"type="or@logic.ly" uid="x1""#);
+		let mut edited_simul = simulate(&edited);
+		assert_eq!(edited_simul.cache_stats(), CacheStats { hits: 0, misses: 1 });
+		assert_eq!(edited_simul.get_outputs_wide(&[true, true], 100), [true, true]);
+		// Export names are the column names of the table
+		let renamed = fixtures::HALF_ADDER.replace(r#"exportName="c""#, r#"exportName="carry""#);
+		assert_eq!(simulate(&renamed).cache_stats(), CacheStats { hits: 0, misses: 1 });
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+	#[test]
+	fn saved_switch_state(){
+		// In the file, a is off and b is on
+		let circuit = parse_xml(fixtures::XOR_OLD).unwrap();
+		let mut saved = Simulation::from_saved_state(circuit.clone());
+		assert_eq!(saved.get_outputs(&HashMap::new(), 100), HashMap::from([(String::from("q"), true)]));
+		assert_eq!(saved.get_outputs(&HashMap::from([("a", true)]), 100), HashMap::from([(String::from("q"), false)]));
+		assert_eq!(saved.get_truth_table(100), load(fixtures::XOR).get_truth_table(100));
+		assert!(saved.use_lookup_table());
+		assert_eq!(saved.get_outputs(&HashMap::new(), 100), HashMap::from([(String::from("q"), true)]));
+		let mut unsaved: Simulation = circuit.into();
+		assert_eq!(unsaved.get_outputs(&HashMap::new(), 100), HashMap::from([(String::from("q"), false)]));
+	}
+	#[test]
+	fn save_restore_state(){
+		let mut simul = load(fixtures::RIPPLE_ADDER);
+		for (_, val) in simul.get_inputs_mut() { *val = true; }
+		simul.update_until_done(100);
+		let state = simul.save_state();
+		let mut resumed = load(fixtures::RIPPLE_ADDER);
+		resumed.restore_state(&state).unwrap();
+		assert_eq!(resumed, simul);
+		assert_eq!(resumed.save_state(), state);
+
+		let mut other = load(fixtures::XOR);
+		assert!(other.restore_state(&state).is_err());
+		assert!(resumed.restore_state(&state[..state.len() - 1]).is_err());
+		assert_eq!(resumed, simul);
+		// Input sources see the same ticks after restoring as they did after saving
+		let ticks = Arc::new(std::sync::Mutex::new(vec![]));
+		let recorded = ticks.clone();
+		let mut simul = load(fixtures::XOR);
+		simul.set_input_source("a", Box::new(move |tick| { recorded.lock().unwrap().push(tick); tick % 2 == 1 })).unwrap();
+		(0..3).for_each(|_| { simul.step(); });
+		let state = simul.save_state();
+		let steps = |simul: &mut Simulation| {
+			ticks.lock().unwrap().clear();
+			let q: Vec<bool> = (0..2).map(|_| { simul.step(); simul.get_probes(&["q"])["q"][0] }).collect();
+			(std::mem::take(&mut *ticks.lock().unwrap()), q)
+		};
+		let before = steps(&mut simul);
+		simul.restore_state(&state).unwrap();
+		assert_eq!(steps(&mut simul), before);
+		assert_eq!(before.0, [3, 4]);
+	}
+
+	fn gate_circuit(kind: &str, inputs: usize, function_index: Option<u8>) -> Simulation {
+		load(&fixtures::gate_circuit(kind, inputs, function_index))
+	}
+	/// Asserts that the gate's output only depends on the number of true inputs, as given by `expected[count]`.
+	fn assert_gate_by_count(simul: &mut Simulation, expected: &[bool]) {
+		let table = simul.get_truth_table(100).unwrap();
+		for row in 0..table.num_rows() {
+			assert_eq!(table[row][0], expected[row.count_ones() as usize], "row {row:b}");
+		}
+	}
+	#[test]
+	fn shared_custom_tables(){
+		let simul = load(fixtures::NESTED_ADDER);
+		let mut instances: HashMap<&str, HashSet<*const CustomSimulation>> = HashMap::new();
+		let mut stack = vec![&simul];
+		while let Some(simul) = stack.pop() {
+			for (uid, custom) in &simul.customs {
+				instances.entry(uid).or_default().insert(Arc::as_ptr(custom));
+				stack.push(&custom.simulation);
+			}
+		}
+		// The half adder is used by the full adder's simulation, but there is still only one of it
+		assert_eq!(instances.len(), 2);
+		assert!(instances.values().all(|instances| instances.len() == 1));
+		let half_adder = simul.customs.values().find(|c| c.name == "HA").unwrap();
+		assert_eq!(Arc::strong_count(half_adder), 2);
+		let table = load(fixtures::NESTED_ADDER).get_truth_table(100).unwrap();
+		assert_eq!(table.rows().map(|row| bits_to_int(row.iter())).collect::<Vec<_>>(),
+			(0..16).map(|row| (row >> 2) + (row & 3)).collect::<Vec<_>>());
+	}
+	#[test]
+	fn simulate_custom_from_library(){
+		let circuit = parse_xml(fixtures::HALF_ADDER).unwrap();
+		assert_eq!(circuit.custom_library().len(), 1);
+		let mut half_adder = Simulation::from_custom(&circuit, "Half Adder").unwrap();
+		assert_eq!(half_adder.input_names(), vec!["a", "b"]);
+		assert_eq!(half_adder.output_names(), vec!["s", "c"]);
+		let table = half_adder.get_truth_table(100).unwrap();
+		assert_eq!(table.minterms(0), vec![1, 2]);
+		assert_eq!(table.minterms(1), vec![3]);
+		assert!(Simulation::from_custom(&circuit, "5f0b7c2e-8d7a-4c3e-9b61-2a1d0e4f3c10").is_ok());
+		assert!(Simulation::from_custom(&circuit, "Full Adder").is_err());
+		let nested = parse_xml(fixtures::NESTED_ADDER).unwrap();
+		assert_eq!(Simulation::from_custom(&nested, "Half Adder").unwrap().customs.len(), 0);
+		let full_adder = Simulation::from_custom(&nested, "Full Adder").unwrap();
+		assert_eq!(full_adder.customs.keys().collect::<Vec<_>>(), ["5f0b7c2e-8d7a-4c3e-9b61-2a1d0e4f3c10"]);
+	}
+	#[test]
+	fn slowest_row_ripple_adder(){
+		let mut adder = load(fixtures::RIPPLE_ADDER);
+		let (table, stats) = adder.get_truth_table_with_stats(100).unwrap();
+		assert_eq!(table.inputs(), ["a3", "a2", "a1", "a0", "b3", "b2", "b1", "b0"]);
+		let max = stats.iterations[stats.slowest_row()];
+		// The carry generated by bit 0 ripples all the way to bit 3
+		let full_carry_propagation = |row: usize| {
+			let (a, b) = (row >> 4, row & 0xf);
+			a & b & 1 == 1 && (a ^ b) & 0b0110 == 0b0110
+		};
+		let slowest: Vec<_> = (0..table.num_rows()).filter(|&row| stats.iterations[row] == max).collect();
+		assert_eq!(slowest, (0..table.num_rows()).filter(|&row| full_carry_propagation(row)).collect::<Vec<_>>());
+		assert!(full_carry_propagation(stats.slowest_row()));
+		assert_eq!(stats.histogram().values().sum::<usize>(), 256);
+		assert_eq!(stats.histogram()[&0], 1);
+		let trace = adder.trace_row(stats.slowest_row(), 100);
+		assert_eq!(trace.len() as u128, max);
+		// Each pass, the next full adder starts carrying
+		for (step, bit) in trace.iter().zip(["fa0", "fa1", "fa2"]) {
+			assert!(step.iter().any(|(name, values)| name == bit && values[1]), "{step:?}");
+		}
+		assert!(trace[3].iter().any(|(name, _)| name == "fa3"));
+	}
+	#[test]
+	fn all_custom_tables(){
+		let simul = load(fixtures::HALF_ADDER);
+		assert_eq!(simul.format_all_custom_tables(), "HA\nb|a||s|c\n--------\nF|F||F|F\nF|T||T|F\nT|F||T|F\nT|T||F|T\n\n");
+	}
+	#[test]
+	fn assert_matches_first_row(){
+		let expected = load(fixtures::XOR).get_truth_table(100).unwrap();
+		assert_eq!(expected.rows().collect::<Vec<_>>(), [[false], [true], [true], [false]]);
+		assert_eq!(expected.assert_matches(&expected), Ok(()));
+		let or = fixtures::XOR.replace("xor@logic.ly", "or@logic.ly");
+		let mismatch = load(&or).get_truth_table(100).unwrap().assert_matches(&expected).unwrap_err();
+		assert_eq!(mismatch, Mismatch::Row { row: 3, inputs: vec![true, true], expected: vec![false], found: vec![true] });
+		assert_eq!(mismatch.to_string(), "Row 3 (inputs 11): expected outputs 0, found 1");
+		let majority = load(fixtures::MAJORITY).get_truth_table(100).unwrap();
+		assert!(matches!(majority.assert_matches(&expected), Err(Mismatch::Inputs { .. })));
+	}
+	#[test]
+	fn reparse_incremental(){
+		let full_adder = "8c1f4e7a-3b2d-4f6e-a5c9-0d7e1b2f3a41";
+		let simul = load(fixtures::RIPPLE_ADDER);
+		let moved = fixtures::RIPPLE_ADDER.replace(r#"uid="fa3" x="80""#, r#"uid="fa3" x="90""#);
+		let circuit = parse_xml(&moved).unwrap();
+		assert_eq!(simul.reusable_tables(circuit.custom_library()).keys().collect::<Vec<_>>(), [full_adder]);
+		let changed = fixtures::RIPPLE_ADDER.replace("\"or@logic.ly", "\"nor@logic.ly");
+		let circuit = parse_xml(&changed).unwrap();
+		assert!(simul.reusable_tables(circuit.custom_library()).is_empty());
+		assert_eq!(simul.reparse_incremental(&changed).unwrap().get_truth_table(100), load(&changed).get_truth_table(100));
+
+		// A custom circuit that uses a changed one is rebuilt too
+		let half_adder = fixtures::HALF_ADDER;
+		let wrapper = r#"<custom name="Wrapper" type="0a9e3c1b-7d6f-4b2a-8e5c-1f3d2b4a6c80" label="W"><logicly>
+			<object type="switch@logic.ly" uid="a" x="0" y="0" rotation="0" exportName="a" outputs="false"/>
+			<object type="switch@logic.ly" uid="b" x="0" y="0" rotation="0" exportName="b" outputs="false"/>
+			<object type="5f0b7c2e-8d7a-4c3e-9b61-2a1d0e4f3c10" uid="h" x="0" y="0" rotation="0"/>
+			<object type="light_bulb@logic.ly" uid="s" x="0" y="0" rotation="0" exportName="s"/>
+			<object type="light_bulb@logic.ly" uid="c" x="0" y="0" rotation="0" exportName="c"/>
+			<connection inputUID="h" outputUID="a" inputIndex="0" outputIndex="0"/>
+			<connection inputUID="h" outputUID="b" inputIndex="1" outputIndex="0"/>
+			<connection inputUID="s" outputUID="h" inputIndex="0" outputIndex="0"/>
+			<connection inputUID="c" outputUID="h" inputIndex="0" outputIndex="1"/>
+		</logicly></custom>"#;
+		let nested = half_adder.replace("</custom>", &format!("</custom>{wrapper}"));
+		let simul = load(&nested);
+		assert_eq!(simul.reusable_tables(parse_xml(&nested).unwrap().custom_library()).len(), 2);
+		let changed = nested.replacen("and@logic.ly", "or@logic.ly", 1);
+		assert!(simul.reusable_tables(parse_xml(&changed).unwrap().custom_library()).is_empty());
+	}
+	#[test]
+	fn export_import_tables(){
+		let circuit = parse_xml(fixtures::HALF_ADDER).unwrap();
+		let tables = load(fixtures::HALF_ADDER).export_tables();
+		assert_eq!(tables.len(), 1);
+		let json = serde_json::to_string(&tables).unwrap();
+		let imported: HashMap<String, TruthTable> = serde_json::from_str(&json).unwrap();
+		let mut simul = Simulation::from_tables(circuit.clone(), imported).unwrap();
+		assert_eq!(simul.export_tables(), tables);
+		assert_eq!(simul.get_truth_table(100), load(fixtures::HALF_ADDER).get_truth_table(100));
+
+		let xor = load(fixtures::XOR).get_truth_table(100).unwrap();
+		let wrong = tables.keys().map(|uid| (uid.clone(), xor.clone())).collect();
+		let error = Simulation::from_tables(circuit.clone(), wrong).unwrap_err().to_string();
+		assert!(error.starts_with("Invalid truth table for custom circuit"), "{error}");
+		let mut truncated = tables.clone();
+		truncated.values_mut().for_each(|table| { table.data.pop(); });
+		assert!(Simulation::from_tables(circuit.clone(), truncated).is_err());
+		let unknown = HashMap::from([(String::from("nonexistent"), xor)]);
+		assert_eq!(Simulation::from_tables(circuit, unknown).unwrap_err().to_string(), "Truth table for unknown custom circuit nonexistent");
+	}
+	#[test]
+	fn strict_unconnected_output(){
+		let xml = fixtures::gate_circuit("or", 2, None).replace(r#"<connection inputUID="q" outputUID="g" inputIndex="0" outputIndex="0"/>"#, "");
+		let error = Simulation::from_strict(parse_xml(&xml).unwrap()).unwrap_err();
+		assert_eq!(error.to_string(), "Unconnected outputs: q");
+		assert!(Simulation::from_strict(parse_xml(fixtures::XOR).unwrap()).is_ok());
+	}
+	#[test]
+	fn xor_xnor_multi_input(){
+		// Indexed by the number of true inputs.
+		let xor_odd = [false, true, false, true, false];
+		let xor_one = [false, true, false, false, false];
+		let xnor_odd = [true, false, true, false, true];
+		let xnor_one = [true, false, true, true, true];
+		for n in 2..=4 {
+			assert_gate_by_count(&mut gate_circuit("xor", n, None), &xor_odd[..=n]);
+			assert_gate_by_count(&mut gate_circuit("xor", n, Some(0)), &xor_odd[..=n]);
+			assert_gate_by_count(&mut gate_circuit("xor", n, Some(1)), &xor_one[..=n]);
+			assert_gate_by_count(&mut gate_circuit("xnor", n, None), &xnor_odd[..=n]);
+			assert_gate_by_count(&mut gate_circuit("xnor", n, Some(0)), &xnor_odd[..=n]);
+			assert_gate_by_count(&mut gate_circuit("xnor", n, Some(1)), &xnor_one[..=n]);
+		}
+	}
+	#[test]
+	fn xnor_three_inputs(){
+		let table = gate_circuit("xnor", 3, None).get_truth_table(100).unwrap();
+		assert_eq!(table.minterms(0), vec![0, 3, 5, 6]);
+		let table = gate_circuit("xnor", 3, Some(1)).get_truth_table(100).unwrap();
+		assert_eq!(table.minterms(0), vec![0, 3, 5, 6, 7]);
+	}
+}
@@ -0,0 +1,94 @@
+//! Callbacks that run when watched objects change, for embedding the simulator in a front end.
+
+use std::fmt;
+
+/// An object to watch with [`super::Simulation::on_change`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProbeTarget {
+	/// The input or output with this export name.
+	Name(String),
+	/// The object with this uid.
+	Uid(String),
+}
+
+/// Identifies a callback, so that it can be removed with [`super::Simulation::unsubscribe`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(usize);
+
+/// Passed to a callback when the object it watches changes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangeEvent {
+	pub subscription: SubscriptionId,
+	/// The export name or uid of the object.
+	pub name: String,
+	pub old: Vec<bool>,
+	pub new: Vec<bool>,
+}
+
+pub(super) type ChangeCallback = Box<dyn FnMut(&ChangeEvent) + Send + Sync>;
+
+struct Observer {
+	id: SubscriptionId,
+	/// Index of the watched object.
+	index: usize,
+	name: String,
+	/// The values last passed to the callback, or the values when it was registered.
+	last: Vec<bool>,
+	/// Whether to also call the callback for values that only last until the next update pass.
+	glitches: bool,
+	callback: ChangeCallback,
+}
+
+/// The callbacks registered on a simulation. Callbacks cannot be cloned or compared,
+/// so a clone of a simulation starts without any, and they are ignored when comparing simulations.
+#[derive(Default)]
+pub(super) struct Observers {
+	next_id: usize,
+	list: Vec<Observer>,
+}
+impl Clone for Observers {
+	fn clone(&self) -> Self {
+		Observers::default()
+	}
+}
+impl PartialEq for Observers {
+	fn eq(&self, _: &Self) -> bool {
+		true
+	}
+}
+impl fmt::Debug for Observers {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Observers").field("count", &self.list.len()).finish()
+	}
+}
+impl Observers {
+	pub(super) fn add(&mut self, index: usize, name: String, initial: Vec<bool>, glitches: bool, callback: ChangeCallback) -> SubscriptionId {
+		let id = SubscriptionId(self.next_id);
+		self.next_id += 1;
+		self.list.push(Observer { id, index, name, last: initial, glitches, callback });
+		id
+	}
+	/// Returns false if there was no such callback.
+	pub(super) fn remove(&mut self, id: SubscriptionId) -> bool {
+		let len = self.list.len();
+		self.list.retain(|observer| observer.id != id);
+		self.list.len() != len
+	}
+	/// Calls the callbacks whose object has changed since they were last called.
+	/// Unless `settled` is set, only callbacks that asked for glitches are considered.
+	/// Object `i` has the values `state[slots[i]..slots[i + 1]]`.
+	pub(super) fn notify(&mut self, state: &[bool], slots: &[usize], settled: bool) {
+		for observer in &mut self.list {
+			if !(settled || observer.glitches) { continue }
+			let values = &state[slots[observer.index]..slots[observer.index + 1]];
+			if values == observer.last { continue }
+			let event = ChangeEvent {
+				subscription: observer.id,
+				name: observer.name.clone(),
+				old: std::mem::replace(&mut observer.last, values.to_vec()),
+				new: values.to_vec(),
+			};
+			(observer.callback)(&event);
+		}
+	}
+}
@@ -0,0 +1,143 @@
+//! Progress reports and cancellation for long computations, like the truth tables of circuits with many inputs.
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Rows computed between two progress events. The last row of a table is always reported.
+const progress_interval: usize = 1024;
+
+/// What a [`ProgressEvent`] counts.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ProgressTask {
+	/// Rows of the truth table of the simulated circuit.
+	TruthTable,
+	/// Rows of the truth table of the custom circuit with this display name.
+	CustomTable(String),
+	/// States explored by [`super::Simulation::state_reachability`], out of every encoding of the state bits.
+	States,
+	/// Files checked by [`crate::batch::check_files`].
+	Files,
+}
+impl fmt::Display for ProgressTask {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ProgressTask::TruthTable => write!(f, "Truth table"),
+			ProgressTask::CustomTable(name) => write!(f, "Custom circuit {name}"),
+			ProgressTask::States => write!(f, "State search"),
+			ProgressTask::Files => write!(f, "Files"),
+		}
+	}
+}
+
+/// Passed to the callback set with [`super::SimulationConfig::progress`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProgressEvent {
+	pub task: ProgressTask,
+	/// Number of rows or files finished so far.
+	pub done: usize,
+	pub total: usize,
+	/// Time since the task started.
+	pub elapsed: Duration,
+}
+impl ProgressEvent {
+	pub fn per_second(&self) -> f64 {
+		self.done as f64 / self.elapsed.as_secs_f64().max(1e-9)
+	}
+}
+
+/// Stops computations using a config with this token, set with [`super::SimulationConfig::cancellation`], from another thread.
+/// Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+impl CancellationToken {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	/// Makes computations stop before their next row, or their next file for [`crate::batch::check_files`].
+	pub fn cancel(&self) {
+		self.0.store(true, Ordering::Relaxed);
+	}
+	pub fn is_cancelled(&self) -> bool {
+		self.0.load(Ordering::Relaxed)
+	}
+}
+/// Tokens are equal if they are clones of each other.
+impl PartialEq for CancellationToken {
+	fn eq(&self, other: &Self) -> bool {
+		Arc::ptr_eq(&self.0, &other.0)
+	}
+}
+impl Eq for CancellationToken {}
+
+/// The error returned by a computation that was stopped with a [`CancellationToken`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cancelled {
+	pub task: ProgressTask,
+	/// Number of rows, states, or files finished before the computation stopped.
+	pub done: usize,
+	pub total: usize,
+}
+impl fmt::Display for Cancelled {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} cancelled after {} of {}", self.task, self.done, self.total)
+	}
+}
+impl std::error::Error for Cancelled {}
+
+pub type ProgressCallback = Box<dyn Fn(ProgressEvent) + Send + Sync>;
+type SharedCallback = Arc<dyn Fn(ProgressEvent) + Send + Sync>;
+
+/// The progress callback of a config. Clones share the callback, and it is ignored when comparing configs.
+#[derive(Clone, Default)]
+pub(crate) struct ProgressHook(Option<SharedCallback>);
+impl ProgressHook {
+	pub(super) fn new(callback: ProgressCallback) -> Self {
+		ProgressHook(Some(Arc::from(callback)))
+	}
+	/// Starts counting `total` rows or files. `task` is only called if there is a callback.
+	pub(crate) fn start(&self, task: impl FnOnce() -> ProgressTask, total: usize) -> Progress {
+		Progress(self.0.clone().map(|callback| Counter { callback, task: task(), total, start: Instant::now() }))
+	}
+}
+impl PartialEq for ProgressHook {
+	fn eq(&self, _: &Self) -> bool {
+		true
+	}
+}
+impl Eq for ProgressHook {}
+impl fmt::Debug for ProgressHook {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("ProgressHook").field(&self.0.is_some()).finish()
+	}
+}
+
+struct Counter {
+	callback: SharedCallback,
+	task: ProgressTask,
+	total: usize,
+	start: Instant,
+}
+impl Counter {
+	fn report(&self, done: usize) {
+		(self.callback)(ProgressEvent { task: self.task.clone(), done, total: self.total, elapsed: self.start.elapsed() });
+	}
+}
+
+/// One task being counted, started with [`ProgressHook::start`]. Does nothing if there is no callback.
+pub(crate) struct Progress(Option<Counter>);
+impl Progress {
+	/// Reports that `done` rows are finished, if that is a multiple of the interval or the last row.
+	pub(crate) fn rows_done(&self, done: usize) {
+		if let Some(counter) = &self.0 && (done.is_multiple_of(progress_interval) || done == counter.total) {
+			counter.report(done);
+		}
+	}
+	/// Reports that `done` files are finished. Every file is reported, as each one takes a whole table.
+	pub(crate) fn files_done(&self, done: usize) {
+		if let Some(counter) = &self.0 {
+			counter.report(done);
+		}
+	}
+}
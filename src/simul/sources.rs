@@ -0,0 +1,52 @@
+//! Inputs whose values come from callbacks, for simulating a circuit as one part of a larger model.
+
+use std::fmt;
+
+pub(super) type InputCallback = Box<dyn FnMut(u64) -> bool + Send + Sync>;
+
+/// The callbacks driving inputs of a simulation, with the index of the input each one drives.
+/// Like [`super::observe::Observers`], they are dropped when a simulation is cloned, and ignored when comparing simulations.
+#[derive(Default)]
+pub(super) struct InputSources {
+	list: Vec<(usize, InputCallback)>,
+}
+impl Clone for InputSources {
+	fn clone(&self) -> Self {
+		InputSources::default()
+	}
+}
+impl PartialEq for InputSources {
+	fn eq(&self, _: &Self) -> bool {
+		true
+	}
+}
+impl fmt::Debug for InputSources {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("InputSources").field("inputs", &self.list.iter().map(|(index, _)| index).collect::<Vec<_>>()).finish()
+	}
+}
+impl InputSources {
+	/// Drives object `index` with `callback`, replacing any callback already driving it.
+	pub(super) fn set(&mut self, index: usize, callback: InputCallback) {
+		self.remove(index);
+		self.list.push((index, callback));
+	}
+	/// Returns false if the object was not driven by a callback.
+	pub(super) fn remove(&mut self, index: usize) -> bool {
+		let len = self.list.len();
+		self.list.retain(|(i, _)| *i != index);
+		self.list.len() != len
+	}
+	pub(super) fn is_empty(&self) -> bool {
+		self.list.is_empty()
+	}
+	/// Sets each driven input to the value its callback gives for `tick`, and returns the indices of the inputs that changed.
+	/// Object `i` has the value `state[slots[i]]`.
+	pub(super) fn update(&mut self, state: &mut [bool], slots: &[usize], tick: u64) -> Vec<usize> {
+		self.list.iter_mut().filter_map(|(index, callback)| {
+			let value = callback(tick);
+			let changed = std::mem::replace(&mut state[slots[*index]], value) != value;
+			changed.then_some(*index)
+		}).collect()
+	}
+}
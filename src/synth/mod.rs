@@ -0,0 +1,446 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+
+use crate::io::{Circuit, CustomCircuit, InputType, Object, ObjectInner, SimpleGateType, UidGenerator, XorType};
+use crate::opt::logic_depth_with;
+use crate::simul::BoolExpr;
+
+/// A gate type that all other simple gates can be built from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Technology {
+	Nand, Nor
+}
+impl Technology {
+	fn gate_type(self) -> SimpleGateType {
+		match self {
+			Technology::Nand => SimpleGateType::Nand,
+			Technology::Nor => SimpleGateType::Nor,
+		}
+	}
+}
+
+/// Returns an equivalent circuit where every simple gate, including those inside custom circuit definitions,
+/// has been replaced by a network of Nand or Nor gates.
+/// Inputs, outputs, labels, custom gate instances, and gates without any inputs are left unchanged.
+pub fn map_technology(circuit: &Circuit, tech: Technology) -> Circuit {
+	Circuit {
+		objects: map_objects(&circuit.objects, tech),
+		customs: circuit.customs.as_ref().map(|customs| customs.iter().map(|c| CustomCircuit {
+			objects: map_objects(&c.objects, tech),
+			name: c.name.clone(),
+			uid: c.uid.clone(),
+			label: c.label.clone(),
+			locations: c.locations.clone(),
+		}).collect()),
+		merged_customs: circuit.merged_customs.clone(),
+		warnings: circuit.warnings.clone(),
+		schema_version: circuit.schema_version,
+		// The mapped gates are wired differently, so the old bend points would not fit
+		wire_points: HashMap::new(),
+	}
+}
+
+/// Number of simple gates at the top level and in each custom circuit definition.
+pub fn gate_count(circuit: &Circuit) -> usize {
+	circuit.customs.iter().flatten().map(|c| &c.objects)
+		.chain([&circuit.objects])
+		.flatten()
+		.filter(|o| matches!(o.inner, ObjectInner::SimpleGate { .. }))
+		.count()
+}
+
+/// Estimated size and speed of a circuit, with custom gate instances expanded into the gates they contain.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SynthReport {
+	/// Number of simple gates of each type.
+	pub gates: BTreeMap<String, usize>,
+	/// Estimated number of transistors used by the gates of each type, in static CMOS.
+	pub transistors: BTreeMap<String, usize>,
+	/// Number of gate levels on the longest path from an input to an output.
+	/// Each custom gate instance counts as the depth of its definition, so this is an upper bound.
+	pub depth: usize,
+}
+impl SynthReport {
+	pub fn gate_count(&self) -> usize {
+		self.gates.values().sum()
+	}
+	pub fn transistor_count(&self) -> usize {
+		self.transistors.values().sum()
+	}
+	/// Estimates one list of objects. `customs` holds the reports of the custom circuits it uses, by uid.
+	fn new(objects: &[Object], customs: &HashMap<&str, SynthReport>) -> Self {
+		let mut report = SynthReport::default();
+		for obj in objects {
+			match &obj.inner {
+				ObjectInner::SimpleGate { kind, connections, .. } => {
+					let inverted = obj.inverted_inputs().iter().filter(|&&i| i).count();
+					*report.gates.entry(kind.to_string()).or_default() += 1;
+					*report.transistors.entry(kind.to_string()).or_default() += transistors(*kind, connections.len()) + 2 * inverted;
+				},
+				ObjectInner::CustomGate { uuid, .. } => {
+					let custom = &customs[&uuid[..]];
+					for (kind, count) in &custom.gates { *report.gates.entry(kind.clone()).or_default() += count; }
+					for (kind, count) in &custom.transistors { *report.transistors.entry(kind.clone()).or_default() += count; }
+				},
+				_ => {},
+			}
+		}
+		report.depth = logic_depth_with(objects, |obj| match &obj.inner {
+			ObjectInner::SimpleGate { .. } => 1,
+			ObjectInner::CustomGate { uuid, .. } => customs[&uuid[..]].depth,
+			_ => 0,
+		});
+		report
+	}
+}
+impl Display for SynthReport {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(f, "Gates:")?;
+		for (kind, count) in &self.gates {
+			writeln!(f, "  {kind}: {count} ({} transistors)", self.transistors[kind])?;
+		}
+		writeln!(f, "  total: {} ({} transistors)", self.gate_count(), self.transistor_count())?;
+		writeln!(f, "Depth: {} gate levels", self.depth)
+	}
+}
+
+/// Estimated number of transistors in a static CMOS gate with `inputs` inputs, not counting inverted inputs.
+/// Xor and Xnor gates with more than two inputs are counted as a chain of two input gates.
+fn transistors(kind: SimpleGateType, inputs: usize) -> usize {
+	use SimpleGateType as S;
+	match kind {
+		S::Not => 2,
+		S::Buffer => 4,
+		S::Nand | S::Nor => 2 * inputs,
+		S::And | S::Or => 2 * inputs + 2,
+		S::Xor if inputs <= 1 => 4,
+		S::Xnor if inputs <= 1 => 2,
+		S::Xor | S::Xnor => 12 * (inputs - 1),
+	}
+}
+
+impl Circuit {
+	/// Estimates the gate count, transistor count, and logic depth of this circuit.
+	pub fn synthesis_report(&self) -> SynthReport {
+		let mut customs = HashMap::new();
+		for custom in self.custom_library() {
+			let report = SynthReport::new(&custom.objects, &customs);
+			customs.insert(&custom.uid[..], report);
+		}
+		SynthReport::new(&self.objects, &customs)
+	}
+}
+
+/// An expression built from a single type of two input universal gate, as returned by [`map_expr`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GateExpr {
+	/// The value of an input, by index, as in [`BoolExpr::Input`].
+	Input(usize),
+	Gate(Box<GateExpr>, Box<GateExpr>),
+}
+impl GateExpr {
+	fn gate(a: GateExpr, b: GateExpr) -> GateExpr {
+		GateExpr::Gate(Box::new(a), Box::new(b))
+	}
+	/// Negates the expression, removing a double negation instead of adding a gate.
+	fn not(self) -> GateExpr {
+		match self {
+			GateExpr::Gate(a, b) if a == b => *a,
+			x => GateExpr::gate(x.clone(), x),
+		}
+	}
+	/// Evaluates the expression using gates of type `tech`, given the value of each input.
+	pub fn eval(&self, tech: Technology, inputs: &[bool]) -> bool {
+		match self {
+			GateExpr::Input(i) => inputs[*i],
+			GateExpr::Gate(a, b) => match tech {
+				Technology::Nand => !(a.eval(tech, inputs) & b.eval(tech, inputs)),
+				Technology::Nor => !(a.eval(tech, inputs) | b.eval(tech, inputs)),
+			},
+		}
+	}
+	/// Number of gates in the expression. Shared subexpressions are counted each time they appear.
+	pub fn gate_count(&self) -> usize {
+		match self {
+			GateExpr::Input(_) => 0,
+			GateExpr::Gate(a, b) => 1 + a.gate_count() + b.gate_count(),
+		}
+	}
+	/// Writes the expression like `nand(a, nand(b, b))`, using the names in `inputs`.
+	pub fn format(&self, tech: Technology, inputs: &[&str]) -> String {
+		match self {
+			GateExpr::Input(i) => inputs[*i].to_string(),
+			GateExpr::Gate(a, b) => format!("{}({}, {})", tech.gate_type().to_string().to_lowercase(), a.format(tech, inputs), b.format(tech, inputs)),
+		}
+	}
+}
+
+/// Returns an equivalent expression that only uses Nand or Nor gates.
+pub fn map_expr(expr: &BoolExpr, tech: Technology) -> GateExpr {
+	// The universal gate computes the negation of its "primary" function: And for Nand, Or for Nor.
+	let primary = |a: GateExpr, b: GateExpr| GateExpr::gate(a, b).not();
+	let dual = |a: GateExpr, b: GateExpr| GateExpr::gate(a.not(), b.not());
+	match expr {
+		BoolExpr::Input(i) => GateExpr::Input(*i),
+		BoolExpr::Not(a) => map_expr(a, tech).not(),
+		BoolExpr::And(a, b) | BoolExpr::Or(a, b) => {
+			let (a, b) = (map_expr(a, tech), map_expr(b, tech));
+			if matches!(expr, BoolExpr::And(..)) == (tech == Technology::Nand) { primary(a, b) } else { dual(a, b) }
+		},
+		BoolExpr::Xor(a, b) => {
+			let (a, b) = (map_expr(a, tech), map_expr(b, tech));
+			match tech {
+				Technology::Nand => {
+					let t = GateExpr::gate(a.clone(), b.clone());
+					GateExpr::gate(GateExpr::gate(a, t.clone()), GateExpr::gate(b, t))
+				},
+				// Neither both false nor both true
+				Technology::Nor => GateExpr::gate(GateExpr::gate(a.clone(), b.clone()), dual(a, b)),
+			}
+		},
+	}
+}
+
+/// An object output, as (output index, object index).
+type Node = (u32, usize);
+
+struct Mapper {
+	tech: Technology,
+	objects: Vec<Object>,
+	/// Pairs of nodes that are known to be the negation of each other, stored in both directions.
+	negations: HashMap<Node, Node>,
+	constant_low: Option<Node>,
+	uids: UidGenerator,
+}
+
+fn map_objects(objects: &[Object], tech: Technology) -> Vec<Object> {
+	let mut mapper = Mapper {
+		tech,
+		objects: objects.to_vec(),
+		negations: HashMap::new(),
+		constant_low: None,
+		uids: UidGenerator::avoiding("gen", objects),
+	};
+	for (i, obj) in objects.iter().enumerate() {
+		let ObjectInner::SimpleGate { kind, xor_type, connections, .. } = &obj.inner else { continue };
+		if connections.is_empty() { continue }
+		let inverted = obj.inverted_inputs().iter().chain(std::iter::repeat(&false));
+		let inputs: Vec<Node> = connections.iter().zip(inverted)
+			.map(|(c, &inverted)| {
+				let node = c.unwrap_or_else(|| mapper.constant_low(obj));
+				if inverted { mapper.not(obj, node) } else { node }
+			})
+			.collect();
+		let root = mapper.root(obj, *kind, *xor_type, &inputs);
+		if let [a, b] = root[..] && a == b {
+			mapper.negations.entry(a).or_insert((0, i));
+			mapper.negations.entry((0, i)).or_insert(a);
+		}
+		let (x, y) = obj.position();
+		mapper.objects[i] = Object::new(obj.uid().to_string(), x, y, obj.rotation(), mapper.gate(root));
+	}
+	mapper.objects
+}
+
+impl Mapper {
+	fn gate(&self, inputs: Vec<Node>) -> ObjectInner {
+		ObjectInner::SimpleGate {
+			xor_type: XorType::Odd,
+			kind: self.tech.gate_type(),
+			connections: inputs.into_iter().map(Some).collect(),
+			inverted: vec![],
+		}
+	}
+	/// Adds a universal gate driven by `inputs`, positioned at `source`.
+	fn emit(&mut self, source: &Object, inputs: Vec<Node>) -> Node {
+		if let [a, b] = inputs[..] && a == b {
+			return self.not(source, a);
+		}
+		let (x, y) = source.position();
+		let uid = self.uids.next_uid();
+		self.objects.push(Object::new(uid, x, y, source.rotation(), self.gate(inputs)));
+		(0, self.objects.len() - 1)
+	}
+	fn not(&mut self, source: &Object, node: Node) -> Node {
+		if let Some(&negated) = self.negations.get(&node) {
+			return negated;
+		}
+		let (x, y) = source.position();
+		let uid = self.uids.next_uid();
+		self.objects.push(Object::new(uid, x, y, source.rotation(), self.gate(vec![node, node])));
+		let negated = (0, self.objects.len() - 1);
+		self.negations.insert(node, negated);
+		self.negations.insert(negated, node);
+		negated
+	}
+	fn constant_low(&mut self, source: &Object) -> Node {
+		*self.constant_low.get_or_insert_with(|| {
+			let (x, y) = source.position();
+			let uid = self.uids.next_uid();
+			self.objects.push(Object::new(uid, x, y, source.rotation(), ObjectInner::Input {
+				export_name: None,
+				kind: InputType::False,
+				value: false,
+			}));
+			(0, self.objects.len() - 1)
+		})
+	}
+	/// Returns the inputs of a universal gate that computes And (or Or) of `inputs`, optionally negated.
+	fn root_of(&mut self, source: &Object, and: bool, negate: bool, inputs: &[Node]) -> Vec<Node> {
+		// The universal gate computes the negation of its "primary" function: And for Nand, Or for Nor.
+		let primary = and == (self.tech == Technology::Nand);
+		match (primary, negate) {
+			(true, true) => inputs.to_vec(),
+			(true, false) => { let g = self.emit(source, inputs.to_vec()); vec![g, g] },
+			// De Morgan: the dual function is the universal gate applied to the negated inputs
+			(false, false) => inputs.iter().map(|&x| self.not(source, x)).collect(),
+			(false, true) => {
+				let negated = inputs.iter().map(|&x| self.not(source, x)).collect();
+				let g = self.emit(source, negated);
+				vec![g, g]
+			},
+		}
+	}
+	/// Returns the inputs of a universal gate that computes the Xor (or Xnor) of two nodes.
+	fn xor_root(&mut self, source: &Object, a: Node, b: Node, negate: bool) -> Vec<Node> {
+		// The classic four gate network computes Xor for Nand, and Xnor for Nor.
+		let t = self.emit(source, vec![a, b]);
+		let root = vec![self.emit(source, vec![a, t]), self.emit(source, vec![b, t])];
+		if (self.tech == Technology::Nand) != negate {
+			root
+		} else {
+			let g = self.emit(source, root);
+			vec![g, g]
+		}
+	}
+	/// Returns the inputs of the universal gate that replaces a simple gate.
+	fn root(&mut self, source: &Object, kind: SimpleGateType, xor_type: XorType, inputs: &[Node]) -> Vec<Node> {
+		use SimpleGateType as S;
+		match kind {
+			S::Buffer => { let n = self.not(source, inputs[0]); vec![n, n] },
+			S::Not => vec![inputs[0], inputs[0]],
+			S::And => self.root_of(source, true, false, inputs),
+			S::Nand => self.root_of(source, true, true, inputs),
+			S::Or => self.root_of(source, false, false, inputs),
+			S::Nor => self.root_of(source, false, true, inputs),
+			S::Xor | S::Xnor if inputs.len() == 1 =>
+				self.root(source, if kind == S::Xor { S::Buffer } else { S::Not }, xor_type, inputs),
+			S::Xor | S::Xnor if xor_type == XorType::Odd || inputs.len() == 2 => {
+				let mut acc = inputs[0];
+				for &x in &inputs[1..inputs.len() - 1] {
+					let root = self.xor_root(source, acc, x, false);
+					acc = self.emit(source, root);
+				}
+				self.xor_root(source, acc, inputs[inputs.len() - 1], kind == S::Xnor)
+			},
+			S::Xor | S::Xnor => {
+				// Exactly one input is true: Or over each input And-ed with the negation of all others
+				let terms: Vec<Node> = (0..inputs.len()).map(|i| {
+					let literals: Vec<Node> = inputs.iter().enumerate()
+						.map(|(j, &x)| if i == j { x } else { self.not(source, x) })
+						.collect();
+					let root = self.root_of(source, true, false, &literals);
+					self.emit(source, root)
+				}).collect();
+				self.root_of(source, false, kind == S::Xnor, &terms)
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::io::{parse_xml, Circuit, ObjectInner};
+	use crate::simul::Simulation;
+	use crate::synth::*;
+	use crate::util::fixtures;
+
+	fn assert_mapping_equivalent(circuit: Circuit) {
+		let expected = Simulation::from(circuit.clone()).get_truth_table(1000).unwrap();
+		for tech in [Technology::Nand, Technology::Nor] {
+			let mapped = map_technology(&circuit, tech);
+			for obj in &mapped.objects {
+				if let ObjectInner::SimpleGate { kind, .. } = obj.inner {
+					assert_eq!(kind, tech.gate_type());
+				}
+			}
+			assert_eq!(Simulation::from(mapped).get_truth_table(1000).unwrap(), expected, "{tech:?}");
+		}
+	}
+	#[test]
+	fn map_fixtures(){
+		assert_mapping_equivalent(parse_xml(fixtures::XOR).unwrap());
+		assert_mapping_equivalent(parse_xml(fixtures::MAJORITY).unwrap());
+	}
+	#[test]
+	fn map_simple_gates(){
+		for kind in ["buffer", "not"] {
+			assert_mapping_equivalent(parse_xml(&fixtures::gate_circuit(kind, 1, None)).unwrap());
+		}
+		for kind in ["and", "nand", "or", "nor", "xor", "xnor"] {
+			for inputs in 1..=4 {
+				assert_mapping_equivalent(parse_xml(&fixtures::gate_circuit(kind, inputs, None)).unwrap());
+				assert_mapping_equivalent(parse_xml(&fixtures::gate_circuit(kind, inputs, Some(1))).unwrap());
+			}
+		}
+	}
+	#[test]
+	fn map_inverted_inputs(){
+		for kind in ["and", "or", "xor"] {
+			let mut circuit = parse_xml(&fixtures::gate_circuit(kind, 3, None)).unwrap();
+			circuit.objects[3].set_input_inverted(1, true).unwrap();
+			assert_mapping_equivalent(circuit);
+		}
+	}
+	#[test]
+	fn synthesis_reports(){
+		let xor = parse_xml(fixtures::XOR).unwrap().synthesis_report();
+		assert_eq!(xor.gates, BTreeMap::from([("Xor".to_string(), 1)]));
+		assert_eq!((xor.transistor_count(), xor.depth), (12, 1));
+		let adder = parse_xml(fixtures::RIPPLE_ADDER).unwrap().synthesis_report();
+		assert_eq!(adder.gates, BTreeMap::from([("And".to_string(), 8), ("Or".to_string(), 4), ("Xor".to_string(), 8)]));
+		assert_eq!(adder.transistors, BTreeMap::from([("And".to_string(), 48), ("Or".to_string(), 24), ("Xor".to_string(), 96)]));
+		assert_eq!(adder.depth, 12);
+		assert_eq!(adder.to_string(), "\
+Gates:
+  And: 8 (48 transistors)
+  Or: 4 (24 transistors)
+  Xor: 8 (96 transistors)
+  total: 20 (168 transistors)
+Depth: 12 gate levels
+");
+		let mut inverted = parse_xml(&fixtures::gate_circuit("nand", 3, None)).unwrap();
+		inverted.objects[3].set_input_inverted(0, true).unwrap();
+		assert_eq!(inverted.synthesis_report().transistor_count(), 8);
+	}
+	#[test]
+	fn map_expressions(){
+		let inputs = ["a", "b", "c"];
+		for source in ["a", "!a", "!!a", "a & b", "a | b", "a ^ b", "!(a ^ b) | c", "a & !b ^ c", "(a | b) & (b ^ c)"] {
+			let expr = BoolExpr::parse(source, &inputs).unwrap();
+			for tech in [Technology::Nand, Technology::Nor] {
+				let mapped = map_expr(&expr, tech);
+				for row in 0..8 {
+					let values = [row & 1 != 0, row & 2 != 0, row & 4 != 0];
+					assert_eq!(mapped.eval(tech, &values), expr.eval(&values), "{source} with {tech:?}");
+				}
+			}
+		}
+		let not = map_expr(&BoolExpr::parse("!a", &inputs).unwrap(), Technology::Nand);
+		assert_eq!(not.format(Technology::Nand, &inputs), "nand(a, a)");
+		assert_eq!(map_expr(&BoolExpr::parse("!!a", &inputs).unwrap(), Technology::Nor), GateExpr::Input(0));
+		let xor = map_expr(&BoolExpr::parse("a ^ b", &inputs).unwrap(), Technology::Nand);
+		assert_eq!(xor.format(Technology::Nand, &inputs), "nand(nand(a, nand(a, b)), nand(b, nand(a, b)))");
+		assert_eq!(map_expr(&BoolExpr::parse("a & b", &inputs).unwrap(), Technology::Nor).gate_count(), 3);
+	}
+	#[test]
+	fn map_gate_counts(){
+		let xor = parse_xml(fixtures::XOR).unwrap();
+		assert_eq!(gate_count(&map_technology(&xor, Technology::Nand)), 4);
+		assert_eq!(gate_count(&map_technology(&xor, Technology::Nor)), 5);
+		let uids: Vec<_> = map_technology(&xor, Technology::Nand).objects.iter().map(|o| o.uid().to_string()).collect();
+		assert_eq!(uids, ["a", "b", "g", "q", "gen-0001", "gen-0002", "gen-0003"]);
+		let not = parse_xml(&fixtures::gate_circuit("not", 1, None)).unwrap();
+		assert_eq!(gate_count(&map_technology(&not, Technology::Nand)), 1);
+	}
+}
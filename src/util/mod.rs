@@ -6,12 +6,131 @@ pub fn int_to_bits(int: usize, len: u8) -> Vec<bool> {
   let len = len as usize;
   (0..len).map(|i| int & (1 << (len - i - 1)) != 0).collect()
 }
+/// Writes values as a string of 0s and 1s, like `0110`.
+pub fn bit_string(values: &[bool]) -> String {
+  values.iter().map(|&v| if v { '1' } else { '0' }).collect()
+}
+
+/// Calls `f` on every item, spreading the items over up to `threads` threads. The results are in the same order as the items.
+pub(crate) fn parallel_map<T: Send, R: Send>(items: &mut [T], threads: usize, f: impl Fn(&mut T) -> R + Sync) -> Vec<R> {
+  if threads <= 1 || items.len() <= 1 {
+    return items.iter_mut().map(f).collect();
+  }
+  let chunk_size = items.len().div_ceil(threads);
+  std::thread::scope(|scope| {
+    let handles: Vec<_> = items.chunks_mut(chunk_size)
+      .map(|chunk| scope.spawn(|| chunk.iter_mut().map(&f).collect::<Vec<_>>()))
+      .collect();
+    handles.into_iter().flat_map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))).collect()
+  })
+}
+
+/// Circuits for tests, and generators for larger ones.
+#[cfg(any(test, feature = "testing"))]
+pub mod fixtures {
+  pub const XOR: &str = include_str!("../../fixtures/xor.xml");
+  pub const MAJORITY: &str = include_str!("../../fixtures/majority.xml");
+  pub const HALF_ADDER: &str = include_str!("../../fixtures/half_adder.xml");
+  pub const REDUNDANT: &str = include_str!("../../fixtures/redundant.xml");
+  /// A 4 bit adder built from full adder custom circuits, listed from the most significant bit
+  /// so that carries ripple through one bit per update pass.
+  pub const RIPPLE_ADDER: &str = include_str!("../../fixtures/ripple_adder.xml");
+  /// Three copies of (a and b) and c, with the inputs of some gates swapped, driving q0, q1, and q2.
+  pub const AND_SUBTREES: &str = include_str!("../../fixtures/and_subtrees.xml");
+  /// Adds 1 to the 4 bit input x3..x0, giving y3..y0.
+  pub const INCREMENTER: &str = include_str!("../../fixtures/incrementer.xml");
+  /// A 2 bit adder (x1 x0 + y1 y0 = c s1 s0) built from two full adders, each built from two half adders.
+  pub const NESTED_ADDER: &str = include_str!("../../fixtures/nested_adder.xml");
+  /// A reference full adder (spec_sum, spec_cout) next to one built to match it (impl_sum, impl_cout),
+  /// whose carry wrongly reads a instead of a xor b, so impl_cout is wrong when only b and cin are on.
+  pub const CHECKED_ADDER: &str = include_str!("../../fixtures/checked_adder.xml");
+  /// A digit display d showing a on input 0, b on input 1, and a and b on input 3, next to a light bulb q showing a and b.
+  pub const DIGIT: &str = include_str!("../../fixtures/digit.xml");
+  /// [`XOR`] with the connection from b coming from an object that was deleted.
+  pub const DANGLING: &str = include_str!("../../fixtures/dangling.xml");
+  /// An xor custom circuit, with the styling, view settings, and other attributes and elements
+  /// that Logicly writes but this crate does not model.
+  pub const STYLED: &str = include_str!("../../fixtures/styled.xml");
+  /// [`XOR`] in an older format, with switch states written as 1 and 0 and no rotation on unrotated objects.
+  pub const XOR_OLD: &str = include_str!("../../fixtures/xor_old.xml");
+  /// A circuit with one of each problem found by `Circuit::validate`, and a custom circuit with a floating gate input.
+  pub const MESSY: &str = include_str!("../../fixtures/messy.xml");
+  /// [`XOR`] with an XML declaration, preceded by a UTF-8 byte order mark.
+  pub const XOR_BOM: &[u8] = include_bytes!("../../fixtures/xor_bom.xml");
+  /// [`XOR`] with an XML declaration, encoded as UTF-16LE with a byte order mark.
+  pub const XOR_UTF16LE: &[u8] = include_bytes!("../../fixtures/xor_utf16le.xml");
+
+  /// Builds a combinational circuit with `inputs` switches named i0, i1..., `gates` two-input gates,
+  /// and a light bulb named q0, q1... on each of the last `outputs` gates.
+  /// Each gate reads two earlier switches or gates, chosen by a fixed pseudo-random sequence.
+  /// Every gate only depends on earlier objects, so the circuit is always stable.
+  pub fn synthetic_circuit(inputs: usize, gates: usize, outputs: usize) -> String {
+    let kinds = ["and", "or", "xor", "nand", "nor", "xnor", "xor", "xnor"];
+    let mut seed: u64 = 0x2545f4914f6cdd1d;
+    let mut next = |n: usize| {
+      seed ^= seed << 13;
+      seed ^= seed >> 7;
+      seed ^= seed << 17;
+      seed as usize % n
+    };
+    let mut xml = String::from("<logicly>");
+    let mut connections = String::new();
+    for i in 0..inputs {
+      xml += &format!(r#"<object type="switch@logic.ly" uid="i{i}" x="0" y="0" rotation="0" exportName="i{i}" outputs="false"/>"#);
+    }
+    let node = |n: usize| if n < inputs { format!("i{n}") } else { format!("g{}", n - inputs) };
+    for g in 0..gates {
+      xml += &format!(r#"<object type="{}@logic.ly" uid="g{g}" x="0" y="0" rotation="0" inputs="2"/>"#, kinds[next(kinds.len())]);
+      for input in 0..2 {
+        let source = next(inputs + g);
+        connections += &format!(r#"<connection inputUID="g{g}" outputUID="{}" inputIndex="{input}" outputIndex="0"/>"#, node(source));
+      }
+    }
+    for q in 0..outputs {
+      xml += &format!(r#"<object type="light_bulb@logic.ly" uid="q{q}" x="0" y="0" rotation="0" exportName="q{q}"/>"#);
+      connections += &format!(r#"<connection inputUID="q{q}" outputUID="g{}" inputIndex="0" outputIndex="0"/>"#, gates - outputs + q);
+    }
+    xml + &connections + r#"<setting name="gridSize" value="10"/></logicly>"#
+  }
+  /// Builds a circuit with `customs` custom circuits named c0, c1... that do not use each other, and one instance of each
+  /// with every input wired to a switch named i. Custom circuit k is a [`synthetic_circuit`] with `gates + k` gates and one output.
+  pub fn sibling_customs(customs: usize, inputs: usize, gates: usize) -> String {
+    let mut xml = String::from(r#"<logicly><object type="switch@logic.ly" uid="i" x="0" y="0" rotation="0" exportName="i" outputs="false"/>"#);
+    let mut connections = String::new();
+    let mut definitions = String::new();
+    for c in 0..customs {
+      let uuid = format!("00000000-0000-4000-8000-{c:012}");
+      xml += &format!(r#"<object type="{uuid}" uid="c{c}" x="0" y="0" rotation="0"/>"#);
+      for input in 0..inputs {
+        connections += &format!(r#"<connection inputUID="c{c}" outputUID="i" inputIndex="{input}" outputIndex="0"/>"#);
+      }
+      definitions += &format!(r#"<custom name="c{c}" type="{uuid}" label="C{c}">{}</custom>"#, synthetic_circuit(inputs, gates + c, 1));
+    }
+    xml + &connections + &definitions + "</logicly>"
+  }
+  /// Builds a circuit with `inputs` switches named i0, i1... wired to a single gate, which drives a light bulb named q.
+  pub fn gate_circuit(kind: &str, inputs: usize, function_index: Option<u8>) -> String {
+    let mut xml = String::from("<logicly>");
+    for i in 0..inputs {
+      xml += &format!(r#"<object type="switch@logic.ly" uid="i{i}" x="0" y="0" rotation="0" exportName="i{i}" outputs="false"/>"#);
+    }
+    let function_index = function_index.map(|f| format!(r#" functionIndex="{f}""#)).unwrap_or_default();
+    xml += &format!(r#"<object type="{kind}@logic.ly" uid="g" x="0" y="0" rotation="0" inputs="{inputs}"{function_index}/>"#);
+    xml += r#"<object type="light_bulb@logic.ly" uid="q" x="0" y="0" rotation="0" exportName="q"/>"#;
+    for i in 0..inputs {
+      xml += &format!(r#"<connection inputUID="g" outputUID="i{i}" inputIndex="{i}" outputIndex="0"/>"#);
+    }
+    xml += r#"<connection inputUID="q" outputUID="g" inputIndex="0" outputIndex="0"/><setting name="gridSize" value="10"/></logicly>"#;
+    xml
+  }
+}
 
 #[cfg(test)]
 mod tests {
   use super::*;
 
   #[test]
+  #[allow(clippy::useless_vec)]
   fn test(){
     assert_eq!(bits_to_int(vec![true, false, false, true, true, true, true, true].iter()), 0b10011111);
     assert_eq!(bits_to_int(vec![false, false, false, true, true, true, true, false].iter()), 0b00011110);
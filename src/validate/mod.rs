@@ -0,0 +1,371 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use anyhow::{anyhow, Result};
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::io::{feedback_loops, Circuit, InputType, Object, ObjectInner, SimpleGateType};
+use crate::simul::Simulation;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+	/// The circuit is almost certainly wrong, or cannot be used as intended.
+	Error,
+	/// The circuit works, but probably not as intended.
+	Warning,
+	/// Worth knowing about, but often deliberate.
+	Info,
+}
+impl Display for Severity {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Severity::Error => "error",
+			Severity::Warning => "warning",
+			Severity::Info => "info",
+		})
+	}
+}
+
+/// Which diagnostics [`Circuit::validate`] reports.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ValidateLevel {
+	/// Only errors.
+	Errors,
+	/// Errors and warnings.
+	#[default]
+	Warnings,
+	/// Every diagnostic, including informational ones.
+	All,
+}
+impl ValidateLevel {
+	fn includes(self, severity: Severity) -> bool {
+		match self {
+			ValidateLevel::Errors => severity == Severity::Error,
+			ValidateLevel::Warnings => severity != Severity::Info,
+			ValidateLevel::All => true,
+		}
+	}
+}
+
+/// A single problem found by [`Circuit::validate`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Diagnostic {
+	pub severity: Severity,
+	/// Stable name of the check that found the problem, like `floating-input`.
+	pub code: &'static str,
+	/// Name of the custom circuit definition the problem is in, or None for the top level.
+	pub custom: Option<String>,
+	/// Uids of the objects involved.
+	pub uids: Vec<String>,
+	/// Where each object in `uids` is in Logicly, as (x, y).
+	pub positions: Vec<(f64, f64)>,
+	pub message: String,
+}
+impl Diagnostic {
+	fn new(severity: Severity, code: &'static str, uids: Vec<String>, message: String) -> Self {
+		Self { severity, code, custom: None, uids, positions: vec![], message }
+	}
+}
+impl Display for Diagnostic {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}[{}]: ", self.severity, self.code)?;
+		if let Some(custom) = &self.custom {
+			write!(f, "in custom circuit {custom}: ")?;
+		}
+		f.write_str(&self.message)
+	}
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ValidationReport {
+	/// The top level comes first, then each custom circuit definition in dependency order.
+	pub diagnostics: Vec<Diagnostic>,
+}
+impl ValidationReport {
+	pub fn has_errors(&self) -> bool {
+		self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+	}
+	/// Lists errors, then warnings, then other diagnostics, each with the position of the first object involved.
+	pub fn format_by_severity(&self) -> String {
+		let mut out = String::new();
+		for severity in [Severity::Error, Severity::Warning, Severity::Info] {
+			for diagnostic in self.diagnostics.iter().filter(|d| d.severity == severity) {
+				out += &diagnostic.to_string();
+				if let Some((x, y)) = diagnostic.positions.first() {
+					out += &format!(" (at {x}, {y})");
+				}
+				out.push('\n');
+			}
+		}
+		if out.is_empty() { out += "No problems found\n" }
+		out
+	}
+}
+impl Display for ValidationReport {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if self.diagnostics.is_empty() {
+			return writeln!(f, "No problems found");
+		}
+		for diagnostic in &self.diagnostics {
+			writeln!(f, "{diagnostic}")?;
+		}
+		Ok(())
+	}
+}
+
+/// A structural check, which adds a diagnostic for each problem in a list of objects.
+type Check = fn(&[Object], &mut Vec<Diagnostic>);
+const checks: &[Check] = &[
+	duplicate_names, floating_inputs, undriven_outputs, constant_outputs, degenerate_gates, feedback, unused_objects, unnamed_pins,
+];
+/// The code of every diagnostic, which can be allowed or denied in a [`LintConfig`].
+pub const rules: &[&str] = &[
+	"duplicate-name", "floating-input", "undriven-output", "constant-output", "degenerate-gate", "feedback-loop",
+	"unused-input", "unused-gate", "unnamed-input", "unnamed-output",
+];
+/// Circuits with at most this many named inputs are simulated by [`Circuit::lint`] to find constant outputs.
+const lint_simulation_max_inputs: usize = 12;
+
+/// Which rules [`Circuit::lint`] skips, and which it reports as errors.
+#[derive(Clone, Debug, Default)]
+pub struct LintConfig {
+	pub allow: Vec<String>,
+	pub deny: Vec<String>,
+}
+
+impl Circuit {
+	/// Runs every structural check on the top level and each custom circuit definition, without simulating anything.
+	pub fn validate(&self, level: ValidateLevel) -> ValidationReport {
+		let levels = [(None, &self.objects)].into_iter()
+			.chain(self.custom_library().iter().map(|c| (Some(&c.name), &c.objects)));
+		let mut diagnostics = vec![];
+		for (custom, objects) in levels {
+			let mut found = vec![];
+			for check in checks { check(objects, &mut found); }
+			let position = |uid: &String| objects.iter().find(|o| o.uid() == uid).map(Object::position);
+			diagnostics.extend(found.into_iter()
+				.filter(|d| level.includes(d.severity))
+				.map(|d| Diagnostic { custom: custom.cloned(), positions: d.uids.iter().filter_map(position).collect(), ..d }));
+		}
+		ValidationReport { diagnostics }
+	}
+	/// Runs [`Self::validate`], and also simulates small circuits to find outputs that never change.
+	/// Allowed rules are dropped, and denied rules become errors.
+	pub fn lint(&self, config: &LintConfig) -> Result<ValidationReport> {
+		if let Some(unknown) = config.allow.iter().chain(&config.deny).find(|rule| !rules.contains(&&rule[..])) {
+			return Err(anyhow!("unknown rule {unknown}, expected one of {}", rules.join(", ")));
+		}
+		let mut report = self.validate(ValidateLevel::All);
+		if self.named_input_count() <= lint_simulation_max_inputs && let Some(table) = Simulation::from(self.clone()).get_truth_table(1000) {
+			let outputs: Vec<_> = self.objects.iter().filter(|o| o.is_named_output()).collect();
+			for (column, output) in outputs.into_iter().enumerate() {
+				let reported = report.diagnostics.iter()
+					.any(|d| d.custom.is_none() && matches!(d.code, "constant-output" | "undriven-output") && d.uids[0] == output.uid());
+				let value = table[0][column];
+				if reported || table.rows().any(|row| row[column] != value) { continue }
+				report.diagnostics.push(Diagnostic {
+					positions: vec![output.position()],
+					..Diagnostic::new(Severity::Warning, "constant-output", vec![output.uid().to_string()],
+						format!("output {} is always {} for every combination of inputs", describe(output), value as u8))
+				});
+			}
+		}
+		report.diagnostics.retain(|d| !config.allow.iter().any(|rule| rule == d.code));
+		for diagnostic in &mut report.diagnostics {
+			if config.deny.iter().any(|rule| rule == diagnostic.code) { diagnostic.severity = Severity::Error }
+		}
+		Ok(report)
+	}
+}
+
+/// The export name of an object, followed by its uid if that is different, so that objects sharing a name can be told apart.
+fn describe(object: &Object) -> String {
+	match object.export_name_or_uid() {
+		name if name == object.uid() => name.to_string(),
+		name => format!("{name} ({})", object.uid()),
+	}
+}
+
+fn is_gate(object: &Object) -> bool {
+	matches!(object.inner, ObjectInner::SimpleGate { .. } | ObjectInner::CustomGate { .. })
+}
+
+/// Named inputs or named outputs that share an export name, which makes truth table columns ambiguous.
+fn duplicate_names(objects: &[Object], out: &mut Vec<Diagnostic>) {
+	for (kind, filter) in [("inputs", Object::is_named_input as fn(&Object) -> bool), ("outputs", Object::is_named_output)] {
+		let mut by_name: BTreeMap<&str, Vec<&Object>> = BTreeMap::new();
+		for object in objects.iter().filter(|o| filter(o)) {
+			by_name.entry(object.export_name_or_uid()).or_default().push(object);
+		}
+		for (name, objects) in by_name.into_iter().filter(|(_, objects)| objects.len() > 1) {
+			out.push(Diagnostic::new(Severity::Error, "duplicate-name", objects.iter().map(|o| o.uid().to_string()).collect(),
+				format!("{} {kind} are named {name}", objects.len())));
+		}
+	}
+}
+
+/// Gate inputs with nothing connected, which read as false.
+fn floating_inputs(objects: &[Object], out: &mut Vec<Diagnostic>) {
+	for object in objects.iter().filter(|o| is_gate(o)) {
+		let floating: Vec<_> = object.connections().iter().enumerate().filter(|(_, c)| c.is_none()).map(|(i, _)| i.to_string()).collect();
+		if !floating.is_empty() {
+			out.push(Diagnostic::new(Severity::Warning, "floating-input", vec![object.uid().to_string()],
+				format!("gate {} has nothing connected to input {}", object.uid(), floating.join(", "))));
+		}
+	}
+}
+
+/// Outputs with nothing connected to them.
+fn undriven_outputs(objects: &[Object], out: &mut Vec<Diagnostic>) {
+	for object in objects.iter().filter(|o| o.is_output() && o.connections().iter().all(Option::is_none)) {
+		out.push(Diagnostic::new(Severity::Warning, "undriven-output", vec![object.uid().to_string()],
+			format!("output {} has nothing connected to it", describe(object))));
+	}
+}
+
+/// Outputs wired straight to constants.
+fn constant_outputs(objects: &[Object], out: &mut Vec<Diagnostic>) {
+	let constant = |source: usize| match objects[source].inner {
+		ObjectInner::Input { kind: InputType::True, .. } => Some(true),
+		ObjectInner::Input { kind: InputType::False, .. } => Some(false),
+		_ => None,
+	};
+	for object in objects.iter().filter(|o| o.is_output()) {
+		let sources: Vec<_> = object.connections().iter().flatten().map(|&(_, source)| source).collect();
+		let Some(values) = sources.iter().map(|&s| constant(s)).collect::<Option<Vec<_>>>() else { continue };
+		if values.is_empty() { continue }
+		let mut uids = vec![object.uid().to_string()];
+		uids.extend(sources.iter().map(|&s| objects[s].uid().to_string()));
+		let values: String = values.iter().map(|&v| if v { '1' } else { '0' }).collect();
+		out.push(Diagnostic::new(Severity::Warning, "constant-output", uids, format!("output {} is always {values}", describe(object))));
+	}
+}
+
+/// Gates with several inputs that all read the same signal, so they act as a buffer or an inverter.
+fn degenerate_gates(objects: &[Object], out: &mut Vec<Diagnostic>) {
+	for object in objects {
+		let ObjectInner::SimpleGate { kind, connections, .. } = &object.inner else { continue };
+		if matches!(kind, SimpleGateType::Buffer | SimpleGateType::Not) || connections.len() < 2 { continue }
+		let inverted = |i: usize| object.inverted_inputs().get(i).copied().unwrap_or(false);
+		if let first @ Some((_, source)) = connections[0]
+			&& connections.iter().enumerate().all(|(i, c)| *c == first && inverted(i) == inverted(0)) {
+			out.push(Diagnostic::new(Severity::Warning, "degenerate-gate", vec![object.uid().to_string(), objects[source].uid().to_string()],
+				format!("{kind} gate {} reads {} on every input", object.uid(), describe(&objects[source]))));
+		}
+	}
+}
+
+/// Gates whose outputs feed back into their own inputs, which may oscillate or latch unpredictably.
+fn feedback(objects: &[Object], out: &mut Vec<Diagnostic>) {
+	for path in feedback_loops(objects) {
+		let uids: Vec<String> = path.iter().map(|&i| objects[i].uid().to_string()).collect();
+		let description = uids.iter().chain(uids.first()).join(" -> ");
+		out.push(Diagnostic::new(Severity::Warning, "feedback-loop", uids, format!("gates form a loop with no clock: {description}")));
+	}
+}
+
+/// Gates and named inputs that nothing reads.
+fn unused_objects(objects: &[Object], out: &mut Vec<Diagnostic>) {
+	let mut used = vec![false; objects.len()];
+	for &(_, source) in objects.iter().flat_map(Object::connections).flatten() {
+		used[source] = true;
+	}
+	for (object, _) in objects.iter().zip(used).filter(|(_, used)| !used) {
+		if is_gate(object) {
+			out.push(Diagnostic::new(Severity::Info, "unused-gate", vec![object.uid().to_string()],
+				format!("nothing reads the output of gate {}", object.uid())));
+		} else if object.is_named_input() {
+			out.push(Diagnostic::new(Severity::Info, "unused-input", vec![object.uid().to_string()],
+				format!("nothing reads input {}", describe(object))));
+		}
+	}
+}
+
+/// Switches, buttons, and light bulbs without an export name, which are left out of truth tables and custom circuit pins.
+fn unnamed_pins(objects: &[Object], out: &mut Vec<Diagnostic>) {
+	for object in objects {
+		let (code, kind) = match &object.inner {
+			ObjectInner::Input { export_name: None, kind: InputType::Switch | InputType::Button, .. } => ("unnamed-input", "input"),
+			ObjectInner::Output { export_name: None, .. } => ("unnamed-output", "output"),
+			_ => continue,
+		};
+		out.push(Diagnostic::new(Severity::Info, code, vec![object.uid().to_string()],
+			format!("{kind} {} has no export name, so it is left out of truth tables", object.uid())));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::io::parse_xml;
+	use crate::util::fixtures;
+	use crate::validate::*;
+
+	#[test]
+	fn validate_messy(){
+		let circuit = parse_xml(fixtures::MESSY).unwrap();
+		let codes = |level| circuit.validate(level).diagnostics.into_iter().map(|d| (d.custom, d.code)).collect::<Vec<_>>();
+		let top = |code| (None, code);
+		assert_eq!(codes(ValidateLevel::All), [
+			top("duplicate-name"),
+			top("floating-input"),
+			top("undriven-output"),
+			top("constant-output"),
+			top("degenerate-gate"),
+			top("unused-input"),
+			top("unused-gate"),
+			top("unnamed-input"),
+			top("unnamed-output"),
+			(Some(String::from("Broken")), "floating-input"),
+		]);
+		assert_eq!(codes(ValidateLevel::Warnings).len(), 6);
+		assert_eq!(codes(ValidateLevel::Errors), [top("duplicate-name")]);
+
+		let report = circuit.validate(ValidateLevel::Errors);
+		assert!(report.has_errors());
+		assert_eq!(report.diagnostics[0].uids, ["a", "a2"]);
+		assert_eq!(report.to_string(), "error[duplicate-name]: 2 inputs are named a\n");
+		let json = serde_json::to_value(&report).unwrap();
+		assert_eq!(json["diagnostics"][0]["severity"], "error");
+		assert_eq!(json["diagnostics"][0]["code"], "duplicate-name");
+		assert_eq!(parse_xml(fixtures::HALF_ADDER).unwrap().validate(ValidateLevel::All).to_string(), "No problems found\n");
+		let latch = crate::circuit!{ in s, r; q = nor(r, nq); nq = nor(s, q); out out = q; };
+		assert_eq!(latch.validate(ValidateLevel::All).to_string(), "warning[feedback-loop]: gates form a loop with no clock: q -> nq -> q\n");
+	}
+	#[test]
+	fn lint_rules(){
+		let circuit = parse_xml(fixtures::MESSY).unwrap();
+		let config = |allow: &[&str], deny: &[&str]| LintConfig {
+			allow: allow.iter().map(|s| s.to_string()).collect(),
+			deny: deny.iter().map(|s| s.to_string()).collect(),
+		};
+		let report = circuit.lint(&config(&["unnamed-output", "unnamed-input"], &["floating-input"])).unwrap();
+		assert!(report.diagnostics.iter().all(|d| !d.code.starts_with("unnamed-")));
+		assert!(report.diagnostics.iter().filter(|d| d.code == "floating-input").all(|d| d.severity == Severity::Error));
+		assert_eq!(report.diagnostics.iter().filter(|d| d.severity == Severity::Error).count(), 3);
+		assert_eq!(report.format_by_severity().lines().take(3).collect::<Vec<_>>(), [
+			"error[duplicate-name]: 2 inputs are named a (at 0, 0)",
+			"error[floating-input]: gate f has nothing connected to input 1 (at 160, 0)",
+			"error[floating-input]: in custom circuit Broken: gate n has nothing connected to input 1 (at 80, 0)",
+		]);
+		// Without the duplicate name and denied rules, there is nothing to fail on
+		assert!(!circuit.lint(&config(&["duplicate-name"], &[])).unwrap().has_errors());
+		assert_eq!(circuit.lint(&config(&["floating-inputs"], &[])).unwrap_err().to_string().split(',').next(),
+			Some("unknown rule floating-inputs"));
+
+		let json = serde_json::to_value(circuit.lint(&config(&[], &[])).unwrap()).unwrap();
+		let floating = json["diagnostics"].as_array().unwrap().iter().find(|d| d["code"] == "floating-input").unwrap();
+		assert_eq!(floating, &serde_json::json!({
+			"severity": "warning",
+			"code": "floating-input",
+			"custom": null,
+			"uids": ["f"],
+			"positions": [[160.0, 0.0]],
+			"message": "gate f has nothing connected to input 1",
+		}));
+
+		let simulated = crate::circuit!{ in a; out q = and(a, not(a)); out r = a; }.lint(&config(&[], &[])).unwrap();
+		assert_eq!(simulated.diagnostics.iter().map(|d| &d.message[..]).collect::<Vec<_>>(), ["output q is always 0 for every combination of inputs"]);
+	}
+}
@@ -0,0 +1,220 @@
+//! Checks of sequential circuits, like counters and registers, driven one clock cycle at a time.
+//! A clock cycle sets the clock input high and lets the circuit settle, then sets it low and lets it settle again.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use crate::simul::Simulation;
+
+/// Named inputs or outputs that together hold a number, like q0, q1, q2, where q0 is the least significant bit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bus {
+	pub prefix: String,
+	pub width: u8,
+}
+impl Bus {
+	/// Export names of the bits, least significant first.
+	pub fn names(&self) -> Vec<String> {
+		(0..self.width).map(|bit| format!("{}{bit}", self.prefix)).collect()
+	}
+}
+/// Reads a bus written as `PREFIX:WIDTH`, like `q:4` for q0 to q3.
+impl FromStr for Bus {
+	type Err = anyhow::Error;
+	fn from_str(s: &str) -> Result<Self> {
+		let (prefix, width) = s.split_once(':').ok_or_else(|| anyhow!("expected PREFIX:WIDTH, like q:4, found {s}"))?;
+		let width: u8 = width.parse().map_err(|_| anyhow!("invalid width {width} in {s}"))?;
+		if prefix.is_empty() || width == 0 || width > 64 {
+			return Err(anyhow!("expected a prefix and a width from 1 to 64, found {s}"));
+		}
+		Ok(Bus { prefix: prefix.to_string(), width })
+	}
+}
+impl Display for Bus {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}{}..{0}0", self.prefix, self.width - 1)
+	}
+}
+
+/// A bus that held the wrong value after a clock cycle. See [`counter`] and [`register`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SequenceMismatch {
+	/// The clock cycle after which the bus was read, counting from 1. 0 means before the first cycle that was checked.
+	pub cycle: usize,
+	/// Prefix of the bus.
+	pub bus: String,
+	pub expected: u64,
+	pub found: u64,
+}
+impl Display for SequenceMismatch {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "After clock cycle {}, expected {}={}, found {}", self.cycle, self.bus, self.expected, self.found)
+	}
+}
+impl std::error::Error for SequenceMismatch {}
+
+fn set(sim: &mut Simulation, values: &[(&str, bool)], limit: u128) -> Result<()> {
+	sim.apply_inputs(&values.iter().copied().collect(), limit)?;
+	Ok(())
+}
+
+fn clock_cycle(sim: &mut Simulation, clock: &str, limit: u128) -> Result<()> {
+	set(sim, &[(clock, true)], limit)?;
+	set(sim, &[(clock, false)], limit)
+}
+
+fn read(sim: &Simulation, bus: &Bus) -> Result<u64> {
+	let names = bus.names();
+	let values = sim.get_probes(&names.iter().map(|n| &n[..]).collect::<Vec<_>>());
+	names.iter().enumerate().try_fold(0, |total, (bit, name)| match values.get(name).map(|v| &v[..]) {
+		Some([value]) => Ok(total | (*value as u64) << bit),
+		Some(_) => Err(anyhow!("{name} has more than one value")),
+		None => Err(anyhow!("No input or output named {name}")),
+	})
+}
+
+/// Resets the simulation, with every input low, and lets it settle.
+fn start(sim: &mut Simulation, limit: u128) -> Result<()> {
+	sim.reset_state();
+	match sim.update_until_done(limit) {
+		true => Ok(()),
+		false => Err(anyhow!("circuit did not stabilize within {limit} update passes")),
+	}
+}
+
+/// Checks that `q` goes up by one every clock cycle, going back to 0 after `modulus - 1`, and returns the number of cycles run.
+///
+/// The simulation is reset first. If `reset` is given, it is held high for one clock cycle, after which `q` must be 0.
+/// Otherwise counting starts from whatever `q` settles to after the simulation is reset.
+/// Runs `modulus + 1` cycles, so that the count wraps around at least once, and fails if that does not fit in a `usize`.
+/// Fails with a [`SequenceMismatch`] at the first wrong value.
+pub fn counter(sim: &mut Simulation, q: &Bus, clock: &str, reset: Option<&str>, modulus: u64, limit: u128) -> Result<usize> {
+	if modulus < 2 || (q.width < 64 && modulus > 1 << q.width) {
+		return Err(anyhow!("a {} bit counter cannot count modulo {modulus}", q.width));
+	}
+	let cycles = usize::try_from(modulus).ok().and_then(|modulus| modulus.checked_add(1))
+		.ok_or_else(|| anyhow!("too many clock cycles to count modulo {modulus}"))?;
+	start(sim, limit)?;
+	if let Some(reset) = reset {
+		set(sim, &[(reset, true)], limit)?;
+		clock_cycle(sim, clock, limit)?;
+		set(sim, &[(reset, false)], limit)?;
+	}
+	let mut expected = read(sim, q)?;
+	if reset.is_some() && expected != 0 {
+		return Err(SequenceMismatch { cycle: 0, bus: q.prefix.clone(), expected: 0, found: expected }.into());
+	}
+	if expected >= modulus {
+		return Err(anyhow!("{} starts at {expected}, which is not below the modulus {modulus}", q.prefix));
+	}
+	for cycle in 1..=cycles {
+		clock_cycle(sim, clock, limit)?;
+		expected = (expected + 1) % modulus;
+		let found = read(sim, q)?;
+		if found != expected {
+			return Err(SequenceMismatch { cycle, bus: q.prefix.clone(), expected, found }.into());
+		}
+	}
+	Ok(cycles)
+}
+
+/// Checks that `q` takes the value of `d` every clock cycle where `enable` is high, and keeps its value when it is low.
+/// Without an enable input, `q` must load `d` every cycle.
+///
+/// The simulation is reset, then `cycles` clock cycles are run with pseudo-random values of `d` and `enable`,
+/// the same values every time. `enable` is high in the first cycle, so that the value of `q` is known from then on.
+/// Fails with a [`SequenceMismatch`] at the first wrong value.
+pub fn register(sim: &mut Simulation, d: &Bus, q: &Bus, clock: &str, enable: Option<&str>, cycles: usize, limit: u128) -> Result<()> {
+	if d.width != q.width {
+		return Err(anyhow!("{d} and {q} have different widths"));
+	}
+	start(sim, limit)?;
+	let d_names = d.names();
+	let mask = u64::MAX >> (64 - d.width);
+	let mut seed: u64 = 0x9e3779b97f4a7c15;
+	let mut expected = 0;
+	for cycle in 1..=cycles {
+		seed ^= seed << 13;
+		seed ^= seed >> 7;
+		seed ^= seed << 17;
+		let value = seed & mask;
+		let load = cycle == 1 || enable.is_none() || seed >> 63 == 1;
+		let mut values: HashMap<&str, bool> = d_names.iter().enumerate().map(|(bit, name)| (&name[..], value >> bit & 1 == 1)).collect();
+		if let Some(enable) = enable {
+			values.insert(enable, load);
+		}
+		sim.apply_inputs(&values, limit)?;
+		clock_cycle(sim, clock, limit)?;
+		if load { expected = value; }
+		let found = read(sim, q)?;
+		if found != expected {
+			return Err(SequenceMismatch { cycle, bus: q.prefix.clone(), expected, found }.into());
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::io::dsl::parse_dsl;
+	use crate::verify::*;
+
+	/// A rising edge triggered D flip-flop named `name`, made of two nand gate D latches, with its output at `{name}_q`.
+	fn flip_flop(name: &str, d: &str) -> String {
+		format!("
+			{name}_ms = nand({d}, not(clk)); {name}_mr = nand(not({d}), not(clk));
+			{name}_m = nand({name}_ms, {name}_mn); {name}_mn = nand({name}_mr, {name}_m);
+			{name}_ss = nand({name}_m, clk); {name}_sr = nand({name}_mn, clk);
+			{name}_q = nand({name}_ss, {name}_qn); {name}_qn = nand({name}_sr, {name}_q);
+		")
+	}
+
+	/// A 3 bit binary counter with a synchronous reset. If `broken`, bit 2 toggles whenever bit 1 is set, ignoring bit 0.
+	fn counter_circuit(broken: bool) -> Simulation {
+		let carry = if broken { "f1_q" } else { "and(f1_q, f0_q)" };
+		let mut source = String::from("in clk, rst;");
+		for (i, next) in ["not(f0_q)", "xor(f1_q, f0_q)", &format!("xor(f2_q, {carry})")].into_iter().enumerate() {
+			source += &flip_flop(&format!("f{i}"), &format!("and({next}, not(rst))"));
+			source += &format!("out q{i} = f{i}_q;");
+		}
+		parse_dsl(&source).unwrap().into()
+	}
+
+	/// A 2 bit register with an enable input. If `broken`, it loads every cycle.
+	fn register_circuit(broken: bool) -> Simulation {
+		let mut source = String::from("in clk, en, d0, d1;");
+		for i in 0..2 {
+			let next = if broken { format!("d{i}") } else { format!("or(and(en, d{i}), and(not(en), f{i}_q))") };
+			source += &flip_flop(&format!("f{i}"), &next);
+			source += &format!("out q{i} = f{i}_q;");
+		}
+		parse_dsl(&source).unwrap().into()
+	}
+
+	#[test]
+	fn check_counter(){
+		let q: Bus = "q:3".parse().unwrap();
+		assert_eq!(counter(&mut counter_circuit(false), &q, "clk", Some("rst"), 8, 100).unwrap(), 9);
+		let error = counter(&mut counter_circuit(false), &q, "clk", Some("rst"), 5, 100).unwrap_err();
+		assert_eq!(error.downcast_ref(), Some(&SequenceMismatch { cycle: 5, bus: "q".to_string(), expected: 0, found: 5 }));
+		let error = counter(&mut counter_circuit(true), &q, "clk", Some("rst"), 8, 100).unwrap_err();
+		assert_eq!(error.to_string(), "After clock cycle 3, expected q=3, found 7");
+		// Without a reset input, counting starts from the value after a reset of the simulation
+		assert_eq!(counter(&mut counter_circuit(false), &q, "clk", None, 8, 100).unwrap(), 9);
+		assert_eq!(counter(&mut counter_circuit(false), &q, "clk", None, 9, 100).unwrap_err().to_string(), "a 3 bit counter cannot count modulo 9");
+		assert_eq!(counter(&mut counter_circuit(false), &"q:64".parse().unwrap(), "clk", None, u64::MAX, 100).unwrap_err().to_string(), "too many clock cycles to count modulo 18446744073709551615");
+		assert_eq!(counter(&mut counter_circuit(false), &"r:3".parse().unwrap(), "clk", None, 8, 100).unwrap_err().to_string(), "No input or output named r0");
+	}
+	#[test]
+	fn check_register(){
+		let (d, q): (Bus, Bus) = ("d:2".parse().unwrap(), "q:2".parse().unwrap());
+		register(&mut register_circuit(false), &d, &q, "clk", Some("en"), 50, 100).unwrap();
+		register(&mut register_circuit(true), &d, &q, "clk", None, 50, 100).unwrap();
+		let error = register(&mut register_circuit(true), &d, &q, "clk", Some("en"), 50, 100).unwrap_err();
+		assert!(error.downcast_ref::<SequenceMismatch>().is_some(), "{error}");
+		assert_eq!("q:0".parse::<Bus>().unwrap_err().to_string(), "expected a prefix and a width from 1 to 64, found q:0");
+	}
+}
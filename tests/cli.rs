@@ -0,0 +1,51 @@
+//! Runs the `logicly-rs` command.
+#![cfg(feature = "cli")]
+
+use std::process::Command;
+
+use logicly_rs::io::{parse_raw_xml, save_raw_circuit};
+
+fn run(args: &[&str]) -> String {
+	let output = Command::new(env!("CARGO_BIN_EXE_logicly-rs"))
+		.args(args)
+		.output()
+		.expect("could not run logicly-rs");
+	assert!(output.status.success(), "logicly-rs {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr));
+	String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn completions(){
+	for shell in ["bash", "zsh", "fish", "powershell", "elvish"] {
+		let script = run(&["completions", shell]);
+		for command in ["truth-table", "verify", "analyze", "completions"] {
+			assert!(script.contains(command), "{shell} script does not mention {command}");
+		}
+	}
+	assert!(run(&["completions", "bash"]).contains("logicly-rs __complete-signals \"$file\" --kind $kind"));
+	assert!(run(&["completions", "fish"]).contains("complete -c logicly-rs -l set -f -a '(__logicly_rs_signals inputs \"=\")'"));
+}
+
+#[test]
+fn complete_signals(){
+	let path = concat!(env!("CARGO_TARGET_TMPDIR"), "/half_adder.logicly");
+	save_raw_circuit(&parse_raw_xml(include_str!("../fixtures/half_adder.xml")).unwrap(), path).unwrap();
+	assert_eq!(run(&["__complete-signals", path, "--kind", "inputs"]), "x\ny\n");
+	assert_eq!(run(&["__complete-signals", path, "--kind", "outputs"]), "sum\ncarry\n");
+	assert_eq!(run(&["__complete-signals", path]).lines().count(), 4);
+	assert_eq!(run(&["__complete-signals", "does-not-exist.logicly"]), "");
+}
+
+#[test]
+fn verify_against_conflicts(){
+	// The options are rejected before either file is read
+	let path = "circuit.logicly";
+	for option in [&["--cycles", "5"][..], &["--clock", "a"], &["--reset", "a"]] {
+		let output = Command::new(env!("CARGO_BIN_EXE_logicly-rs"))
+			.args(["verify", path, "--against", path]).args(option)
+			.output()
+			.expect("could not run logicly-rs");
+		assert!(!output.status.success(), "{} was accepted with --against", option[0]);
+		assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+	}
+}
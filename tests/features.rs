@@ -0,0 +1,28 @@
+//! Checks that the library still builds with each feature set documented in the crate docs.
+//! Each test runs a nested `cargo check`, so they are ignored by default: run them with `cargo test --test features -- --ignored`.
+
+use std::process::Command;
+
+fn check(args: &[&str]) {
+	let status = Command::new(env!("CARGO"))
+		.args(["check", "--lib"])
+		.args(args)
+		// A separate directory, so that this does not wait for the lock held by the cargo running the tests
+		.arg("--target-dir").arg(concat!(env!("CARGO_TARGET_TMPDIR"), "/features"))
+		.current_dir(env!("CARGO_MANIFEST_DIR"))
+		.status()
+		.expect("could not run cargo");
+	assert!(status.success(), "cargo check --lib {} failed", args.join(" "));
+}
+
+#[test]
+#[ignore]
+fn no_default_features(){
+	check(&["--no-default-features"]);
+}
+
+#[test]
+#[ignore]
+fn testing_without_cli(){
+	check(&["--no-default-features", "--features", "testing"]);
+}
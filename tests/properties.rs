@@ -0,0 +1,113 @@
+//! Property tests on randomly generated combinational circuits.
+//! The circuits are written in the text format of `logicly_rs::io::dsl`, so a failing case shrinks to a short, readable circuit.
+#![allow(non_upper_case_globals)]
+
+use std::collections::HashMap;
+
+use logicly_rs::io::{dsl::dsl_to_xml, parse_raw_xml, parse_xml};
+use logicly_rs::opt::optimize;
+use logicly_rs::simul::{Simulation, SimulationConfig, TruthTable};
+use logicly_rs::synth::{Technology, map_technology};
+use logicly_rs::util::int_to_bits;
+use proptest::prelude::*;
+
+const kinds: &[&str] = &["and", "or", "nand", "nor", "xor", "xnor", "not", "buffer"];
+const limit: u128 = 1000;
+
+/// A circuit with `inputs` switches, then gates that each read from switches or earlier gates, so there are no loops.
+/// Signals are numbered with the switches first, and indices wrap around the signals defined so far.
+#[derive(Clone, Debug)]
+struct Spec {
+	inputs: usize,
+	gates: Vec<(usize, Vec<usize>)>,
+	outputs: Vec<usize>,
+}
+impl Spec {
+	fn dsl(&self) -> String {
+		let mut names: Vec<String> = (0..self.inputs).map(|i| format!("i{i}")).collect();
+		let mut source = format!("in {}\n", names.join(", "));
+		for (n, (kind, args)) in self.gates.iter().enumerate() {
+			let kind = kinds[kind % kinds.len()];
+			let arity = if matches!(kind, "not" | "buffer") { 1 } else { args.len() };
+			let args: Vec<&str> = args[..arity].iter().map(|&a| &names[a % names.len()][..]).collect();
+			source += &format!("g{n} = {kind}({})\n", args.join(", "));
+			names.push(format!("g{n}"));
+		}
+		for (n, &source_index) in self.outputs.iter().enumerate() {
+			source += &format!("out o{n} = {}\n", names[source_index % names.len()]);
+		}
+		source
+	}
+	fn xml(&self) -> String {
+		dsl_to_xml(&self.dsl()).expect("generated circuits are valid")
+	}
+}
+
+fn spec() -> impl Strategy<Value = Spec> {
+	(
+		1..=4usize,
+		prop::collection::vec((0..kinds.len(), prop::collection::vec(any::<usize>(), 2..=3)), 0..12),
+		prop::collection::vec(any::<usize>(), 1..=3),
+	).prop_map(|(inputs, gates, outputs)| Spec { inputs, gates, outputs })
+}
+
+fn table(simul: &mut Simulation) -> TruthTable {
+	simul.get_truth_table(limit).expect("combinational circuits are stable")
+}
+
+fn without_lookup(xml: &str) -> Simulation {
+	Simulation::with_config(parse_xml(xml).unwrap(), SimulationConfig::default().lookup_table_max_inputs(0)).unwrap()
+}
+
+proptest! {
+	#![proptest_config(ProptestConfig::with_cases(128))]
+
+	#[test]
+	fn xml_round_trip(spec in spec()){
+		let xml = spec.xml();
+		let raw = parse_raw_xml(&xml).unwrap();
+		let written = raw.to_xml();
+		// Source positions differ, so compare what is written instead of the parsed structures
+		prop_assert_eq!(&parse_raw_xml(&written).unwrap().to_xml(), &written);
+		prop_assert_eq!(parse_xml(&written).unwrap(), parse_xml(&xml).unwrap());
+	}
+
+	#[test]
+	fn engines_agree(spec in spec()){
+		let xml = spec.xml();
+		let expected = table(&mut Simulation::from(parse_xml(&xml).unwrap()));
+		let mut simul = without_lookup(&xml);
+		prop_assert_eq!(&table(&mut simul), &expected);
+		let num_inputs = expected.inputs().len() as u8;
+		for row in 0..expected.num_rows() {
+			prop_assert_eq!(&simul.get_outputs_wide(&int_to_bits(row, num_inputs), limit)[..], &expected[row]);
+		}
+		// Toggling inputs one at a time without resetting, in Gray code order
+		let mut incremental = without_lookup(&xml);
+		let inputs: Vec<&str> = expected.inputs().iter().map(|n| &n[..]).collect();
+		let outputs: Vec<&str> = expected.outputs().iter().map(|n| &n[..]).collect();
+		for step in 0..expected.num_rows() {
+			let row = step ^ (step >> 1);
+			let changes: HashMap<&str, bool> = inputs.iter().copied().zip(int_to_bits(row, num_inputs)).collect();
+			incremental.apply_inputs(&changes, limit).unwrap();
+			let values = incremental.get_probes(&outputs);
+			let found: Vec<bool> = outputs.iter().map(|o| values[*o][0]).collect();
+			prop_assert_eq!(&found[..], &expected[row], "row {}", row);
+		}
+	}
+
+	#[test]
+	fn optimize_preserves_table(spec in spec()){
+		let circuit = parse_xml(&spec.xml()).unwrap();
+		let (optimized, _) = optimize(&circuit);
+		prop_assert_eq!(table(&mut optimized.into()), table(&mut circuit.into()));
+	}
+
+	#[test]
+	fn map_technology_preserves_table(spec in spec()){
+		let circuit = parse_xml(&spec.xml()).unwrap();
+		for tech in [Technology::Nand, Technology::Nor] {
+			prop_assert_eq!(table(&mut map_technology(&circuit, tech).into()), table(&mut circuit.clone().into()));
+		}
+	}
+}